@@ -87,10 +87,47 @@ pub struct SavedCameraExtension {
     pub active_sub: bool,
 }
 
+/// How bodies react to running into a collider. Intersect lists are filled in the same way for all of them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CollisionResponse {
+    /// Never blocks anything.
+    #[default]
+    Trigger,
+    /// Blocks bodies coming from any side.
+    Solid,
+    /// Only blocks bodies falling onto it from above, like a platform that can be jumped up through.
+    OneWayUp,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SavedRectColliderExtension {
     pub width: fixed::types::I20F12,
     pub height: fixed::types::I20F12,
+    #[serde(default)]
+    pub offset_x: fixed::types::I20F12,
+    #[serde(default)]
+    pub offset_y: fixed::types::I20F12,
+    #[serde(default)]
+    pub response: CollisionResponse,
+    #[serde(default)]
+    pub is_static: bool,
+    #[serde(default)]
+    pub continuous: bool,
+}
+
+/// Where a line of text goes, relative to its node's position.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TextAlign {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SavedLabelExtension {
+    pub text: String,
+    pub align: TextAlign,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -99,6 +136,7 @@ pub enum SavedNodeExtension {
     Sprite(SavedSpriteExtension),
     Camera(SavedCameraExtension),
     RectCollider(SavedRectColliderExtension),
+    Label(SavedLabelExtension),
 }
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
@@ -117,11 +155,108 @@ pub struct SavedNode {
     pub node_extension: SavedNodeExtension,
     pub script_type_id: Option<NonZeroU32>,
     pub enabled: bool,
+    #[serde(default)]
+    pub user_data: u32,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SavedNodeGraph {
     pub nodes: Vec<SavedNode>,
+    /// Backdrop colour as 0xRRGGBB, set when this graph is loaded as a scene.
+    /// None leaves the backdrop as it was.
+    #[serde(default)]
+    pub backdrop_colour: Option<u32>,
+}
+
+/// Problems that make a SavedNodeGraph unsafe to spawn.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GraphError {
+    /// The node at this index has a child, parent or sibling index past the end of the graph.
+    IndexOutOfRange(u32),
+    NoRoot,
+    /// A second node with no parent, at this index.
+    MultipleRoots(u32),
+    /// The node at this index is reached twice, or its parent chain never gets to the root.
+    Cycle(u32),
+    /// The node at this index has a parent index that doesn't match the node whose child list it's in.
+    ParentMismatch(u32),
+    /// The node at this index can't be reached from the root through child and sibling links.
+    Unreachable(u32),
+}
+
+impl core::fmt::Display for GraphError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            GraphError::IndexOutOfRange(i) => write!(f, "node {i} has a link index out of range"),
+            GraphError::NoRoot => write!(f, "graph has no root node"),
+            GraphError::MultipleRoots(i) => write!(f, "node {i} is a second root node"),
+            GraphError::Cycle(i) => write!(f, "node {i} is part of a cycle"),
+            GraphError::ParentMismatch(i) => write!(f, "node {i} has the wrong parent index"),
+            GraphError::Unreachable(i) => write!(f, "node {i} can't be reached from the root"),
+        }
+    }
+}
+
+impl SavedNodeGraph {
+    /// Checks that the child, parent and sibling indices form a single tree,
+    /// with every node reachable from the root and parent indices that match the child links.
+    pub fn validate(&self) -> Result<(), GraphError> {
+        let len = self.nodes.len();
+        let mut root = None;
+        for (i, node) in self.nodes.iter().enumerate() {
+            let in_range = node.child_index.is_none_or(|x| (x.get() as usize) < len)
+                && node.sibling_index.is_none_or(|x| (x.get() as usize) < len)
+                && node.parent_index.is_none_or(|x| (x as usize) < len);
+            if !in_range {
+                return Err(GraphError::IndexOutOfRange(i as u32));
+            }
+            if node.parent_index.is_none() {
+                if root.is_some() {
+                    return Err(GraphError::MultipleRoots(i as u32));
+                }
+                root = Some(i);
+            }
+        }
+        let root = root.ok_or(GraphError::NoRoot)?;
+
+        // Every parent chain has to reach the root within len steps
+        for i in 0..len {
+            let mut cur = i;
+            let mut steps = 0;
+            while let Some(parent) = self.nodes[cur].parent_index {
+                cur = parent as usize;
+                steps += 1;
+                if steps > len {
+                    return Err(GraphError::Cycle(i as u32));
+                }
+            }
+        }
+
+        // Walking the child and sibling links from the root shouldn't reach any node twice,
+        // and each node should name the node whose child list it was found in as its parent
+        let mut visited = alloc::vec![false; len];
+        let mut stack = alloc::vec![(root, None)];
+        while let Some((i, parent)) = stack.pop() {
+            if visited[i] {
+                return Err(GraphError::Cycle(i as u32));
+            }
+            visited[i] = true;
+            let node = &self.nodes[i];
+            if node.parent_index != parent {
+                return Err(GraphError::ParentMismatch(i as u32));
+            }
+            if let Some(sibling) = node.sibling_index {
+                stack.push((sibling.get() as usize, parent));
+            }
+            if let Some(child) = node.child_index {
+                stack.push((child.get() as usize, Some(i as u32)));
+            }
+        }
+        match visited.iter().position(|v| !v) {
+            Some(i) => Err(GraphError::Unreachable(i as u32)),
+            None => Ok(()),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -138,6 +273,171 @@ pub struct SavedGameData {
     pub graphics: HashMap<String, SavedGraphic>,
 }
 
+/// A script on a running node, for snapshots.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SavedScriptState {
+    pub type_id: NonZeroU32,
+    pub started: bool,
+    /// From the script's `save_state`.
+    pub state: Option<Vec<u8>>,
+}
+
+/// A contact, follow target or camera target in a snapshot, as an index into `SavedSnapshot::node_states`.
+pub type SavedNodeRef = u32;
+
+/// A node's `Body`, for snapshots.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SavedBody {
+    pub velocity: (fixed::types::I20F12, fixed::types::I20F12),
+    pub gravity: fixed::types::I20F12,
+    /// The node hit and the normal, horizontally then vertically.
+    pub contacts: [Option<(SavedNodeRef, (fixed::types::I20F12, fixed::types::I20F12))>; 2],
+}
+
+/// Which screen's graphics engine a node's sprites are drawn by.
+/// The main engine is on the top screen and the sub engine on the bottom, unless they've been swapped.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum Screen {
+    #[default]
+    Both,
+    Main,
+    Sub,
+}
+
+impl Screen {
+    /// Whether something set to this screen is drawn on `screen`, which is Main or Sub.
+    #[must_use]
+    pub fn shows_on(self, screen: Screen) -> bool {
+        self == Screen::Both || self == screen
+    }
+}
+
+/// Effect for a `SpawnAnimation`, applied to the node's sprite.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum SpawnEffect {
+    /// An affine sprite grows from nothing up to its scale.
+    ScaleUp,
+    /// The sprite is drawn with mosaic until the animation is over.
+    Mosaic,
+}
+
+/// How a sprite is combined with what's behind it.
+/// The hardware only has one set of blend coefficients per screen, so every Alpha sprite on a screen
+/// has the same opacity, and if there are any Additive sprites on the screen the Alpha ones are drawn additive too.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BlendMode {
+    #[default]
+    Normal,
+    /// Semi-transparent, using the opacity set with `Hierarchy::set_sprite_alpha`.
+    Alpha,
+    /// Adds the sprite's colour onto what's behind it, for glows and explosions.
+    Additive,
+}
+
+/// A node's `SpawnAnimation`, for snapshots.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SavedSpawnAnimation {
+    pub effect: SpawnEffect,
+    pub frames: u32,
+    /// Frames played so far, and the sprite's scale and mosaic to go back to, while it's playing.
+    pub playing: Option<(u32, (fixed::types::I20F12, fixed::types::I20F12), bool)>,
+}
+
+/// Settings of a sprite that aren't in its `SavedSpriteExtension`, for snapshots.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SavedSpriteState {
+    pub palette_override: Option<u8>,
+    pub blend_mode: BlendMode,
+    pub mosaic: bool,
+    /// Palette bank and frames left.
+    pub flash: Option<(u8, u32)>,
+}
+
+/// A camera's `CameraFollow`, for snapshots.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SavedCameraFollow {
+    pub target: SavedNodeRef,
+    pub screen_pos: (fixed::types::I20F12, fixed::types::I20F12),
+    pub stiffness: fixed::types::I20F12,
+    pub dead_zone: Option<(fixed::types::I20F12, fixed::types::I20F12)>,
+}
+
+/// Settings of a camera that aren't in its `SavedCameraExtension`, for snapshots.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SavedCameraState {
+    pub follow: Option<SavedCameraFollow>,
+    /// Magnitude, frames left and total frames.
+    pub shake: Option<(fixed::types::I20F12, u32, u32)>,
+    pub shake_offset: (fixed::types::I20F12, fixed::types::I20F12),
+}
+
+/// State of a running node that isn't in its `SavedNode`, for snapshots.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SavedNodeState {
+    /// Every script on the node, in order.
+    pub scripts: Vec<SavedScriptState>,
+    pub started: bool,
+    pub script_enabled: bool,
+    pub lifetime: Option<u32>,
+    pub offscreen_destroy_margin: Option<fixed::types::I20F12>,
+    /// Current health, max health, and whether the node is destroyed when it runs out.
+    pub health: Option<(u32, u32, bool)>,
+    pub screen: Option<Screen>,
+    pub on_screen: bool,
+    pub parallax: Option<fixed::types::I20F12>,
+    /// Target and offset.
+    pub follow: Option<(SavedNodeRef, (fixed::types::I20F12, fixed::types::I20F12))>,
+    pub body: Option<SavedBody>,
+    pub spawn_animation: Option<SavedSpawnAnimation>,
+    pub sprite: Option<SavedSpriteState>,
+    pub camera: Option<SavedCameraState>,
+}
+
+impl SavedNodeState {
+    /// Every other node this state refers to.
+    pub fn node_refs(&self) -> impl Iterator<Item = SavedNodeRef> + '_ {
+        let follow = self.follow.map(|(target, _)| target);
+        let contacts = self.body.iter().flat_map(|b| b.contacts.iter().flatten().map(|&(node, _)| node));
+        let camera = self.camera.as_ref().and_then(|c| c.follow.as_ref()).map(|f| f.target);
+        follow.into_iter().chain(contacts).chain(camera)
+    }
+}
+
+/// A running game, from the engine's `Hierarchy::snapshot`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SavedSnapshot {
+    /// Everything under the hierarchy root, one graph per child of the root, in order.
+    pub graphs: Vec<SavedNodeGraph>,
+    /// A state for every node in `graphs`, going through each graph's nodes in order.
+    pub node_states: Vec<SavedNodeState>,
+    pub frame_count: u32,
+    pub current_scene: Option<String>,
+    /// Which of `graphs` is the current scene, if it's still there.
+    pub scene_root: Option<u32>,
+    pub paused: bool,
+    /// Opacity of sprites with alpha blending.
+    pub sprite_alpha: u8,
+    /// Block size of sprites with mosaic.
+    pub sprite_mosaic: (u8, u8),
+}
+
+/// Script type ID for scripts registered without an explicit ID, made by hashing the name (32 bit FNV-1a).
+/// The engine and editor both use this, and IDs end up saved in projects, so it must never change.
+pub const fn script_id_from_name(name: &str) -> NonZeroU32 {
+    let bytes = name.as_bytes();
+    let mut hash: u32 = 0x811c_9dc5;
+    let mut i = 0;
+    while i < bytes.len() {
+        hash ^= bytes[i] as u32;
+        hash = hash.wrapping_mul(0x0100_0193);
+        i += 1;
+    }
+    match NonZeroU32::new(hash) {
+        Some(id) => id,
+        None => NonZeroU32::MIN,
+    }
+}
+
 pub fn serialize<T>(h: &T) -> Vec<u8>
 where
     T: Serialize,
@@ -151,3 +451,113 @@ where
 {
     postcard::from_bytes(h).unwrap()
 }
+
+pub use postcard::Error as SerializationError;
+
+/// Like `deserialize`, but returns an error for bytes that aren't a valid `T` instead of panicking.
+pub fn try_deserialize<'a, T>(h: &'a [u8]) -> Result<T, SerializationError>
+where
+    T: Deserialize<'a>,
+{
+    postcard::from_bytes(h)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn script_id_from_name_stable() {
+        // Known FNV-1a values - if these change, existing projects lose their scripts
+        assert_eq!(script_id_from_name("").get(), 0x811c_9dc5);
+        assert_eq!(script_id_from_name("a").get(), 0xe40c_292c);
+        assert_eq!(script_id_from_name("PlayerScript"), script_id_from_name("PlayerScript"));
+    }
+
+    fn node(parent: Option<u32>, child: Option<u32>, sibling: Option<u32>) -> SavedNode {
+        SavedNode {
+            child_index: child.and_then(NonZeroU32::new),
+            parent_index: parent,
+            sibling_index: sibling.and_then(NonZeroU32::new),
+            name: String::new(),
+            transform: SavedTransform { x: fixed::types::I20F12::ZERO, y: fixed::types::I20F12::ZERO },
+            node_extension: SavedNodeExtension::None,
+            script_type_id: None,
+            enabled: true,
+            user_data: 0,
+        }
+    }
+
+    // Root (0) with children 1 and 2, and 3 under 1
+    fn valid_graph() -> SavedNodeGraph {
+        SavedNodeGraph { nodes: alloc::vec![
+            node(None, Some(1), None),
+            node(Some(0), Some(3), Some(2)),
+            node(Some(0), None, None),
+            node(Some(1), None, None),
+        ], backdrop_colour: None}
+    }
+
+    #[test]
+    fn validate_valid_graph() {
+        assert_eq!(valid_graph().validate(), Ok(()));
+    }
+
+    #[test]
+    fn validate_index_out_of_range() {
+        let mut graph = valid_graph();
+        graph.nodes[2].sibling_index = NonZeroU32::new(4);
+        assert_eq!(graph.validate(), Err(GraphError::IndexOutOfRange(2)));
+        let mut graph = valid_graph();
+        graph.nodes[3].parent_index = Some(9);
+        assert_eq!(graph.validate(), Err(GraphError::IndexOutOfRange(3)));
+    }
+
+    #[test]
+    fn validate_root_count() {
+        let mut graph = valid_graph();
+        graph.nodes[2].parent_index = None;
+        assert_eq!(graph.validate(), Err(GraphError::MultipleRoots(2)));
+        let mut graph = valid_graph();
+        graph.nodes[0].parent_index = Some(3);
+        assert_eq!(graph.validate(), Err(GraphError::NoRoot));
+        assert_eq!(SavedNodeGraph { nodes: Vec::new(), backdrop_colour: None }.validate(), Err(GraphError::NoRoot));
+    }
+
+    #[test]
+    fn validate_cycles() {
+        // Sibling link pointing back to an earlier node
+        let mut graph = valid_graph();
+        graph.nodes[2].sibling_index = NonZeroU32::new(1);
+        assert_eq!(graph.validate(), Err(GraphError::Cycle(1)));
+        // Parent chain that loops without reaching the root
+        let mut graph = valid_graph();
+        graph.nodes[1].parent_index = Some(3);
+        assert_eq!(graph.validate(), Err(GraphError::Cycle(1)));
+    }
+
+    #[test]
+    fn validate_parent_mismatch() {
+        // Node 3 is in node 1's child list, but says its parent is the root
+        let mut graph = valid_graph();
+        graph.nodes[3].parent_index = Some(0);
+        assert_eq!(graph.validate(), Err(GraphError::ParentMismatch(3)));
+        // Sibling of node 1 claiming node 1 as its parent
+        let mut graph = valid_graph();
+        graph.nodes[2].parent_index = Some(1);
+        assert_eq!(graph.validate(), Err(GraphError::ParentMismatch(2)));
+    }
+
+    #[test]
+    fn validate_unreachable() {
+        // Node 2 still points at the root, but nothing links to it
+        let mut graph = valid_graph();
+        graph.nodes[1].sibling_index = None;
+        assert_eq!(graph.validate(), Err(GraphError::Unreachable(2)));
+    }
+
+    #[test]
+    fn script_id_from_name_distinct() {
+        assert_ne!(script_id_from_name("PlayerScript"), script_id_from_name("EnemyScript"));
+    }
+}
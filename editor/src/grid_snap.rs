@@ -0,0 +1,81 @@
+use imgui::Ui;
+use fixed::types::I20F12;
+
+const DEFAULT_GRID_SIZE: u32 = 8;
+// Big enough for any sensible grid, and far below where I20F12 would overflow in snap
+const MAX_GRID_SIZE: u32 = 4096;
+
+pub struct GridSnap {
+    pub enabled: bool,
+    pub size: u32,
+}
+
+impl GridSnap {
+    pub const fn new() -> Self {
+        Self {
+            enabled: false,
+            size: DEFAULT_GRID_SIZE,
+        }
+    }
+
+    // Rounds to the nearest multiple of the grid size, unless snapping is off or bypassed
+    pub fn snap(&self, value: I20F12, bypass: bool) -> I20F12 {
+        if !self.enabled || bypass || self.size == 0 {
+            return value;
+        }
+        let size = I20F12::from_num(self.size);
+        ((value + size / 2) / size).floor() * size
+    }
+
+    // Keeps a typed in size within 1..=MAX_GRID_SIZE, so a typo can't break snapping
+    pub fn set_size(&mut self, size: u32) {
+        self.size = size.clamp(1, MAX_GRID_SIZE);
+    }
+
+    // Hold Alt to temporarily place things freely
+    pub fn bypassed(ui: &Ui) -> bool {
+        ui.io().key_alt
+    }
+
+    pub fn draw_menu(&mut self, ui: &Ui) {
+        ui.menu("Snap", || {
+            ui.checkbox("Snap to Grid", &mut self.enabled);
+            let mut size = self.size;
+            if ui.input_scalar("Grid Size", &mut size).build() {
+                self.set_size(size);
+            }
+            ui.text_disabled("Hold Alt to bypass");
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snap_to_nearest() {
+        let grid_snap = GridSnap { enabled: true, size: 8 };
+        assert_eq!(grid_snap.snap(I20F12::from_num(11), false), I20F12::from_num(8));
+        assert_eq!(grid_snap.snap(I20F12::from_num(13), false), I20F12::from_num(16));
+        assert_eq!(grid_snap.snap(I20F12::from_num(-5), false), I20F12::from_num(-8));
+    }
+
+    #[test]
+    fn size_clamped() {
+        let mut grid_snap = GridSnap { enabled: true, size: 8 };
+        grid_snap.set_size(1_000_000);
+        assert_eq!(grid_snap.size, MAX_GRID_SIZE);
+        assert_eq!(grid_snap.snap(I20F12::from_num(3000), false), I20F12::from_num(4096));
+        grid_snap.set_size(0);
+        assert_eq!(grid_snap.size, 1);
+    }
+
+    #[test]
+    fn snap_bypassed() {
+        let mut grid_snap = GridSnap { enabled: true, size: 8 };
+        assert_eq!(grid_snap.snap(I20F12::from_num(11), true), I20F12::from_num(11));
+        grid_snap.enabled = false;
+        assert_eq!(grid_snap.snap(I20F12::from_num(11), false), I20F12::from_num(11));
+    }
+}
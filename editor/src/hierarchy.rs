@@ -9,12 +9,13 @@ pub struct Transform {
     pub y: fixed::types::I20F12,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum NodeExtension {
     None,
     Sprite(SpriteExtension),
     Camera(CameraExtension),
     RectCollider(RectColliderExtension),
+    Label(LabelExtension),
 }
 
 impl std::fmt::Display for NodeExtension {
@@ -24,6 +25,7 @@ impl std::fmt::Display for NodeExtension {
             NodeExtension::Sprite(_) => write!(f, "Sprite"),
             NodeExtension::Camera(_) => write!(f, "Camera"),
             NodeExtension::RectCollider(_) => write!(f, "Rect Collider"),
+            NodeExtension::Label(_) => write!(f, "Label"),
         }
     }
 }
@@ -44,7 +46,16 @@ impl NodeExtension {
                 },
             }),
             sandstone_common::SavedNodeExtension::Camera(c) => NodeExtension::Camera(CameraExtension { active_main: c.active_main, active_sub: c.active_sub }),
-            sandstone_common::SavedNodeExtension::RectCollider(c) => NodeExtension::RectCollider(RectColliderExtension { width: c.width, height: c.height })
+            sandstone_common::SavedNodeExtension::RectCollider(c) => NodeExtension::RectCollider(RectColliderExtension {
+                width: c.width,
+                height: c.height,
+                offset_x: c.offset_x,
+                offset_y: c.offset_y,
+                response: c.response,
+                is_static: c.is_static,
+                continuous: c.continuous,
+            }),
+            sandstone_common::SavedNodeExtension::Label(l) => NodeExtension::Label(LabelExtension { text: l.text, align: l.align }),
         }
     }
 
@@ -63,7 +74,16 @@ impl NodeExtension {
                 },
             }),
             NodeExtension::Camera(c) => sandstone_common::SavedNodeExtension::Camera(sandstone_common::SavedCameraExtension { active_main: c.active_main, active_sub: c.active_sub }),
-            NodeExtension::RectCollider(c) => sandstone_common::SavedNodeExtension::RectCollider(sandstone_common::SavedRectColliderExtension { width: c.width, height: c.height }),
+            NodeExtension::RectCollider(c) => sandstone_common::SavedNodeExtension::RectCollider(sandstone_common::SavedRectColliderExtension {
+                width: c.width,
+                height: c.height,
+                offset_x: c.offset_x,
+                offset_y: c.offset_y,
+                response: c.response,
+                is_static: c.is_static,
+                continuous: c.continuous,
+            }),
+            NodeExtension::Label(l) => sandstone_common::SavedNodeExtension::Label(sandstone_common::SavedLabelExtension { text: l.text.clone(), align: l.align }),
         }
     }
 }
@@ -92,7 +112,7 @@ pub enum SpriteType {
     Affine(AffineSpriteData),
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Clone, Debug)]
 pub struct SpriteExtension {
     pub graphic_asset: String,
     pub sprite_type: SpriteType,
@@ -108,9 +128,20 @@ pub struct CameraExtension {
 pub struct RectColliderExtension {
     pub width: fixed::types::I20F12,
     pub height: fixed::types::I20F12,
+    pub offset_x: fixed::types::I20F12,
+    pub offset_y: fixed::types::I20F12,
+    pub response: sandstone_common::CollisionResponse,
+    pub is_static: bool,
+    pub continuous: bool,
 }
 
-#[derive(Debug)]
+#[derive(Default, Clone, Debug)]
+pub struct LabelExtension {
+    pub text: String,
+    pub align: sandstone_common::TextAlign,
+}
+
+#[derive(Clone, Debug)]
 pub struct Node {
     pub child_index: Option<NonZeroUsize>,
     pub parent_index: Option<usize>,
@@ -120,12 +151,57 @@ pub struct Node {
     pub node_extension: NodeExtension,
     pub script_type_id: Option<NonZeroU32>,
     pub enabled: bool,
+    pub user_data: u32,
 }
 
-pub struct NodeGraph(pub StableVec<Node>);
+// The nodes, and the backdrop colour (0xRRGGBB) set when the graph is loaded as a scene
+#[derive(Clone)]
+pub struct NodeGraph(pub StableVec<Node>, pub Option<u32>);
 impl NodeGraph {
     pub fn new() -> Self {
-        Self (StableVec::new())
+        Self (StableVec::new(), None)
+    }
+
+    /// Converts a graph from the saved format, as stored in the project file or sent back by the play mode runtime.
+    pub fn from_saved(graph: sandstone_common::SavedNodeGraph) -> Self {
+        let mut new_graph = Self::new();
+        for node in graph.nodes {
+            new_graph.0.push(Node {
+                child_index: node.child_index.map(nzu32_to_nzusize),
+                parent_index: node.parent_index.map(|x| x as usize),
+                sibling_index: node.sibling_index.map(nzu32_to_nzusize),
+                name: node.name,
+                transform: Transform { x: node.transform.x, y: node.transform.y },
+                node_extension: NodeExtension::from_saved(node.node_extension),
+                script_type_id: node.script_type_id,
+                enabled: node.enabled,
+                user_data: node.user_data,
+            });
+        }
+        new_graph.1 = graph.backdrop_colour;
+        new_graph
+    }
+}
+
+#[inline(always)]
+fn nzu32_to_nzusize(x: NonZeroU32) -> NonZeroUsize {
+    // This is fully safe, as new_unchecked only fails if input is 0 - the input is NonZero
+    unsafe { NonZeroUsize::new_unchecked(u32::from(x) as usize) }
+}
+
+// Node with no extension or script, for building graphs in tests. A child_index of 0 means no child.
+#[cfg(test)]
+pub fn test_node(name: &str, child_index: usize, parent_index: Option<usize>) -> Node {
+    Node {
+        child_index: NonZeroUsize::new(child_index),
+        parent_index,
+        sibling_index: None,
+        name: String::from(name),
+        transform: Transform::default(),
+        node_extension: NodeExtension::None,
+        script_type_id: None,
+        enabled: true,
+        user_data: 0,
     }
 }
 
@@ -160,6 +236,17 @@ impl Hierarchy {
                     if ui.selectable("Add Node") {
                         self.add_node(project_data, selected);
                     }
+                    // Root node has no siblings to move past
+                    if let Selected::Node(node_idx) = *selected {
+                        if let Some(node_idx) = NonZeroUsize::new(node_idx) {
+                            if ui.selectable("Move Up") {
+                                self.move_sibling(project_data, node_idx, true);
+                            }
+                            if ui.selectable("Move Down") {
+                                self.move_sibling(project_data, node_idx, false);
+                            }
+                        }
+                    }
                 }
                 self.draw_hierarchy_node(ui, project_data, selected, 0);
             });
@@ -248,6 +335,7 @@ impl Hierarchy {
             node_extension: NodeExtension::None,
             script_type_id: None,
             enabled: true,
+            user_data: 0,
         });
         self.current_graph_idx = project_data.graphs.len();
         // If this is the first graph created, make it the Main Graph
@@ -279,6 +367,7 @@ impl Hierarchy {
                 node_extension: NodeExtension::None,
                 script_type_id: None,
                 enabled: true,
+                user_data: 0,
             });
             Hierarchy::link_node(graph, NonZeroUsize::new(new_index).unwrap(), 0);
             *selected = Selected::Node(new_index);
@@ -308,6 +397,45 @@ impl Hierarchy {
         }
     }
 
+    /// Copies a node and all of its children, and adds the copy right after the original.
+    pub fn duplicate_node(&mut self, project_data: &mut ProjectData, selected: &mut Selected, node_idx: NonZeroUsize) {
+        if let Some(graph) = project_data.graphs.get_mut(self.current_graph_idx) {
+            let parent_idx = graph.0[usize::from(node_idx)].parent_index.unwrap();
+            let new_idx = Hierarchy::copy_subtree(graph, usize::from(node_idx), parent_idx);
+            // copy_subtree puts the copy at the front of the child list, so move it after the original
+            Hierarchy::unlink_node(graph, new_idx);
+            graph.0[usize::from(new_idx)].sibling_index = graph.0[usize::from(node_idx)].sibling_index.replace(new_idx);
+            graph.0[usize::from(new_idx)].name = Hierarchy::unique_name(graph, new_idx);
+            *selected = Selected::Node(usize::from(new_idx));
+        }
+    }
+
+    /// Renames a node so that no other child of its parent has the same name.
+    pub fn make_name_unique(&mut self, project_data: &mut ProjectData, node_idx: NonZeroUsize) {
+        if let Some(graph) = project_data.graphs.get_mut(self.current_graph_idx) {
+            graph.0[usize::from(node_idx)].name = Hierarchy::unique_name(graph, node_idx);
+        }
+    }
+
+    // Swaps the node with the sibling before or after it.
+    fn move_sibling(&mut self, project_data: &mut ProjectData, node_idx: NonZeroUsize, up: bool) {
+        if let Some(graph) = project_data.graphs.get_mut(self.current_graph_idx) {
+            let parent_idx = graph.0[usize::from(node_idx)].parent_index.unwrap();
+            let mut children: Vec<usize> = Vec::new();
+            Hierarchy::loop_over_children(graph, parent_idx, |_, idx| children.push(idx));
+            let pos = children.iter().position(|&idx| idx == usize::from(node_idx)).unwrap();
+            let other_pos = if up { pos.checked_sub(1) } else { Some(pos + 1).filter(|&p| p < children.len()) };
+            let Some(other_pos) = other_pos else { return; };
+            children.swap(pos, other_pos);
+
+            // Relink the whole child list in the new order
+            graph.0[parent_idx].child_index = NonZeroUsize::new(children[0]);
+            for (i, &child) in children.iter().enumerate() {
+                graph.0[child].sibling_index = children.get(i + 1).and_then(|&x| NonZeroUsize::new(x));
+            }
+        }
+    }
+
     pub fn delete_node(&mut self, project_data: &mut ProjectData, selected: &mut Selected, node_idx: NonZeroUsize) {
         // Deselect node just in case it is deleted
         *selected = Selected::None;
@@ -330,6 +458,53 @@ impl Hierarchy {
         }
     }
     
+    // Gives the node's name with the lowest free _1, _2.. suffix, or the name unchanged if no sibling uses it.
+    fn unique_name(graph: &mut NodeGraph, node_idx: NonZeroUsize) -> String {
+        let node_idx_usize = usize::from(node_idx);
+        let name = graph.0[node_idx_usize].name.clone();
+        let mut sibling_names: Vec<String> = Vec::new();
+        if let Some(parent_idx) = graph.0[node_idx_usize].parent_index {
+            Hierarchy::loop_over_children(graph, parent_idx, |node, idx| {
+                if idx != node_idx_usize {
+                    sibling_names.push(node.name.clone());
+                }
+            });
+        }
+        if !sibling_names.contains(&name) {
+            return name;
+        }
+
+        // Duplicating "Enemy_1" should give "Enemy_2", not "Enemy_1_1"
+        let base_name = match name.rsplit_once('_') {
+            Some((base, suffix)) if !suffix.is_empty() && suffix.chars().all(|c| c.is_ascii_digit()) => base,
+            _ => name.as_str(),
+        };
+        let mut suffix = 1;
+        loop {
+            let new_name = format!("{base_name}_{suffix}");
+            if !sibling_names.contains(&new_name) {
+                return new_name;
+            }
+            suffix += 1;
+        }
+    }
+
+    fn copy_subtree(graph: &mut NodeGraph, node_idx: usize, parent: usize) -> NonZeroUsize {
+        let mut new_node = graph.0[node_idx].clone();
+        new_node.child_index = None;
+        new_node.sibling_index = None;
+        let new_idx = NonZeroUsize::new(graph.0.push(new_node)).unwrap();
+        Hierarchy::link_node(graph, new_idx, parent);
+
+        // link_node adds to the front of the child list, so copy in reverse to keep the order
+        let mut children: Vec<usize> = Vec::new();
+        Hierarchy::loop_over_children(graph, node_idx, |_, idx| children.push(idx));
+        for child in children.into_iter().rev() {
+            Hierarchy::copy_subtree(graph, child, usize::from(new_idx));
+        }
+        new_idx
+    }
+
     fn unlink_node(graph: &mut NodeGraph, node_idx: NonZeroUsize) {
         let node_idx_usize = usize::from(node_idx);
         let node_sibling_idx = graph.0[node_idx_usize].sibling_index;
@@ -426,6 +601,93 @@ mod tests {
         assert_eq!(graph[2].parent_index, Some(1));
     }
 
+    #[test]
+    fn duplicate_node_unique_name() {
+        let (mut h, mut project_data, mut selected) = (Hierarchy::new(), ProjectData::new(), Selected::None);
+        h.add_graph(&mut project_data, &mut selected);
+        h.add_node(&mut project_data, &mut selected);
+        h.add_node(&mut project_data, &mut selected);
+        project_data.graphs[0].0[2].name = String::from("Enemy");
+        h.move_node(&mut project_data, NodeMove { node_idx: 2, new_parent_idx: 1 });
+
+        h.duplicate_node(&mut project_data, &mut selected, NonZeroUsize::new(2).unwrap());
+        let Selected::Node(new_idx) = selected else { panic!("Duplicate wasn't selected") };
+        assert_eq!(project_data.graphs[0].0[new_idx].name, "Enemy_1");
+        assert_eq!(project_data.graphs[0].0[new_idx].parent_index, Some(1));
+        h.duplicate_node(&mut project_data, &mut selected, NonZeroUsize::new(new_idx).unwrap());
+        let Selected::Node(new_idx) = selected else { panic!("Duplicate wasn't selected") };
+        assert_eq!(project_data.graphs[0].0[new_idx].name, "Enemy_2");
+
+        // Duplicating a parent copies its children too
+        h.duplicate_node(&mut project_data, &mut selected, NonZeroUsize::new(1).unwrap());
+        let Selected::Node(new_idx) = selected else { panic!("Duplicate wasn't selected") };
+        assert_eq!(project_data.graphs[0].0.num_elements(), 9);
+        assert_eq!(project_data.graphs[0].0[new_idx].name, "Node 0_1");
+        let mut copied_names = Vec::new();
+        Hierarchy::loop_over_children(&mut project_data.graphs[0], new_idx, |node, _| copied_names.push(node.name.clone()));
+        assert_eq!(copied_names, ["Enemy", "Enemy_1", "Enemy_2"]);
+    }
+
+    #[test]
+    fn duplicate_node_sibling_order() {
+        let (mut h, mut project_data, mut selected) = (Hierarchy::new(), ProjectData::new(), Selected::None);
+        h.add_graph(&mut project_data, &mut selected);
+        h.add_node(&mut project_data, &mut selected);
+        h.add_node(&mut project_data, &mut selected);
+        h.add_node(&mut project_data, &mut selected);
+        let child_order = |project_data: &mut ProjectData| {
+            let mut order = Vec::new();
+            Hierarchy::loop_over_children(&mut project_data.graphs[0], 0, |_, idx| order.push(idx));
+            order
+        };
+        assert_eq!(child_order(&mut project_data), [3, 2, 1]);
+        h.duplicate_node(&mut project_data, &mut selected, NonZeroUsize::new(2).unwrap());
+        assert_eq!(child_order(&mut project_data), [3, 2, 4, 1]);
+        // Duplicating the last child makes the copy the new last child
+        h.duplicate_node(&mut project_data, &mut selected, NonZeroUsize::new(1).unwrap());
+        assert_eq!(child_order(&mut project_data), [3, 2, 4, 1, 5]);
+    }
+
+    #[test]
+    fn make_name_unique() {
+        let (mut h, mut project_data, mut selected) = (Hierarchy::new(), ProjectData::new(), Selected::None);
+        h.add_graph(&mut project_data, &mut selected);
+        h.add_node(&mut project_data, &mut selected);
+        h.add_node(&mut project_data, &mut selected);
+        project_data.graphs[0].0[1].name = String::from("Enemy");
+        project_data.graphs[0].0[2].name = String::from("Enemy");
+        h.make_name_unique(&mut project_data, NonZeroUsize::new(1).unwrap());
+        assert_eq!(project_data.graphs[0].0[1].name, "Enemy_1");
+        h.make_name_unique(&mut project_data, NonZeroUsize::new(2).unwrap());
+        assert_eq!(project_data.graphs[0].0[2].name, "Enemy");
+    }
+
+    #[test]
+    fn move_sibling() {
+        let (mut h, mut project_data, mut selected) = (Hierarchy::new(), ProjectData::new(), Selected::None);
+        h.add_graph(&mut project_data, &mut selected);
+        h.add_node(&mut project_data, &mut selected);
+        h.add_node(&mut project_data, &mut selected);
+        h.add_node(&mut project_data, &mut selected);
+        let child_order = |project_data: &mut ProjectData| {
+            let mut order = Vec::new();
+            Hierarchy::loop_over_children(&mut project_data.graphs[0], 0, |_, idx| order.push(idx));
+            order
+        };
+        // New nodes are added to the front
+        assert_eq!(child_order(&mut project_data), [3, 2, 1]);
+        h.move_sibling(&mut project_data, NonZeroUsize::new(1).unwrap(), true);
+        assert_eq!(child_order(&mut project_data), [3, 1, 2]);
+        h.move_sibling(&mut project_data, NonZeroUsize::new(1).unwrap(), true);
+        assert_eq!(child_order(&mut project_data), [1, 3, 2]);
+        h.move_sibling(&mut project_data, NonZeroUsize::new(1).unwrap(), true);
+        assert_eq!(child_order(&mut project_data), [1, 3, 2]);
+        h.move_sibling(&mut project_data, NonZeroUsize::new(3).unwrap(), false);
+        assert_eq!(child_order(&mut project_data), [1, 2, 3]);
+        h.move_sibling(&mut project_data, NonZeroUsize::new(3).unwrap(), false);
+        assert_eq!(child_order(&mut project_data), [1, 2, 3]);
+    }
+
     #[test]
     fn delete_node() {
         let (mut h, mut project_data, mut selected) = (Hierarchy::new(), ProjectData::new(), Selected::None);
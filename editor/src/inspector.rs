@@ -1,16 +1,33 @@
+use std::num::NonZeroU32;
 use imgui::Ui;
-use crate::{hierarchy::{Hierarchy, NodeExtension, SpriteExtension, CameraExtension, RectColliderExtension, SpriteType, AffineSpriteData}, project_data::ProjectData, Selected};
-
-pub fn draw_inspector(ui: &Ui, hierarchy: &mut Hierarchy, project_data: &mut ProjectData, selected: &mut Selected) {
-    ui.window("Inspector")
-        .build(|| {
-            match *selected {
-                Selected::None => {},
-                Selected::File(_) => { file_inspector(ui, project_data, selected); },
-                Selected::Node(_) => { node_inspector(ui, hierarchy, project_data, selected); },
-                Selected::Graph(_) => { graph_inspector(ui, project_data, selected); },
-            }
-        });
+use sandstone_common::{CollisionResponse, TextAlign};
+use crate::{grid_snap::GridSnap, hierarchy::{Hierarchy, Transform, NodeExtension, SpriteExtension, CameraExtension, RectColliderExtension, LabelExtension, SpriteType, AffineSpriteData}, project_data::ProjectData, Selected};
+
+pub struct Inspector {
+    transform_clipboard: Option<Transform>,
+    // Unsnapped position while it's being dragged, so small drags can add up to reach the next grid line
+    position_drag: Option<[f32; 2]>,
+}
+
+impl Inspector {
+    pub const fn new() -> Self {
+        Self {
+            transform_clipboard: None,
+            position_drag: None,
+        }
+    }
+
+    pub fn draw_inspector(&mut self, ui: &Ui, hierarchy: &mut Hierarchy, project_data: &mut ProjectData, selected: &mut Selected, grid_snap: &GridSnap) {
+        ui.window("Inspector")
+            .build(|| {
+                match *selected {
+                    Selected::None => {},
+                    Selected::File(_) => { file_inspector(ui, project_data, selected); },
+                    Selected::Node(_) => { node_inspector(ui, hierarchy, project_data, selected, self, grid_snap); },
+                    Selected::Graph(_) => { graph_inspector(ui, project_data, selected); },
+                }
+            });
+    }
 }
 
 fn file_inspector(ui: &Ui, project_data: &mut ProjectData, selected: &mut Selected) {
@@ -29,17 +46,32 @@ fn file_inspector(ui: &Ui, project_data: &mut ProjectData, selected: &mut Select
     }
 }
 
-fn node_inspector(ui: &Ui, hierarchy: &mut Hierarchy, project_data: &mut ProjectData, selected: &mut Selected) {
+fn node_inspector(ui: &Ui, hierarchy: &mut Hierarchy, project_data: &mut ProjectData, selected: &mut Selected, inspector: &mut Inspector, grid_snap: &GridSnap) {
     let &mut Selected::Node(selected_index) = selected else { return; };
     let Some(graph) = project_data.graphs.get_mut(hierarchy.current_graph_idx)  else { return; };
     let selected_node = &mut graph.0[selected_index];
     ui.input_text("Name", &mut selected_node.name).build();
     ui.checkbox("Enabled", &mut selected_node.enabled);
+    imgui::Drag::new("User Data").build(ui, &mut selected_node.user_data);
 
-    let mut pos: [f32; 2] = [selected_node.transform.x.to_num::<f32>(), selected_node.transform.y.to_num::<f32>()];
-    imgui::Drag::new("Position").build_array(ui, &mut pos);
-    selected_node.transform.x = fixed::types::I20F12::from_num(pos[0]);
-    selected_node.transform.y = fixed::types::I20F12::from_num(pos[1]);
+    let mut pos: [f32; 2] = inspector.position_drag.unwrap_or([selected_node.transform.x.to_num::<f32>(), selected_node.transform.y.to_num::<f32>()]);
+    let pos_changed = imgui::Drag::new("Position").build_array(ui, &mut pos);
+    inspector.position_drag = ui.is_item_active().then_some(pos);
+    if pos_changed {
+        let bypass_snap = GridSnap::bypassed(ui);
+        selected_node.transform.x = grid_snap.snap(fixed::types::I20F12::from_num(pos[0]), bypass_snap);
+        selected_node.transform.y = grid_snap.snap(fixed::types::I20F12::from_num(pos[1]), bypass_snap);
+    }
+    if ui.button("Copy Transform") {
+        inspector.transform_clipboard = Some(selected_node.transform);
+    }
+    ui.same_line();
+    // Nothing to paste until a transform has been copied
+    ui.disabled(inspector.transform_clipboard.is_none(), || {
+        if ui.button("Paste Transform") {
+            paste_transform(inspector.transform_clipboard, &mut selected_node.transform);
+        }
+    });
 
     if let Some(_cb) = ui.begin_combo("Extension", format!("{}", selected_node.node_extension)) {
         if ui.selectable("None") {
@@ -54,6 +86,9 @@ fn node_inspector(ui: &Ui, hierarchy: &mut Hierarchy, project_data: &mut Project
         if ui.selectable("Rect Collider") {
             selected_node.node_extension = NodeExtension::RectCollider(RectColliderExtension::default());
         }
+        if ui.selectable("Label") {
+            selected_node.node_extension = NodeExtension::Label(LabelExtension::default());
+        }
     }
 
     match &mut selected_node.node_extension {
@@ -103,21 +138,85 @@ fn node_inspector(ui: &Ui, hierarchy: &mut Hierarchy, project_data: &mut Project
                 .build_array(ui, &mut dims);
             c.width = fixed::types::I20F12::from_num(dims[0]);
             c.height = fixed::types::I20F12::from_num(dims[1]);
+            let mut offset: [f32; 2] = [c.offset_x.to_num::<f32>(), c.offset_y.to_num::<f32>()];
+            imgui::Drag::new("Offset")
+                .build_array(ui, &mut offset);
+            c.offset_x = fixed::types::I20F12::from_num(offset[0]);
+            c.offset_y = fixed::types::I20F12::from_num(offset[1]);
+            if let Some(_cb) = ui.begin_combo("Response", format!("{:?}", c.response)) {
+                for response in [CollisionResponse::Trigger, CollisionResponse::Solid, CollisionResponse::OneWayUp] {
+                    if ui.selectable(format!("{response:?}")) {
+                        c.response = response;
+                    }
+                }
+            }
+            ui.checkbox("Static", &mut c.is_static);
+            ui.checkbox("Continuous", &mut c.continuous);
+        },
+        NodeExtension::Label(l) => {
+            ui.input_text_multiline("Text", &mut l.text, [0.0, 0.0]).build();
+            if let Some(_cb) = ui.begin_combo("Align", format!("{:?}", l.align)) {
+                for align in [TextAlign::Left, TextAlign::Center, TextAlign::Right] {
+                    if ui.selectable(format!("{align:?}")) {
+                        l.align = align;
+                    }
+                }
+            }
         },
     }
 
-    let mut script_id: u32 = selected_node.script_type_id.map_or(0, u32::from);
-    ui.input_scalar("Script ID", &mut script_id).build();
-    selected_node.script_type_id = std::num::NonZeroU32::new(script_id);
+    // Combo box for Script
+    let script_options = script_options(&project_data.scripts);
+    let script_preview = script_label(&project_data.scripts, selected_node.script_type_id);
+    if let Some(_cb) = ui.begin_combo("Script", script_preview) {
+        for (name, type_id) in script_options {
+            if ui.selectable(name) {
+                selected_node.script_type_id = type_id;
+            }
+        }
+    }
+    if project_data.scripts.is_empty() && ui.is_item_hovered() {
+        ui.tooltip_text("No scripts found - use Run > Scan Scripts to find them");
+    }
 
     // Root node cannot be deleted
     if let Some(selected_index) = std::num::NonZeroUsize::new(selected_index) {
+        if ui.button("Duplicate") {
+            hierarchy.duplicate_node(project_data, selected, selected_index);
+        }
+        ui.same_line();
+        if ui.button("Make Unique") {
+            hierarchy.make_name_unique(project_data, selected_index);
+        }
+        ui.same_line();
         if ui.button("Delete") {
             hierarchy.delete_node(project_data, selected, selected_index);
         }
     }
 }
 
+fn paste_transform(transform_clipboard: Option<Transform>, target: &mut Transform) {
+    if let Some(transform) = transform_clipboard {
+        *target = transform;
+    }
+}
+
+// Choices for the Script combo box, with "None" first to detach the script.
+fn script_options(scripts: &[(u32, String)]) -> Vec<(String, Option<NonZeroU32>)> {
+    std::iter::once((String::from("None"), None))
+        .chain(scripts.iter().map(|(id, name)| (name.clone(), NonZeroU32::new(*id))))
+        .collect()
+}
+
+fn script_label(scripts: &[(u32, String)], type_id: Option<NonZeroU32>) -> String {
+    let Some(type_id) = type_id else { return String::from("None"); };
+    match scripts.iter().find(|(id, _)| *id == type_id.get()) {
+        Some((_, name)) => name.clone(),
+        // ID might be from a script that has since been removed, or from before the scripts were scanned
+        None => format!("Unknown ({type_id})"),
+    }
+}
+
 fn graph_inspector(ui: &Ui, project_data: &mut ProjectData, selected: &mut Selected) {
     let &mut Selected::Graph(selected_index) = selected else { return; };
     let Some(node) = project_data.graphs[selected_index].0.get_mut(0) else { return; };
@@ -127,4 +226,74 @@ fn graph_inspector(ui: &Ui, project_data: &mut ProjectData, selected: &mut Selec
     } else if ui.button("Set as Main Graph") {
         project_data.main_graph = Some(selected_index as u32);
     }
+
+    let backdrop_colour = &mut project_data.graphs[selected_index].1;
+    let mut use_backdrop = backdrop_colour.is_some();
+    if ui.checkbox("Set Backdrop Colour", &mut use_backdrop) {
+        *backdrop_colour = use_backdrop.then_some(0);
+    }
+    if let Some(colour) = backdrop_colour {
+        let mut rgb = colour_to_rgb(*colour);
+        if ui.color_edit3("Backdrop Colour", &mut rgb) {
+            *colour = rgb_to_colour(rgb);
+        }
+    }
+}
+
+fn colour_to_rgb(colour: u32) -> [f32; 3] {
+    [(colour >> 16) & 0xFF, (colour >> 8) & 0xFF, colour & 0xFF].map(|x| x as f32 / 255.0)
+}
+
+fn rgb_to_colour(rgb: [f32; 3]) -> u32 {
+    rgb.iter().fold(0, |colour, x| (colour << 8) | (x.clamp(0.0, 1.0) * 255.0).round() as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copy_paste_transform() {
+        let mut inspector = Inspector::new();
+        let source = Transform { x: fixed::types::I20F12::from_num(12), y: fixed::types::I20F12::from_num(-34) };
+        let mut target = Transform::default();
+
+        // Pasting with nothing copied leaves the target alone
+        paste_transform(inspector.transform_clipboard, &mut target);
+        assert_eq!((target.x, target.y), (fixed::types::I20F12::ZERO, fixed::types::I20F12::ZERO));
+
+        inspector.transform_clipboard = Some(source);
+        paste_transform(inspector.transform_clipboard, &mut target);
+        assert_eq!((target.x, target.y), (source.x, source.y));
+    }
+
+    #[test]
+    fn select_script() {
+        let scripts = vec![(1, String::from("PlayerScript")), (5, String::from("EnemyScript"))];
+        let options = script_options(&scripts);
+        assert_eq!(options[0], (String::from("None"), None));
+
+        let mut script_type_id = NonZeroU32::new(1);
+        assert_eq!(script_label(&scripts, script_type_id), "PlayerScript");
+
+        let (_, enemy_id) = options.iter().find(|(name, _)| name == "EnemyScript").unwrap();
+        script_type_id = *enemy_id;
+        assert_eq!(script_type_id, NonZeroU32::new(5));
+        assert_eq!(script_label(&scripts, script_type_id), "EnemyScript");
+
+        script_type_id = options[0].1;
+        assert_eq!(script_type_id, None);
+        assert_eq!(script_label(&scripts, script_type_id), "None");
+    }
+
+    #[test]
+    fn unknown_script_label() {
+        assert_eq!(script_label(&[], NonZeroU32::new(3)), "Unknown (3)");
+    }
+
+    #[test]
+    fn backdrop_colour_round_trip() {
+        assert_eq!(colour_to_rgb(0xFF0000), [1.0, 0.0, 0.0]);
+        assert_eq!(rgb_to_colour(colour_to_rgb(0x3A2E3F)), 0x3A2E3F);
+    }
 }
@@ -8,9 +8,11 @@ mod files;
 mod image_helper;
 mod output_log;
 mod world_editor;
+mod grid_snap;
+mod play_mode;
 
 use std::ffi::CString;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, mpsc};
 use std::thread;
 
 pub enum Selected {
@@ -28,8 +30,13 @@ fn main() {
     let mut proj_loader = project_loader::ProjectLoader::new();
     let project_data = Arc::new(Mutex::new(project_data::ProjectData::new()));
     let mut world_editor = world_editor::WorldEditor::new();
+    let mut inspector = inspector::Inspector::new();
+    let mut grid_snap = grid_snap::GridSnap::new();
     let mut selected = Selected::None;
     let mut building_frames = 0;
+    let mut playing: Option<play_mode::PlayMode> = None;
+    // Graph being played, and where the runner comes back from the thread building it
+    let mut play_start: Option<(usize, mpsc::Receiver<Result<play_mode::PlayRunner, String>>)> = None;
     
     let mut first_loop = true;
 
@@ -40,7 +47,7 @@ fn main() {
     let graphs_name = CString::new("Graphs").unwrap();
     let log_name = CString::new("Log").unwrap();
 
-    ui::mainloop(move |ui, renderer, exit| {
+    ui::mainloop(move |ui, renderer, exit, close_requested| {
         // Uncomment this to show the Dear IMGUI demo window, to see all the widget possibilities:
         // ui.show_demo_window(&mut true);
 
@@ -79,21 +86,25 @@ fn main() {
                 imgui::sys::igDockBuilderFinish(dockspace_id);
             }
         }
+        let mut stop_play = false;
         ui.main_menu_bar(|| {
             ui.menu("File", || {
-                if ui.menu_item("New") {
+                // The graphs are the running game's while playing, so the project can't be swapped out or saved
+                if ui.menu_item_config("New").enabled(playing.is_none()).build() {
                     proj_loader.open_load_project_modal();
                 }
-                if ui.menu_item("Open") {
+                if ui.menu_item_config("Open").enabled(playing.is_none()).build() {
                     proj_loader.open_project_file_dialog();
                 }
                 // this Ctrl-S doesn't actually set up that shortcut, just displays the text
-                if ui.menu_item_config("Save").shortcut("Ctrl+S").build() {
-                    project_loader::save_project(&mut project_data.lock().unwrap());
+                if ui.menu_item_config("Save").shortcut("Ctrl+S").enabled(playing.is_none()).build() {
+                    if let Err(msg) = project_loader::save_project(&mut project_data.lock().unwrap()) {
+                        proj_loader.show_error(msg);
+                    }
                 }
                 ui.separator();
                 if ui.menu_item_config("Quit").shortcut("Alt+F4").build() {
-                    *exit = true;
+                    *close_requested = true;
                 }
             });
             ui.menu("Run", || {
@@ -106,6 +117,40 @@ fn main() {
                     build = true;
                     clean = true;
                 }
+                if ui.menu_item("Scan Scripts") {
+                    let p_data = project_data.clone();
+                    thread::spawn(move || {
+                        if let Err(msg) = project_builder::scan_scripts(&mut p_data.lock().unwrap()) {
+                            log::error!("{msg}");
+                        }
+                    });
+                    building_frames = 0;
+                }
+                if ui.menu_item("Export Game Data") {
+                    let p_data = project_data.clone();
+                    thread::spawn(move || {
+                        let mut p_data = p_data.lock().unwrap();
+                        if let Err(msg) = project_builder::scan_scripts(&mut p_data)
+                            .and_then(|_| project_builder::export_game_data(&p_data)) {
+                            log::error!("{msg}");
+                        }
+                    });
+                    building_frames = 0;
+                }
+                ui.separator();
+                if ui.menu_item_config("Play").enabled(playing.is_none() && play_start.is_none()).build() {
+                    let (tx, rx) = mpsc::channel();
+                    let graph_idx = hierarchy_obj.current_graph_idx;
+                    let p_data = project_data.clone();
+                    thread::spawn(move || {
+                        let _ = tx.send(play_mode::PlayRunner::start(&mut p_data.lock().unwrap(), graph_idx));
+                    });
+                    play_start = Some((graph_idx, rx));
+                    building_frames = 0;
+                }
+                if ui.menu_item_config("Stop").enabled(playing.is_some()).build() {
+                    stop_play = true;
+                }
                 if build {
                     let p_data = project_data.clone();
                     thread::spawn(move || {
@@ -119,17 +164,52 @@ fn main() {
                     building_frames = 0;
                 }
             });
+            grid_snap.draw_menu(ui);
             if ui.menu_item("About") {}
         });
 
         if let Ok(mut project_data) = project_data.try_lock() {
             project_data.check_file_scanner(renderer);
             proj_loader.update(ui, &mut project_data, &mut hierarchy_obj, renderer, &mut selected);
+            if *close_requested {
+                *close_requested = false;
+                proj_loader.request_quit(&project_data, exit);
+            }
+            proj_loader.update_quit_prompt(ui, &mut project_data, playing.is_some(), exit);
+
+            if let Some((graph_idx, result)) = play_start.as_ref().and_then(|(idx, rx)| rx.try_recv().ok().map(|r| (*idx, r))) {
+                play_start = None;
+                match result {
+                    Ok(runner) => {
+                        playing = Some(play_mode::PlayMode::new(&project_data, graph_idx, Some(runner)));
+                        selected = Selected::None;
+                    },
+                    Err(msg) => log::error!("{msg}"),
+                }
+            }
+            if let Some(play) = &mut playing {
+                if let Err(msg) = play.update(ui, &mut project_data) {
+                    log::error!("{msg}");
+                    stop_play = true;
+                }
+                // Nodes come and go while playing, so the selected one may be gone
+                if let Selected::Node(node_idx) = selected {
+                    if project_data.graphs.get(hierarchy_obj.current_graph_idx).map_or(true, |g| g.0.get(node_idx).is_none()) {
+                        selected = Selected::None;
+                    }
+                }
+            }
+            if stop_play {
+                if let Some(play) = playing.take() {
+                    play.stop(&mut project_data);
+                    selected = Selected::None;
+                }
+            }
 
-            inspector::draw_inspector(ui, &mut hierarchy_obj, &mut project_data, &mut selected);
+            inspector.draw_inspector(ui, &mut hierarchy_obj, &mut project_data, &mut selected, &grid_snap);
             hierarchy_obj.draw_hierarchy(ui, &mut project_data, &mut selected);
             files::draw_files(ui, &mut project_data, &mut selected);
-            world_editor.draw_world_editor(ui, &mut hierarchy_obj, &mut project_data, &mut selected);
+            world_editor.draw_world_editor(ui, &mut hierarchy_obj, &mut project_data, &mut selected, &grid_snap);
         } else {
             // Bouncing back and forth animation
             const LOAD_BAR_WIDTH: usize = 30;
@@ -0,0 +1,169 @@
+use std::io::{BufReader, Read, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use imgui::{Ui, Key};
+use sandstone_common::SavedNodeGraph;
+use crate::project_data::ProjectData;
+use crate::hierarchy::NodeGraph;
+use crate::project_builder;
+
+// Keyboard keys for each DS button, in the KEYINPUT bit order the runtime expects
+const BUTTON_KEYS: [Key; 10] = [
+    Key::X, // A
+    Key::Z, // B
+    Key::Backspace, // Select
+    Key::Enter, // Start
+    Key::RightArrow,
+    Key::LeftArrow,
+    Key::UpArrow,
+    Key::DownArrow,
+    Key::S, // R
+    Key::A, // L
+];
+
+/// The play mode runtime, running the user code on this computer in a separate process.
+/// See `project_builder::build_play_runtime` for what it sends back each frame.
+pub struct PlayRunner {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl PlayRunner {
+    /// Builds the play mode runtime, and starts it running the graph as the scene.
+    pub fn start(project_data: &mut ProjectData, graph_idx: usize) -> Result<Self, String> {
+        let Some(scene) = project_data.graphs.get(graph_idx).and_then(|g| g.0.get(0)).map(|root| root.name.clone()) else {
+            return Err(String::from("Failed: No graph is open to play"));
+        };
+        let runtime_path = project_builder::build_play_runtime(project_data)?;
+        let mut child = Command::new(runtime_path)
+            .arg(project_data.get_path().join("build/game_data.bin"))
+            .arg(scene)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn().map_err(|e| format!("Couldn't start play mode: {e}"))?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        Ok(Self { child, stdin, stdout })
+    }
+
+    // Runs a frame with the buttons held, and returns the scene as it is afterwards
+    fn step(&mut self, keys: u16) -> Result<SavedNodeGraph, String> {
+        let mut len = [0; 4];
+        writeln!(self.stdin, "{keys}")
+            .and_then(|_| self.stdout.read_exact(&mut len))
+            .map_err(|_| self.crash_message())?;
+        let mut data = vec![0; u32::from_le_bytes(len) as usize];
+        self.stdout.read_exact(&mut data).map_err(|_| self.crash_message())?;
+        Ok(sandstone_common::deserialize(&data))
+    }
+
+    // The runtime only stops by itself when it panics, so this has the panic message
+    fn crash_message(&mut self) -> String {
+        let _ = self.child.wait();
+        let mut stderr = String::new();
+        if let Some(mut pipe) = self.child.stderr.take() {
+            let _ = pipe.read_to_string(&mut stderr);
+        }
+        format!("Play mode stopped:\n{stderr}")
+    }
+
+    fn stop(mut self) {
+        // Fails if it has already stopped, which is fine
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Runs the game in the editor, showing the scene as it plays in place of the graph it started from.
+/// Graphs go back to how they were when it stops, so nothing that happens while playing is kept.
+pub struct PlayMode {
+    runner: Option<PlayRunner>,
+    graph_idx: usize,
+    // The graphs as they were before playing, put back by stop
+    edit_graphs: Vec<NodeGraph>,
+}
+
+impl PlayMode {
+    pub fn new(project_data: &ProjectData, graph_idx: usize, runner: Option<PlayRunner>) -> Self {
+        Self {
+            runner,
+            graph_idx,
+            edit_graphs: project_data.graphs.clone(),
+        }
+    }
+
+    /// Runs a frame of the game, with the DS buttons held that match the keys held on the keyboard.
+    pub fn update(&mut self, ui: &Ui, project_data: &mut ProjectData) -> Result<(), String> {
+        let Some(runner) = &mut self.runner else { return Ok(()); };
+        let mut keys = 0;
+        // Typing into a text box shouldn't press buttons
+        if !ui.io().want_text_input {
+            for (bit, &key) in BUTTON_KEYS.iter().enumerate() {
+                if ui.is_key_down(key) {
+                    keys |= 1 << bit;
+                }
+            }
+        }
+        let graph = runner.step(keys)?;
+        self.apply_frame(project_data, graph);
+        Ok(())
+    }
+
+    // Shows the scene as it is after a frame, in place of the graph it started from
+    fn apply_frame(&self, project_data: &mut ProjectData, graph: SavedNodeGraph) {
+        // A script destroyed the scene, so keep showing how it last looked
+        if graph.nodes.is_empty() {
+            return;
+        }
+        let Some(dest) = project_data.graphs.get_mut(self.graph_idx) else { return; };
+        let mut new_graph = NodeGraph::from_saved(graph);
+        // Live nodes don't have a backdrop colour, so keep the one from the editor
+        new_graph.1 = self.edit_graphs[self.graph_idx].1;
+        *dest = new_graph;
+    }
+
+    /// Stops the game, and puts the graphs back to how they were before playing.
+    pub fn stop(self, project_data: &mut ProjectData) {
+        if let Some(runner) = self.runner {
+            runner.stop();
+        }
+        project_data.graphs = self.edit_graphs;
+        log::info!("Stopped play mode");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fixed::types::I20F12;
+    use crate::hierarchy::test_node;
+
+    #[test]
+    fn stop_restores_edited_graph() {
+        let mut project_data = ProjectData::new();
+        let mut graph = NodeGraph::new();
+        graph.0.push(test_node("Level", 1, None));
+        graph.0.push(test_node("Player", 0, Some(0)));
+        graph.1 = Some(0x3A2E3F);
+        project_data.graphs.push(graph);
+        let edited = sandstone_common::serialize(&project_data.export_saved_graphs());
+
+        let play_mode = PlayMode::new(&project_data, 0, None);
+        let mut frame = project_data.export_saved_graphs().remove(0);
+        frame.nodes[1].transform.x = I20F12::from_num(50);
+        frame.backdrop_colour = None;
+        play_mode.apply_frame(&mut project_data, frame);
+        assert_eq!(project_data.graphs[0].0[1].transform.x, I20F12::from_num(50));
+        assert_eq!(project_data.graphs[0].1, Some(0x3A2E3F));
+
+        // An empty frame means the scene was destroyed, so the last one stays
+        play_mode.apply_frame(&mut project_data, SavedNodeGraph { nodes: Vec::new(), backdrop_colour: None });
+        assert_eq!(project_data.graphs[0].0[1].transform.x, I20F12::from_num(50));
+
+        // Edits made while playing are thrown away too
+        project_data.graphs[0].0[0].name = String::from("Renamed");
+        play_mode.stop(&mut project_data);
+        assert_eq!(sandstone_common::serialize(&project_data.export_saved_graphs()), edited);
+    }
+}
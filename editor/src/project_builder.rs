@@ -1,16 +1,26 @@
-use std::io::Write;
 use std::process::Command;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use quote::quote;
 use crate::project_data::ProjectData;
+use crate::hierarchy::NodeExtension;
 
 static ARM9_CARGO: &str = include_str!("runtime_files/arm9-cargo.toml");
 static ARM9_CARGO_CONFIG: &str = include_str!("runtime_files/arm9-cargo-config.toml");
 static ARM7_CARGO: &str = include_str!("runtime_files/arm7-cargo.toml");
 static ARM7_CARGO_CONFIG: &str = include_str!("runtime_files/arm7-cargo-config.toml");
+static PLAY_CARGO: &str = include_str!("runtime_files/play-cargo.toml");
 static RUST_TOOLCHAIN: &str = include_str!("runtime_files/rust-toolchain.toml");
 
+// The crates that are generated to run the user code
+#[derive(Clone, Copy)]
+enum Runtime {
+    Arm9,
+    Arm7,
+    // Runs the game without the DS hardware, for the editor's play mode
+    Play,
+}
+
 pub fn build(project_data: &mut ProjectData) {
     // todo: handle IO errors
     log::info!("Starting build...");
@@ -21,43 +31,11 @@ pub fn build(project_data: &mut ProjectData) {
     let arm7_path = build_path.join("arm7_runtime");
 
     // todo: this code is a travesty. desperately needs cleanup
-    let mut user_script_ids: Vec<(u32, &str)> = Vec::new();
-    // unfortunately out-dir doesn't work here, so target-dir has to do.
-    let user_code_target_path = build_path.join("user-code");
-    let rustdoc_command_output = Command::new("rustup")
-        .args(["run", "nightly"])
-        .arg("cargo")
-        .arg("rustdoc")
-        .args(["--target-dir", user_code_target_path.to_str().unwrap()])
-        .arg("--")
-        .args(["--output-format", "json"])
-        .current_dir(project_data.get_path().join("code"))
-        .output().unwrap();
-    if !rustdoc_command_output.status.success() {
-        log::error!("Failed to run Rustdoc on user code:\n{}", String::from_utf8_lossy(&rustdoc_command_output.stderr));
+    if let Err(msg) = scan_scripts(project_data) {
+        log::error!("{msg}");
         return;
     }
-    // todo: would rather not hardcode this path
-    let json_path = user_code_target_path.join("thumbv5te-none-eabi/doc/sandstone_user_code.json");
-    let json_data: serde_json::Value = serde_json::from_slice(&std::fs::read(json_path).unwrap()).unwrap();
-    // Root of the JSON is the Crate, this accesses the Items list
-    // that contains all items in the crate in a flat list.
-    for (_, item) in json_data["index"].as_object().unwrap() {
-        if item["kind"] == "impl" {
-            if item["inner"].get("trait").map_or(false, |tr| tr["name"].as_str().map_or(false, |n| n == "HasTypeId")) {
-                let docstring = item["docs"].as_str().unwrap();
-                if let Some((_, text_after_key)) = docstring.split_once("{script_type_id=") {
-                    let type_id = text_after_key.split('}').take(1).next().unwrap().parse::<u32>().unwrap();
-                    let script_name = item["inner"]["for"]["inner"]["name"].as_str().unwrap();
-                    user_script_ids.push((type_id, script_name));
-                }
-            }
-        }
-    }
-
-    log::info!("Found user scripts: {:?}", user_script_ids);
-    let (script_ids, script_names): (Vec<u32>, Vec<&str>) = user_script_ids.into_iter().unzip();
-    let script_name_tokens = script_names.into_iter().map(|s| proc_macro2::TokenStream::from_str(s).unwrap());
+    let script_registry = script_registry_code(project_data);
 
     let arm9_code = quote! {
         #![no_std]
@@ -67,20 +45,18 @@ pub fn build(project_data: &mut ProjectData) {
         use alloc::boxed::Box;
         use sandstone_user_code as user_code;
 
-        fn script_factory(id: NonZeroU32) -> Box<dyn sandstone::Script> {
-            match u32::from(id) {
-                #(#script_ids => Box::new(user_code::#script_name_tokens::default()),)*
-                _ => panic!("Invalid script ID: {}", id)
-            }
-        }
+        #script_registry
 
         #[no_mangle]
         extern "C" fn main() -> ! {
             let game_data_raw = include_bytes!("../../game_data.bin");
-            sandstone::main_loop(game_data_raw, script_factory);
+            sandstone::main_loop(game_data_raw, sandstone::ScriptRegistry {
+                factory: script_factory,
+                name: script_name,
+            });
         }
     };
-    create_runtime_crate(true, &arm9_path, &arm9_code.to_string());
+    create_runtime_crate(Runtime::Arm9, &arm9_path, &arm9_code.to_string());
 
     let arm7_code = quote! {
         #![no_std]
@@ -92,31 +68,208 @@ pub fn build(project_data: &mut ProjectData) {
             sandstone_arm7::main_loop();
         }
     };
-    create_runtime_crate(false, &arm7_path, &arm7_code.to_string());
+    create_runtime_crate(Runtime::Arm7, &arm7_path, &arm7_code.to_string());
 
-    let graphical_assets = match convert_graphical_assets(project_data) {
-        Ok(data) => data,
-        Err(msg) => { log::error!("{msg}"); return; }
+    if let Err(msg) = export_game_data(project_data) {
+        log::error!("{msg}");
+        return;
+    }
+
+    let rom_path = build_path.join(project_data.name.clone() + ".nds");
+    match build_rom(&rom_path, &arm9_path, &arm7_path, false) {
+        Ok(_) => log::info!("Successfully built {}", rom_path.to_string_lossy()),
+        Err(msg) => log::error!("{msg}")
+    }
+}
+
+/// Builds the runtime for the editor's play mode, which runs the user code on this computer instead of a DS.
+/// Also exports the game data it loads. Returns the path to the runtime's executable.
+pub fn build_play_runtime(project_data: &mut ProjectData) -> Result<PathBuf, String> {
+    log::info!("Building play mode...");
+    let play_path = project_data.get_path().join("build/play_runtime");
+    scan_scripts(project_data)?;
+    export_game_data(project_data)?;
+
+    let script_registry = script_registry_code(project_data);
+    let play_code = quote! {
+        use std::io::{BufRead, Write};
+        use core::num::NonZeroU32;
+        use sandstone_user_code as user_code;
+
+        #script_registry
+
+        // Each line on stdin is the buttons held for a frame, in the KEYINPUT layout with 1 meaning pressed.
+        // After each frame, the current scene is written to stdout as a serialized SavedNodeGraph,
+        // preceded by its length as a little endian u32. It has no nodes if the scene has been destroyed.
+        fn main() {
+            let mut args = std::env::args().skip(1);
+            let game_data_path = args.next().expect("Missing the game data path");
+            let scene = args.next().expect("Missing the scene name");
+            let game_data_raw = std::fs::read(game_data_path).expect("Couldn't read the game data");
+            let mut hierarchy = sandstone::hierarchy::Hierarchy::from_game_data(
+                sandstone::sandstone_common::deserialize(&game_data_raw),
+                sandstone::ScriptRegistry {
+                    factory: script_factory,
+                    name: script_name,
+                },
+            );
+            hierarchy.set_scene(&scene);
+            hierarchy.process_pending_scene_change();
+
+            let mut stdout = std::io::stdout().lock();
+            for line in std::io::stdin().lock().lines() {
+                let keys: u16 = line.unwrap().trim().parse().unwrap();
+                hierarchy.step(sandstone::input::FrameInput { keys: sandstone::input::Buttons::from_bits_truncate(keys) });
+                let graph = match hierarchy.scene_root() {
+                    Some(root) => hierarchy.to_saved_graph(root),
+                    None => sandstone::sandstone_common::SavedNodeGraph { nodes: Vec::new(), backdrop_colour: None },
+                };
+                let data = sandstone::sandstone_common::serialize(&graph);
+                stdout.write_all(&(data.len() as u32).to_le_bytes()).unwrap();
+                stdout.write_all(&data).unwrap();
+                stdout.flush().unwrap();
+            }
+        }
     };
-    let graphs = project_data.export_saved_graphs();
+    create_runtime_crate(Runtime::Play, &play_path, &play_code.to_string());
+    build_runtime_crate(&play_path, false)?;
+    log::info!("Built play mode");
+    Ok(play_path.join("play").with_extension(std::env::consts::EXE_EXTENSION))
+}
+
+// The script_factory and script_name functions for the ScriptRegistry, with an arm for each scanned script
+fn script_registry_code(project_data: &ProjectData) -> proc_macro2::TokenStream {
+    let (script_ids, script_names): (Vec<u32>, Vec<&str>) = project_data.scripts.iter().map(|(id, name)| (*id, name.as_str())).unzip();
+    let script_name_tokens = script_names.iter().map(|s| proc_macro2::TokenStream::from_str(s).unwrap());
+    quote! {
+        fn script_factory(id: NonZeroU32) -> Option<Box<dyn sandstone::Script>> {
+            match u32::from(id) {
+                #(#script_ids => Some(Box::new(user_code::#script_name_tokens::default())),)*
+                _ => None
+            }
+        }
+
+        fn script_name(id: NonZeroU32) -> Option<&'static str> {
+            match u32::from(id) {
+                #(#script_ids => Some(#script_names),)*
+                _ => None
+            }
+        }
+    }
+}
+
+/// Writes the graphs and graphics to build/game_data.bin, which the ARM9 runtime embeds.
+pub fn export_game_data(project_data: &ProjectData) -> Result<(), String> {
+    let problems = validate_project(project_data);
+    if !problems.is_empty() {
+        return Err(format!("Failed: Project has problems:\n{}", problems.join("\n")));
+    }
     let Some(main_graph_idx) = project_data.main_graph else {
-        log::error!("Failed: No main graph has been set");
-        return;
+        return Err(String::from("Failed: No main graph has been set"));
+    };
+    // The index comes from the project file, so it might not point at a graph
+    let Some(main_graph) = project_data.graphs.get(main_graph_idx as usize).and_then(|g| g.0.get(0)) else {
+        return Err(format!("Failed: Main graph {main_graph_idx} doesn't exist"));
     };
+    let main_graph = main_graph.name.clone();
 
+    let graphical_assets = convert_graphical_assets(project_data)?;
+    let graphs = project_data.export_saved_graphs();
     let serialised_data = sandstone_common::serialize(&sandstone_common::SavedGameData{
-        main_graph: graphs[main_graph_idx as usize].nodes[0].name.clone(),
+        main_graph,
         graphs: graphs.into_iter().map(|x| (x.nodes[0].name.clone(), x)).collect(),
         graphics: graphical_assets,
     });
-    let mut game_data_file = std::fs::File::create(build_path.join("game_data.bin")).unwrap();
-    game_data_file.write_all(&serialised_data).unwrap();
+    let build_path = project_data.get_path().join("build");
+    std::fs::create_dir_all(&build_path)
+        .and_then(|_| std::fs::write(build_path.join("game_data.bin"), serialised_data))
+        .map_err(|e| format!("Failed to write game data: {e}"))?;
+    log::info!("Exported game data to {}", build_path.join("game_data.bin").display());
+    Ok(())
+}
 
-    let rom_path = build_path.join(project_data.name.clone() + ".nds");
-    match build_rom(&rom_path, &arm9_path, &arm7_path, false) {
-        Ok(_) => log::info!("Successfully built {}", rom_path.to_string_lossy()),
-        Err(msg) => log::error!("{msg}")
+// Finds references that would break when the game runs.
+fn validate_project(project_data: &ProjectData) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut graph_names: Vec<&str> = Vec::new();
+    for graph in &project_data.graphs {
+        let Some(root) = graph.0.get(0) else { continue; };
+        // Graphs are looked up by name, so a duplicate would replace the other one
+        if graph_names.contains(&root.name.as_str()) {
+            problems.push(format!("There's more than one graph named \"{}\"", root.name));
+        }
+        graph_names.push(&root.name);
+
+        for (_, node) in &graph.0 {
+            if let NodeExtension::Sprite(sprite) = &node.node_extension {
+                if !project_data.graphical_assets.contains_key(&sprite.graphic_asset) {
+                    problems.push(format!("Node \"{}\" in graph \"{}\" uses missing graphic \"{}\"", node.name, root.name, sprite.graphic_asset));
+                }
+            }
+            if let Some(type_id) = node.script_type_id {
+                if !project_data.scripts.iter().any(|(id, _)| *id == type_id.get()) {
+                    problems.push(format!("Node \"{}\" in graph \"{}\" uses unknown script ID {type_id}", node.name, root.name));
+                }
+            }
+        }
     }
+    problems
+}
+
+/// Finds the scripts in the user code, so they can be attached to nodes.
+pub fn scan_scripts(project_data: &mut ProjectData) -> Result<(), String> {
+    // unfortunately out-dir doesn't work here, so target-dir has to do.
+    let user_code_target_path = project_data.get_path().join("build/user-code");
+    let rustdoc_command_output = Command::new("rustup")
+        .args(["run", "nightly"])
+        .arg("cargo")
+        .arg("rustdoc")
+        .args(["--target-dir", user_code_target_path.to_str().unwrap()])
+        .arg("--")
+        .args(["--output-format", "json"])
+        .current_dir(project_data.get_path().join("code"))
+        .output().unwrap();
+    if !rustdoc_command_output.status.success() {
+        return Err(format!("Failed to run Rustdoc on user code:\n{}", String::from_utf8_lossy(&rustdoc_command_output.stderr)));
+    }
+    // todo: would rather not hardcode this path
+    let json_path = user_code_target_path.join("thumbv5te-none-eabi/doc/sandstone_user_code.json");
+    let json_data: serde_json::Value = serde_json::from_slice(&std::fs::read(json_path).unwrap()).unwrap();
+    let user_script_ids = find_user_scripts(&json_data)?;
+
+    log::info!("Found user scripts: {:?}", user_script_ids);
+    project_data.scripts = user_script_ids.into_iter().map(|(id, name)| (id, name.to_string())).collect();
+    Ok(())
+}
+
+// Finds the scripts registered with register_script!, from the rustdoc JSON of the user code.
+fn find_user_scripts(json_data: &serde_json::Value) -> Result<Vec<(u32, &str)>, String> {
+    let mut user_script_ids: Vec<(u32, &str)> = Vec::new();
+    // Root of the JSON is the Crate, this accesses the Items list
+    // that contains all items in the crate in a flat list.
+    for (_, item) in json_data["index"].as_object().unwrap() {
+        if item["kind"] == "impl" {
+            if item["inner"].get("trait").map_or(false, |tr| tr["name"].as_str().map_or(false, |n| n == "HasTypeId")) {
+                let docstring = item["docs"].as_str().unwrap();
+                if let Some((_, text_after_key)) = docstring.split_once("{script_type_id=") {
+                    let id_text = text_after_key.split('}').take(1).next().unwrap();
+                    let script_name = item["inner"]["for"]["inner"]["name"].as_str().unwrap();
+                    // Registered without an ID, so the engine made one from the name
+                    let type_id = if id_text == "auto" {
+                        sandstone_common::script_id_from_name(script_name).get()
+                    } else {
+                        id_text.parse::<u32>().unwrap()
+                    };
+                    // Two scripts with the same ID would both map to the same arm of the script factory
+                    if let Some((_, other_name)) = user_script_ids.iter().find(|(id, _)| *id == type_id) {
+                        return Err(format!("Failed: Scripts {other_name} and {script_name} both have script ID {type_id}"));
+                    }
+                    user_script_ids.push((type_id, script_name));
+                }
+            }
+        }
+    }
+    Ok(user_script_ids)
 }
 
 pub fn clean_build(project_data: &mut ProjectData) {
@@ -162,11 +315,12 @@ fn build_runtime_crate(path: &Path, release: bool) -> Result<(), String> {
     }
 }
 
-fn create_runtime_crate(arm9: bool, path: &Path, code: &str) {
-    let (cargo_toml, cargo_config) = if arm9 {
-        (ARM9_CARGO, ARM9_CARGO_CONFIG)
-    } else {
-        (ARM7_CARGO, ARM7_CARGO_CONFIG)
+fn create_runtime_crate(runtime: Runtime, path: &Path, code: &str) {
+    let (cargo_toml, cargo_config) = match runtime {
+        Runtime::Arm9 => (ARM9_CARGO, Some(ARM9_CARGO_CONFIG)),
+        Runtime::Arm7 => (ARM7_CARGO, Some(ARM7_CARGO_CONFIG)),
+        // Built for this computer rather than the DS, so it doesn't need the target config
+        Runtime::Play => (PLAY_CARGO, None),
     };
     // Regenerate crate if it doesn't exist
     if !path.join("Cargo.toml").exists() {
@@ -179,12 +333,14 @@ fn create_runtime_crate(arm9: bool, path: &Path, code: &str) {
         }
 
         // Create directories
-        std::fs::create_dir_all(path.join(".cargo")).unwrap();
         std::fs::create_dir_all(path.join("src")).unwrap();
 
         // Create files
         std::fs::write(path.join("Cargo.toml"), cargo_toml).unwrap();
-        std::fs::write(path.join(".cargo/config.toml"), cargo_config).unwrap();
+        if let Some(cargo_config) = cargo_config {
+            std::fs::create_dir_all(path.join(".cargo")).unwrap();
+            std::fs::write(path.join(".cargo/config.toml"), cargo_config).unwrap();
+        }
         std::fs::write(path.join("rust-toolchain.toml"), RUST_TOOLCHAIN).unwrap();
     }
     std::fs::write(path.join("src/main.rs"), code).unwrap();
@@ -221,3 +377,116 @@ fn convert_graphical_assets(project_data: &ProjectData) -> Result<sandstone_comm
     }
     Ok(saved_graphics)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::hierarchy::{Node, NodeGraph, SpriteExtension, test_node};
+
+    fn test_graph(root_name: &str, child: Node) -> NodeGraph {
+        let mut graph = NodeGraph::new();
+        graph.0.push(test_node(root_name, 1, None));
+        graph.0.push(child);
+        graph
+    }
+
+    fn sprite_node(graphic_asset: &str) -> Node {
+        Node {
+            node_extension: NodeExtension::Sprite(SpriteExtension { graphic_asset: String::from(graphic_asset), ..Default::default() }),
+            script_type_id: std::num::NonZeroU32::new(1),
+            ..test_node("Sprite", 0, Some(0))
+        }
+    }
+
+    #[test]
+    fn validate_dangling_references() {
+        let mut project_data = ProjectData::new();
+        project_data.scripts = vec![(1, String::from("PlayerScript"))];
+        project_data.graphical_assets.insert(String::from("player"), crate::project_data::GraphicalAsset {
+            path: std::path::PathBuf::from("player.png"),
+            size: sandstone_common::SpriteSize::_16x16,
+            texture: None,
+        });
+        project_data.graphs.push(test_graph("Player", sprite_node("player")));
+        assert!(validate_project(&project_data).is_empty());
+
+        project_data.graphs.push(test_graph("Enemy", sprite_node("enemy")));
+        let problems = validate_project(&project_data);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("\"enemy\""));
+
+        project_data.scripts.clear();
+        assert_eq!(validate_project(&project_data).len(), 3);
+    }
+
+    #[test]
+    fn validate_duplicate_graph_names() {
+        let mut project_data = ProjectData::new();
+        project_data.scripts = vec![(1, String::from("PlayerScript"))];
+        project_data.graphs.push(test_graph("Player", sprite_node("")));
+        project_data.graphs.push(test_graph("Player", sprite_node("")));
+        for graph in &mut project_data.graphs {
+            graph.0[1].node_extension = NodeExtension::None;
+        }
+        let problems = validate_project(&project_data);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("more than one graph"));
+    }
+
+    #[test]
+    fn export_missing_main_graph() {
+        let mut project_data = ProjectData::new();
+        project_data.graphs.push(test_graph("Player", test_node("Child", 0, Some(0))));
+        project_data.main_graph = Some(1);
+        assert_eq!(export_game_data(&project_data), Err(String::from("Failed: Main graph 1 doesn't exist")));
+    }
+
+    fn script_impl(script_name: &str, type_id: &str) -> serde_json::Value {
+        json!({
+            "kind": "impl",
+            "docs": format!("{{script_type_id={type_id}}}"),
+            "inner": {
+                "trait": { "name": "HasTypeId" },
+                "for": { "inner": { "name": script_name } },
+            },
+        })
+    }
+
+    #[test]
+    fn find_user_scripts_valid() {
+        let json_data = json!({ "index": {
+            "0:1": script_impl("PlayerScript", "1"),
+            "0:2": script_impl("EnemyScript", "2"),
+            "0:3": { "kind": "struct", "docs": null, "inner": {} },
+        }});
+        let mut ids = find_user_scripts(&json_data).unwrap();
+        ids.sort();
+        assert_eq!(ids, vec![(1, "PlayerScript"), (2, "EnemyScript")]);
+    }
+
+    #[test]
+    fn find_user_scripts_duplicate_id() {
+        let json_data = json!({ "index": {
+            "0:1": script_impl("PlayerScript", "1"),
+            "0:2": script_impl("EnemyScript", "1"),
+        }});
+        let err = find_user_scripts(&json_data).unwrap_err();
+        assert!(err.contains("PlayerScript"));
+        assert!(err.contains("EnemyScript"));
+    }
+
+    #[test]
+    fn find_user_scripts_auto_id() {
+        let json_data = json!({ "index": {
+            "0:1": script_impl("PlayerScript", "auto"),
+            "0:2": script_impl("EnemyScript", "auto"),
+        }});
+        let mut ids = find_user_scripts(&json_data).unwrap();
+        ids.sort_by_key(|(_, name)| *name);
+        assert_eq!(ids, vec![
+            (sandstone_common::script_id_from_name("EnemyScript").get(), "EnemyScript"),
+            (sandstone_common::script_id_from_name("PlayerScript").get(), "PlayerScript"),
+        ]);
+    }
+}
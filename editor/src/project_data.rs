@@ -17,6 +17,10 @@ pub struct ProjectData {
     file_scanner_watcher: Option<notify::RecommendedWatcher>,
     file_scanner_timer: i32,
     pub graphical_assets: HashMap<String, GraphicalAsset>,
+    // Script type IDs and names found in the user code, by the last build or script scan
+    pub scripts: Vec<(u32, String)>,
+    // The project as it was last saved or loaded, to check for unsaved changes
+    pub saved_snapshot: Option<String>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -47,6 +51,8 @@ impl ProjectData {
             file_scanner_watcher: None,
             file_scanner_timer: -1,
             graphical_assets: HashMap::default(),
+            scripts: Vec::new(),
+            saved_snapshot: None,
         }
     }
 
@@ -135,7 +141,7 @@ impl ProjectData {
         self.graphs.iter().map(|graph| {
             old_indices.clear();
             old_indices.resize(graph.0.find_last_index().map_or(0, |x| x + 1), 0);
-            let mut saved_graph = SavedNodeGraph { nodes: Vec::with_capacity(graph.0.num_elements()) };
+            let mut saved_graph = SavedNodeGraph { nodes: Vec::with_capacity(graph.0.num_elements()), backdrop_colour: graph.1 };
     
             // Create the nodes with placeholder indices
             for (i, node) in &graph.0 {
@@ -149,6 +155,7 @@ impl ProjectData {
                     node_extension: node.node_extension.to_saved(),
                     script_type_id: node.script_type_id,
                     enabled: node.enabled,
+                    user_data: node.user_data,
                 });
             }
     
@@ -1,19 +1,19 @@
 use std::sync::mpsc;
 use std::thread;
 use std::path::{Path, PathBuf};
-use std::fs::File;
-use std::io::Write;
-use std::num::{NonZeroU32, NonZeroUsize};
 use imgui::Ui;
 use serde::{Serialize, Deserialize};
 use include_dir::{include_dir, Dir};
 use sandstone_common::HashMap;
 use crate::Selected;
 use crate::project_data::{ProjectData, GraphicalAsset};
-use crate::hierarchy::{NodeGraph, Node, Transform, Hierarchy, NodeExtension};
+use crate::hierarchy::{NodeGraph, Hierarchy};
 
 static TEMPLATE_CODE: Dir = include_dir!("$CARGO_MANIFEST_DIR/src/template_project_code");
 
+// Bump this when SavedProjectData changes in a way older editors can't read
+const PROJECT_FORMAT_VERSION: u32 = 1;
+
 pub struct ProjectLoader {
     new_project_path_buffer: String,
     new_project_name_buffer: String,
@@ -21,6 +21,9 @@ pub struct ProjectLoader {
     file_dialog_receiver: mpsc::Receiver<FileDialogReturnInfo>,
     open_load_project_modal: bool,
     close_load_project_modal: bool,
+    error_message: Option<String>,
+    open_error_modal: bool,
+    open_unsaved_changes_modal: bool,
 }
 
 impl ProjectLoader {
@@ -33,6 +36,57 @@ impl ProjectLoader {
             file_dialog_receiver: rx,
             open_load_project_modal: true,
             close_load_project_modal: false,
+            error_message: None,
+            open_error_modal: false,
+            open_unsaved_changes_modal: false,
+        }
+    }
+
+    pub fn show_error(&mut self, message: String) {
+        log::error!("{message}");
+        self.error_message = Some(message);
+        self.open_error_modal = true;
+    }
+
+    // Quits straight away if everything is saved, otherwise asks first
+    pub fn request_quit(&mut self, project_data: &ProjectData, exit: &mut bool) {
+        if has_unsaved_changes(project_data) {
+            self.open_unsaved_changes_modal = true;
+        } else {
+            *exit = true;
+        }
+    }
+
+    // While playing, the graphs are the running game's rather than the edited ones, so they can't be saved
+    pub fn update_quit_prompt(&mut self, ui: &Ui, project_data: &mut ProjectData, playing: bool, exit: &mut bool) {
+        ui.modal_popup_config("Unsaved Changes").resizable(false).always_auto_resize(true).build(|| {
+            ui.text(format!("{} has unsaved changes.", project_data.name));
+            if playing {
+                ui.text_disabled("Stop playing to save them.");
+            }
+            ui.disabled(playing, || {
+                if ui.button("Save and Quit") {
+                    match save_project(project_data) {
+                        Ok(()) => *exit = true,
+                        Err(msg) => self.show_error(msg),
+                    }
+                    ui.close_current_popup();
+                }
+            });
+            ui.same_line();
+            if ui.button("Quit Without Saving") {
+                *exit = true;
+                ui.close_current_popup();
+            }
+            ui.same_line();
+            if ui.button("Cancel") {
+                ui.close_current_popup();
+            }
+        });
+
+        if self.open_unsaved_changes_modal {
+            ui.open_popup("Unsaved Changes");
+            self.open_unsaved_changes_modal = false;
         }
     }
 
@@ -78,7 +132,9 @@ impl ProjectLoader {
                     text_wrap_token.end();
                     ui.spacing();
                     if ui.button("Create") {
-                        create_new_project(&total_path, self.new_project_name_buffer.clone(), project_data, hierarchy, renderer, selected);
+                        if let Err(msg) = create_new_project(&total_path, self.new_project_name_buffer.clone(), project_data, hierarchy, renderer, selected) {
+                            self.show_error(msg);
+                        }
                         ui.close_current_popup();
                     }
                     tab_token.end();
@@ -114,85 +170,118 @@ impl ProjectLoader {
                     self.new_project_path_buffer = path;
                 }
                 FileDialogReturnInfo::OpenProject(Some(path)) => {
-                    load_project(Path::new(&path), project_data, hierarchy, renderer, selected);
-                    self.close_load_project_modal = true;
+                    match load_project(Path::new(&path), project_data, hierarchy, renderer, selected) {
+                        Ok(()) => self.close_load_project_modal = true,
+                        Err(msg) => self.show_error(msg),
+                    }
                 }
                 _ => {}
             }
         }
+
+        // Draw the error popup modal
+        ui.modal_popup_config("Error").resizable(false).always_auto_resize(true).build(|| {
+            if let Some(msg) = &self.error_message {
+                ui.text(msg);
+            }
+            if ui.button("OK") {
+                self.error_message = None;
+                ui.close_current_popup();
+            }
+        });
+        if self.open_error_modal {
+            ui.open_popup("Error");
+            self.open_error_modal = false;
+        }
     }
 }
     
-fn create_new_project(path: &Path, name: String, project_data: &mut ProjectData, hierarchy: &mut Hierarchy, renderer: &mut imgui_glow_renderer::AutoRenderer, selected: &mut Selected) {
+fn create_new_project(path: &Path, name: String, project_data: &mut ProjectData, hierarchy: &mut Hierarchy, renderer: &mut imgui_glow_renderer::AutoRenderer, selected: &mut Selected) -> Result<(), String> {
     // todo: get better way to clear these fields without doing it manually here
     project_data.name = name;
     project_data.graphical_assets = HashMap::default();
     project_data.main_graph = None;
     project_data.set_path_without_watch(path.to_path_buf());
     project_data.graphs = Vec::new();
-    save_project(project_data);
+    project_data.scripts = Vec::new();
+    save_project(project_data)?;
 
     // Create user code crate
-    TEMPLATE_CODE.extract(path.join("code").to_str().unwrap()).unwrap();
+    TEMPLATE_CODE.extract(path.join("code"))
+        .map_err(|e| format!("Couldn't create the code folder: {e}"))?;
 
     // Create assets folder
-    std::fs::create_dir_all(path.join("assets")).unwrap();
+    std::fs::create_dir_all(path.join("assets"))
+        .map_err(|e| format!("Couldn't create the assets folder: {e}"))?;
 
-    load_project(path, project_data, hierarchy, renderer, selected);
+    load_project(path, project_data, hierarchy, renderer, selected)
 }
 
-fn load_project(path: &Path, project_data: &mut ProjectData, hierarchy: &mut Hierarchy, renderer: &mut imgui_glow_renderer::AutoRenderer, selected: &mut Selected) {
-    // todo: handle IO errors
-    let project_data_file = File::open(path.join("project_info.ron")).unwrap();
-    let saved_project_data: SavedProjectData = ron::de::from_reader(project_data_file).unwrap();
+fn load_project(path: &Path, project_data: &mut ProjectData, hierarchy: &mut Hierarchy, renderer: &mut imgui_glow_renderer::AutoRenderer, selected: &mut Selected) -> Result<(), String> {
+    let project_file_path = path.join("project_info.ron");
+    let project_text = std::fs::read_to_string(&project_file_path)
+        .map_err(|e| format!("Couldn't open {}: {e}", project_file_path.display()))?;
+    let saved_project_data = parse_project(&project_text)?;
 
     project_data.set_path(path.to_path_buf());
+    apply_saved_project(project_data, saved_project_data);
+    hierarchy.current_graph_idx = 0;
+    *selected = Selected::None;
+    project_data.find_graphical_assets(renderer);
+    Ok(())
+}
+
+fn parse_project(project_text: &str) -> Result<SavedProjectData, String> {
+    let saved_project_data: SavedProjectData = ron::de::from_str(project_text)
+        .map_err(|e| format!("Project file is invalid: {e}"))?;
+    if saved_project_data.version > PROJECT_FORMAT_VERSION {
+        return Err(format!("Project was saved by a newer version of the editor (format {}, this editor supports up to {PROJECT_FORMAT_VERSION})",
+            saved_project_data.version));
+    }
+    for (i, graph) in saved_project_data.graphs.iter().enumerate() {
+        graph.validate().map_err(|e| format!("Project file is invalid: graph {i}: {e}"))?;
+    }
+    Ok(saved_project_data)
+}
+
+fn apply_saved_project(project_data: &mut ProjectData, saved_project_data: SavedProjectData) {
     project_data.name = saved_project_data.name;
     project_data.graphical_assets = saved_project_data.graphical_assets.iter().map(|(k, v)| (k.clone(), v.clone().with_path(project_data.get_path().join(&v.path)))).collect();
     project_data.main_graph = saved_project_data.main_graph;
+    project_data.scripts = saved_project_data.scripts;
     project_data.graphs.clear();
     project_data.graphs.reserve(saved_project_data.graphs.len());
     for graph in saved_project_data.graphs {
-        let mut new_graph = NodeGraph::new();
-        for node in graph.nodes {
-            new_graph.0.push(Node {
-                child_index: node.child_index.map(nzu32_to_nzusize),
-                parent_index: node.parent_index.map(|x| x as usize),
-                sibling_index: node.sibling_index.map(nzu32_to_nzusize),
-                name: node.name,
-                transform: Transform { x: node.transform.x, y: node.transform.y },
-                node_extension: NodeExtension::from_saved(node.node_extension),
-                script_type_id: node.script_type_id,
-                enabled: node.enabled
-            });
-        }
-        project_data.graphs.push(new_graph);
+        project_data.graphs.push(NodeGraph::from_saved(graph));
     }
-    hierarchy.current_graph_idx = 0;
-    *selected = Selected::None;
-    project_data.find_graphical_assets(renderer);
+    // Freshly loaded, so there's nothing unsaved yet
+    project_data.saved_snapshot = Some(serialize_project(project_data));
 }
 
-pub fn save_project(project_data: &mut ProjectData) {
-    // todo: handle IO errors
-    std::fs::create_dir_all(project_data.get_path()).unwrap();
-    
+fn serialize_project(project_data: &ProjectData) -> String {
     let saved_project_data = SavedProjectData {
+        version: PROJECT_FORMAT_VERSION,
         name: project_data.name.clone(),
         main_graph: project_data.main_graph,
         graphs: project_data.export_saved_graphs(),
-        graphical_assets: project_data.graphical_assets.iter().map(|(k, v)| (k.clone(), v.clone().with_path(v.path.strip_prefix(project_data.get_path()).unwrap().to_path_buf()))).collect(),
+        graphical_assets: project_data.graphical_assets.iter().map(|(k, v)| (k.clone(), v.clone().with_path(v.path.strip_prefix(project_data.get_path()).unwrap_or(v.path.as_path()).to_path_buf()))).collect(),
+        scripts: project_data.scripts.clone(),
     };
-    let ser_project_data = ron::ser::to_string_pretty(&saved_project_data, ron::ser::PrettyConfig::default()).unwrap();
+    ron::ser::to_string_pretty(&saved_project_data, ron::ser::PrettyConfig::default()).unwrap()
+}
 
-    let mut project_data_file = File::create(project_data.get_path().join("project_info.ron")).unwrap();
-    project_data_file.write_all(ser_project_data.as_bytes()).unwrap();
+pub fn save_project(project_data: &mut ProjectData) -> Result<(), String> {
+    let ser_project_data = serialize_project(project_data);
+    std::fs::create_dir_all(project_data.get_path())
+        .and_then(|_| std::fs::write(project_data.get_path().join("project_info.ron"), &ser_project_data))
+        .map_err(|e| format!("Couldn't save project: {e}"))?;
+    project_data.saved_snapshot = Some(ser_project_data);
+    Ok(())
 }
 
-#[inline(always)]
-fn nzu32_to_nzusize(x: NonZeroU32) -> NonZeroUsize {
-    // This is fully safe, as new_unchecked only fails if input is 0 - the input is NonZero
-    unsafe { NonZeroUsize::new_unchecked(u32::from(x) as usize) }
+// Compares against the last save, so undoing a change by hand doesn't count as unsaved
+pub fn has_unsaved_changes(project_data: &ProjectData) -> bool {
+    project_data.saved_snapshot.as_ref().map_or(false, |snapshot| *snapshot != serialize_project(project_data))
 }
 
 enum FileDialogReturnInfo {
@@ -213,8 +302,77 @@ impl imgui::InputTextCallbackHandler for FileNameInputFilter {
 
 #[derive(Serialize, Deserialize)]
 pub struct SavedProjectData {
+    // Projects from before this was added are format 0
+    #[serde(default)]
+    version: u32,
     name: String,
     main_graph: Option<u32>,
     graphs: Vec<sandstone_common::SavedNodeGraph>,
     graphical_assets: HashMap<String, GraphicalAsset>,
+    // Older projects were saved before scripts were scanned
+    #[serde(default)]
+    scripts: Vec<(u32, String)>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::num::NonZeroU32;
+    use crate::hierarchy::test_node;
+
+    #[test]
+    fn project_round_trip() {
+        let mut project_data = ProjectData::new();
+        project_data.name = String::from("Test Project");
+        let mut graph = NodeGraph::new();
+        graph.0.push(test_node("Root", 1, None));
+        graph.0.push(test_node("Parent", 2, Some(0)));
+        graph.0.push(test_node("Child", 0, Some(1)));
+        graph.0[2].transform.x = fixed::types::I20F12::from_num(12);
+        graph.0[2].script_type_id = NonZeroU32::new(3);
+        graph.0[2].user_data = 42;
+        graph.1 = Some(0x3A2E3F);
+        project_data.graphs.push(graph);
+
+        let project_text = serialize_project(&project_data);
+        let mut loaded_project_data = ProjectData::new();
+        apply_saved_project(&mut loaded_project_data, parse_project(&project_text).unwrap());
+
+        assert_eq!(loaded_project_data.name, "Test Project");
+        assert_eq!(serialize_project(&loaded_project_data), project_text);
+        let graph = &loaded_project_data.graphs[0].0;
+        assert_eq!(graph.num_elements(), 3);
+        assert_eq!(graph[2].parent_index, Some(1));
+        assert_eq!(graph[2].transform.x, fixed::types::I20F12::from_num(12));
+        assert_eq!(graph[2].script_type_id, NonZeroU32::new(3));
+        assert_eq!(graph[2].user_data, 42);
+        assert_eq!(loaded_project_data.graphs[0].1, Some(0x3A2E3F));
+        assert!(!has_unsaved_changes(&loaded_project_data));
+
+        loaded_project_data.graphs[0].0[1].name = String::from("Renamed");
+        assert!(has_unsaved_changes(&loaded_project_data));
+    }
+
+    #[test]
+    fn invalid_project_file() {
+        assert!(parse_project("not a project").is_err());
+    }
+
+    #[test]
+    fn malformed_graph() {
+        let mut project_data = ProjectData::new();
+        let mut graph = NodeGraph::new();
+        graph.0.push(test_node("Root", 0, None));
+        graph.0.push(test_node("Orphan", 0, Some(0)));
+        project_data.graphs.push(graph);
+        assert!(parse_project(&serialize_project(&project_data)).unwrap_err().contains("can't be reached"));
+    }
+
+    #[test]
+    fn newer_project_version() {
+        let project_data = ProjectData::new();
+        let project_text = serialize_project(&project_data).replacen(
+            &format!("version: {PROJECT_FORMAT_VERSION}"), &format!("version: {}", PROJECT_FORMAT_VERSION + 1), 1);
+        assert!(parse_project(&project_text).unwrap_err().contains("newer version"));
+    }
 }
@@ -11,7 +11,9 @@ const TITLE: &str = "Sandstone";
 
 type Window = WindowedContext<glutin::PossiblyCurrent>;
 
-pub fn mainloop<F: FnMut(&mut Ui, &mut imgui_glow_renderer::AutoRenderer, &mut bool) + 'static>(mut run_app: F) {
+// run_app sets the first bool to exit. The second is set when the window's close button is pressed,
+// so the app can ask about unsaved changes before exiting.
+pub fn mainloop<F: FnMut(&mut Ui, &mut imgui_glow_renderer::AutoRenderer, &mut bool, &mut bool) + 'static>(mut run_app: F) {
     // Common setup for creating a winit window and imgui context, not specifc
     // to this renderer at all except that glutin is used to create the window
     // since it will give us access to a GL context
@@ -33,6 +35,7 @@ pub fn mainloop<F: FnMut(&mut Ui, &mut imgui_glow_renderer::AutoRenderer, &mut b
 
     let mut last_frame = Instant::now();
     let mut exit = false;
+    let mut close_requested = false;
 
     // Standard winit event loop
     event_loop.run(move |event, _, control_flow| {
@@ -56,7 +59,7 @@ pub fn mainloop<F: FnMut(&mut Ui, &mut imgui_glow_renderer::AutoRenderer, &mut b
 
                 let ui = imgui_context.frame();
 
-                run_app(ui, &mut ig_renderer, &mut exit);
+                run_app(ui, &mut ig_renderer, &mut exit, &mut close_requested);
                 if exit {
                     *control_flow = glutin::event_loop::ControlFlow::Exit;
                 }
@@ -75,7 +78,7 @@ pub fn mainloop<F: FnMut(&mut Ui, &mut imgui_glow_renderer::AutoRenderer, &mut b
                 event: glutin::event::WindowEvent::CloseRequested,
                 ..
             } => {
-                *control_flow = glutin::event_loop::ControlFlow::Exit;
+                close_requested = true;
             }
             event => {
                 winit_platform.handle_event(imgui_context.io_mut(), window.window(), &event);
@@ -1,5 +1,7 @@
 use imgui::{Ui, ImColor32};
-use crate::{hierarchy::{Hierarchy, NodeExtension, SpriteType}, project_data::ProjectData, Selected};
+use fixed::types::I20F12;
+use sandstone_common::TextAlign;
+use crate::{grid_snap::GridSnap, hierarchy::{Hierarchy, NodeExtension, SpriteType, Transform}, project_data::ProjectData, Selected};
 
 const DS_SCREEN_X: u32 = 256;
 const DS_SCREEN_Y: u32 = 192;
@@ -7,6 +9,12 @@ const CAM_OUTLINE_COLOUR: ImColor32 = ImColor32::from_rgba(100, 100, 100, 150);
 const CAM_OUTLINE_THICKNESS: f32 = 2.0;
 const COLLIDER_OUTLINE_COLOUR: ImColor32 = ImColor32::from_rgba(4, 217, 50, 200);
 const COLLIDER_OUTLINE_THICKNESS: f32 = 2.0;
+const PLACEHOLDER_SIZE: f32 = 8.0;
+// Labels are drawn with the DS console's 8x8 characters
+const LABEL_CHAR_SIZE: f32 = 8.0;
+const LABEL_TEXT_COLOUR: ImColor32 = ImColor32::from_rgb(255, 255, 255);
+const PLACEHOLDER_OUTLINE_COLOUR: ImColor32 = ImColor32::from_rgba(200, 200, 200, 150);
+const PLACEHOLDER_OUTLINE_THICKNESS: f32 = 1.0;
 const SELECTED_OUTLINE_COLOUR: ImColor32 = ImColor32::from_rgba(13, 169, 252, 200);
 const SELECTED_OUTLINE_THICKNESS: f32 = 2.0;
 const BG_COLOUR: ImColor32 = ImColor32::from_rgb(30, 30, 30);
@@ -15,17 +23,40 @@ const GRID_LINE_COLOUR: ImColor32 = ImColor32::from_rgb(50, 50, 50);
 const GRID_LINE_THICKNESS: f32 = 1.0;
 
 pub struct WorldEditor {
-    editor_cam_pos: [f32; 2]
+    editor_cam_pos: [f32; 2],
+    node_drag: Option<NodeDrag>,
+}
+
+// A node being moved by dragging it with the mouse
+struct NodeDrag {
+    node_idx: usize,
+    start_transform: Transform,
+    start_mouse_world_pos: [f32; 2],
+}
+
+// Screen space area of a node, for clicking on it
+struct NodeHitBox {
+    node_idx: usize,
+    top_left: [f32; 2],
+    bottom_right: [f32; 2],
+}
+
+impl NodeHitBox {
+    fn contains(&self, point: [f32; 2]) -> bool {
+        point[0] >= self.top_left[0] && point[0] <= self.bottom_right[0] &&
+        point[1] >= self.top_left[1] && point[1] <= self.bottom_right[1]
+    }
 }
 
 impl WorldEditor {
     pub const fn new() -> Self {
         Self {
-            editor_cam_pos: [GRID_LINE_DISTANCE, GRID_LINE_DISTANCE]
+            editor_cam_pos: [GRID_LINE_DISTANCE, GRID_LINE_DISTANCE],
+            node_drag: None,
         }
     }
 
-    pub fn draw_world_editor(&mut self, ui: &Ui, hierarchy: &mut Hierarchy, project_data: &mut ProjectData, selected: &mut Selected) {
+    pub fn draw_world_editor(&mut self, ui: &Ui, hierarchy: &mut Hierarchy, project_data: &mut ProjectData, selected: &mut Selected, grid_snap: &GridSnap) {
         let _t = ui.push_style_var(imgui::StyleVar::WindowPadding([0.0, 0.0]));
         ui.window("World")
             .build(|| {
@@ -62,12 +93,42 @@ impl WorldEditor {
                     self.editor_cam_pos = [self.editor_cam_pos[0] + drag_delta[0], self.editor_cam_pos[1] + drag_delta[1]];
                 }
 
-                let start_pos = [self.editor_cam_pos[0] + canvas_pos[0], self.editor_cam_pos[1] + canvas_pos[1]];
-                Self::draw_node_recursive(ui, hierarchy, project_data, selected, 0, &draw_list, start_pos);
+                let origin = [self.editor_cam_pos[0] + canvas_pos[0], self.editor_cam_pos[1] + canvas_pos[1]];
+                let mut hit_boxes = Vec::new();
+                Self::draw_node_recursive(ui, hierarchy, project_data, selected, 0, &draw_list, origin, [0.0, 0.0], &mut hit_boxes);
+
+                // Clicking selects the topmost node under the mouse, and dragging moves it
+                let mouse_pos = ui.io().mouse_pos;
+                if ui.is_window_hovered() && ui.is_mouse_clicked(imgui::MouseButton::Left) {
+                    // Nodes are drawn in order, so the last one hit is on top
+                    self.node_drag = hit_boxes.iter().rev().find(|b| b.contains(mouse_pos)).and_then(|hit_box| {
+                        let graph = project_data.graphs.get(hierarchy.current_graph_idx)?;
+                        *selected = Selected::Node(hit_box.node_idx);
+                        Some(NodeDrag {
+                            node_idx: hit_box.node_idx,
+                            start_transform: graph.0[hit_box.node_idx].transform,
+                            start_mouse_world_pos: screen_to_world(mouse_pos, origin),
+                        })
+                    });
+                }
+                if let Some(drag) = &self.node_drag {
+                    if ui.is_mouse_down(imgui::MouseButton::Left) {
+                        if let Some(node) = project_data.graphs.get_mut(hierarchy.current_graph_idx).and_then(|g| g.0.get_mut(drag.node_idx)) {
+                            let mouse_world_pos = screen_to_world(mouse_pos, origin);
+                            let delta = [mouse_world_pos[0] - drag.start_mouse_world_pos[0], mouse_world_pos[1] - drag.start_mouse_world_pos[1]];
+                            let bypass_snap = GridSnap::bypassed(ui);
+                            node.transform.x = grid_snap.snap(drag.start_transform.x + I20F12::from_num(delta[0]), bypass_snap);
+                            node.transform.y = grid_snap.snap(drag.start_transform.y + I20F12::from_num(delta[1]), bypass_snap);
+                        }
+                    } else {
+                        self.node_drag = None;
+                    }
+                }
             });
     }
 
     // todo: this recursive node logic is duplicated in Hierarchy. how to deduplicate?
+    #[allow(clippy::too_many_arguments)]
     fn draw_node_recursive(
         ui: &Ui,
         hierarchy: &mut Hierarchy,
@@ -75,12 +136,17 @@ impl WorldEditor {
         selected: &mut Selected,
         node_idx: usize,
         draw_list: &imgui::DrawListMut,
-        position: [f32; 2],
+        origin: [f32; 2],
+        parent_world_pos: [f32; 2],
+        hit_boxes: &mut Vec<NodeHitBox>,
     ){
         if let Some(graph) = project_data.graphs.get(hierarchy.current_graph_idx) {
             if let Some(node) = graph.0.get(node_idx) {
-                let node_canvas_pos = [node.transform.x.to_num::<i32>() as f32, node.transform.y.to_num::<i32>() as f32];
-                let node_canvas_pos = [node_canvas_pos[0] + position[0], node_canvas_pos[1] + position[1]];
+                let node_world_pos = [
+                    parent_world_pos[0] + node.transform.x.to_num::<i32>() as f32,
+                    parent_world_pos[1] + node.transform.y.to_num::<i32>() as f32,
+                ];
+                let node_canvas_pos = world_to_screen(node_world_pos, origin);
                 let node_selected = matches!(selected, &mut Selected::Node(x) if x == node_idx);
 
                 match &node.node_extension {
@@ -106,6 +172,9 @@ impl WorldEditor {
                             if node_selected {
                                 draw_selected_rect_around(draw_list, p_min, p_max);
                             }
+                            hit_boxes.push(NodeHitBox { node_idx, top_left: p_min, bottom_right: p_max });
+                        } else {
+                            draw_placeholder(draw_list, node_idx, node_canvas_pos, node_selected, hit_boxes);
                         }
                     },
                     NodeExtension::Camera(_) => {
@@ -117,9 +186,12 @@ impl WorldEditor {
                         if node_selected {
                             draw_selected_rect_around(draw_list, top_left, bottom_right);
                         }
+                        // Only grab cameras by the corner, otherwise clicking anywhere on screen would pick the camera
+                        let handle_bottom_right = [top_left[0] + PLACEHOLDER_SIZE, top_left[1] + PLACEHOLDER_SIZE];
+                        hit_boxes.push(NodeHitBox { node_idx, top_left, bottom_right: handle_bottom_right });
                     }
                     NodeExtension::RectCollider(c) => {
-                        let top_left = node_canvas_pos;
+                        let top_left = [node_canvas_pos[0] + c.offset_x.to_num::<f32>(), node_canvas_pos[1] + c.offset_y.to_num::<f32>()];
                         let bottom_right = [top_left[0] + c.width.to_num::<f32>(), top_left[1] + c.height.to_num::<f32>()];
                         draw_list.add_rect(top_left, bottom_right, COLLIDER_OUTLINE_COLOUR)
                             .thickness(COLLIDER_OUTLINE_THICKNESS)
@@ -127,12 +199,30 @@ impl WorldEditor {
                         if node_selected {
                             draw_selected_rect_around(draw_list, top_left, bottom_right);
                         }
+                        // Only grab colliders by the corner, so big ones don't block clicks on the nodes inside them
+                        let handle_bottom_right = [top_left[0] + PLACEHOLDER_SIZE, top_left[1] + PLACEHOLDER_SIZE];
+                        hit_boxes.push(NodeHitBox { node_idx, top_left, bottom_right: handle_bottom_right });
                     },
-                    _ => {
+                    NodeExtension::Label(l) => {
+                        let lines = l.text.split('\n').count() as f32;
+                        let width = l.text.split('\n').map(|line| line.chars().count()).max().unwrap_or(0) as f32 * LABEL_CHAR_SIZE;
+                        let x_offset = match l.align {
+                            TextAlign::Left => 0.0,
+                            TextAlign::Center => (width / LABEL_CHAR_SIZE / 2.0).floor() * LABEL_CHAR_SIZE,
+                            TextAlign::Right => width,
+                        };
+                        let top_left = [node_canvas_pos[0] - x_offset, node_canvas_pos[1]];
+                        let bottom_right = [top_left[0] + width.max(PLACEHOLDER_SIZE), top_left[1] + lines * LABEL_CHAR_SIZE];
+                        draw_list.add_text(top_left, LABEL_TEXT_COLOUR, &l.text);
                         if node_selected {
-                            draw_list.add_circle(node_canvas_pos, 2.0, SELECTED_OUTLINE_COLOUR)
-                                .filled(true)
-                                .build();
+                            draw_selected_rect_around(draw_list, top_left, bottom_right);
+                        }
+                        hit_boxes.push(NodeHitBox { node_idx, top_left, bottom_right });
+                    },
+                    NodeExtension::None => {
+                        // Root node is just the graph's origin, so there's nothing to draw or grab
+                        if node_idx != 0 {
+                            draw_placeholder(draw_list, node_idx, node_canvas_pos, node_selected, hit_boxes);
                         }
                     },
                 }
@@ -141,7 +231,7 @@ impl WorldEditor {
                 if let Some(mut cur_child_idx) = node.child_index {
                     loop {
                         let cur_child_idx_usize = usize::from(cur_child_idx);
-                        Self::draw_node_recursive(ui, hierarchy, project_data, selected, cur_child_idx_usize, draw_list, node_canvas_pos);
+                        Self::draw_node_recursive(ui, hierarchy, project_data, selected, cur_child_idx_usize, draw_list, origin, node_world_pos, hit_boxes);
                         cur_child_idx = match graph.0[cur_child_idx_usize].sibling_index {
                             Some(x) => x,
                             None => break,
@@ -153,6 +243,28 @@ impl WorldEditor {
     }
 }
 
+// The canvas has no zoom, so converting is just an offset by where the world origin is on screen
+fn world_to_screen(world_pos: [f32; 2], origin: [f32; 2]) -> [f32; 2] {
+    [world_pos[0] + origin[0], world_pos[1] + origin[1]]
+}
+
+fn screen_to_world(screen_pos: [f32; 2], origin: [f32; 2]) -> [f32; 2] {
+    [screen_pos[0] - origin[0], screen_pos[1] - origin[1]]
+}
+
+// Box for nodes that have nothing else to show, so they can still be seen and grabbed
+fn draw_placeholder(draw_list: &imgui::DrawListMut, node_idx: usize, position: [f32; 2], node_selected: bool, hit_boxes: &mut Vec<NodeHitBox>) {
+    let top_left = position;
+    let bottom_right = [top_left[0] + PLACEHOLDER_SIZE, top_left[1] + PLACEHOLDER_SIZE];
+    draw_list.add_rect(top_left, bottom_right, PLACEHOLDER_OUTLINE_COLOUR)
+        .thickness(PLACEHOLDER_OUTLINE_THICKNESS)
+        .build();
+    if node_selected {
+        draw_selected_rect_around(draw_list, top_left, bottom_right);
+    }
+    hit_boxes.push(NodeHitBox { node_idx, top_left, bottom_right });
+}
+
 fn rotate_point(point: [f32; 2], center: [f32; 2], radians: f32) -> [f32; 2] {
     let sin = radians.sin();
     let cos = radians.cos();
@@ -173,3 +285,28 @@ fn draw_selected_rect_around(draw_list: &imgui::DrawListMut, top_left: [f32; 2],
         .thickness(SELECTED_OUTLINE_THICKNESS)
         .build();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn screen_world_mapping() {
+        let origin = [150.0, 80.0];
+        assert_eq!(world_to_screen([0.0, 0.0], origin), origin);
+        assert_eq!(world_to_screen([10.0, -20.0], origin), [160.0, 60.0]);
+        assert_eq!(screen_to_world([160.0, 60.0], origin), [10.0, -20.0]);
+
+        let world_pos = [37.5, 12.25];
+        assert_eq!(screen_to_world(world_to_screen(world_pos, origin), origin), world_pos);
+    }
+
+    #[test]
+    fn hit_box_contains() {
+        let hit_box = NodeHitBox { node_idx: 1, top_left: [10.0, 10.0], bottom_right: [26.0, 18.0] };
+        assert!(hit_box.contains([10.0, 10.0]));
+        assert!(hit_box.contains([20.0, 15.0]));
+        assert!(!hit_box.contains([27.0, 15.0]));
+        assert!(!hit_box.contains([20.0, 9.0]));
+    }
+}
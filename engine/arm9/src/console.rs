@@ -0,0 +1,11 @@
+//! Text output on the sub screen.
+//! With the `no-hardware` feature, there is no screen, so the text is thrown away.
+
+#[cfg(feature = "hardware")]
+pub use ironds::display::console::{set_cursor_pos, print};
+
+#[cfg(feature = "no-hardware")]
+pub fn set_cursor_pos(_x: u8, _y: u8) {}
+
+#[cfg(feature = "no-hardware")]
+pub fn print(_s: &str) {}
@@ -0,0 +1,149 @@
+//! Collider outlines drawn over the main screen, for checking collider bounds against sprites.
+//! They're drawn into a direct colour bitmap on main BG3, which uses VRAM bank B while turned on.
+//! Turned on and off with `Hierarchy::set_collider_debug_draw`.
+
+use alloc::vec::Vec;
+use crate::{hierarchy::{Hierarchy, HierarchyPoolTrait}, node::{camera::ActiveCameras, rect_collider}};
+
+const SCREEN_WIDTH: i32 = crate::hierarchy::SCREEN_WIDTH as i32;
+const SCREEN_HEIGHT: i32 = crate::hierarchy::SCREEN_HEIGHT as i32;
+// The bitmap is 256x256, of which the top 192 rows are on screen
+#[cfg(feature = "hardware")]
+const BITMAP_SIZE: usize = 256;
+
+#[cfg(feature = "hardware")]
+const DISPCNT_MAIN: usize = 0x0400_0000;
+#[cfg(feature = "hardware")]
+const BG3CNT_MAIN: usize = 0x0400_000E;
+#[cfg(feature = "hardware")]
+const BG3PA_MAIN: usize = 0x0400_0030;
+#[cfg(feature = "hardware")]
+const VRAMCNT_B: usize = 0x0400_0241;
+#[cfg(feature = "hardware")]
+const MAIN_BG_VRAM: usize = 0x0600_0000;
+// Enabled, mapped to main BG at 0x06000000
+#[cfg(feature = "hardware")]
+const VRAMCNT_B_MAIN_BG: u8 = 0x81;
+// 256x256 direct colour bitmap, at the start of main BG VRAM
+#[cfg(feature = "hardware")]
+const BG3CNT_DIRECT_BITMAP: u16 = 0x4084;
+#[cfg(feature = "hardware")]
+const DISPCNT_DISPLAY_BG3: u32 = 1 << 11;
+// BG mode 5, where BG3 can be a bitmap
+#[cfg(feature = "hardware")]
+const DISPCNT_BG_MODE_5: u32 = 5;
+// Green, with the bit that makes a bitmap pixel visible
+#[cfg(feature = "hardware")]
+const OUTLINE_COLOUR: u16 = 0x8000 | 0x03E0;
+
+// Pixels along the edges of the box with the given corners, leaving out any that are off the screen.
+#[cfg_attr(feature = "no-hardware", allow(dead_code))]
+pub(crate) fn box_pixels(min: (i32, i32), max: (i32, i32)) -> impl Iterator<Item = (i32, i32)> {
+    let xs = min.0.max(0)..=max.0.min(SCREEN_WIDTH - 1);
+    let ys = min.1.max(0)..=max.1.min(SCREEN_HEIGHT - 1);
+    let horizontal = [min.1, max.1].into_iter()
+        .filter(|y| (0..SCREEN_HEIGHT).contains(y))
+        .flat_map(move |y| xs.clone().map(move |x| (x, y)));
+    let vertical = [min.0, max.0].into_iter()
+        .filter(|x| (0..SCREEN_WIDTH).contains(x))
+        .flat_map(move |x| ys.clone().map(move |y| (x, y)));
+    horizontal.chain(vertical)
+}
+
+#[derive(Default)]
+pub(crate) struct DebugDrawHandler {
+    enabled: bool,
+    // Corners of the boxes drawn last frame, so they can be rubbed out
+    drawn: Vec<((i32, i32), (i32, i32))>,
+}
+
+impl DebugDrawHandler {
+    #[must_use]
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if enabled == self.enabled {
+            return;
+        }
+        self.enabled = enabled;
+        #[cfg(feature = "hardware")]
+        if enabled {
+            Self::show_bitmap();
+        } else {
+            for (min, max) in core::mem::take(&mut self.drawn) {
+                Self::fill_box(min, max, 0);
+            }
+            Self::hide_bitmap();
+        }
+        #[cfg(feature = "no-hardware")]
+        self.drawn.clear();
+    }
+
+    pub fn collider_update(&mut self, hierarchy: &Hierarchy, cameras: ActiveCameras) {
+        if !self.enabled {
+            return;
+        }
+        let mut boxes = Vec::new();
+        if let Some(camera) = cameras.main {
+            let camera_pos = hierarchy.borrow(camera).draw_position(hierarchy);
+            for col in hierarchy.iter_colliders() {
+                if hierarchy.borrow(col.node_handle).global_enabled {
+                    let [min, _, max, _] = rect_collider::collider_outline(hierarchy, col, camera_pos);
+                    boxes.push((min, max));
+                }
+            }
+        }
+
+        // Boxes that haven't moved are drawn again anyway, in case a rubbed out one crossed them
+        #[cfg(feature = "hardware")]
+        {
+            for &(min, max) in self.drawn.iter().filter(|b| !boxes.contains(b)) {
+                Self::fill_box(min, max, 0);
+            }
+            for &(min, max) in boxes.iter() {
+                Self::fill_box(min, max, OUTLINE_COLOUR);
+            }
+        }
+        self.drawn = boxes;
+    }
+
+    #[cfg(feature = "hardware")]
+    fn fill_box(min: (i32, i32), max: (i32, i32), colour: u16) {
+        for (x, y) in box_pixels(min, max) {
+            unsafe {
+                core::ptr::write_volatile((MAIN_BG_VRAM as *mut u16).add(y as usize * BITMAP_SIZE + x as usize), colour);
+            }
+        }
+    }
+
+    #[cfg(feature = "hardware")]
+    fn show_bitmap() {
+        unsafe {
+            core::ptr::write_volatile(VRAMCNT_B as *mut u8, VRAMCNT_B_MAIN_BG);
+            // VRAM can't be written a byte at a time, so this can't be a memset
+            for i in 0..BITMAP_SIZE * BITMAP_SIZE {
+                core::ptr::write_volatile((MAIN_BG_VRAM as *mut u16).add(i), 0);
+            }
+            core::ptr::write_volatile(BG3CNT_MAIN as *mut u16, BG3CNT_DIRECT_BITMAP);
+            // Unscaled and unrotated, at the top left
+            let affine: [u16; 4] = [0x100, 0, 0, 0x100];
+            for (i, value) in affine.into_iter().enumerate() {
+                core::ptr::write_volatile((BG3PA_MAIN as *mut u16).add(i), value);
+            }
+            core::ptr::write_volatile((BG3PA_MAIN + 8) as *mut u32, 0);
+            core::ptr::write_volatile((BG3PA_MAIN + 12) as *mut u32, 0);
+            let dispcnt = core::ptr::read_volatile(DISPCNT_MAIN as *const u32);
+            core::ptr::write_volatile(DISPCNT_MAIN as *mut u32, (dispcnt & !0b111) | DISPCNT_BG_MODE_5 | DISPCNT_DISPLAY_BG3);
+        }
+    }
+
+    #[cfg(feature = "hardware")]
+    fn hide_bitmap() {
+        unsafe {
+            let dispcnt = core::ptr::read_volatile(DISPCNT_MAIN as *const u32);
+            core::ptr::write_volatile(DISPCNT_MAIN as *mut u32, dispcnt & !DISPCNT_DISPLAY_BG3);
+        }
+    }
+}
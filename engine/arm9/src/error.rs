@@ -0,0 +1,68 @@
+//! Errors for things that can go wrong at runtime and that the game might want to handle,
+//! like a misspelled graph name. Broken invariants inside the engine still panic.
+
+use core::num::NonZeroU32;
+use alloc::string::String;
+use sandstone_common::{GraphError, SerializationError};
+use crate::vram::VramError;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The handle's object has been destroyed.
+    InvalidHandle,
+    /// There's no graph (prefab) with this name in the game data.
+    UnknownGraph(String),
+    /// The graph's child, parent and sibling indices don't form a tree.
+    MalformedGraph(String, GraphError),
+    /// The graph already has as many live copies as its instance limit allows.
+    InstanceLimitReached(String),
+    /// There's no graphic with this name in the game data.
+    UnknownGraphic(String),
+    /// The graphic doesn't fit in VRAM.
+    OutOfVram(VramError),
+    /// The node doesn't have a sprite extension.
+    NotASprite,
+    /// No script is registered with this type ID.
+    UnknownScript(NonZeroU32),
+    /// The node doesn't have a script.
+    NoScript,
+    /// The node's script isn't the type it was cast to.
+    ScriptTypeMismatch,
+    /// The bytes couldn't be read, e.g. a snapshot from a different version of the game.
+    SerializationError(SerializationError),
+    /// The snapshot doesn't have a state for each of its nodes, or refers to a node it doesn't have.
+    MalformedSnapshot,
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::InvalidHandle => f.write_str("handle points to an object that doesn't exist"),
+            Self::UnknownGraph(name) => f.write_fmt(format_args!("no graph named {name}")),
+            Self::MalformedGraph(name, e) => f.write_fmt(format_args!("graph {name} is malformed: {e}")),
+            Self::InstanceLimitReached(name) => f.write_fmt(format_args!("reached the instance limit for {name}")),
+            Self::UnknownGraphic(name) => f.write_fmt(format_args!("no graphic named {name}")),
+            Self::OutOfVram(e) => f.write_fmt(format_args!("{e}")),
+            Self::NotASprite => f.write_str("node doesn't have a sprite"),
+            Self::UnknownScript(id) => f.write_fmt(format_args!("no script registered with ID {id}")),
+            Self::NoScript => f.write_str("node doesn't have a script"),
+            Self::ScriptTypeMismatch => f.write_str("node's script is a different type"),
+            Self::SerializationError(e) => f.write_fmt(format_args!("couldn't deserialize: {e}")),
+            Self::MalformedSnapshot => f.write_str("snapshot's node states don't match its nodes"),
+        }
+    }
+}
+
+impl core::error::Error for Error {}
+
+impl From<VramError> for Error {
+    fn from(e: VramError) -> Self {
+        Self::OutOfVram(e)
+    }
+}
+
+impl From<SerializationError> for Error {
+    fn from(e: SerializationError) -> Self {
+        Self::SerializationError(e)
+    }
+}
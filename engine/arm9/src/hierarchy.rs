@@ -1,12 +1,32 @@
 use core::num::NonZeroU32;
-use alloc::{string::String, boxed::Box, vec::Vec};
+use fixed::types::I20F12;
+use sandstone_common::{
+    SavedNodeGraph, SavedNode, SavedNodeExtension, SavedTransform, SavedNodeState, SavedSnapshot,
+    SavedScriptState, SavedBody, SavedSpawnAnimation, SavedSpriteState, SavedCameraState, SavedCameraFollow,
+};
+use alloc::{string::String, boxed::Box, vec::Vec, collections::VecDeque};
 use crate::{
     Script,
     ScriptContext,
-    pool::{Pool, Handle},
-    node::{Transform, Node, NodeScriptData, NodeExtensionHandle, NodeExtensionPools, sprite::SpriteExtensionHandler, camera::CameraExtensionHandler, rect_collider}
+    ScriptRegistry,
+    Error,
+    pool::{Pool, Handle, Ticket},
+    random::GlobalRng,
+    math::{Vec2, Rect, tween::{Easing, tween}},
+    node::{
+        Transform, Node, Health, Follow, Body, Contact, SpawnAnimation, SpawnEffect, NodeScriptData, NodeExtensionHandle, NodeExtensionPools, ExtensionType, TypedHandle, Screen,
+        sprite::SpriteExtensionHandler, camera::{CameraExtensionHandler, CameraFollow}, rect_collider, label::LabelExtensionHandler,
+    },
+    debug_draw::DebugDrawHandler,
+    names::{NameId, NameTable},
+    input::{self, FrameInput, InputProvider, HardwareInput, InputRecorder},
 };
 
+pub const SCREEN_WIDTH: u32 = 256;
+pub const SCREEN_HEIGHT: u32 = 192;
+/// Number of nodes `Hierarchy::new` makes room for, including the root.
+pub const DEFAULT_NODE_CAPACITY: usize = 64;
+
 pub trait HierarchyPoolTrait<T> {
     fn borrow(&self, handle: Handle<T>) -> &T;
     fn try_borrow(&self, handle: Handle<T>) -> Option<&T>;
@@ -15,17 +35,168 @@ pub trait HierarchyPoolTrait<T> {
     fn handle_from_index(&self, index: usize) -> Option<Handle<T>>;
 }
 
+/// Makes a single node, from `Hierarchy::build_node`. The node is spawned like a one node graph,
+/// so it gets its extension, script and start the same way a node from the editor would.
+pub struct NodeBuilder<'a> {
+    hierarchy: &'a mut Hierarchy,
+    node: SavedNode,
+}
+
+impl NodeBuilder<'_> {
+    #[must_use]
+    pub fn transform(mut self, transform: Transform) -> Self {
+        self.node.transform = SavedTransform { x: transform.x, y: transform.y };
+        self
+    }
+
+    #[must_use]
+    pub fn extension(mut self, extension: SavedNodeExtension) -> Self {
+        self.node.node_extension = extension;
+        self
+    }
+
+    /// The ID of a registered script, as given to `register_script!`.
+    #[must_use]
+    pub fn script_id(mut self, type_id: NonZeroU32) -> Self {
+        self.node.script_type_id = Some(type_id);
+        self
+    }
+
+    #[must_use]
+    pub fn enabled(mut self, enabled: bool) -> Self {
+        self.node.enabled = enabled;
+        self
+    }
+
+    #[must_use]
+    pub fn user_data(mut self, user_data: u32) -> Self {
+        self.node.user_data = user_data;
+        self
+    }
+
+    /// Adds the node as the first child of `parent`, and returns its handle.
+    pub fn build_under(self, parent: Handle<Node>) -> Handle<Node> {
+        let graph = SavedNodeGraph { nodes: alloc::vec![self.node], backdrop_colour: None };
+        self.hierarchy.instantiate_graph(&graph, parent, None)
+    }
+}
+
+/// Result of a raycast.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RayHit {
+    /// The node with the collider that was hit.
+    pub node: Handle<Node>,
+    /// Distance from the ray's origin to where it hit, in pixels.
+    pub distance: I20F12,
+}
+
+/// A spawn-time parameter, for `Hierarchy::spawn_object_with_params`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Value {
+    Int(i32),
+    Fixed(I20F12),
+    Bool(bool),
+}
+
+impl Value {
+    /// The value as an integer, if it is one.
+    #[must_use]
+    pub fn as_int(self) -> Option<i32> {
+        if let Self::Int(x) = self { Some(x) } else { None }
+    }
+
+    /// The value as a fixed point number. Integers are converted.
+    #[must_use]
+    pub fn as_fixed(self) -> Option<I20F12> {
+        match self {
+            Self::Fixed(x) => Some(x),
+            Self::Int(x) => Some(I20F12::from_num(x)),
+            Self::Bool(_) => None,
+        }
+    }
+
+    /// The value as a bool, if it is one.
+    #[must_use]
+    pub fn as_bool(self) -> Option<bool> {
+        if let Self::Bool(x) = self { Some(x) } else { None }
+    }
+}
+
+// What a spawned node's start can find out about how it was spawned
+struct SpawnInfo {
+    spawner: Option<Handle<Node>>,
+    params: Vec<(String, Value)>,
+}
+
+/// Callback run with a context for a node, e.g. when a timer or tween finishes.
+pub type NodeCallback = Box<dyn FnOnce(&mut ScriptContext)>;
+
+struct Tween {
+    node: Handle<Node>,
+    from: Vec2,
+    to: Vec2,
+    frame: u32,
+    frames: u32,
+    easing: Easing,
+    on_complete: Option<NodeCallback>,
+}
+// Recycling and instance limit for a graph, and the copies of it that are tracked for them
+struct GraphSpawnSettings {
+    graph: String,
+    recycling: bool,
+    instance_limit: Option<usize>,
+    live_instances: usize,
+    // Slots left behind by destroyed copies, for spawning it again
+    free_slots: Vec<Ticket<Node>>,
+}
+
+struct Timer {
+    node: Handle<Node>,
+    frames_left: u32,
+    callback: Box<dyn FnOnce(&mut ScriptContext)>,
+}
+
 pub struct Hierarchy {
     pub root: Handle<Node>,
     pub(crate) object_pool: Pool<Node>,
     pub(crate) node_ext_pools: NodeExtensionPools,
-    to_start_stack: Vec<Handle<Node>>,
+    // Nodes waiting for start, in the order they should start
+    to_start_queue: VecDeque<Handle<Node>>,
     to_destroy_stack: Vec<Handle<Node>>,
+    timers: Vec<Timer>,
+    tweens: Vec<Tween>,
+    enable_transitions: Vec<(Handle<Node>, bool)>,
+    // Nodes playing their spawn animation
+    spawn_animations: Vec<Handle<Node>>,
+    // Nodes whose health ran out this frame, waiting for on_death
+    pending_deaths: Vec<Handle<Node>>,
+    // Spawn info for spawned nodes that haven't started yet, and for the node that's starting now
+    pending_spawn_info: Vec<(Handle<Node>, SpawnInfo)>,
+    start_info: Option<(Handle<Node>, SpawnInfo)>,
     pub(crate) game_data: sandstone_common::SavedGameData,
     sprite_handler: SpriteExtensionHandler,
+    names: NameTable,
     camera_handler: CameraExtensionHandler,
-    script_factory: fn(NonZeroU32) -> Box<dyn Script>,
+    label_handler: LabelExtensionHandler,
+    debug_draw: DebugDrawHandler,
+    pub(crate) collision_grid: rect_collider::CollisionGrid,
+    graph_settings: Vec<GraphSpawnSettings>,
+    // Graphs that failed validation when the game data was loaded, so they're refused without checking again on every spawn
+    malformed_graphs: Vec<(String, sandstone_common::GraphError)>,
+    // Spawned copies of graphs with settings, and which settings they were spawned with
+    tracked_roots: crate::HashMap<Handle<Node>, usize>,
+    scripts: ScriptRegistry,
     pending_scene: Option<String>,
+    current_scene: Option<String>,
+    // Root node of the current scene, which is replaced on a scene change
+    scene_root: Option<Handle<Node>>,
+    backdrop_colour: Option<u32>,
+    paused: bool,
+    frame_count: u32,
+    input: FrameInput,
+    input_provider: Box<dyn InputProvider>,
+    input_recorder: Option<InputRecorder>,
+    shut_down: bool,
 }
 
 macro hierarchy_pool_methods ($t:ty, $( $pool:ident ).+) {
@@ -66,46 +237,123 @@ hierarchy_pool_methods!(Node, object_pool);
 hierarchy_pool_methods!(crate::node::sprite::SpriteExtension, node_ext_pools.sprite_pool);
 hierarchy_pool_methods!(crate::node::camera::CameraExtension, node_ext_pools.camera_pool);
 hierarchy_pool_methods!(crate::node::rect_collider::RectColliderExtension, node_ext_pools.rect_collider_pool);
+hierarchy_pool_methods!(crate::node::label::LabelExtension, node_ext_pools.label_pool);
 
 impl Hierarchy {
-    pub fn new(game_data_raw: &[u8], script_factory: fn(NonZeroU32) -> Box<dyn Script>) -> Self {
-        let mut object_pool: Pool<Node> = Pool::new();
+    pub fn new(game_data_raw: &[u8], scripts: ScriptRegistry) -> Self {
+        Self::with_capacity(game_data_raw, scripts, DEFAULT_NODE_CAPACITY)
+    }
+
+    /// Makes room for `capacity` nodes (including the root) up front,
+    /// so spawning up to that many won't need to reallocate in the middle of a frame.
+    pub fn with_capacity(game_data_raw: &[u8], scripts: ScriptRegistry, capacity: usize) -> Self {
+        Self::from_game_data_with_capacity(sandstone_common::deserialize(game_data_raw), scripts, capacity)
+    }
+
+    /// Builds a hierarchy from game data that's already in memory, rather than serialized,
+    /// e.g. graphs put together in code for tests or tools.
+    pub fn from_game_data(game_data: sandstone_common::SavedGameData, scripts: ScriptRegistry) -> Self {
+        Self::from_game_data_with_capacity(game_data, scripts, DEFAULT_NODE_CAPACITY)
+    }
+
+    fn from_game_data_with_capacity(game_data: sandstone_common::SavedGameData, scripts: ScriptRegistry, capacity: usize) -> Self {
+        let mut object_pool: Pool<Node> = Pool::with_capacity(capacity);
         let root = object_pool.add(Node {
-            child_handle: None,
-            parent_handle: None,
-            sibling_handle: None,
-            name: String::new(),
-            transform: Transform::default(),
-            node_extension: NodeExtensionHandle::None,
-            script_data: None,
-            enabled: true,
-            global_transform: Transform::default(),
-            global_enabled: false,
+            global_enabled: true,
+            started: true,
+            ..Node::new(NameId::default(), Transform::default(), NodeExtensionHandle::None)
         });
+        let malformed_graphs = game_data.graphs.iter()
+            .filter_map(|(name, graph)| graph.validate().err().map(|e| (name.clone(), e)))
+            .inspect(|(name, e)| crate::log::error!("Graph {name} is malformed and can't be spawned: {e}"))
+            .collect();
 
         Self {
             root,
             object_pool,
             node_ext_pools: NodeExtensionPools::new(),
-            to_start_stack: Vec::new(),
+            to_start_queue: VecDeque::new(),
             to_destroy_stack: Vec::new(),
-            game_data: sandstone_common::deserialize(game_data_raw),
+            timers: Vec::new(),
+            tweens: Vec::new(),
+            enable_transitions: Vec::new(),
+            spawn_animations: Vec::new(),
+            pending_deaths: Vec::new(),
+            pending_spawn_info: Vec::new(),
+            start_info: None,
+            game_data,
             sprite_handler: SpriteExtensionHandler::new(),
+            names: NameTable::new(),
             camera_handler: CameraExtensionHandler::new(),
-            script_factory,
+            label_handler: LabelExtensionHandler::default(),
+            debug_draw: DebugDrawHandler::default(),
+            collision_grid: rect_collider::CollisionGrid::default(),
+            graph_settings: Vec::new(),
+            malformed_graphs,
+            tracked_roots: crate::HashMap::default(),
+            scripts,
             pending_scene: None,
+            current_scene: None,
+            scene_root: None,
+            backdrop_colour: None,
+            paused: false,
+            frame_count: 0,
+            input: FrameInput::default(),
+            input_provider: Box::new(HardwareInput),
+            input_recorder: None,
+            shut_down: false,
         }
     }
 
+    /// Number of script updates that have run so far, including paused ones.
+    /// Wraps back to 0 after u32::MAX, which is over 2 years of frames at 60 FPS.
+    #[must_use]
+    pub fn frame_count(&self) -> u32 {
+        self.frame_count
+    }
+
+    /// Sets the opacity of every sprite with `BlendMode::Alpha`, from 0 (invisible) to `MAX_SPRITE_ALPHA` (opaque).
+    pub fn set_sprite_alpha(&mut self, alpha: u8) {
+        self.sprite_handler.blend_alpha = alpha.min(crate::node::sprite::MAX_SPRITE_ALPHA);
+    }
+
+    /// Sets the block size for sprites with mosaic turned on, from 1 (no effect) to `MAX_MOSAIC_SIZE` pixels.
+    /// Animating this is good for dissolving sprites in and out.
+    pub fn set_sprite_mosaic(&mut self, width: u8, height: u8) {
+        use crate::node::sprite::MAX_MOSAIC_SIZE;
+        self.sprite_handler.mosaic_size = (width.clamp(1, MAX_MOSAIC_SIZE), height.clamp(1, MAX_MOSAIC_SIZE));
+    }
+
+    /// Turns outlines around every enabled collider on the main screen on or off, for checking them against sprites.
+    /// Uses main BG3 and VRAM bank B while on.
+    pub fn set_collider_debug_draw(&mut self, enabled: bool) {
+        self.debug_draw.set_enabled(enabled);
+    }
+
+    #[must_use]
+    pub fn collider_debug_draw(&self) -> bool {
+        self.debug_draw.enabled()
+    }
+
+    /// Freezes the game. While paused, scripts get `paused_update` instead of `update`,
+    /// and timers and lifetimes stop counting down. Sprites keep being drawn where they were.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    #[must_use]
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
     /// Shortcut to set_scene for the main scene.
     pub fn set_scene_main(&mut self) {
-        // SAFETY: this isn't normally allowed because accessing self.game_data and set_scene
-        // at the same time is 2 borrows. this is safe as long as set_scene doesn't modify game_data.main_graph
-        // Is there a way to avoid this jank, without doing a clone?
-        self.set_scene(unsafe {&*(self.game_data.main_graph.as_str() as *const str)});
+        // set_scene would copy the name anyway, so cloning it here costs nothing extra
+        self.pending_scene = Some(self.game_data.main_graph.clone());
     }
 
-    /// Destroys the current scene, and starts the new one.
+    /// Destroys the current scene, and starts the new one at the end of the frame.
+    /// Other nodes under the root, like ones spawned with `ScriptContext::spawn_at_root`, are kept.
     pub fn set_scene(&mut self, name: &str) {
         self.pending_scene = Some(String::from(name));
     }
@@ -113,39 +361,174 @@ impl Hierarchy {
     pub fn process_pending_scene_change(&mut self) {
         if let Some(name) = &self.pending_scene {
             let name = name.clone();
-            if let Some(old_scene_root) = self.borrow(self.root).child_handle {
+            if let Some(old_scene_root) = self.scene_root.take() {
                 self.destroy_node(old_scene_root);
             }
-            self.spawn_object(&name, self.root);
+            if let Some(colour) = self.game_data.graphs.get(&name).and_then(|x| x.backdrop_colour) {
+                self.set_backdrop_colour(colour);
+            }
+            self.scene_root = Some(self.spawn_object(&name, self.root));
             self.process_pending_destroys();
             self.run_pending_script_starts();
             self.pending_scene = None;
+            crate::log::info!("Loaded scene {name}");
+            self.current_scene = Some(name);
+        }
+    }
+
+    /// Name of the graph the current scene was loaded from, or None before the first scene has loaded.
+    #[must_use]
+    pub fn current_scene_name(&self) -> Option<&str> {
+        self.current_scene.as_deref()
+    }
+
+    /// Root node of the current scene, or None before the first scene has loaded or if a script destroyed it.
+    #[must_use]
+    pub fn scene_root(&self) -> Option<Handle<Node>> {
+        self.scene_root.filter(|&root| self.object_pool.try_borrow(root).is_some())
+    }
+
+    /// Sets the backdrop colour shown behind everything on both screens, as 0xRRGGBB.
+    /// Scenes with their own backdrop colour replace this when they load.
+    pub fn set_backdrop_colour(&mut self, colour: u32) {
+        crate::set_bg_colour(colour);
+        self.backdrop_colour = Some(colour);
+    }
+
+    /// The last backdrop colour set, or None if it hasn't been set since the hierarchy was created.
+    #[must_use]
+    pub fn backdrop_colour(&self) -> Option<u32> {
+        self.backdrop_colour
+    }
+
+    /// Reloads the current scene from scratch at the end of the frame, like calling set_scene with its name.
+    /// Only the scene's nodes are reset - nodes spawned directly under the root are kept.
+    pub fn restart_scene(&mut self) {
+        if let Some(name) = self.current_scene.clone() {
+            self.set_scene(&name);
         }
     }
 
+    /// Panics if the graph doesn't exist, or it has an instance limit that's been reached - use `try_spawn_object` for those.
     pub fn spawn_object(&mut self, graph_name: &str, parent: Handle<Node>) -> Handle<Node> {
-        let saved_graph = self.game_data.graphs.get(graph_name)
-            .unwrap_or_else(|| panic!("Tried to spawn invalid graph: {graph_name}"));
+        self.try_spawn_object(graph_name, parent)
+            .unwrap_or_else(|e| panic!("Tried to spawn {graph_name}: {e}"))
+    }
+
+    /// Like `spawn_object`, but nothing is spawned and an error is returned if the graph doesn't exist
+    /// or its instance limit has been reached.
+    pub fn try_spawn_object(&mut self, graph_name: &str, parent: Handle<Node>) -> Result<Handle<Node>, Error> {
+        self.spawn_graph_copies(graph_name, parent, 1, |_| None)?.first().copied()
+            .ok_or_else(|| Error::InstanceLimitReached(String::from(graph_name)))
+    }
+
+    /// Spawns `count` copies of a graph under `parent`, looking the graph up once and making room for them all up front.
+    /// `placement` gives the local transform of each copy's root node, by index. The handles are returned in the same order.
+    /// If the graph's instance limit is reached part way through, the rest aren't spawned.
+    pub fn spawn_object_batch(&mut self, graph_name: &str, parent: Handle<Node>, count: usize, mut placement: impl FnMut(usize) -> Transform) -> Result<Vec<Handle<Node>>, Error> {
+        self.spawn_graph_copies(graph_name, parent, count, |i| Some(placement(i)))
+    }
+
+    /// Turns recycling on or off for a graph that gets spawned and destroyed a lot, like a bullet.
+    /// While it's on, the pool slots of destroyed copies are kept for the next copies of that graph,
+    /// instead of the pool growing. The new copies get fresh scripts, which are started as normal,
+    /// so no script state carries over. Turning it off hands the kept slots back to the pool.
+    pub fn set_graph_recycling(&mut self, graph_name: &str, enabled: bool) {
+        let settings = self.graph_settings_mut(graph_name);
+        settings.recycling = enabled;
+        if !enabled {
+            for ticket in core::mem::take(&mut settings.free_slots) {
+                self.object_pool.release(ticket);
+            }
+        }
+    }
+
+    /// Caps how many copies of a graph can be alive at once, so something spawning it every frame by mistake
+    /// can't use up all the memory. Past the limit, spawning it is refused and logged. None takes the limit off.
+    /// Only copies spawned after the first limit or recycling setting for the graph count towards it.
+    pub fn set_graph_instance_limit(&mut self, graph_name: &str, limit: Option<usize>) {
+        self.graph_settings_mut(graph_name).instance_limit = limit;
+    }
+
+    /// Number of copies of a graph that are alive, counting from when it was first given a limit or recycling setting.
+    #[must_use]
+    pub fn graph_instance_count(&self, graph_name: &str) -> usize {
+        self.graph_settings.iter().find(|g| g.graph == graph_name).map_or(0, |g| g.live_instances)
+    }
+
+    fn graph_settings_mut(&mut self, graph_name: &str) -> &mut GraphSpawnSettings {
+        let index = self.graph_settings.iter().position(|g| g.graph == graph_name).unwrap_or_else(|| {
+            self.graph_settings.push(GraphSpawnSettings {
+                graph: String::from(graph_name),
+                recycling: false,
+                instance_limit: None,
+                live_instances: 0,
+                free_slots: Vec::new(),
+            });
+            self.graph_settings.len() - 1
+        });
+        &mut self.graph_settings[index]
+    }
+
+    fn spawn_graph_copies(&mut self, graph_name: &str, parent: Handle<Node>, count: usize, mut placement: impl FnMut(usize) -> Option<Transform>) -> Result<Vec<Handle<Node>>, Error> {
+        if let Some((_, e)) = self.malformed_graphs.iter().find(|(name, _)| name == graph_name) {
+            return Err(Error::MalformedGraph(String::from(graph_name), *e));
+        }
+        // Taken out of the game data while spawning, so the hierarchy can be changed while reading it
+        let (name, saved_graph) = self.game_data.graphs.remove_entry(graph_name)
+            .ok_or_else(|| Error::UnknownGraph(String::from(graph_name)))?;
+
+        let settings = self.graph_settings.iter().position(|g| g.graph == graph_name);
+        let mut count = count;
+        let mut recycled = 0;
+        if let Some(g) = settings.map(|i| &self.graph_settings[i]) {
+            let allowed = g.instance_limit.map_or(count, |limit| limit.saturating_sub(g.live_instances));
+            if allowed < count {
+                crate::log::warn!("Reached the instance limit of {} for {graph_name}, not spawning any more", g.live_instances + allowed);
+                count = allowed;
+            }
+            recycled = g.free_slots.len();
+        }
+        let bin = settings.filter(|&i| self.graph_settings[i].recycling);
+        self.object_pool.reserve((count * saved_graph.nodes.len()).saturating_sub(recycled));
+        let new_roots = (0..count).map(|i| {
+            let new_root = self.instantiate_graph(&saved_graph, parent, bin);
+            if let Some(settings) = settings {
+                self.graph_settings[settings].live_instances += 1;
+                self.tracked_roots.insert(new_root, settings);
+            }
+            if let Some(transform) = placement(i) {
+                self.object_pool.borrow_mut(new_root).transform = transform;
+                let parent_node = self.object_pool.borrow(parent);
+                self.update_global_position_recursive(new_root, parent_node.global_transform, parent_node.global_enabled, parent_node.global_screen, parent_node.global_parallax);
+            }
+            new_root
+        }).collect();
+        self.game_data.graphs.insert(name, saved_graph);
+        Ok(new_roots)
+    }
+
+    fn instantiate_graph(&mut self, saved_graph: &SavedNodeGraph, parent: Handle<Node>, bin: Option<usize>) -> Handle<Node> {
+        self.instantiate_graph_nodes(saved_graph, parent, bin).0
+    }
 
+    // Like instantiate_graph, but also gives back every new node, in the same order as the graph's nodes
+    fn instantiate_graph_nodes(&mut self, saved_graph: &SavedNodeGraph, parent: Handle<Node>, bin: Option<usize>) -> (Handle<Node>, Vec<Handle<Node>>) {
         // Push the nodes onto the object pool, with placeholder child, parent and sibling handles
         let new_handles: Vec<Handle<Node>> = saved_graph.nodes.iter().map(|node| {
-            let handle = self.object_pool.add(Node {
-                child_handle: None,
-                parent_handle: None,
-                sibling_handle: None,
-                name: node.name.clone(),
-                transform: Transform { x: node.transform.x, y: node.transform.y },
-                node_extension: NodeExtensionHandle::None,
-                script_data: node.script_type_id.map(|id| NodeScriptData {
-                    type_id: id,
-                    script: (self.script_factory)(id),
-                }),
+            let new_node = Node {
+                scripts: node.script_type_id.and_then(|id| Self::create_script_data(self.scripts, id)).into_iter().collect(),
                 enabled: node.enabled,
-                global_transform: Transform::default(),
-                global_enabled: false,
-            });
+                user_data: node.user_data,
+                ..Node::new(self.names.intern(&node.name), Transform { x: node.transform.x, y: node.transform.y }, NodeExtensionHandle::None)
+            };
+            let handle = match bin.and_then(|b| self.graph_settings[b].free_slots.pop()) {
+                Some(ticket) => self.object_pool.reuse(ticket, new_node),
+                None => self.object_pool.add(new_node),
+            };
             self.object_pool.borrow_mut(handle).node_extension =
                 self.node_ext_pools.add_from_saved(handle, &node.node_extension);
+            self.load_sprite_graphic(handle);
             handle
         }).collect();
         
@@ -153,229 +536,4186 @@ impl Hierarchy {
         let mut new_obj_root: Option<Handle<Node>> = None;
         for (snode, handle) in saved_graph.nodes.iter().zip(new_handles.iter()) {
             let node = self.object_pool.borrow_mut(*handle);
-            node.child_handle = snode.child_index.map(|idx| new_handles[u32::from(idx) as usize]);
-            node.sibling_handle = snode.sibling_index.map(|idx| new_handles[u32::from(idx) as usize]);
-            node.parent_handle = snode.parent_index.map(|idx| new_handles[idx as usize]).or_else(|| {
+            node.child_index = snode.child_index.map(|idx| new_handles[u32::from(idx) as usize].raw());
+            node.sibling_index = snode.sibling_index.map(|idx| new_handles[u32::from(idx) as usize].raw());
+            node.parent_index = snode.parent_index.map(|idx| new_handles[idx as usize].raw()).or_else(|| {
                 new_obj_root = Some(*handle);
-                Some(parent)
+                Some(parent.raw())
             });
-            self.to_start_stack.push(*handle);
         }
         let new_obj_root = new_obj_root.expect("Tried to create graph with no root node");
         self.link_new_child(parent, new_obj_root);
-        new_obj_root
+        // Start in hierarchy order, rather than the order they're saved in
+        self.to_start_queue.extend(self.subtree_handles(new_obj_root));
+        // Give the new nodes their global state straight away, so the first
+        // update_global_positions after spawning isn't mistaken for them being enabled.
+        let parent_node = self.object_pool.borrow(parent);
+        self.update_global_position_recursive(new_obj_root, parent_node.global_transform, parent_node.global_enabled, parent_node.global_screen, parent_node.global_parallax);
+        (new_obj_root, new_handles)
     }
 
-    fn link_new_child(&mut self, parent: Handle<Node>, child: Handle<Node>) {
-        let parent_obj = self.object_pool.borrow_mut(parent);
-        self.object_pool.borrow_mut(child).sibling_handle = parent_obj.child_handle.replace(child);
+    /// Like `try_spawn_object`, but the spawned node's script can read the parameters with
+    /// `ScriptContext::param` during its `start`, e.g. to give each enemy a different speed.
+    pub fn spawn_object_with_params(&mut self, graph_name: &str, parent: Handle<Node>, params: &[(&str, Value)]) -> Result<Handle<Node>, Error> {
+        let handle = self.try_spawn_object(graph_name, parent)?;
+        let params = params.iter().map(|&(name, value)| (String::from(name), value)).collect();
+        self.pending_spawn_info.push((handle, SpawnInfo { spawner: None, params }));
+        Ok(handle)
     }
 
-    pub fn pretty_print_hierarchy_structure(&self) {
-        for i in 0..self.object_pool.vec_len() {
-            if let Some(handle) = self.object_pool.handle_from_index_checked(i) {
-                let node = self.object_pool.borrow(handle);
-                ironds::nocash::print(&alloc::format!("{:?}", handle));
-                ironds::nocash::print(&node.name);
-                ironds::nocash::print(alloc::format!("Child: {:?}", node.child_handle).as_str());
-                ironds::nocash::print(alloc::format!("Sibling: {:?}", node.sibling_handle).as_str());
-                ironds::nocash::print(alloc::format!("Parent: {:?}", node.parent_handle).as_str());
-                ironds::nocash::print("");
-            }
+    /// Like `try_spawn_object`, but the spawned node's script can get `spawner` with
+    /// `ScriptContext::spawner` during its `start`, e.g. so a bullet knows who fired it.
+    pub fn spawn_object_from(&mut self, graph_name: &str, parent: Handle<Node>, spawner: Handle<Node>) -> Result<Handle<Node>, Error> {
+        let handle = self.try_spawn_object(graph_name, parent)?;
+        self.pending_spawn_info.push((handle, SpawnInfo { spawner: Some(spawner), params: Vec::new() }));
+        Ok(handle)
+    }
+
+    /// Starts making a node in code, without a graph for it in the game data.
+    #[must_use]
+    pub fn build_node(&mut self, name: &str) -> NodeBuilder<'_> {
+        NodeBuilder {
+            hierarchy: self,
+            node: SavedNode {
+                child_index: None,
+                parent_index: None,
+                sibling_index: None,
+                name: String::from(name),
+                transform: SavedTransform { x: I20F12::ZERO, y: I20F12::ZERO },
+                node_extension: SavedNodeExtension::None,
+                script_type_id: None,
+                enabled: true,
+                user_data: 0,
+            },
+        }
+    }
+
+    // Spawn info for the node that's currently starting
+    fn start_info(&self, handle: Handle<Node>) -> Option<&SpawnInfo> {
+        self.start_info.as_ref().filter(|(h, _)| *h == handle).map(|(_, info)| info)
+    }
+
+    // Parameter passed to the starting node by spawn_object_with_params
+    pub(crate) fn start_param(&self, handle: Handle<Node>, name: &str) -> Option<Value> {
+        self.start_info(handle)?.params.iter().find(|(n, _)| n == name).map(|&(_, value)| value)
+    }
+
+    // Node that spawned the starting node with spawn_object_from
+    pub(crate) fn start_spawner(&self, handle: Handle<Node>) -> Option<Handle<Node>> {
+        self.start_info(handle)?.spawner
+    }
+
+    // A missing script shouldn't take the whole game down, so the node just goes without
+    fn create_script_data(scripts: ScriptRegistry, type_id: NonZeroU32) -> Option<NodeScriptData> {
+        let script = (scripts.factory)(type_id);
+        if script.is_none() {
+            crate::log::warn!("No script registered with ID {type_id}, spawning without it");
+        }
+        script.map(|script| NodeScriptData { type_id, script, started: false })
+    }
+
+    /// Adds another script to the node, after the ones it already has. It gets its own `start`
+    /// at the end of the frame if the node has already started, and updates after that.
+    pub fn add_script(&mut self, handle: Handle<Node>, type_id: NonZeroU32) -> Result<(), Error> {
+        let script_data = (self.scripts.factory)(type_id).map(|script| NodeScriptData { type_id, script, started: false })
+            .ok_or(Error::UnknownScript(type_id))?;
+        let node = self.object_pool.try_borrow_mut(handle).ok_or(Error::InvalidHandle)?;
+        node.scripts.push(script_data);
+        if node.started {
+            self.to_start_queue.push_back(handle);
         }
+        Ok(())
+    }
+
+    /// Copies a node and all of its children, with their current transforms, to a new parent.
+    /// Scripts can't be copied, so the copies get freshly created scripts from the script factory,
+    /// which are started like newly spawned ones. Script state is reset, not copied.
+    /// Scripts are taken out of their node while they run, so a node cloning itself is copied without scripts.
+    pub fn clone_subtree(&mut self, root: Handle<Node>, new_parent: Handle<Node>) -> Handle<Node> {
+        let new_root = self.clone_node_recursive(root, new_parent);
+        self.link_new_child(new_parent, new_root);
+        let parent_node = self.object_pool.borrow(new_parent);
+        self.update_global_position_recursive(new_root, parent_node.global_transform, parent_node.global_enabled, parent_node.global_screen, parent_node.global_parallax);
+        new_root
     }
 
-    // todo: recursive search?
-    // could have fast path for situation where search root is graph root node
-    // as we can iterate over vec sequentially instead of following the tree
+    // Requests VRAM for the node's sprite graphic, if it has a sprite
+    fn load_sprite_graphic(&mut self, handle: Handle<Node>) {
+        let NodeExtensionHandle::Sprite(sprite) = self.object_pool.borrow(handle).node_extension else { return; };
+        self.sprite_handler.sync_graphic(self.node_ext_pools.sprite_pool.borrow_mut(sprite), &self.game_data);
+    }
 
+    /// Where a graphic is in OBJ VRAM, or None if it isn't loaded.
+    /// Graphics are loaded when the first sprite using them spawns, and unloaded when the last one is destroyed.
     #[must_use]
-    pub fn find_by_name(&mut self, search_root: Handle<Node>, name: &str) -> Option<Handle<Node>> {
-        self.find(search_root, |x| x.name == name)
+    pub fn graphic_vram_slot(&self, name: &str) -> Option<crate::vram::VramSlot> {
+        self.sprite_handler.graphics.get(name)
     }
 
+    /// Number of live sprites using the graphic.
     #[must_use]
-    pub fn find_by_script_type<T>(&mut self, search_root: Handle<Node>) -> Option<Handle<Node>>
-    where T: Script + HasTypeId {
-        self.find(search_root, |x| {
-            match &x.script_data {
-                Some(s_data) => s_data.type_id == <T as HasTypeId>::type_id(),
-                None => false
-            }
-        })
+    pub fn graphic_ref_count(&self, name: &str) -> u32 {
+        self.sprite_handler.graphics.ref_count(name)
     }
 
+    /// Number of times a graphic has been copied into VRAM. Sprites sharing a graphic share one upload.
     #[must_use]
-    pub fn find<P>(&mut self, search_root: Handle<Node>, mut predicate: P) -> Option<Handle<Node>>
-    where P: FnMut(&Node) -> bool, {
-        let mut cur_node_handle = self.object_pool.borrow(search_root).child_handle?;
-        loop {
-            let cur_node = self.object_pool.borrow(cur_node_handle);
-            if predicate(cur_node) {
-                return Some(cur_node_handle);
-            }
-            cur_node_handle = cur_node.sibling_handle?;
-        }
+    pub fn graphic_upload_count(&self) -> u32 {
+        self.sprite_handler.graphics.upload_count()
     }
 
-    pub fn destroy_node(&mut self, handle: Handle<Node>) {
-        self.to_destroy_stack.push(handle);
+    /// Switches the node's sprite to another graphic, which also changes its size to the new graphic's.
+    /// The old graphic is unloaded if no other sprites use it.
+    /// If the node has no sprite or the graphic couldn't be loaded, the sprite is left as it was.
+    pub fn set_sprite_graphic(&mut self, handle: Handle<Node>, graphic_asset: &str) -> Result<(), Error> {
+        let node = self.object_pool.try_borrow(handle).ok_or(Error::InvalidHandle)?;
+        let NodeExtensionHandle::Sprite(sprite) = node.node_extension else { return Err(Error::NotASprite); };
+        let sprite = self.node_ext_pools.sprite_pool.borrow_mut(sprite);
+        self.sprite_handler.set_graphic(sprite, graphic_asset, &self.game_data)
+            .ok_or_else(|| Error::UnknownGraphic(String::from(graphic_asset)))??;
+        Ok(())
     }
 
-    pub(crate) fn run_pending_script_starts(&mut self) {
-        while let Some(handle) = self.to_start_stack.pop() {
-            let mut context = ScriptContext {
-                hierarchy: self,
-                handle,
-            };
-            // this could return None if an object was immediately destroyed after creating it
-            let mut script_data = if let Some(item) = context.hierarchy.try_borrow_mut(handle) {
-                if let Some(script_data) = item.script_data.take() {
-                    script_data
-                } else {
-                    continue; // stop early - item has no Script
-                }
-            } else {
-                continue; // stop early - invalid handle on start stack (should panic here?)
-            };
-            script_data.script.start(&mut context);
+    /// Loads the graphic and keeps it loaded even when no sprites use it, e.g. for UI that comes and goes.
+    pub fn pin_graphic(&mut self, name: &str) -> Result<(), Error> {
+        self.sprite_handler.pin_graphic(name, &self.game_data)
+            .ok_or_else(|| Error::UnknownGraphic(String::from(name)))??;
+        Ok(())
+    }
 
-            // put script back
-            if let Some(item) = context.hierarchy.try_borrow_mut(handle) {
-                item.script_data = Some(script_data);
-            }
-        }
+    /// Lets the graphic be unloaded again once no sprites use it.
+    pub fn unpin_graphic(&mut self, name: &str) {
+        self.sprite_handler.graphics.unpin(name);
     }
 
-    pub(crate) fn run_script_update(&mut self) {
-        for i in 0..self.object_pool.vec_len() {
-            if let Some(handle) = self.handle_from_index(i) {
-                if self.to_start_stack.contains(&handle) {
-                    continue;
-                }
-                let mut context = ScriptContext {
-                    hierarchy: self,
-                    handle,
-                };
-                // this could return None if an object was immediately destroyed after creating it
-                let mut script_data = if let Some(item) = context.hierarchy.try_borrow_mut(handle) {
-                    // return early - node is disabled
-                    if !item.global_enabled { continue; }
-                    if let Some(script_data) = item.script_data.take() {
-                        script_data
-                    } else {
-                        continue; // return early - item has no Script
-                    }
-                } else {
-                    continue; // return early - invalid handle (should panic here?)
-                };
-                script_data.script.update(&mut context);
+    fn clone_node_recursive(&mut self, handle: Handle<Node>, parent: Handle<Node>) -> Handle<Node> {
+        let node = self.object_pool.borrow(handle);
+        let (node_extension, first_child) = (node.node_extension, self.first_child(handle));
+        let new_node = Node {
+            parent_index: Some(parent.raw()),
+            scripts: node.scripts.iter().filter_map(|s| Self::create_script_data(self.scripts, s.type_id)).collect(),
+            enabled: node.enabled,
+            user_data: node.user_data,
+            script_enabled: node.script_enabled,
+            lifetime: node.lifetime,
+            offscreen_destroy_margin: node.offscreen_destroy_margin,
+            health: node.health,
+            follow: node.follow,
+            spawn_animation: node.spawn_animation.map(|a| SpawnAnimation::new(a.effect, a.frames)),
+            body: node.body,
+            parallax: node.parallax,
+            screen: node.screen,
+            ..Node::new(node.name, node.transform, NodeExtensionHandle::None)
+        };
+        let new_handle = self.object_pool.add(new_node);
+        self.object_pool.borrow_mut(new_handle).node_extension =
+            self.node_ext_pools.clone_extension(new_handle, node_extension);
+        self.load_sprite_graphic(new_handle);
+        self.to_start_queue.push_back(new_handle);
 
-                // put script back
-                if let Some(item) = context.hierarchy.try_borrow_mut(handle) {
-                    item.script_data = Some(script_data);
-                }
+        // Clone the children, keeping them in the same order
+        let mut prev_new_child: Option<Handle<Node>> = None;
+        let mut cur_child = first_child;
+        while let Some(child) = cur_child {
+            let new_child = self.clone_node_recursive(child, new_handle);
+            match prev_new_child {
+                Some(prev) => self.object_pool.borrow_mut(prev).sibling_index = Some(new_child.raw()),
+                None => self.object_pool.borrow_mut(new_handle).child_index = Some(new_child.raw()),
             }
+            prev_new_child = Some(new_child);
+            cur_child = self.next_sibling(child);
         }
+        new_handle
     }
 
-    // Also updates the global "enabled" state.
-    pub(crate) fn update_global_positions(&mut self) {
-        let root = self.borrow(self.root);
-        self.update_global_position_recursive(self.root, root.transform, root.enabled);
+    /// Converts a live node and its children back into a graph, the reverse of spawn_object.
+    /// The node is saved as the graph's root, so its parent isn't included.
+    /// Scripts are saved by type only, and a script that is currently running is left out.
+    /// Graphs hold one script per node, so only a node's first script is saved.
+    #[must_use]
+    pub fn to_saved_graph(&self, root: Handle<Node>) -> SavedNodeGraph {
+        let mut nodes = Vec::new();
+        self.save_node_recursive(root, None, &mut nodes);
+        SavedNodeGraph { nodes, backdrop_colour: None }
     }
 
-    fn update_global_position_recursive(&mut self, handle: Handle<Node>, transform: Transform, enabled: bool) {
-        let node = self.borrow_mut(handle);
-        let new_enabled = node.enabled && enabled;
-        let new_transform = Transform {
-            x: node.transform.x + transform.x,
-            y: node.transform.y + transform.y,
-        };
-        node.global_enabled = new_enabled;
-        node.global_transform = new_transform;
-        // Update child nodes recursively
-        if let Some(mut cur_child_handle) = node.child_handle {
-            loop {
-                self.update_global_position_recursive(cur_child_handle, new_transform, new_enabled);
-                cur_child_handle = match self.borrow(cur_child_handle).sibling_handle {
-                    Some(x) => x,
-                    None => break,
-                };
+    fn save_node_recursive(&self, handle: Handle<Node>, parent_index: Option<u32>, nodes: &mut Vec<SavedNode>) -> u32 {
+        let node = self.object_pool.borrow(handle);
+        let index = nodes.len() as u32;
+        nodes.push(SavedNode {
+            child_index: None,
+            parent_index,
+            sibling_index: None,
+            name: String::from(self.names.resolve(node.name)),
+            transform: SavedTransform { x: node.transform.x, y: node.transform.y },
+            node_extension: self.node_ext_pools.to_saved(node.node_extension),
+            script_type_id: node.scripts.first().map(|s| s.type_id),
+            enabled: node.enabled,
+            user_data: node.user_data,
+        });
+
+        // Save the children, and wire up the indices as we go
+        let mut prev_child_index: Option<u32> = None;
+        let mut cur_child = self.first_child(handle);
+        while let Some(child) = cur_child {
+            let child_index = self.save_node_recursive(child, Some(index), nodes);
+            // Only the root can be at index 0, so this is never None
+            let child_index_nz = NonZeroU32::new(child_index);
+            match prev_child_index {
+                Some(prev) => nodes[prev as usize].sibling_index = child_index_nz,
+                None => nodes[index as usize].child_index = child_index_nz,
             }
+            prev_child_index = Some(child_index);
+            cur_child = self.next_sibling(child);
         }
+        index
     }
 
-    pub(crate) fn run_extension_init(&mut self) {
-        self.sprite_handler.sprite_init(&self.game_data);
+    /// Saves everything under the root for `restore` to go back to: the nodes, their scripts' state from
+    /// `Script::save_state`, their bodies, follow constraints, spawn animations and sprite and camera settings.
+    /// Timers, tweens, pending destroys and scene changes aren't saved, and neither is the random number generator.
+    #[must_use]
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut graphs = Vec::new();
+        let mut handles = Vec::new();
+        let mut scene_root = None;
+        let mut cur_child = self.first_child(self.root);
+        while let Some(child) = cur_child {
+            if Some(child) == self.scene_root {
+                scene_root = Some(graphs.len() as u32);
+            }
+            graphs.push(self.to_saved_graph(child));
+            // Same order as the graph's nodes
+            handles.extend(self.subtree_handles(child));
+            cur_child = self.next_sibling(child);
+        }
+        // Handles change when restored, so nodes refer to each other by where they are in the snapshot
+        let indices: crate::HashMap<Handle<Node>, u32> = handles.iter().enumerate().map(|(i, &h)| (h, i as u32)).collect();
+        let node_states = handles.iter().map(|&handle| self.save_node_state(handle, &indices)).collect();
+        sandstone_common::serialize(&SavedSnapshot {
+            graphs,
+            node_states,
+            frame_count: self.frame_count,
+            current_scene: self.current_scene.clone(),
+            scene_root,
+            paused: self.paused,
+            sprite_alpha: self.sprite_handler.blend_alpha,
+            sprite_mosaic: self.sprite_handler.mosaic_size,
+        })
     }
 
-    pub(crate) fn run_extension_update(&mut self) {
-        rect_collider::check_collisions(self);
-        let cameras = self.camera_handler.get_active_cameras(self);
-        self.sprite_handler.sprite_update(self, cameras);
+    // References to nodes outside of the snapshot are left out
+    fn save_node_state(&self, handle: Handle<Node>, indices: &crate::HashMap<Handle<Node>, u32>) -> SavedNodeState {
+        let node = self.object_pool.borrow(handle);
+        let node_ref = |h: Handle<Node>| indices.get(&h).copied();
+        let pair = |v: Vec2| (v.x, v.y);
+        SavedNodeState {
+            scripts: node.scripts.iter().map(|s| SavedScriptState {
+                type_id: s.type_id,
+                started: s.started,
+                state: s.script.save_state(),
+            }).collect(),
+            started: node.started,
+            script_enabled: node.script_enabled,
+            lifetime: node.lifetime,
+            offscreen_destroy_margin: node.offscreen_destroy_margin,
+            health: node.health.map(|h| (h.current, h.max, h.destroy_on_death)),
+            screen: node.screen,
+            on_screen: node.on_screen,
+            parallax: node.parallax,
+            follow: node.follow.and_then(|f| Some((node_ref(f.target)?, pair(f.offset)))),
+            body: node.body.map(|b| SavedBody {
+                velocity: pair(b.velocity),
+                gravity: b.gravity,
+                contacts: b.contacts.map(|c| c.and_then(|c| Some((node_ref(c.node)?, pair(c.normal))))),
+            }),
+            spawn_animation: node.spawn_animation.map(|a| SavedSpawnAnimation {
+                effect: a.effect,
+                frames: a.frames,
+                playing: a.playing,
+            }),
+            sprite: if let NodeExtensionHandle::Sprite(h) = node.node_extension {
+                let sprite = self.node_ext_pools.sprite_pool.borrow(h);
+                Some(SavedSpriteState {
+                    palette_override: sprite.palette_override,
+                    blend_mode: sprite.blend_mode,
+                    mosaic: sprite.mosaic,
+                    flash: sprite.flash,
+                })
+            } else {
+                None
+            },
+            camera: if let NodeExtensionHandle::Camera(h) = node.node_extension {
+                let camera = self.node_ext_pools.camera_pool.borrow(h);
+                Some(SavedCameraState {
+                    follow: camera.follow.and_then(|f| Some(SavedCameraFollow {
+                        target: node_ref(f.target)?,
+                        screen_pos: pair(f.screen_pos),
+                        stiffness: f.stiffness,
+                        dead_zone: f.dead_zone.map(pair),
+                    })),
+                    shake: camera.shake,
+                    shake_offset: pair(camera.shake_offset),
+                })
+            } else {
+                None
+            },
+        }
     }
 
-    pub(crate) fn process_pending_destroys(&mut self) {
-        // unlink parent and sibling
-        while let Some(root_handle) = self.to_destroy_stack.pop() {
-            self.unlink_node(root_handle);
-            // Recursively delete children of node
-            self.process_destroy(root_handle);
+    /// Replaces everything under the root with a snapshot from `snapshot`.
+    /// Every node is recreated, so all handles to nodes from before the restore are invalid afterwards,
+    /// including ones kept in scripts' saved state - find the nodes again instead.
+    /// The replaced nodes' scripts don't get `on_destroy`, and pending timers, tweens, destroys and scene changes are dropped.
+    /// Collider intersect lists are empty until the next collision check.
+    /// Restored nodes don't count towards instance limits.
+    /// If the bytes aren't a valid snapshot, an error is returned and nothing is changed.
+    pub fn restore(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        let mut snapshot: SavedSnapshot = sandstone_common::try_deserialize(bytes)?;
+        // Check everything before the current nodes are torn down
+        for (i, graph) in snapshot.graphs.iter().enumerate() {
+            graph.validate().map_err(|e| Error::MalformedGraph(alloc::format!("snapshot graph {i}"), e))?;
+        }
+        let node_count: usize = snapshot.graphs.iter().map(|g| g.nodes.len()).sum();
+        if snapshot.node_states.len() != node_count
+            || snapshot.node_states.iter().flat_map(|s| s.node_refs()).any(|r| r as usize >= node_count) {
+            return Err(Error::MalformedSnapshot);
         }
-    }
 
-    fn process_destroy(&mut self, handle: Handle<Node>) {
-        // todo: by taking and not putting back, these objects don't get on the free stack
-        let Some((_t, node)) = self.object_pool.try_take(handle) else {
-            panic!("Tried to destroy node with invalid handle");
-        };
-        self.node_ext_pools.destroy_extension(node.node_extension);
-        let mut handle = match node.child_handle {
-            Some(h) => h,
-            None => return,
-        };
-        loop {
-            let sibling = self.borrow(handle).sibling_handle;
-            self.process_destroy(handle);
-            handle = match sibling {
-                Some(x) => x,
-                None => break,
-            };
+        while let Some(child) = self.first_child(self.root) {
+            self.unlink_node(child);
+            self.process_destroy(child, None);
         }
-    }
+        self.to_destroy_stack.clear();
+        self.timers.clear();
+        self.tweens.clear();
+        self.spawn_animations.clear();
+        self.enable_transitions.clear();
+        self.pending_deaths.clear();
+        self.pending_spawn_info.clear();
+        self.pending_scene = None;
 
-    fn unlink_node(&mut self, handle: Handle<Node>) {
-        let node = self.object_pool.borrow(handle);
-        let sibling_handle = node.sibling_handle;
-        let parent_handle =
-            node.parent_handle.unwrap_or_else(|| panic!("Tried to unlink root node"));
-        let parent = self.object_pool.borrow_mut(parent_handle);
-        if parent.child_handle.unwrap() == handle {
-            parent.child_handle = sibling_handle;
-        } else {
-            self.loop_over_children(parent_handle, |node, _| {
-                if node.sibling_handle == Some(handle) {
-                    node.sibling_handle = sibling_handle;
-                }
-            });
+        // The scripts are made from the node states instead, along with their saved state
+        for node in snapshot.graphs.iter_mut().flat_map(|g| g.nodes.iter_mut()) {
+            node.script_type_id = None;
+        }
+        // New children go first, so add them backwards to keep the order
+        let mut new_graphs: Vec<(Handle<Node>, Vec<Handle<Node>>)> = snapshot.graphs.iter().rev()
+            .map(|graph| self.instantiate_graph_nodes(graph, self.root, None))
+            .collect();
+        new_graphs.reverse();
+        self.to_start_queue.clear();
+        self.scene_root = snapshot.scene_root.and_then(|i| new_graphs.get(i as usize)).map(|&(root, _)| root);
+        let handles: Vec<Handle<Node>> = new_graphs.into_iter().flat_map(|(_, handles)| handles).collect();
+        for (&handle, state) in handles.iter().zip(snapshot.node_states) {
+            self.restore_node_state(handle, state, &handles);
         }
+        self.frame_count = snapshot.frame_count;
+        self.current_scene = snapshot.current_scene;
+        self.paused = snapshot.paused;
+        self.set_sprite_alpha(snapshot.sprite_alpha);
+        self.set_sprite_mosaic(snapshot.sprite_mosaic.0, snapshot.sprite_mosaic.1);
+        self.update_global_positions();
+        self.enable_transitions.clear();
+        Ok(())
     }
 
-    fn loop_over_children<F: FnMut(&mut Node, Handle<Node>)>(&mut self, handle: Handle<Node>, mut op: F) {
-        if let Some(mut cur_child_handle) = self.object_pool.borrow(handle).child_handle {
-            loop {
-                let cur_child = self.object_pool.borrow_mut(cur_child_handle);
-                op(cur_child, cur_child_handle);
-                cur_child_handle = match cur_child.sibling_handle {
-                    Some(x) => x,
-                    None => break,
+    // `handles` are the restored nodes, in the same order as the snapshot's node states
+    fn restore_node_state(&mut self, handle: Handle<Node>, state: SavedNodeState, handles: &[Handle<Node>]) {
+        let vec2 = |(x, y)| Vec2::new(x, y);
+        let node = self.object_pool.borrow_mut(handle);
+        node.started = state.started;
+        node.script_enabled = state.script_enabled;
+        node.lifetime = state.lifetime;
+        node.offscreen_destroy_margin = state.offscreen_destroy_margin;
+        node.health = state.health.map(|(current, max, destroy_on_death)| Health { current, max, destroy_on_death });
+        node.screen = state.screen;
+        node.on_screen = state.on_screen;
+        node.parallax = state.parallax;
+        node.follow = state.follow.map(|(target, offset)| Follow { target: handles[target as usize], offset: vec2(offset) });
+        node.body = state.body.map(|b| Body {
+            velocity: vec2(b.velocity),
+            gravity: b.gravity,
+            contacts: b.contacts.map(|c| c.map(|(n, normal)| Contact { node: handles[n as usize], normal: vec2(normal) })),
+        });
+        node.spawn_animation = state.spawn_animation.map(|a| SpawnAnimation {
+            effect: a.effect,
+            frames: a.frames,
+            playing: a.playing,
+        });
+        // Each script is made from its own type ID, so its saved state always goes to the right script
+        for saved in state.scripts {
+            if let Some(mut script_data) = Self::create_script_data(self.scripts, saved.type_id) {
+                script_data.started = saved.started;
+                if let Some(script_state) = &saved.state {
+                    script_data.script.load_state(script_state);
                 }
+                node.scripts.push(script_data);
             }
         }
+        if !node.started || node.scripts.iter().any(|s| !s.started) {
+            self.to_start_queue.push_back(handle);
+        }
+        if node.spawn_animation.is_some_and(|a| a.is_playing()) {
+            self.spawn_animations.push(handle);
+        }
+
+        match (node.node_extension, state.sprite, state.camera) {
+            (NodeExtensionHandle::Sprite(h), Some(saved), _) => {
+                let sprite = self.node_ext_pools.sprite_pool.borrow_mut(h);
+                sprite.palette_override = saved.palette_override;
+                sprite.blend_mode = saved.blend_mode;
+                sprite.mosaic = saved.mosaic;
+                sprite.flash = saved.flash;
+            },
+            (NodeExtensionHandle::Camera(h), _, Some(saved)) => {
+                let camera = self.node_ext_pools.camera_pool.borrow_mut(h);
+                camera.follow = saved.follow.map(|f| CameraFollow {
+                    target: handles[f.target as usize],
+                    screen_pos: vec2(f.screen_pos),
+                    stiffness: f.stiffness,
+                    dead_zone: f.dead_zone.map(vec2),
+                });
+                camera.shake = saved.shake;
+                camera.shake_offset = vec2(saved.shake_offset);
+            },
+            _ => {},
+        }
     }
-}
 
-pub trait HasTypeId {
-    fn type_id() -> NonZeroU32;
+    fn link_new_child(&mut self, parent: Handle<Node>, child: Handle<Node>) {
+        let parent_obj = self.object_pool.borrow_mut(parent);
+        self.object_pool.borrow_mut(child).sibling_index = parent_obj.child_index.replace(child.raw());
+    }
+
+    /// Name of the registered script with this type ID, or None if there isn't one.
+    #[must_use]
+    pub fn script_name(&self, type_id: NonZeroU32) -> Option<&'static str> {
+        (self.scripts.name)(type_id)
+    }
+
+    /// Like `borrow`, but logs an error and returns None for an invalid handle instead of panicking.
+    /// For code that should keep the game running through a bad frame.
+    #[track_caller]
+    #[must_use]
+    pub fn borrow_or_log(&self, handle: Handle<Node>) -> Option<&Node> {
+        let node = self.object_pool.try_borrow(handle);
+        if node.is_none() {
+            crate::log::error!("Tried to borrow an invalid node handle {handle:?} at {}", core::panic::Location::caller());
+        }
+        node
+    }
+
+    /// Like `borrow_mut`, but logs an error and returns None for an invalid handle instead of panicking.
+    #[track_caller]
+    #[must_use]
+    pub fn borrow_mut_or_log(&mut self, handle: Handle<Node>) -> Option<&mut Node> {
+        let node = self.object_pool.try_borrow_mut(handle);
+        if node.is_none() {
+            crate::log::error!("Tried to mutably borrow an invalid node handle {handle:?} at {}", core::panic::Location::caller());
+        }
+        node
+    }
+
+    /// Like `Node::cast_script`, but logs an error and returns None instead of panicking
+    /// if the handle is invalid, the node has no script, or the script is a different type.
+    #[track_caller]
+    #[must_use]
+    pub fn cast_script_or_log<T>(&self, handle: Handle<Node>) -> Option<&T>
+    where T: Script + HasTypeId {
+        let node = self.borrow_or_log(handle)?;
+        if !self.check_script_type::<T>(handle) {
+            return None;
+        }
+        Some(node.cast_script())
+    }
+
+    /// Like `Node::cast_script_mut`, but logs an error and returns None instead of panicking.
+    #[track_caller]
+    #[must_use]
+    pub fn cast_script_mut_or_log<T>(&mut self, handle: Handle<Node>) -> Option<&mut T>
+    where T: Script + HasTypeId {
+        self.borrow_or_log(handle)?;
+        if !self.check_script_type::<T>(handle) {
+            return None;
+        }
+        Some(self.object_pool.borrow_mut(handle).cast_script_mut())
+    }
+
+    // Logs why the node's script can't be cast to T, if it can't
+    #[track_caller]
+    fn check_script_type<T>(&self, handle: Handle<Node>) -> bool
+    where T: Script + HasTypeId {
+        let expected = core::any::type_name::<T>();
+        let scripts = &self.object_pool.borrow(handle).scripts;
+        match scripts.first() {
+            Some(_) if scripts.get(T::type_id()).is_some() => true,
+            Some(s_data) => {
+                crate::log::error!("Tried to cast the {} script on {} to {expected} at {}",
+                    self.script_name(s_data.type_id).unwrap_or("Unknown"), self.node_path(handle), core::panic::Location::caller());
+                false
+            }
+            None => {
+                crate::log::error!("Tried to cast the script on {} to {expected}, but it has no script, at {}",
+                    self.node_path(handle), core::panic::Location::caller());
+                false
+            }
+        }
+    }
+
+    /// The names of the node and its ancestors, like "Root/GameScene/Player/Bullet". Useful for debug prints.
+    #[must_use]
+    pub fn node_path(&self, handle: Handle<Node>) -> String {
+        let mut names: Vec<&str> = Vec::new();
+        let mut cur = Some(handle);
+        while let Some(h) = cur {
+            let node = self.object_pool.borrow(h);
+            names.push(match self.names.resolve(node.name) {
+                _ if h == self.root => "Root",
+                "" => "<unnamed>",
+                name => name,
+            });
+            cur = node.parent_index.map(|i| self.object_pool.handle_from_raw(i));
+        }
+        names.reverse();
+        names.join("/")
+    }
+
+    pub fn pretty_print_hierarchy_structure(&self) {
+        for i in 0..self.object_pool.vec_len() {
+            if let Some(handle) = self.object_pool.handle_from_index_checked(i) {
+                let node = self.object_pool.borrow(handle);
+                crate::log::debug!("{:?}", handle);
+                crate::log::debug!("{}", self.names.resolve(node.name));
+                for script_data in node.scripts.iter() {
+                    let name = self.script_name(script_data.type_id).unwrap_or("Unknown");
+                    crate::log::debug!("Script: {} ({})", name, script_data.type_id);
+                }
+                crate::log::debug!("Child: {:?}", node.child_index);
+                crate::log::debug!("Sibling: {:?}", node.sibling_index);
+                crate::log::debug!("Parent: {:?}", node.parent_index);
+            }
+        }
+    }
+
+    // todo: recursive search for other search roots?
+
+    #[must_use]
+    pub fn find_by_name(&mut self, search_root: Handle<Node>, name: &str) -> Option<Handle<Node>> {
+        // A name that was never interned can't belong to any node
+        let name = self.names.get(name)?;
+        self.find(search_root, |x| x.name == name)
+    }
+
+    #[must_use]
+    pub fn node_name(&self, handle: Handle<Node>) -> &str {
+        self.names.resolve(self.object_pool.borrow(handle).name)
+    }
+
+    pub fn set_node_name(&mut self, handle: Handle<Node>, name: &str) {
+        self.object_pool.borrow_mut(handle).name = self.names.intern(name);
+    }
+
+    /// The id nodes with this name have, for comparing against `Node::name` without string compares.
+    /// None if no node has ever had the name.
+    #[must_use]
+    pub fn name_id(&self, name: &str) -> Option<NameId> {
+        self.names.get(name)
+    }
+
+    /// Finds the node with the given name under `parent` (searching like `find`), or spawns the graph there if there isn't one yet.
+    /// The spawned node is given the name, so the next call finds it - useful for singleton manager nodes.
+    pub fn find_or_spawn_by_name(&mut self, name: &str, graph_name: &str, parent: Handle<Node>) -> Handle<Node> {
+        if let Some(handle) = self.find_by_name(parent, name) {
+            return handle;
+        }
+        let handle = self.spawn_object(graph_name, parent);
+        self.set_node_name(handle, name);
+        handle
+    }
+
+    /// Checks that the node has an extension of type `E`, giving a handle that can fetch it without checking again.
+    #[must_use]
+    pub fn typed_handle<E: ExtensionType>(&self, node: Handle<Node>) -> Option<TypedHandle<E>> {
+        let extension = E::from_extension_handle(self.object_pool.borrow(node).node_extension)?;
+        Some(TypedHandle { node, extension })
+    }
+
+    /// Like `find_by_name`, but only finds a node with an extension of type `E`.
+    #[must_use]
+    pub fn find_by_name_typed<E: ExtensionType>(&mut self, search_root: Handle<Node>, name: &str) -> Option<TypedHandle<E>> {
+        let name = self.names.get(name)?;
+        let node = self.find(search_root, |x| x.name == name && E::from_extension_handle(x.node_extension).is_some())?;
+        self.typed_handle(node)
+    }
+
+    #[must_use]
+    pub fn get_extension<E>(&self, handle: TypedHandle<E>) -> &E
+    where Self: HierarchyPoolTrait<E> {
+        self.borrow(handle.extension)
+    }
+
+    #[must_use]
+    pub fn get_extension_mut<E>(&mut self, handle: TypedHandle<E>) -> &mut E
+    where Self: HierarchyPoolTrait<E> {
+        self.borrow_mut(handle.extension)
+    }
+
+    pub fn find_by_script_type<T>(&mut self, search_root: Handle<Node>) -> Option<Handle<Node>>
+    where T: Script + HasTypeId {
+        self.find(search_root, |x| {
+            x.scripts.get(<T as HasTypeId>::type_id()).is_some()
+        })
+    }
+
+    /// Finds a direct child of `search_root` that matches the predicate.
+    /// Searching from the hierarchy root checks every node instead, however deep,
+    /// in pool order rather than tree order.
+    #[must_use]
+    pub fn find<P>(&mut self, search_root: Handle<Node>, mut predicate: P) -> Option<Handle<Node>>
+    where P: FnMut(&Node) -> bool, {
+        // Fast path - go through the pool in order instead of following the tree
+        if search_root == self.root {
+            return (0..self.object_pool.vec_len())
+                .filter_map(|i| self.object_pool.handle_from_index_checked(i))
+                .find(|&handle| handle != self.root && predicate(self.object_pool.borrow(handle)));
+        }
+        let mut cur_node_index = self.object_pool.borrow(search_root).child_index?;
+        loop {
+            let cur_node = self.object_pool.borrow_raw(cur_node_index);
+            if predicate(cur_node) {
+                return Some(self.object_pool.handle_from_raw(cur_node_index));
+            }
+            cur_node_index = cur_node.sibling_index?;
+        }
+    }
+
+    #[must_use]
+    pub fn first_child(&self, parent: Handle<Node>) -> Option<Handle<Node>> {
+        self.object_pool.borrow(parent).child_index.map(|i| self.object_pool.handle_from_raw(i))
+    }
+
+    /// None for the root node.
+    #[must_use]
+    pub fn parent(&self, handle: Handle<Node>) -> Option<Handle<Node>> {
+        self.object_pool.borrow(handle).parent_index.map(|i| self.object_pool.handle_from_raw(i))
+    }
+
+    #[must_use]
+    pub fn last_child(&self, parent: Handle<Node>) -> Option<Handle<Node>> {
+        let mut cur = self.first_child(parent)?;
+        while let Some(next) = self.next_sibling(cur) {
+            cur = next;
+        }
+        Some(cur)
+    }
+
+    #[must_use]
+    pub fn next_sibling(&self, handle: Handle<Node>) -> Option<Handle<Node>> {
+        self.object_pool.borrow(handle).sibling_index.map(|i| self.object_pool.handle_from_raw(i))
+    }
+
+    /// Siblings only link forwards, so this searches from the parent's first child.
+    #[must_use]
+    pub fn previous_sibling(&self, handle: Handle<Node>) -> Option<Handle<Node>> {
+        let parent = self.parent(handle)?;
+        let mut cur = self.first_child(parent)?;
+        if cur == handle {
+            return None;
+        }
+        while let Some(next) = self.next_sibling(cur) {
+            if next == handle {
+                return Some(cur);
+            }
+            cur = next;
+        }
+        None
+    }
+
+    /// True if `child` is directly under `parent`.
+    #[must_use]
+    pub fn has_child(&self, parent: Handle<Node>, child: Handle<Node>) -> bool {
+        self.object_pool.borrow(child).parent_index == Some(parent.raw())
+    }
+
+    /// True if `descendant` is somewhere below `ancestor` in the tree. A node isn't its own ancestor.
+    #[must_use]
+    pub fn is_ancestor_of(&self, ancestor: Handle<Node>, descendant: Handle<Node>) -> bool {
+        let ancestor = ancestor.raw();
+        let mut cur = self.object_pool.borrow(descendant).parent_index;
+        while let Some(i) = cur {
+            if i == ancestor {
+                return true;
+            }
+            cur = self.object_pool.borrow_raw(i).parent_index;
+        }
+        false
+    }
+
+    #[must_use]
+    pub fn is_descendant_of(&self, descendant: Handle<Node>, ancestor: Handle<Node>) -> bool {
+        self.is_ancestor_of(ancestor, descendant)
+    }
+
+    /// True if the node and all of its ancestors are enabled, so it's actually being updated and drawn.
+    /// Unlike the cached state the frame passes use, this is up to date straight after changing `enabled`.
+    #[must_use]
+    pub fn is_active_in_hierarchy(&self, handle: Handle<Node>) -> bool {
+        let mut node = self.object_pool.borrow(handle);
+        loop {
+            if !node.enabled {
+                return false;
+            }
+            let Some(parent) = node.parent_index else { return true; };
+            node = self.object_pool.borrow_raw(parent);
+        }
+    }
+
+    /// Moves the node so it comes just before `target` in their parent's children.
+    /// Both nodes must have the same parent.
+    pub fn move_child_before(&mut self, node: Handle<Node>, target: Handle<Node>) {
+        let parent = self.sibling_parent(node, target);
+        if node == target {
+            return;
+        }
+        self.unlink_node(node);
+        match self.previous_sibling(target) {
+            Some(prev) => self.object_pool.borrow_mut(prev).sibling_index = Some(node.raw()),
+            None => self.object_pool.borrow_mut(parent).child_index = Some(node.raw()),
+        }
+        self.object_pool.borrow_mut(node).sibling_index = Some(target.raw());
+    }
+
+    /// Moves the node so it comes just after `target` in their parent's children.
+    /// Both nodes must have the same parent.
+    pub fn move_child_after(&mut self, node: Handle<Node>, target: Handle<Node>) {
+        self.sibling_parent(node, target);
+        if node == target {
+            return;
+        }
+        self.unlink_node(node);
+        let next = self.object_pool.borrow_mut(target).sibling_index.replace(node.raw());
+        self.object_pool.borrow_mut(node).sibling_index = next;
+    }
+
+    fn sibling_parent(&self, node: Handle<Node>, target: Handle<Node>) -> Handle<Node> {
+        let parent = self.parent(node);
+        assert!(parent.is_some() && parent == self.parent(target),
+            "Tried to reorder nodes that don't have the same parent");
+        parent.unwrap()
+    }
+
+    /// Stops or resumes updates for the node's script, while it keeps being drawn and colliding.
+    pub fn set_script_enabled(&mut self, handle: Handle<Node>, enabled: bool) {
+        self.borrow_mut(handle).script_enabled = enabled;
+    }
+
+    /// Destroys the node and its children at the end of the frame, after all the updates have run,
+    /// so it's safe to use on any node from inside a script. Destroying a node more than once is fine.
+    pub fn destroy_node(&mut self, handle: Handle<Node>) {
+        self.to_destroy_stack.push(handle);
+    }
+
+    /// Takes health away from the node, stopping at 0. Nodes without health aren't affected.
+    /// When it runs out, the node's script gets `on_death` later in the frame, and then the node is destroyed
+    /// unless `destroy_on_death` is off. Returns true if this was the hit that killed it.
+    pub fn apply_damage(&mut self, handle: Handle<Node>, amount: u32) -> bool {
+        let Some(health) = &mut self.object_pool.borrow_mut(handle).health else { return false; };
+        if health.is_dead() {
+            return false;
+        }
+        health.current = health.current.saturating_sub(amount);
+        if health.is_dead() {
+            self.pending_deaths.push(handle);
+        }
+        health.is_dead()
+    }
+
+    // Deaths wait until here so that on_death still runs when a script damages its own node.
+    pub(crate) fn run_deaths(&mut self) {
+        let deaths = core::mem::take(&mut self.pending_deaths);
+        for &handle in deaths.iter() {
+            self.run_script_callback(handle, |script, context| script.on_death(context));
+            if self.try_borrow(handle).and_then(|n| n.health).is_some_and(|h| h.destroy_on_death) {
+                self.destroy_node(handle);
+            }
+        }
+    }
+
+    /// Destroys the node and its children right away. Prefer destroy_node inside scripts,
+    /// as other scripts this frame might still be expecting the node to exist.
+    /// Like destroy_node, destroying a node that's already gone does nothing.
+    pub fn destroy_node_immediate(&mut self, handle: Handle<Node>) {
+        if self.try_borrow(handle).is_none() {
+            return;
+        }
+        for node in self.subtree_handles(handle) {
+            self.run_script_callback(node, |script, context| script.on_destroy(context));
+        }
+        // An on_destroy might have already destroyed the node, by destroying it or a parent immediately
+        if self.try_borrow(handle).is_none() {
+            return;
+        }
+        self.unlink_node(handle);
+        self.process_destroy(handle, None);
+    }
+
+    /// Destroys every child of the node (and their children), but keeps the node itself.
+    /// Like destroy_node, this happens at the end of the frame.
+    pub fn destroy_children(&mut self, parent: Handle<Node>) {
+        let mut cur_child = self.first_child(parent);
+        while let Some(child) = cur_child {
+            self.to_destroy_stack.push(child);
+            cur_child = self.next_sibling(child);
+        }
+    }
+
+    // A node's start always runs before its first update: update skips nodes that haven't
+    // been through here yet, and this is run after the update pass so nodes spawned during
+    // frame N are started at the end of frame N and updated from frame N + 1 onwards.
+    // Nodes start in the order they were queued. Nodes spawned by a start call join the back
+    // of the queue, so they start in the same loop, after everything that was already waiting.
+    pub(crate) fn run_pending_script_starts(&mut self) {
+        while let Some(handle) = self.to_start_queue.pop_front() {
+            self.start_info = self.pending_spawn_info.iter()
+                .position(|(h, _)| *h == handle)
+                .map(|i| self.pending_spawn_info.swap_remove(i));
+            let mut context = ScriptContext {
+                hierarchy: self,
+                handle,
+                rng: GlobalRng,
+            };
+            // this could return None if an object was immediately destroyed after creating it
+            let mut scripts = if let Some(item) = context.hierarchy.try_borrow_mut(handle) {
+                item.started = true;
+                if item.scripts.is_empty() {
+                    continue; // stop early - item has no Script
+                }
+                core::mem::take(&mut item.scripts)
+            } else {
+                continue; // stop early - invalid handle on start queue (should panic here?)
+            };
+            // Only scripts that haven't started, in case some were added after the node started
+            for script_data in scripts.iter_mut().filter(|s| !s.started) {
+                script_data.started = true;
+                script_data.script.start(&mut context);
+            }
+
+            // put scripts back
+            if let Some(item) = context.hierarchy.try_borrow_mut(handle) {
+                item.scripts.put_back(scripts);
+            }
+        }
+        self.start_info = None;
+    }
+
+    /// Runs the callback with a context for the given node, after the given number of frames.
+    /// The timer is cancelled if the node is destroyed before then.
+    pub fn add_timer(&mut self, node: Handle<Node>, frames: u32, callback: Box<dyn FnOnce(&mut ScriptContext)>) {
+        self.timers.push(Timer { node, frames_left: frames, callback });
+    }
+
+    // Timers tick at the start of the frame, so a timer added during frame N with a
+    // duration of D fires before the script updates of frame N + D.
+    pub(crate) fn run_timers(&mut self) {
+        if self.paused {
+            return;
+        }
+        for timer in self.timers.iter_mut() {
+            timer.frames_left = timer.frames_left.saturating_sub(1);
+        }
+        // Take the expired timers out first, so the callbacks are free to add new ones
+        let (expired, remaining) = core::mem::take(&mut self.timers)
+            .into_iter()
+            .partition::<Vec<Timer>, _>(|t| t.frames_left == 0);
+        self.timers = remaining;
+        for timer in expired {
+            if self.try_borrow(timer.node).is_none() {
+                continue;
+            }
+            let mut context = ScriptContext {
+                hierarchy: self,
+                handle: timer.node,
+                rng: GlobalRng,
+            };
+            (timer.callback)(&mut context);
+        }
+    }
+
+    /// Moves the node's local position to `target` over the given number of frames, landing exactly on it.
+    /// Replaces any tween the node already had. The callback runs once it arrives, unless the node is destroyed first.
+    pub fn tween_position(&mut self, node: Handle<Node>, target: Vec2, frames: u32, easing: Easing, on_complete: Option<NodeCallback>) {
+        self.tweens.retain(|t| t.node != node);
+        let from = self.object_pool.borrow(node).transform.position();
+        self.tweens.push(Tween { node, from, to: target, frame: 0, frames, easing, on_complete });
+    }
+
+    /// Stops the node's tween where it is, without running its callback.
+    pub fn cancel_tween(&mut self, node: Handle<Node>) {
+        self.tweens.retain(|t| t.node != node);
+    }
+
+    // Like timers, tweens step at the start of the frame and stop while paused
+    pub(crate) fn run_tweens(&mut self) {
+        if self.paused {
+            return;
+        }
+        self.tweens.retain(|t| self.object_pool.try_borrow(t.node).is_some());
+        for t in self.tweens.iter_mut() {
+            t.frame = t.frame.saturating_add(1);
+            let position = tween(t.from, t.to, t.frame, t.frames, t.easing);
+            self.object_pool.borrow_mut(t.node).transform.set_position(position);
+        }
+        let (finished, running) = core::mem::take(&mut self.tweens)
+            .into_iter()
+            .partition::<Vec<Tween>, _>(|t| t.frame >= t.frames);
+        self.tweens = running;
+        for t in finished {
+            if let Some(callback) = t.on_complete {
+                let mut context = ScriptContext {
+                    hierarchy: self,
+                    handle: t.node,
+                    rng: GlobalRng,
+                };
+                callback(&mut context);
+            }
+        }
+    }
+
+    // Nodes spawned during this pass are not updated until the next frame, whether they were
+    // pushed onto the end of the pool or into a freed slot, as their start hasn't run yet.
+    // Destroying is deferred until after the pass, so nodes never disappear mid-iteration.
+    pub(crate) fn run_script_update(&mut self) {
+        for i in 0..self.object_pool.vec_len() {
+            if let Some(handle) = self.handle_from_index(i) {
+                let mut context = ScriptContext {
+                    hierarchy: self,
+                    handle,
+                    rng: GlobalRng,
+                };
+                // this could return None if an object was immediately destroyed after creating it
+                let mut scripts = if let Some(item) = context.hierarchy.try_borrow_mut(handle) {
+                    // return early - node or script is disabled, or hasn't been started yet
+                    if !item.global_enabled || !item.script_enabled || !item.started { continue; }
+                    // Held back by the spawn animation
+                    if item.spawn_animation.is_some_and(|a| a.is_playing()) { continue; }
+                    if item.scripts.is_empty() {
+                        continue; // return early - item has no Script
+                    }
+                    core::mem::take(&mut item.scripts)
+                } else {
+                    continue; // return early - invalid handle (should panic here?)
+                };
+                for script_data in scripts.iter_mut().filter(|s| s.started) {
+                    if context.hierarchy.paused {
+                        script_data.script.paused_update(&mut context);
+                    } else {
+                        script_data.script.update(&mut context);
+                    }
+                }
+
+                // put scripts back
+                if let Some(item) = context.hierarchy.try_borrow_mut(handle) {
+                    item.scripts.put_back(scripts);
+                }
+            }
+        }
+        self.frame_count = self.frame_count.wrapping_add(1);
+    }
+
+    pub(crate) fn run_lifetimes(&mut self) {
+        if self.paused {
+            return;
+        }
+        for i in 0..self.object_pool.vec_len() {
+            if let Some(handle) = self.object_pool.handle_from_index_checked(i) {
+                let node = self.object_pool.borrow_mut(handle);
+                if let Some(lifetime) = &mut node.lifetime {
+                    *lifetime = lifetime.saturating_sub(1);
+                    if *lifetime == 0 {
+                        node.lifetime = None;
+                        self.destroy_node(handle);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Bounds of the node in world space, sized using its sprite or collider (including the collider's offset).
+    /// Nodes without either, or with a sprite whose graphic doesn't exist, are treated as a single point.
+    #[must_use]
+    pub fn world_bounds(&self, handle: Handle<Node>) -> Rect {
+        let node = self.object_pool.borrow(handle);
+        let (offset, size) = match node.node_extension {
+            NodeExtensionHandle::Sprite(s) => {
+                let sprite = self.node_ext_pools.sprite_pool.borrow(s);
+                // Sprites can be spawned with a missing graphic, which was already reported then
+                let (width, height) = self.game_data.graphics.get(&sprite.graphic_asset).map_or((0, 0), |g| g.size.to_dimensions());
+                (Vec2::ZERO, Vec2::new(I20F12::from_num(width), I20F12::from_num(height)))
+            },
+            NodeExtensionHandle::RectCollider(c) => {
+                let collider = self.node_ext_pools.rect_collider_pool.borrow(c);
+                (Vec2::new(collider.offset_x, collider.offset_y), Vec2::new(collider.width, collider.height))
+            },
+            _ => (Vec2::ZERO, Vec2::ZERO),
+        };
+        Rect::from_pos_size(node.global_transform.position() + offset, size)
+    }
+
+    /// Every enabled node with a sprite or collider whose world bounds overlap the rectangle.
+    #[must_use]
+    pub fn query_aabb(&self, rect: Rect) -> Vec<Handle<Node>> {
+        let mut results = Vec::new();
+        self.query_aabb_into(rect, &mut results);
+        results
+    }
+
+    /// Same as query_aabb, but adds the results to an existing Vec so it can be reused without allocating.
+    pub fn query_aabb_into(&self, rect: Rect, results: &mut Vec<Handle<Node>>) {
+        for i in 0..self.object_pool.vec_len() {
+            if let Some(handle) = self.object_pool.handle_from_index_checked(i) {
+                let node = self.object_pool.borrow(handle);
+                let has_bounds = matches!(node.node_extension, NodeExtensionHandle::Sprite(_) | NodeExtensionHandle::RectCollider(_));
+                if has_bounds && node.global_enabled && self.world_bounds(handle).intersects(&rect) {
+                    results.push(handle);
+                }
+            }
+        }
+    }
+
+    /// Every live collider, enabled or not. Colliders are kept in their own pool,
+    /// so this skips over the other nodes without having to look at them.
+    pub fn iter_colliders(&self) -> impl Iterator<Item = &rect_collider::RectColliderExtension> {
+        self.node_ext_pools.rect_collider_pool.iter()
+    }
+
+    /// Casts a ray from `origin` in the direction of `dir`, and finds the closest enabled collider it hits
+    /// within `max_dist` pixels. A ray starting inside a collider hits it at distance 0.
+    #[must_use]
+    pub fn raycast(&self, origin: Vec2, dir: Vec2, max_dist: I20F12) -> Option<RayHit> {
+        let dir = dir.normalize();
+        self.iter_colliders()
+            .filter(|col| self.object_pool.borrow(col.node_handle).global_enabled)
+            .filter_map(|col| {
+                self.world_bounds(col.node_handle).ray_intersection(origin, dir, max_dist)
+                    .map(|distance| RayHit { node: col.node_handle, distance })
+            })
+            .min_by_key(|hit| hit.distance)
+    }
+
+    /// Enabled colliders containing the world point, topmost first.
+    /// Deeper nodes in the hierarchy count as being on top, as children are usually drawn over their parents.
+    #[must_use]
+    pub fn colliders_at_point(&self, point: Vec2) -> Vec<Handle<Node>> {
+        let mut hits: Vec<(u32, Handle<Node>)> = self.iter_colliders()
+            .filter(|col| self.object_pool.borrow(col.node_handle).global_enabled)
+            .filter(|col| self.world_bounds(col.node_handle).contains_point(point))
+            .map(|col| (self.depth(col.node_handle), col.node_handle))
+            .collect();
+        hits.sort_by_key(|&(depth, _)| core::cmp::Reverse(depth));
+        hits.into_iter().map(|(_, handle)| handle).collect()
+    }
+
+    /// Converts a position on the sub screen (like a touchscreen position) to world space, using the active sub camera.
+    /// Returns None if there's no active sub camera, as nothing is being shown on that screen.
+    #[must_use]
+    pub fn sub_screen_to_world(&self, screen_pos: Vec2) -> Option<Vec2> {
+        let camera = self.camera_handler.get_active_cameras(self).sub?;
+        let camera_node = self.node_ext_pools.camera_pool.borrow(camera).node_handle;
+        Some(self.object_pool.borrow(camera_node).global_transform.position() + screen_pos)
+    }
+
+    /// Where the node is drawn on the screen it's on, after camera scrolling and parallax.
+    /// Nodes on both screens use the main camera. Returns None if that screen has no active camera.
+    #[must_use]
+    pub fn screen_position(&self, handle: Handle<Node>) -> Option<Vec2> {
+        let node = self.object_pool.borrow(handle);
+        let cameras = self.camera_handler.get_active_cameras(self);
+        let camera = if node.global_screen == Screen::Sub { cameras.sub } else { cameras.main }?;
+        let (x, y) = node.draw_position(self.node_ext_pools.camera_pool.borrow(camera).draw_position(self));
+        Some(Vec2::new(x, y))
+    }
+
+    // Number of parents between the node and the root.
+    fn depth(&self, handle: Handle<Node>) -> u32 {
+        let mut depth = 0;
+        let mut cur = self.object_pool.borrow(handle).parent_index;
+        while let Some(parent) = cur {
+            depth += 1;
+            cur = self.object_pool.borrow_raw(parent).parent_index;
+        }
+        depth
+    }
+
+    // The areas of the world that are visible through the active cameras.
+    pub(crate) fn screen_views(&self) -> impl Iterator<Item = Rect> + '_ {
+        let cameras = self.camera_handler.get_active_cameras(self);
+        let screen_size = Vec2::new(I20F12::from_num(SCREEN_WIDTH), I20F12::from_num(SCREEN_HEIGHT));
+        [cameras.main, cameras.sub].into_iter().flatten().map(move |cam| {
+            let cam = self.node_ext_pools.camera_pool.borrow(cam);
+            Rect::from_pos_size(self.object_pool.borrow(cam.node_handle).global_transform.position(), screen_size)
+        })
+    }
+
+    /// Whether any part of the node's world bounds (see `world_bounds`) is in view of an active camera.
+    #[must_use]
+    pub fn is_on_screen(&self, handle: Handle<Node>) -> bool {
+        let bounds = self.world_bounds(handle);
+        self.screen_views().any(|view| view.intersects(&bounds))
+    }
+
+    pub(crate) fn process_offscreen_destroys(&mut self) {
+        if self.paused {
+            return;
+        }
+        let views: Vec<Rect> = self.screen_views().collect();
+        // Nothing is being displayed, so there's no screen to be outside of
+        if views.is_empty() {
+            return;
+        }
+        for i in 0..self.object_pool.vec_len() {
+            if let Some(handle) = self.object_pool.handle_from_index_checked(i) {
+                let Some(margin) = self.object_pool.borrow(handle).offscreen_destroy_margin else { continue; };
+                let bounds = self.world_bounds(handle);
+                if !views.iter().any(|view| view.expand(margin).intersects(&bounds)) {
+                    self.destroy_node(handle);
+                }
+            }
+        }
+    }
+
+    // Fires on_enter_screen and on_exit_screen for scripts whose node's bounds have come into or gone out of view.
+    pub(crate) fn run_screen_events(&mut self) {
+        if self.paused {
+            return;
+        }
+        let views: Vec<Rect> = self.screen_views().collect();
+        if views.is_empty() {
+            return;
+        }
+        for i in 0..self.object_pool.vec_len() {
+            let Some(handle) = self.object_pool.handle_from_index_checked(i) else { continue; };
+            let node = self.object_pool.borrow(handle);
+            if node.scripts.is_empty() || !node.started || !node.global_enabled {
+                continue;
+            }
+            let bounds = self.world_bounds(handle);
+            let on_screen = views.iter().any(|view| view.intersects(&bounds));
+            let node = self.object_pool.borrow_mut(handle);
+            if node.on_screen != on_screen {
+                node.on_screen = on_screen;
+                self.run_script_callback(handle, |script, context| {
+                    if on_screen {
+                        script.on_enter_screen(context);
+                    } else {
+                        script.on_exit_screen(context);
+                    }
+                });
+            }
+        }
+    }
+
+    /// Whether the node has a body that's standing on something. False for nodes without a body.
+    #[must_use]
+    pub fn grounded(&self, handle: Handle<Node>) -> bool {
+        self.object_pool.borrow(handle).body.is_some_and(|b| b.is_grounded())
+    }
+
+    // Adds gravity to each body's velocity, then moves it, one axis at a time so it can slide along floors and walls.
+    // Running into one on an axis stops the body on that axis.
+    pub(crate) fn run_bodies(&mut self) {
+        if self.paused {
+            return;
+        }
+        for i in 0..self.object_pool.vec_len() {
+            let Some(handle) = self.object_pool.handle_from_index_checked(i) else { continue; };
+            let node = self.object_pool.borrow(handle);
+            let Some(mut body) = node.body.filter(|_| node.global_enabled) else { continue; };
+            body.velocity.y += body.gravity;
+            let mut moved = body.velocity;
+            if let NodeExtensionHandle::RectCollider(col) = node.node_extension {
+                let col = self.node_ext_pools.rect_collider_pool.borrow(col);
+                let (x, x_blocker) = rect_collider::clamp_move(self, col, Vec2::ZERO, true, body.velocity.x);
+                let (y, y_blocker) = rect_collider::clamp_move(self, col, Vec2::new(x, I20F12::ZERO), false, body.velocity.y);
+                // Normals point back the way the body came from
+                let contact = |node, normal| Contact { node, normal };
+                body.set_contacts(
+                    x_blocker.map(|n| contact(n, Vec2::new(-body.velocity.x.signum(), I20F12::ZERO))),
+                    y_blocker.map(|n| contact(n, Vec2::new(I20F12::ZERO, -body.velocity.y.signum()))),
+                );
+                if x_blocker.is_some() {
+                    body.velocity.x = I20F12::ZERO;
+                }
+                if y_blocker.is_some() {
+                    body.velocity.y = I20F12::ZERO;
+                }
+                moved = Vec2::new(x, y);
+            }
+            let parent = self.object_pool.borrow_raw(node.parent_index.expect("Tried to give the root node a body"));
+            let (parent_transform, parent_enabled, parent_screen, parent_parallax) = (parent.global_transform, parent.global_enabled, parent.global_screen, parent.global_parallax);
+            let node = self.object_pool.borrow_mut(handle);
+            node.body = Some(body);
+            let position = node.transform.position();
+            node.transform.set_position(position + moved);
+            self.update_global_position_recursive(handle, parent_transform, parent_enabled, parent_screen, parent_parallax);
+        }
+    }
+
+    // Moves nodes with a Follow to their target, along with their children.
+    pub(crate) fn run_follow_constraints(&mut self) {
+        for i in 0..self.object_pool.vec_len() {
+            let Some(handle) = self.object_pool.handle_from_index_checked(i) else { continue; };
+            let node = self.object_pool.borrow(handle);
+            let Some(follow) = node.follow else { continue; };
+            let Some(target) = self.object_pool.try_borrow(follow.target) else {
+                self.object_pool.borrow_mut(handle).follow = None;
+                continue;
+            };
+            let target_pos = target.global_transform.position();
+            let parent = self.object_pool.borrow_raw(node.parent_index.expect("Tried to make the root node follow something"));
+            let (parent_transform, parent_enabled, parent_screen, parent_parallax) = (parent.global_transform, parent.global_enabled, parent.global_screen, parent.global_parallax);
+            self.object_pool.borrow_mut(handle).transform.set_position(target_pos + follow.offset - parent_transform.position());
+            self.update_global_position_recursive(handle, parent_transform, parent_enabled, parent_screen, parent_parallax);
+        }
+    }
+
+    /// Shakes every active camera. See `CameraExtension::shake`.
+    pub fn shake_camera(&mut self, magnitude: I20F12, frames: u32) {
+        let cameras = self.camera_handler.get_active_cameras(self);
+        for camera in [cameras.main, cameras.sub].into_iter().flatten() {
+            self.node_ext_pools.camera_pool.borrow_mut(camera).shake(magnitude, frames);
+        }
+    }
+
+    // Eases cameras toward their follow targets. Like follow constraints, this also runs while paused.
+    pub(crate) fn run_camera_follow(&mut self) {
+        for i in 0..self.node_ext_pools.camera_pool.vec_len() {
+            let Some(camera_handle) = self.node_ext_pools.camera_pool.handle_from_index_checked(i) else { continue; };
+            let camera = self.node_ext_pools.camera_pool.borrow(camera_handle);
+            let Some(follow) = camera.follow else { continue; };
+            let handle = camera.node_handle;
+            let Some(target) = self.object_pool.try_borrow(follow.target) else {
+                self.node_ext_pools.camera_pool.borrow_mut(camera_handle).follow = None;
+                continue;
+            };
+            let node = self.object_pool.borrow(handle);
+            let moved = follow.step(node.global_transform.position(), target.global_transform.position());
+            if moved == Vec2::ZERO {
+                continue;
+            }
+            let parent = self.object_pool.borrow_raw(node.parent_index.expect("Tried to put a camera on the root node"));
+            let (parent_transform, parent_enabled, parent_screen, parent_parallax) = (parent.global_transform, parent.global_enabled, parent.global_screen, parent.global_parallax);
+            let node = self.object_pool.borrow_mut(handle);
+            let position = node.transform.position();
+            node.transform.set_position(position + moved);
+            self.update_global_position_recursive(handle, parent_transform, parent_enabled, parent_screen, parent_parallax);
+        }
+    }
+
+    // Also updates the global "enabled" state and screen.
+    pub(crate) fn update_global_positions(&mut self) {
+        let root = self.borrow(self.root);
+        self.update_global_position_recursive(self.root, root.transform, root.enabled, Screen::Both, I20F12::ONE);
+    }
+
+    fn update_global_position_recursive(&mut self, handle: Handle<Node>, transform: Transform, enabled: bool, screen: Screen, parallax: I20F12) {
+        let node = self.object_pool.borrow_mut(handle);
+        let new_enabled = node.enabled && enabled;
+        let new_screen = node.screen.unwrap_or(screen);
+        let new_parallax = node.parallax.unwrap_or(parallax);
+        let new_transform = Transform {
+            x: node.transform.x + transform.x,
+            y: node.transform.y + transform.y,
+        };
+        // Nodes that haven't started yet are just getting their initial state
+        if node.started && node.global_enabled != new_enabled {
+            self.enable_transitions.push((handle, new_enabled));
+        }
+        node.global_enabled = new_enabled;
+        node.global_transform = new_transform;
+        node.global_screen = new_screen;
+        node.global_parallax = new_parallax;
+        // Update child nodes recursively
+        let mut cur_child = node.child_index;
+        while let Some(child) = cur_child {
+            let child = self.object_pool.handle_from_raw(child);
+            self.update_global_position_recursive(child, new_transform, new_enabled, new_screen, new_parallax);
+            cur_child = self.object_pool.borrow(child).sibling_index;
+        }
+    }
+
+    // Fires on_enable / on_disable for nodes whose global enabled state changed in update_global_positions.
+    pub(crate) fn run_enable_callbacks(&mut self) {
+        let transitions = core::mem::take(&mut self.enable_transitions);
+        for &(handle, enabled) in transitions.iter() {
+            if enabled {
+                self.start_spawn_animation(handle);
+            }
+            self.run_script_callback(handle, |script, context| {
+                if enabled {
+                    script.on_enable(context);
+                } else {
+                    script.on_disable(context);
+                }
+            });
+        }
+        // Hand the allocation back so it can be reused next frame
+        self.enable_transitions = transitions;
+        self.enable_transitions.clear();
+    }
+
+    fn start_spawn_animation(&mut self, handle: Handle<Node>) {
+        let Some(node) = self.object_pool.try_borrow(handle) else { return; };
+        let Some(animation) = node.spawn_animation.filter(|a| !a.is_playing() && a.frames > 0) else { return; };
+        let (mut scale, mut mosaic) = ((I20F12::ONE, I20F12::ONE), false);
+        if let NodeExtensionHandle::Sprite(sprite) = node.node_extension {
+            let sprite = self.node_ext_pools.sprite_pool.borrow(sprite);
+            if let crate::node::sprite::SpriteType::Affine(affine) = sprite.sprite_type {
+                scale = (affine.scale_x, affine.scale_y);
+            }
+            mosaic = sprite.mosaic;
+        }
+        self.object_pool.borrow_mut(handle).spawn_animation = Some(SpawnAnimation { playing: Some((0, scale, mosaic)), ..animation });
+        self.spawn_animations.push(handle);
+    }
+
+    // Steps spawn animations, like tweens. They finish early if the node is disabled.
+    pub(crate) fn run_spawn_animations(&mut self) {
+        if self.paused {
+            return;
+        }
+        let mut animations = core::mem::take(&mut self.spawn_animations);
+        animations.retain(|&handle| {
+            let Some(node) = self.object_pool.try_borrow_mut(handle) else { return false; };
+            let Some(animation) = &mut node.spawn_animation else { return false; };
+            let Some((elapsed, scale, mosaic)) = &mut animation.playing else { return false; };
+            *elapsed += 1;
+            let finished = *elapsed > animation.frames || !node.global_enabled;
+            let t = I20F12::from_num(*elapsed) / I20F12::from_num(animation.frames);
+            let (scale, mosaic, effect) = (*scale, *mosaic, animation.effect);
+            if finished {
+                animation.playing = None;
+            }
+            if let NodeExtensionHandle::Sprite(sprite) = node.node_extension {
+                let sprite = self.node_ext_pools.sprite_pool.borrow_mut(sprite);
+                match effect {
+                    SpawnEffect::ScaleUp => if let crate::node::sprite::SpriteType::Affine(affine) = &mut sprite.sprite_type {
+                        // Scale 0 can't be drawn, so it starts from a small size
+                        let t = if finished { I20F12::ONE } else { t.max(I20F12::lit("0.0625")) };
+                        (affine.scale_x, affine.scale_y) = (scale.0 * t, scale.1 * t);
+                    },
+                    SpawnEffect::Mosaic => sprite.mosaic = mosaic || !finished,
+                }
+            }
+            !finished
+        });
+        self.spawn_animations = animations;
+    }
+
+    // Takes the scripts out of the node while they run, so they can freely access the hierarchy.
+    // The callback is run for each script in order.
+    fn run_script_callback<F>(&mut self, handle: Handle<Node>, mut callback: F)
+    where F: FnMut(&mut dyn Script, &mut ScriptContext) {
+        let Some(mut scripts) = self.try_borrow_mut(handle).map(|n| core::mem::take(&mut n.scripts)) else {
+            return;
+        };
+        let mut context = ScriptContext {
+            hierarchy: self,
+            handle,
+            rng: GlobalRng,
+        };
+        for script_data in scripts.iter_mut() {
+            callback(script_data.script.as_mut(), &mut context);
+        }
+
+        // put scripts back
+        if let Some(item) = self.try_borrow_mut(handle) {
+            item.scripts.put_back(scripts);
+        }
+    }
+
+    /// Copies the sprites from the last finished frame to the screens.
+    /// OAM can only be written during VBlank, so this must be called right after `wait_for_vblank`, with nothing in between.
+    /// A frame that runs long just means waiting for the next VBlank, so the copy never lands mid-screen.
+    pub fn vblank(&mut self) {
+        self.sprite_handler.vblank();
+    }
+
+    /// The input given to the frame that's running, or the last one if called between frames.
+    #[must_use]
+    pub fn input(&self) -> FrameInput {
+        self.input
+    }
+
+    /// Runs one frame of the game with the given input instead of asking the input provider for it.
+    /// Scripts see the input through `ScriptContext::input` and `input::read_keys`.
+    /// Given the same starting state and the same inputs, this always ends up in the same state,
+    /// so recorded inputs can be replayed. Within the frame, things always happen in this order:
+    /// global positions, bodies, follow constraints, camera follow, enable callbacks and spawn animations, extension updates (including collisions),
+    /// offscreen destroys and screen events, timers and tweens, starts, updates, lifetimes and deaths,
+    /// starts for anything spawned by the updates, destroys, and finally scene changes.
+    pub fn step(&mut self, input: FrameInput) {
+        self.input = input;
+        input::set_frame_input(input);
+        if let Some(recorder) = &mut self.input_recorder {
+            recorder.record(input);
+        }
+        self.update_global_positions();
+        self.run_bodies();
+        self.run_follow_constraints();
+        self.run_camera_follow();
+        self.run_enable_callbacks();
+        self.run_spawn_animations();
+        self.run_extension_update();
+        self.process_offscreen_destroys();
+        self.run_screen_events();
+        self.run_timers();
+        self.run_tweens();
+        // Flush anything spawned by timers, so that start is never later than the first update
+        self.run_pending_script_starts();
+        self.run_script_update();
+        self.run_lifetimes();
+        self.run_deaths();
+        self.run_pending_script_starts();
+        self.process_pending_destroys();
+        self.process_pending_scene_change();
+    }
+
+    /// Runs one frame of the game - everything except waiting for vblank.
+    /// The frame's input comes from the input provider.
+    pub fn run_frame(&mut self) {
+        let input = self.input_provider.next_frame();
+        self.step(input);
+    }
+
+    /// Records the input of every frame from now on, whether it came from `step` or the input provider.
+    /// Starts a new recording if one was already going.
+    pub fn start_input_recording(&mut self) {
+        self.input_recorder = Some(InputRecorder::new());
+    }
+
+    /// Stops recording input, returning what was recorded, or None if it wasn't recording.
+    pub fn stop_input_recording(&mut self) -> Option<InputRecorder> {
+        self.input_recorder.take()
+    }
+
+    /// Changes where `run_frame` gets each frame's input from, e.g. to replay recorded input in tests.
+    /// Hierarchies start out reading the real buttons, with `HardwareInput`.
+    pub fn set_input_provider(&mut self, provider: Box<dyn InputProvider>) {
+        self.input_provider = provider;
+    }
+
+    pub(crate) fn run_extension_update(&mut self) {
+        rect_collider::check_collisions(self);
+        // Picks up graphics that scripts have swapped since the last frame
+        for sprite in self.node_ext_pools.sprite_pool.iter_mut() {
+            self.sprite_handler.sync_graphic(sprite, &self.game_data);
+        }
+        // Flashes are timed like timers and lifetimes, so they stop while paused
+        if !self.paused {
+            for sprite in self.node_ext_pools.sprite_pool.iter_mut() {
+                sprite.update_flash();
+            }
+            for camera in self.node_ext_pools.camera_pool.iter_mut() {
+                camera.update_shake();
+            }
+        }
+        let cameras = self.camera_handler.get_active_cameras(self);
+        self.sprite_handler.sprite_update(self, cameras);
+        let mut label_handler = core::mem::take(&mut self.label_handler);
+        label_handler.label_update(self, cameras);
+        self.label_handler = label_handler;
+        let mut debug_draw = core::mem::take(&mut self.debug_draw);
+        debug_draw.collider_update(self, cameras);
+        self.debug_draw = debug_draw;
+    }
+
+    pub(crate) fn process_pending_destroys(&mut self) {
+        // unlink parent and sibling
+        while let Some(root_handle) = self.to_destroy_stack.pop() {
+            // Already gone - destroyed twice, or as part of another destroyed node
+            if self.object_pool.try_borrow(root_handle).is_none() {
+                continue;
+            }
+            for handle in self.subtree_handles(root_handle) {
+                self.run_script_callback(handle, |script, context| script.on_destroy(context));
+            }
+            // An on_destroy might have already destroyed the node, by destroying it or a parent immediately
+            if self.object_pool.try_borrow(root_handle).is_none() {
+                continue;
+            }
+            self.unlink_node(root_handle);
+            // Recursively delete children of node
+            self.process_destroy(root_handle, None);
+        }
+    }
+
+    /// Runs on_destroy for every node's script, ready for the hierarchy to be thrown away.
+    /// This happens when the hierarchy is dropped, and only the first call does anything.
+    pub fn shutdown(&mut self) {
+        if self.shut_down {
+            return;
+        }
+        self.shut_down = true;
+        for handle in self.subtree_handles(self.root) {
+            self.run_script_callback(handle, |script, context| script.on_destroy(context));
+        }
+    }
+
+    // The node and everything under it, with parents before their children.
+    fn subtree_handles(&self, handle: Handle<Node>) -> Vec<Handle<Node>> {
+        let mut handles = Vec::new();
+        let mut stack = alloc::vec![handle];
+        while let Some(cur) = stack.pop() {
+            handles.push(cur);
+            let first_child = stack.len();
+            let mut child = self.first_child(cur);
+            while let Some(c) = child {
+                stack.push(c);
+                child = self.next_sibling(c);
+            }
+            // Reverse so the first child gets popped first
+            stack[first_child..].reverse();
+        }
+        handles
+    }
+
+    // `bin` is the settings of the tracked graph this is part of a copy of, if any
+    fn process_destroy(&mut self, handle: Handle<Node>, bin: Option<usize>) {
+        let tracked = self.tracked_roots.remove(&handle);
+        if let Some(settings) = tracked {
+            self.graph_settings[settings].live_instances -= 1;
+        }
+        let bin = tracked.or(bin);
+        // todo: unless they're recycled, these objects don't get put back on the free stack
+        let Some((ticket, node)) = self.object_pool.try_take(handle) else {
+            panic!("Tried to destroy node with invalid handle");
+        };
+        if let Some(b) = bin {
+            if self.graph_settings[b].recycling {
+                self.graph_settings[b].free_slots.push(ticket);
+            } else {
+                self.object_pool.release(ticket);
+            }
+        }
+        if let NodeExtensionHandle::Sprite(sprite) = node.node_extension {
+            self.sprite_handler.release_sprite_graphic(self.node_ext_pools.sprite_pool.borrow_mut(sprite));
+        }
+        self.node_ext_pools.destroy_extension(node.node_extension);
+        self.timers.retain(|t| t.node != handle);
+        let mut cur_child = node.child_index;
+        while let Some(child) = cur_child {
+            let child = self.object_pool.handle_from_raw(child);
+            cur_child = self.object_pool.borrow(child).sibling_index;
+            self.process_destroy(child, bin);
+        }
+    }
+
+    fn unlink_node(&mut self, handle: Handle<Node>) {
+        let node = self.object_pool.borrow(handle);
+        let sibling_index = node.sibling_index;
+        let parent_index =
+            node.parent_index.unwrap_or_else(|| panic!("Tried to unlink root node"));
+        let raw = handle.raw();
+        let parent = self.object_pool.borrow_raw_mut(parent_index);
+        if parent.child_index.unwrap() == raw {
+            parent.child_index = sibling_index;
+        } else {
+            let parent_handle = self.object_pool.handle_from_raw(parent_index);
+            self.loop_over_children(parent_handle, |node, _| {
+                if node.sibling_index == Some(raw) {
+                    node.sibling_index = sibling_index;
+                }
+            });
+        }
+    }
+
+    fn loop_over_children<F: FnMut(&mut Node, Handle<Node>)>(&mut self, handle: Handle<Node>, mut op: F) {
+        let mut cur_child = self.object_pool.borrow(handle).child_index;
+        while let Some(child) = cur_child {
+            let child_handle = self.object_pool.handle_from_raw(child);
+            let child = self.object_pool.borrow_mut(child_handle);
+            op(child, child_handle);
+            cur_child = child.sibling_index;
+        }
+    }
+}
+
+impl Drop for Hierarchy {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+pub trait HasTypeId {
+    fn type_id() -> NonZeroU32;
+}
+
+#[cfg(all(test, feature = "no-hardware"))]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use sandstone_common::{BlendMode, CollisionResponse, GraphError, SavedGameData, SavedNodeExtension, SavedCameraExtension, SavedGraphic, SavedRectColliderExtension, SavedSpriteExtension, SavedSpriteType, SpriteSize};
+
+    #[derive(Default)]
+    struct TestScript;
+
+    impl Script for TestScript {
+        fn start(&mut self, _context: &mut ScriptContext) {}
+        fn update(&mut self, _context: &mut ScriptContext) {}
+    }
+
+    #[derive(Default)]
+    struct SelfDestructScript;
+
+    impl Script for SelfDestructScript {
+        fn start(&mut self, _context: &mut ScriptContext) {}
+        fn update(&mut self, context: &mut ScriptContext) {
+            context.destroy(context.handle);
+            // Still there until the end of the frame
+            assert!(context.hierarchy.try_borrow(context.handle).is_some());
+            context.destroy(context.handle);
+        }
+    }
+
+    // Only used by on_destroy_once_per_node, as tests run in parallel
+    static DESTROY_COUNT: core::sync::atomic::AtomicU32 = core::sync::atomic::AtomicU32::new(0);
+
+    #[derive(Default)]
+    struct DestroyCountScript;
+
+    impl Script for DestroyCountScript {
+        fn start(&mut self, _context: &mut ScriptContext) {}
+        fn update(&mut self, _context: &mut ScriptContext) {}
+        fn on_destroy(&mut self, _context: &mut ScriptContext) {
+            DESTROY_COUNT.fetch_add(1, core::sync::atomic::Ordering::Relaxed);
+        }
+    }
+
+    // Moves right by its speed every update. The speed can be set by a spawn parameter.
+    struct MoverScript {
+        speed: I20F12,
+    }
+
+    impl Script for MoverScript {
+        fn start(&mut self, context: &mut ScriptContext) {
+            if let Some(speed) = context.param("speed").and_then(Value::as_fixed) {
+                self.speed = speed;
+            }
+        }
+        fn update(&mut self, context: &mut ScriptContext) {
+            context.hierarchy.borrow_mut(context.handle).transform.x += self.speed;
+        }
+    }
+
+    // Remembers which node spawned it
+    #[derive(Default)]
+    struct SpawnerRecordScript {
+        spawner: Option<Handle<Node>>,
+    }
+
+    impl Script for SpawnerRecordScript {
+        fn start(&mut self, context: &mut ScriptContext) {
+            self.spawner = context.spawner();
+        }
+        fn update(&mut self, _context: &mut ScriptContext) {}
+    }
+
+    impl HasTypeId for SpawnerRecordScript {
+        fn type_id() -> NonZeroU32 {
+            NonZeroU32::new(5).unwrap()
+        }
+    }
+
+    impl HasTypeId for TestScript {
+        fn type_id() -> NonZeroU32 {
+            NonZeroU32::new(1).unwrap()
+        }
+    }
+
+    impl HasTypeId for SelfDestructScript {
+        fn type_id() -> NonZeroU32 {
+            NonZeroU32::new(2).unwrap()
+        }
+    }
+
+    // Counts its updates
+    #[derive(Default)]
+    struct CounterScript {
+        count: u32,
+    }
+
+    impl Script for CounterScript {
+        fn start(&mut self, _context: &mut ScriptContext) {}
+        fn update(&mut self, _context: &mut ScriptContext) {
+            self.count += 1;
+        }
+        fn save_state(&self) -> Option<Vec<u8>> {
+            Some(self.count.to_le_bytes().to_vec())
+        }
+        fn load_state(&mut self, state: &[u8]) {
+            self.count = u32::from_le_bytes(state.try_into().unwrap());
+        }
+    }
+
+    impl HasTypeId for CounterScript {
+        fn type_id() -> NonZeroU32 {
+            NonZeroU32::new(6).unwrap()
+        }
+    }
+
+    std::thread_local! {
+        static CALL_ORDER: core::cell::RefCell<Vec<String>> = const { core::cell::RefCell::new(Vec::new()) };
+    }
+
+    // Records its node's name when it starts and updates, in CALL_ORDER
+    struct OrderRecordScript;
+
+    impl Script for OrderRecordScript {
+        fn start(&mut self, context: &mut ScriptContext) {
+            let name = alloc::format!("start {}", context.hierarchy.node_name(context.handle));
+            CALL_ORDER.with(|o| o.borrow_mut().push(name));
+        }
+        fn update(&mut self, context: &mut ScriptContext) {
+            let name = alloc::format!("update {}", context.hierarchy.node_name(context.handle));
+            CALL_ORDER.with(|o| o.borrow_mut().push(name));
+        }
+    }
+
+    // Records its node's name when it enters and exits the screen, in CALL_ORDER
+    struct ScreenEventScript;
+
+    impl Script for ScreenEventScript {
+        fn start(&mut self, _context: &mut ScriptContext) {}
+        fn update(&mut self, _context: &mut ScriptContext) {}
+        fn on_enter_screen(&mut self, context: &mut ScriptContext) {
+            let name = alloc::format!("enter {}", context.hierarchy.node_name(context.handle));
+            CALL_ORDER.with(|o| o.borrow_mut().push(name));
+        }
+        fn on_exit_screen(&mut self, context: &mut ScriptContext) {
+            let name = alloc::format!("exit {}", context.hierarchy.node_name(context.handle));
+            CALL_ORDER.with(|o| o.borrow_mut().push(name));
+        }
+    }
+
+    // Records its node's name when it dies, in CALL_ORDER
+    struct DeathRecordScript;
+
+    impl Script for DeathRecordScript {
+        fn start(&mut self, _context: &mut ScriptContext) {}
+        fn update(&mut self, _context: &mut ScriptContext) {}
+        fn on_death(&mut self, context: &mut ScriptContext) {
+            let name = alloc::format!("death {}", context.hierarchy.node_name(context.handle));
+            CALL_ORDER.with(|o| o.borrow_mut().push(name));
+        }
+    }
+
+    // Moves its node a pixel in each direction that's held, from ScriptContext::input
+    struct InputMoverScript;
+
+    impl Script for InputMoverScript {
+        fn start(&mut self, _context: &mut ScriptContext) {}
+        fn update(&mut self, context: &mut ScriptContext) {
+            let keys = context.input().keys;
+            let node = context.hierarchy.borrow_mut(context.handle);
+            if keys.contains(crate::input::Buttons::RIGHT) {
+                node.transform.x += I20F12::ONE;
+            }
+            if keys.contains(crate::input::Buttons::DOWN) {
+                node.transform.y += I20F12::ONE;
+            }
+        }
+    }
+
+    // Moves its node to x = 1 three frames after it starts
+    struct TimerScript;
+
+    impl Script for TimerScript {
+        fn start(&mut self, context: &mut ScriptContext) {
+            context.after(3, |context| {
+                let handle = context.handle;
+                context.hierarchy.borrow_mut(handle).transform.x = I20F12::ONE;
+            });
+        }
+        fn update(&mut self, _context: &mut ScriptContext) {}
+    }
+
+    // Spawns a Flash under its own node and a Shot under the root when it starts
+    struct GunScript;
+
+    impl Script for GunScript {
+        fn start(&mut self, context: &mut ScriptContext) {
+            context.spawn_child("Flash");
+            context.spawn_at_root("Shot");
+        }
+        fn update(&mut self, _context: &mut ScriptContext) {}
+    }
+
+    // Spawns a Minion under the root in its first update
+    #[derive(Default)]
+    struct SpawnInUpdateScript {
+        spawned: bool,
+    }
+
+    impl Script for SpawnInUpdateScript {
+        fn start(&mut self, _context: &mut ScriptContext) {}
+        fn update(&mut self, context: &mut ScriptContext) {
+            if !self.spawned {
+                context.spawn_at_root("Minion");
+                self.spawned = true;
+            }
+        }
+    }
+
+    // Keeps a list of its on_enable (true) and on_disable (false) calls
+    #[derive(Default)]
+    struct EnableRecordScript {
+        calls: Vec<bool>,
+    }
+
+    impl Script for EnableRecordScript {
+        fn start(&mut self, _context: &mut ScriptContext) {}
+        fn update(&mut self, _context: &mut ScriptContext) {}
+        fn on_enable(&mut self, _context: &mut ScriptContext) {
+            self.calls.push(true);
+        }
+        fn on_disable(&mut self, _context: &mut ScriptContext) {
+            self.calls.push(false);
+        }
+    }
+
+    impl HasTypeId for EnableRecordScript {
+        fn type_id() -> NonZeroU32 {
+            NonZeroU32::new(14).unwrap()
+        }
+    }
+
+    // Destroys its parent straight away when it's destroyed
+    struct ParentDestroyerScript;
+
+    impl Script for ParentDestroyerScript {
+        fn start(&mut self, _context: &mut ScriptContext) {}
+        fn update(&mut self, _context: &mut ScriptContext) {}
+        fn on_destroy(&mut self, context: &mut ScriptContext) {
+            if let Some(parent) = context.hierarchy.parent(context.handle) {
+                context.hierarchy.destroy_node_immediate(parent);
+            }
+        }
+    }
+
+    const TEST_SCRIPTS: ScriptRegistry = ScriptRegistry {
+        factory: |id| match id.get() {
+            1 => Some(Box::new(TestScript)),
+            2 => Some(Box::new(SelfDestructScript)),
+            3 => Some(Box::new(DestroyCountScript)),
+            4 => Some(Box::new(MoverScript { speed: I20F12::ONE })),
+            5 => Some(Box::new(SpawnerRecordScript::default())),
+            6 => Some(Box::new(CounterScript::default())),
+            7 => Some(Box::new(OrderRecordScript)),
+            8 => Some(Box::new(ScreenEventScript)),
+            9 => Some(Box::new(DeathRecordScript)),
+            10 => Some(Box::new(InputMoverScript)),
+            11 => Some(Box::new(TimerScript)),
+            12 => Some(Box::new(GunScript)),
+            13 => Some(Box::new(SpawnInUpdateScript::default())),
+            14 => Some(Box::new(EnableRecordScript::default())),
+            15 => Some(Box::new(ParentDestroyerScript)),
+            _ => None,
+        },
+        name: |id| match id.get() {
+            1 => Some("TestScript"),
+            2 => Some("SelfDestructScript"),
+            3 => Some("DestroyCountScript"),
+            4 => Some("MoverScript"),
+            5 => Some("SpawnerRecordScript"),
+            6 => Some("CounterScript"),
+            7 => Some("OrderRecordScript"),
+            8 => Some("ScreenEventScript"),
+            9 => Some("DeathRecordScript"),
+            10 => Some("InputMoverScript"),
+            11 => Some("TimerScript"),
+            12 => Some("GunScript"),
+            13 => Some("SpawnInUpdateScript"),
+            14 => Some("EnableRecordScript"),
+            15 => Some("ParentDestroyerScript"),
+            _ => None,
+        },
+    };
+
+    fn saved_node(name: &str, script_type_id: u32) -> SavedNode {
+        SavedNode {
+            child_index: None,
+            parent_index: None,
+            sibling_index: None,
+            name: String::from(name),
+            transform: SavedTransform { x: I20F12::ZERO, y: I20F12::ZERO },
+            node_extension: SavedNodeExtension::None,
+            script_type_id: NonZeroU32::new(script_type_id),
+            enabled: true,
+            user_data: 0,
+        }
+    }
+
+    fn test_hierarchy(graphs: Vec<(&str, SavedNodeGraph)>) -> Hierarchy {
+        let game_data = SavedGameData {
+            main_graph: String::new(),
+            graphs: graphs.into_iter().map(|(name, graph)| (String::from(name), graph)).collect(),
+            graphics: [
+                (String::from("Box16"), SavedGraphic { tiles: vec![0; 4 * 32], palette: vec![0; 32], size: SpriteSize::_16x16 }),
+                (String::from("Box32"), SavedGraphic { tiles: vec![0; 16 * 32], palette: vec![0; 32], size: SpriteSize::_32x32 }),
+            ].into_iter().collect(),
+        };
+        Hierarchy::from_game_data(game_data, TEST_SCRIPTS)
+    }
+
+    // Nodes are all made children of the first one
+    fn tree_graph(mut nodes: Vec<SavedNode>) -> SavedNodeGraph {
+        let len = nodes.len() as u32;
+        for (i, node) in nodes.iter_mut().enumerate().skip(1) {
+            node.parent_index = Some(0);
+            node.sibling_index = NonZeroU32::new(i as u32 + 1).filter(|x| x.get() < len);
+        }
+        nodes[0].child_index = NonZeroU32::new(1).filter(|x| x.get() < len);
+        SavedNodeGraph { nodes, backdrop_colour: None }
+    }
+
+    fn placed_node(name: &str, x: i32, y: i32, node_extension: SavedNodeExtension) -> SavedNode {
+        SavedNode {
+            transform: SavedTransform { x: I20F12::from_num(x), y: I20F12::from_num(y) },
+            node_extension,
+            ..saved_node(name, 0)
+        }
+    }
+
+    fn collider(size: i32) -> SavedNodeExtension {
+        SavedNodeExtension::RectCollider(SavedRectColliderExtension { width: I20F12::from_num(size), height: I20F12::from_num(size), offset_x: I20F12::ZERO, offset_y: I20F12::ZERO, response: CollisionResponse::Trigger, is_static: false, continuous: false })
+    }
+
+    #[test]
+    fn query_aabb_overlap() {
+        let mut disabled = placed_node("Disabled", 4, 4, collider(8));
+        disabled.enabled = false;
+        let mut hierarchy = test_hierarchy(vec![("Scene", tree_graph(vec![
+            saved_node("Scene", 0),
+            placed_node("Inside", 10, 10, collider(8)),
+            placed_node("Partial", 28, 28, collider(8)),
+            placed_node("Outside", 100, 100, collider(8)),
+            placed_node("PartialSprite", -8, -8, SavedNodeExtension::Sprite(SavedSpriteExtension {
+                graphic_asset: String::from("Box16"),
+                sprite_type: SavedSpriteType::Normal,
+            })),
+            placed_node("NoBounds", 16, 16, SavedNodeExtension::None),
+            disabled,
+        ]))]);
+        let scene = hierarchy.spawn_object("Scene", hierarchy.root);
+        hierarchy.run_frame();
+
+        let rect = Rect::new(Vec2::ZERO, Vec2::new(I20F12::from_num(32), I20F12::from_num(32)));
+        let mut found: Vec<&str> = hierarchy.query_aabb(rect).into_iter().map(|h| hierarchy.node_name(h)).collect();
+        found.sort_unstable();
+        assert_eq!(found, ["Inside", "Partial", "PartialSprite"]);
+
+        // Moving the parent moves everything out of the query
+        hierarchy.borrow_mut(scene).transform.x = I20F12::from_num(200);
+        hierarchy.run_frame();
+        assert!(hierarchy.query_aabb(rect).is_empty());
+    }
+
+    #[test]
+    fn timer_fires_on_scheduled_frame() {
+        let mut hierarchy = test_hierarchy(vec![("Timed", SavedNodeGraph { nodes: vec![saved_node("Timed", 11)], backdrop_colour: None })]);
+        let root = hierarchy.root;
+        let kept = hierarchy.spawn_object("Timed", root);
+        let destroyed = hierarchy.spawn_object("Timed", root);
+        // Starts (and adds the timers) in the first frame
+        hierarchy.run_frame();
+        hierarchy.destroy_node(destroyed);
+        hierarchy.run_frame();
+        hierarchy.run_frame();
+        assert_eq!(hierarchy.borrow(kept).transform.x, I20F12::ZERO);
+        // The destroyed node's timer is cancelled
+        assert_eq!(hierarchy.timers.len(), 1);
+
+        hierarchy.run_frame();
+        assert_eq!(hierarchy.borrow(kept).transform.x, I20F12::ONE);
+        assert!(hierarchy.timers.is_empty());
+    }
+
+    #[test]
+    fn spawn_child_parents_under_caller() {
+        let mut hierarchy = test_hierarchy(vec![
+            ("Gun", SavedNodeGraph { nodes: vec![saved_node("Gun", 12)], backdrop_colour: None }),
+            ("Flash", SavedNodeGraph { nodes: vec![saved_node("Flash", 0)], backdrop_colour: None }),
+            ("Shot", SavedNodeGraph { nodes: vec![saved_node("Shot", 0)], backdrop_colour: None }),
+        ]);
+        let root = hierarchy.root;
+        let gun = hierarchy.spawn_object("Gun", root);
+        hierarchy.run_frame();
+        let flash = hierarchy.first_child(gun).unwrap();
+        assert_eq!(hierarchy.node_name(flash), "Flash");
+        assert_eq!(hierarchy.parent(flash), Some(gun));
+        assert_eq!(hierarchy.next_sibling(flash), None);
+        let shot = hierarchy.find_by_name(root, "Shot").unwrap();
+        assert_eq!(hierarchy.parent(shot), Some(root));
+    }
+
+    #[test]
+    fn lifetime_destroys_on_last_frame() {
+        let mut hierarchy = test_hierarchy(vec![("Bullet", SavedNodeGraph { nodes: vec![saved_node("Bullet", 0)], backdrop_colour: None })]);
+        let root = hierarchy.root;
+        let bullet = hierarchy.spawn_object("Bullet", root);
+        let other = hierarchy.spawn_object("Bullet", root);
+        hierarchy.borrow_mut(bullet).lifetime = Some(5);
+        for _ in 0..4 {
+            hierarchy.run_frame();
+        }
+        assert_eq!(hierarchy.borrow(bullet).lifetime, Some(1));
+        hierarchy.run_frame();
+        assert!(hierarchy.try_borrow(bullet).is_none());
+        assert!(hierarchy.try_borrow(other).is_some());
+    }
+
+    #[test]
+    fn offscreen_nodes_destroyed() {
+        let mut camera = saved_node("Camera", 0);
+        camera.node_extension = SavedNodeExtension::Camera(SavedCameraExtension { active_main: true, active_sub: false });
+        let mut box_node = saved_node("Box", 0);
+        box_node.transform = SavedTransform { x: I20F12::from_num(100), y: I20F12::from_num(100) };
+        box_node.node_extension = SavedNodeExtension::RectCollider(SavedRectColliderExtension { width: I20F12::from_num(8), height: I20F12::from_num(8), offset_x: I20F12::ZERO, offset_y: I20F12::ZERO, response: CollisionResponse::Trigger, is_static: false, continuous: false });
+        let mut hierarchy = test_hierarchy(vec![
+            ("Camera", SavedNodeGraph { nodes: vec![camera], backdrop_colour: None }),
+            ("Box", SavedNodeGraph { nodes: vec![box_node], backdrop_colour: None }),
+        ]);
+        let root = hierarchy.root;
+        hierarchy.spawn_object("Camera", root);
+        let [leaving, staying, just_outside] = [(); 3].map(|_| hierarchy.spawn_object("Box", root));
+        for handle in [leaving, staying] {
+            hierarchy.borrow_mut(handle).offscreen_destroy_margin = Some(I20F12::ZERO);
+        }
+        // Within the margin, so it's kept
+        hierarchy.borrow_mut(just_outside).transform.x = I20F12::from_num(260);
+        hierarchy.borrow_mut(just_outside).offscreen_destroy_margin = Some(I20F12::from_num(8));
+        hierarchy.run_frame();
+
+        hierarchy.borrow_mut(leaving).transform.x = I20F12::from_num(-20);
+        hierarchy.run_frame();
+        assert!(hierarchy.try_borrow(leaving).is_none());
+        assert!(hierarchy.try_borrow(staying).is_some());
+        assert!(hierarchy.try_borrow(just_outside).is_some());
+    }
+
+    #[test]
+    fn pause_stops_updates() {
+        let mut hierarchy = test_hierarchy(vec![("Counter", SavedNodeGraph { nodes: vec![saved_node("Counter", 6)], backdrop_colour: None })]);
+        let root = hierarchy.root;
+        let counter = hierarchy.spawn_object("Counter", root);
+        hierarchy.run_frame();
+        hierarchy.set_paused(true);
+        assert!(hierarchy.is_paused());
+        for _ in 0..3 {
+            hierarchy.run_frame();
+        }
+        assert_eq!(hierarchy.borrow(counter).cast_script::<CounterScript>().count, 1);
+        hierarchy.set_paused(false);
+        hierarchy.run_frame();
+        assert_eq!(hierarchy.borrow(counter).cast_script::<CounterScript>().count, 2);
+    }
+
+    #[test]
+    fn spawned_in_update_skips_that_frame() {
+        let mut hierarchy = test_hierarchy(vec![
+            ("Spawner", SavedNodeGraph { nodes: vec![saved_node("Spawner", 13)], backdrop_colour: None }),
+            ("Minion", SavedNodeGraph { nodes: vec![saved_node("Minion", 6)], backdrop_colour: None }),
+        ]);
+        let root = hierarchy.root;
+        hierarchy.spawn_object("Spawner", root);
+        hierarchy.run_frame();
+        // Started at the end of the frame it was spawned in, but not updated
+        let minion = hierarchy.find_by_name(root, "Minion").unwrap();
+        assert!(hierarchy.borrow(minion).started);
+        assert_eq!(hierarchy.borrow(minion).cast_script::<CounterScript>().count, 0);
+        hierarchy.run_frame();
+        assert_eq!(hierarchy.borrow(minion).cast_script::<CounterScript>().count, 1);
+    }
+
+    #[test]
+    fn start_runs_before_first_update() {
+        let mut hierarchy = test_hierarchy(vec![
+            ("A", SavedNodeGraph { nodes: vec![saved_node("A", 0)], backdrop_colour: None }),
+            ("B", SavedNodeGraph { nodes: vec![saved_node("B", 7)], backdrop_colour: None }),
+            ("Spawner", SavedNodeGraph { nodes: vec![saved_node("Spawner", 13)], backdrop_colour: None }),
+            ("Minion", SavedNodeGraph { nodes: vec![saved_node("Minion", 7)], backdrop_colour: None }),
+        ]);
+        let root = hierarchy.root;
+        let a = hierarchy.spawn_object("A", root);
+        hierarchy.run_frame();
+        CALL_ORDER.with(|o| o.borrow_mut().clear());
+        // Spawned by a timer, right before the updates, so it's started and updated in the same frame
+        hierarchy.add_timer(a, 1, Box::new(|context| { context.spawn_at_root("B"); }));
+        hierarchy.run_frame();
+        hierarchy.run_frame();
+        assert_eq!(CALL_ORDER.with(|o| o.take()), ["start B", "update B", "update B"]);
+
+        // Spawned during the updates, so it's started at the end of that frame and updated from the next one
+        hierarchy.spawn_object("Spawner", root);
+        hierarchy.run_frame();
+        assert_eq!(CALL_ORDER.with(|o| o.take()), ["update B", "start Minion"]);
+        hierarchy.run_frame();
+        let order = CALL_ORDER.with(|o| o.take());
+        assert_eq!(order.len(), 2);
+        assert!(order.contains(&String::from("update B")) && order.contains(&String::from("update Minion")));
+    }
+
+    #[test]
+    fn script_disabled_node_stays_enabled() {
+        let mut hierarchy = test_hierarchy(vec![("Enemy", SavedNodeGraph { nodes: vec![saved_node("Enemy", 6)], backdrop_colour: None })]);
+        let root = hierarchy.root;
+        let enemy = hierarchy.spawn_object("Enemy", root);
+        hierarchy.run_frame();
+        hierarchy.set_script_enabled(enemy, false);
+        hierarchy.run_frame();
+        hierarchy.run_frame();
+        assert_eq!(hierarchy.borrow(enemy).cast_script::<CounterScript>().count, 1);
+        // Still enabled, so it's drawn and collides as normal
+        assert!(hierarchy.borrow(enemy).enabled);
+        assert!(hierarchy.borrow(enemy).global_enabled);
+
+        hierarchy.set_script_enabled(enemy, true);
+        hierarchy.run_frame();
+        assert_eq!(hierarchy.borrow(enemy).cast_script::<CounterScript>().count, 2);
+    }
+
+    #[test]
+    fn enable_callbacks_on_transitions() {
+        let mut hierarchy = test_hierarchy(vec![("Panel", SavedNodeGraph { nodes: vec![
+            SavedNode { child_index: NonZeroU32::new(1), ..saved_node("Panel", 0) },
+            SavedNode { parent_index: Some(0), ..saved_node("Button", 14) },
+        ], backdrop_colour: None })]);
+        let root = hierarchy.root;
+        let panel = hierarchy.spawn_object("Panel", root);
+        let button = hierarchy.first_child(panel).unwrap();
+        hierarchy.run_frame();
+        assert!(hierarchy.borrow(button).cast_script::<EnableRecordScript>().calls.is_empty());
+        let mut set_enabled = |handle: Handle<Node>, enabled: bool| {
+            hierarchy.borrow_mut(handle).enabled = enabled;
+            hierarchy.run_frame();
+            core::mem::take(&mut hierarchy.borrow_mut(button).cast_script_mut::<EnableRecordScript>().calls)
+        };
+
+        // Directly
+        assert_eq!(set_enabled(button, false), [false]);
+        assert_eq!(set_enabled(button, false), []);
+        assert_eq!(set_enabled(button, true), [true]);
+
+        // Through the parent
+        assert_eq!(set_enabled(panel, false), [false]);
+        // Already disabled by the parent, so neither of these change anything
+        assert_eq!(set_enabled(button, false), []);
+        assert_eq!(set_enabled(panel, true), []);
+        assert_eq!(set_enabled(button, true), [true]);
+    }
+
+    #[test]
+    fn clone_subtree_has_independent_scripts() {
+        let mut gun = SavedNode { parent_index: Some(0), ..saved_node("Gun", 6) };
+        gun.transform = SavedTransform { x: I20F12::from_num(4), y: I20F12::from_num(-2) };
+        gun.node_extension = SavedNodeExtension::RectCollider(SavedRectColliderExtension { width: I20F12::from_num(4), height: I20F12::from_num(4), offset_x: I20F12::ZERO, offset_y: I20F12::ZERO, response: CollisionResponse::Trigger, is_static: false, continuous: false });
+        let mut hierarchy = test_hierarchy(vec![("Enemy", SavedNodeGraph { nodes: vec![
+            SavedNode { child_index: NonZeroU32::new(1), ..saved_node("Enemy", 6) },
+            gun,
+        ], backdrop_colour: None })]);
+        let root = hierarchy.root;
+        let enemy = hierarchy.spawn_object("Enemy", root);
+        hierarchy.borrow_mut(enemy).transform.x = I20F12::from_num(30);
+        hierarchy.run_frame();
+        hierarchy.run_frame();
+
+        let clone = hierarchy.clone_subtree(enemy, root);
+        assert_ne!(clone, enemy);
+        assert_eq!(hierarchy.node_name(clone), "Enemy");
+        assert_eq!(hierarchy.borrow(clone).transform.x, I20F12::from_num(30));
+        let (gun, cloned_gun) = (hierarchy.first_child(enemy).unwrap(), hierarchy.first_child(clone).unwrap());
+        assert_ne!(gun, cloned_gun);
+        assert_eq!(hierarchy.node_name(cloned_gun), "Gun");
+        assert_eq!(hierarchy.next_sibling(cloned_gun), None);
+        assert_eq!(hierarchy.borrow(cloned_gun).transform, hierarchy.borrow(gun).transform);
+        assert_eq!(hierarchy.borrow(cloned_gun).global_transform.x, I20F12::from_num(34));
+
+        // The copies start from a fresh script, and count on their own from there
+        let count = |hierarchy: &Hierarchy, handle: Handle<Node>| hierarchy.borrow(handle).cast_script::<CounterScript>().count;
+        assert_eq!(count(&hierarchy, clone), 0);
+        hierarchy.run_frame();
+        assert_eq!([enemy, gun, clone, cloned_gun].map(|h| count(&hierarchy, h)), [3, 3, 1, 1]);
+    }
+
+    #[test]
+    fn saved_graph_round_trip() {
+        let placed = |name: &str, x: i32, y: i32| SavedNode {
+            transform: SavedTransform { x: I20F12::from_num(x), y: I20F12::from_num(y) },
+            ..saved_node(name, 0)
+        };
+        // In hierarchy order, as that's how to_saved_graph writes them: Ship, Hull, Light, Gun
+        let mut nodes = vec![
+            SavedNode { child_index: NonZeroU32::new(1), ..saved_node("Ship", 6) },
+            SavedNode { parent_index: Some(0), child_index: NonZeroU32::new(2), sibling_index: NonZeroU32::new(3), ..placed("Hull", 2, 3) },
+            SavedNode { parent_index: Some(1), enabled: false, ..placed("Light", -1, 0) },
+            SavedNode { parent_index: Some(0), script_type_id: NonZeroU32::new(6), ..placed("Gun", 8, 0) },
+        ];
+        nodes[0].transform.x = I20F12::from_num(100.5);
+        nodes[1].node_extension = SavedNodeExtension::RectCollider(SavedRectColliderExtension { width: I20F12::from_num(12), height: I20F12::from_num(12), offset_x: I20F12::ZERO, offset_y: I20F12::ZERO, response: CollisionResponse::Solid, is_static: true, continuous: true });
+        let graph = SavedNodeGraph { nodes, backdrop_colour: None };
+        let bytes = sandstone_common::serialize(&graph);
+        let mut hierarchy = test_hierarchy(vec![("Ship", graph)]);
+        let root = hierarchy.root;
+        let ship = hierarchy.spawn_object("Ship", root);
+        hierarchy.run_frame();
+        assert_eq!(sandstone_common::serialize(&hierarchy.to_saved_graph(ship)), bytes);
+
+        // And a graph saved from the live nodes spawns the same thing again
+        let saved = hierarchy.to_saved_graph(ship);
+        hierarchy.game_data.graphs.insert(String::from("Copy"), saved);
+        let copy = hierarchy.spawn_object("Copy", root);
+        assert_eq!(sandstone_common::serialize(&hierarchy.to_saved_graph(copy)), bytes);
+    }
+
+    #[test]
+    fn grid_broadphase_matches_brute_force() {
+        let mut rng = crate::random::Rng::new(1234);
+        let mut nodes = vec![saved_node("Scene", 0)];
+        for i in 0..80 {
+            let mut node = placed_node("Collider", 0, 0, SavedNodeExtension::RectCollider(SavedRectColliderExtension {
+                width: rng.range_fixed(I20F12::from_num(1), I20F12::from_num(48)),
+                height: rng.range_fixed(I20F12::from_num(1), I20F12::from_num(48)),
+                offset_x: rng.range_fixed(I20F12::from_num(-8), I20F12::from_num(8)),
+                offset_y: rng.range_fixed(I20F12::from_num(-8), I20F12::from_num(8)),
+                response: CollisionResponse::Trigger,
+                is_static: false,
+                continuous: false,
+            }));
+            node.transform.x = rng.range_fixed(I20F12::from_num(-100), I20F12::from_num(200));
+            node.transform.y = rng.range_fixed(I20F12::from_num(-100), I20F12::from_num(200));
+            node.enabled = i % 7 != 0;
+            nodes.push(node);
+        }
+        nodes.push(placed_node("Big", -50, 20, collider(150)));
+        let mut hierarchy = test_hierarchy(vec![("Scene", tree_graph(nodes))]);
+        hierarchy.spawn_object("Scene", hierarchy.root);
+        hierarchy.run_frame();
+
+        let intersect_lists = |hierarchy: &Hierarchy| -> Vec<Vec<Handle<Node>>> {
+            hierarchy.node_ext_pools.rect_collider_pool.iter().map(|c| c.intersect_list.clone()).collect()
+        };
+        let grid_results = intersect_lists(&hierarchy);
+        rect_collider::check_collisions_brute_force(&mut hierarchy);
+        let brute_force_results = intersect_lists(&hierarchy);
+        assert!(brute_force_results.iter().any(|l| !l.is_empty()));
+        assert_eq!(grid_results, brute_force_results);
+    }
+
+    #[test]
+    fn raycast_hits() {
+        let mut hierarchy = test_hierarchy(vec![("Scene", tree_graph(vec![
+            saved_node("Scene", 0),
+            placed_node("Near", 20, 0, collider(8)),
+            placed_node("Far", 50, -4, collider(16)),
+        ]))]);
+        let scene = hierarchy.spawn_object("Scene", hierarchy.root);
+        hierarchy.run_frame();
+        let near = hierarchy.find_by_name(scene, "Near").unwrap();
+        let far = hierarchy.find_by_name(scene, "Far").unwrap();
+        let v = |x: i32, y: i32| Vec2::new(I20F12::from_num(x), I20F12::from_num(y));
+        let range = I20F12::from_num(100);
+
+        // Hits the closest collider, and dir doesn't have to be normalized
+        let hit = hierarchy.raycast(v(0, 4), v(5, 0), range).unwrap();
+        assert_eq!(hit, RayHit { node: near, distance: I20F12::from_num(20) });
+        // Passes below Near
+        assert_eq!(hierarchy.raycast(v(0, 10), v(1, 0), range).unwrap().node, far);
+        // Diagonal hit on Near's top left corner
+        let hit = hierarchy.raycast(v(10, -10), v(1, 1), range).unwrap();
+        assert_eq!(hit.node, near);
+        assert!((hit.distance - I20F12::from_num(14.142)).abs() < I20F12::from_num(0.01));
+
+        // Misses
+        assert_eq!(hierarchy.raycast(v(0, 4), v(-1, 0), range), None);
+        assert_eq!(hierarchy.raycast(v(0, 30), v(1, 0), range), None);
+        assert_eq!(hierarchy.raycast(v(0, 4), v(1, 0), I20F12::from_num(19)), None);
+
+        // Starting inside a collider
+        assert_eq!(hierarchy.raycast(v(24, 4), v(0, 1), range), Some(RayHit { node: near, distance: I20F12::ZERO }));
+    }
+
+    #[test]
+    fn colliders_at_point_topmost_first() {
+        let mut nodes = vec![
+            saved_node("Scene", 0),
+            placed_node("Panel", 0, 0, collider(32)),
+            placed_node("Button", 8, 8, collider(8)),
+            placed_node("Other", 100, 100, collider(8)),
+            placed_node("Camera", 50, 60, SavedNodeExtension::Camera(SavedCameraExtension { active_main: false, active_sub: true })),
+        ];
+        // Button is a child of Panel, the rest are children of Scene
+        nodes[0].child_index = NonZeroU32::new(1);
+        (nodes[1].parent_index, nodes[1].child_index, nodes[1].sibling_index) = (Some(0), NonZeroU32::new(2), NonZeroU32::new(3));
+        nodes[2].parent_index = Some(1);
+        (nodes[3].parent_index, nodes[3].sibling_index) = (Some(0), NonZeroU32::new(4));
+        nodes[4].parent_index = Some(0);
+        let mut hierarchy = test_hierarchy(vec![("Scene", SavedNodeGraph { nodes, backdrop_colour: None })]);
+        let scene = hierarchy.spawn_object("Scene", hierarchy.root);
+        hierarchy.run_frame();
+        let panel = hierarchy.find_by_name(scene, "Panel").unwrap();
+        let button = hierarchy.find_by_name(panel, "Button").unwrap();
+        let v = |x: i32, y: i32| Vec2::new(I20F12::from_num(x), I20F12::from_num(y));
+
+        assert_eq!(hierarchy.colliders_at_point(v(2, 2)), [panel]);
+        assert_eq!(hierarchy.colliders_at_point(v(12, 12)), [button, panel]);
+        assert!(hierarchy.colliders_at_point(v(50, 50)).is_empty());
+
+        // Touch positions are relative to the sub camera
+        assert_eq!(hierarchy.sub_screen_to_world(v(52, 42)), Some(v(102, 102)));
+        let other = hierarchy.find_by_name(scene, "Other").unwrap();
+        assert_eq!(hierarchy.colliders_at_point(hierarchy.sub_screen_to_world(v(52, 42)).unwrap()), [other]);
+    }
+
+    #[test]
+    fn destroy_children_keeps_parent() {
+        let mut hierarchy = test_hierarchy(vec![("Bullets", tree_graph(vec![
+            saved_node("Bullets", 0),
+            saved_node("Bullet", 1),
+            saved_node("Bullet", 0),
+            placed_node("Bullet", 0, 0, collider(4)),
+            saved_node("Bullet", 1),
+        ]))]);
+        let bullets = hierarchy.spawn_object("Bullets", hierarchy.root);
+        hierarchy.run_frame();
+        let mut children = Vec::new();
+        let mut cur_child = hierarchy.first_child(bullets);
+        while let Some(child) = cur_child {
+            children.push(child);
+            cur_child = hierarchy.next_sibling(child);
+        }
+        assert_eq!(children.len(), 4);
+
+        hierarchy.destroy_children(bullets);
+        hierarchy.run_frame();
+        assert!(hierarchy.try_borrow(bullets).is_some());
+        assert_eq!(hierarchy.first_child(bullets), None);
+        assert!(children.iter().all(|&child| hierarchy.try_borrow(child).is_none()));
+        assert_eq!(hierarchy.node_ext_pools.rect_collider_pool.iter().count(), 0);
+    }
+
+    #[test]
+    fn sibling_navigation() {
+        let mut hierarchy = test_hierarchy(vec![("Menu", tree_graph(vec![
+            saved_node("Menu", 0),
+            saved_node("First", 0),
+            saved_node("Second", 0),
+            saved_node("Third", 0),
+        ]))]);
+        let menu = hierarchy.spawn_object("Menu", hierarchy.root);
+        let first = hierarchy.find_by_name(menu, "First").unwrap();
+        let second = hierarchy.find_by_name(menu, "Second").unwrap();
+        let third = hierarchy.find_by_name(menu, "Third").unwrap();
+
+        assert_eq!(hierarchy.first_child(menu), Some(first));
+        assert_eq!(hierarchy.last_child(menu), Some(third));
+        assert_eq!(hierarchy.first_child(first), None);
+        assert_eq!(hierarchy.last_child(first), None);
+
+        assert_eq!(hierarchy.next_sibling(first), Some(second));
+        assert_eq!(hierarchy.next_sibling(second), Some(third));
+        assert_eq!(hierarchy.next_sibling(third), None);
+
+        assert_eq!(hierarchy.previous_sibling(first), None);
+        assert_eq!(hierarchy.previous_sibling(second), Some(first));
+        assert_eq!(hierarchy.previous_sibling(third), Some(second));
+        assert_eq!(hierarchy.previous_sibling(hierarchy.root), None);
+    }
+
+    fn child_names(hierarchy: &Hierarchy, parent: Handle<Node>) -> Vec<String> {
+        let mut names = Vec::new();
+        let mut cur_child = hierarchy.first_child(parent);
+        while let Some(child) = cur_child {
+            names.push(String::from(hierarchy.node_name(child)));
+            cur_child = hierarchy.next_sibling(child);
+        }
+        names
+    }
+
+    #[test]
+    fn reorder_children() {
+        let mut hierarchy = test_hierarchy(vec![("List", tree_graph(vec![
+            saved_node("List", 0),
+            saved_node("A", 0),
+            saved_node("B", 0),
+            saved_node("C", 0),
+            saved_node("D", 0),
+        ]))]);
+        let list = hierarchy.spawn_object("List", hierarchy.root);
+        let [a, b, c, d] = ["A", "B", "C", "D"].map(|name| hierarchy.find_by_name(list, name).unwrap());
+
+        hierarchy.move_child_before(d, a);
+        assert_eq!(child_names(&hierarchy, list), ["D", "A", "B", "C"]);
+        hierarchy.move_child_after(a, b);
+        assert_eq!(child_names(&hierarchy, list), ["D", "B", "A", "C"]);
+        hierarchy.move_child_before(c, b);
+        assert_eq!(child_names(&hierarchy, list), ["D", "C", "B", "A"]);
+        hierarchy.move_child_after(d, a);
+        assert_eq!(child_names(&hierarchy, list), ["C", "B", "A", "D"]);
+        hierarchy.move_child_after(b, b);
+        assert_eq!(child_names(&hierarchy, list), ["C", "B", "A", "D"]);
+        assert_eq!(hierarchy.last_child(list), Some(d));
+        assert_eq!(hierarchy.previous_sibling(a), Some(b));
+        assert_eq!(hierarchy.previous_sibling(c), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "same parent")]
+    fn reorder_rejects_different_parents() {
+        let mut hierarchy = test_hierarchy(vec![("List", tree_graph(vec![saved_node("List", 0), saved_node("A", 0)]))]);
+        let list = hierarchy.spawn_object("List", hierarchy.root);
+        let a = hierarchy.find_by_name(list, "A").unwrap();
+        hierarchy.move_child_before(a, list);
+    }
+
+    #[test]
+    fn frame_count_increments_per_update() {
+        let mut hierarchy = test_hierarchy(vec![]);
+        assert_eq!(hierarchy.frame_count(), 0);
+        hierarchy.run_script_update();
+        assert_eq!(hierarchy.frame_count(), 1);
+        hierarchy.run_frame();
+        hierarchy.set_paused(true);
+        hierarchy.run_frame();
+        assert_eq!(hierarchy.frame_count(), 3);
+
+        hierarchy.frame_count = u32::MAX;
+        hierarchy.run_script_update();
+        assert_eq!(hierarchy.frame_count(), 0);
+    }
+
+    #[test]
+    fn user_data_round_trip() {
+        let mut nodes = vec![saved_node("Enemy", 0), saved_node("Shield", 0)];
+        (nodes[0].user_data, nodes[1].user_data) = (3, 7);
+        let graph = tree_graph(nodes);
+        let graph: SavedNodeGraph = sandstone_common::deserialize(&sandstone_common::serialize(&graph));
+        assert_eq!(graph.nodes[0].user_data, 3);
+
+        let mut hierarchy = test_hierarchy(vec![("Enemy", graph)]);
+        let first = hierarchy.spawn_object("Enemy", hierarchy.root);
+        let second = hierarchy.spawn_object("Enemy", hierarchy.root);
+        hierarchy.borrow_mut(first).user_data -= 1;
+        assert_eq!(hierarchy.borrow(first).user_data, 2);
+        assert_eq!(hierarchy.borrow(second).user_data, 3);
+
+        let saved = hierarchy.to_saved_graph(first);
+        assert_eq!(saved.nodes[0].user_data, 2);
+        assert_eq!(saved.nodes[1].user_data, 7);
+    }
+
+    #[test]
+    fn find_or_spawn_singleton() {
+        let mut hierarchy = test_hierarchy(vec![("Music", SavedNodeGraph { nodes: vec![saved_node("Music", 1)], backdrop_colour: None })]);
+        let root = hierarchy.root;
+        let first = hierarchy.find_or_spawn_by_name("MusicManager", "Music", root);
+        hierarchy.run_frame();
+        let second = hierarchy.find_or_spawn_by_name("MusicManager", "Music", root);
+        assert_eq!(first, second);
+        assert_eq!(hierarchy.node_name(first), "MusicManager");
+        assert_eq!(hierarchy.first_child(root), Some(first));
+        assert_eq!(hierarchy.next_sibling(first), None);
+    }
+
+    #[test]
+    fn destroy_during_update() {
+        let mut hierarchy = test_hierarchy(vec![("Bullet", tree_graph(vec![saved_node("Bullet", 2), saved_node("Trail", 1)]))]);
+        let bullet = hierarchy.spawn_object("Bullet", hierarchy.root);
+        let trail = hierarchy.first_child(bullet).unwrap();
+        hierarchy.run_frame();
+        assert!(hierarchy.try_borrow(bullet).is_none());
+        assert!(hierarchy.try_borrow(trail).is_none());
+        assert_eq!(hierarchy.first_child(hierarchy.root), None);
+
+        let other = hierarchy.spawn_object("Bullet", hierarchy.root);
+        hierarchy.destroy_node_immediate(other);
+        assert!(hierarchy.try_borrow(other).is_none());
+        hierarchy.destroy_node_immediate(other);
+        hierarchy.run_frame();
+    }
+
+    #[test]
+    fn node_path_from_root() {
+        let mut nodes = vec![saved_node("GameScene", 0), saved_node("Player", 0), saved_node("", 0)];
+        (nodes[0].child_index, nodes[1].parent_index) = (NonZeroU32::new(1), Some(0));
+        (nodes[1].child_index, nodes[2].parent_index) = (NonZeroU32::new(2), Some(1));
+        let mut hierarchy = test_hierarchy(vec![("GameScene", SavedNodeGraph { nodes, backdrop_colour: None })]);
+        let scene = hierarchy.spawn_object("GameScene", hierarchy.root);
+        let player = hierarchy.first_child(scene).unwrap();
+        let unnamed = hierarchy.first_child(player).unwrap();
+
+        assert_eq!(hierarchy.node_path(player), "Root/GameScene/Player");
+        assert_eq!(hierarchy.node_path(unnamed), "Root/GameScene/Player/<unnamed>");
+        assert_eq!(hierarchy.node_path(hierarchy.root), "Root");
+    }
+
+    #[test]
+    fn relationship_queries() {
+        let mut nodes = vec![saved_node("Scene", 0), saved_node("Player", 0), saved_node("Gun", 0), saved_node("Enemy", 0)];
+        (nodes[0].child_index, nodes[1].parent_index, nodes[1].sibling_index) = (NonZeroU32::new(1), Some(0), NonZeroU32::new(3));
+        (nodes[1].child_index, nodes[2].parent_index) = (NonZeroU32::new(2), Some(1));
+        nodes[3].parent_index = Some(0);
+        let mut hierarchy = test_hierarchy(vec![("Scene", SavedNodeGraph { nodes, backdrop_colour: None })]);
+        let scene = hierarchy.spawn_object("Scene", hierarchy.root);
+        let [player, enemy] = ["Player", "Enemy"].map(|name| hierarchy.find_by_name(scene, name).unwrap());
+        let gun = hierarchy.first_child(player).unwrap();
+
+        // Direct
+        assert!(hierarchy.has_child(scene, player));
+        assert!(hierarchy.is_ancestor_of(player, gun));
+        assert!(hierarchy.is_descendant_of(gun, player));
+        // Indirect
+        assert!(!hierarchy.has_child(scene, gun));
+        assert!(hierarchy.is_ancestor_of(scene, gun));
+        assert!(hierarchy.is_ancestor_of(hierarchy.root, gun));
+        assert!(hierarchy.is_descendant_of(gun, scene));
+        // Unrelated, or the wrong way round
+        assert!(!hierarchy.has_child(enemy, gun));
+        assert!(!hierarchy.is_ancestor_of(enemy, gun));
+        assert!(!hierarchy.is_ancestor_of(gun, player));
+        assert!(!hierarchy.is_descendant_of(player, gun));
+        assert!(!hierarchy.is_ancestor_of(player, player));
+    }
+
+    #[test]
+    fn with_capacity_doesnt_reallocate() {
+        let game_data = SavedGameData {
+            main_graph: String::new(),
+            graphs: [(String::from("Bullet"), SavedNodeGraph { nodes: vec![saved_node("Bullet", 0)], backdrop_colour: None })].into_iter().collect(),
+            graphics: crate::HashMap::default(),
+        };
+        let mut hierarchy = Hierarchy::with_capacity(&sandstone_common::serialize(&game_data), TEST_SCRIPTS, 100);
+        let capacity = hierarchy.object_pool.capacity();
+        assert!(capacity >= 100);
+        // The root takes up one of the spaces
+        for _ in 0..99 {
+            hierarchy.spawn_object("Bullet", hierarchy.root);
+        }
+        assert_eq!(hierarchy.object_pool.vec_len(), 100);
+        assert_eq!(hierarchy.object_pool.capacity(), capacity);
+    }
+
+    #[test]
+    fn on_destroy_destroys_parent_immediately() {
+        let mut hierarchy = test_hierarchy(vec![("Scene", tree_graph(vec![
+            saved_node("Scene", 0),
+            saved_node("Child", 15),
+            saved_node("Other", 0),
+        ]))]);
+        let root = hierarchy.root;
+        // Destroying the parent at the end of the frame
+        let scene = hierarchy.spawn_object("Scene", root);
+        hierarchy.run_frame();
+        hierarchy.destroy_node(scene);
+        hierarchy.run_frame();
+        assert!(hierarchy.try_borrow(scene).is_none());
+        assert_eq!(hierarchy.first_child(root), None);
+
+        // Destroying the child straight away, which takes its parent with it
+        let scene = hierarchy.spawn_object("Scene", root);
+        let child = hierarchy.first_child(scene).unwrap();
+        hierarchy.run_frame();
+        hierarchy.destroy_node_immediate(child);
+        assert!(hierarchy.try_borrow(scene).is_none());
+        assert!(hierarchy.try_borrow(child).is_none());
+        assert_eq!(hierarchy.first_child(root), None);
+        hierarchy.run_frame();
+    }
+
+    #[test]
+    fn on_destroy_once_per_node() {
+        use core::sync::atomic::Ordering;
+        let mut hierarchy = test_hierarchy(vec![("Scene", tree_graph(vec![
+            saved_node("Scene", 3),
+            saved_node("A", 3),
+            saved_node("B", 0),
+            saved_node("C", 3),
+        ]))]);
+        let scene = hierarchy.spawn_object("Scene", hierarchy.root);
+        let other_scene = hierarchy.spawn_object("Scene", hierarchy.root);
+        hierarchy.run_frame();
+        assert_eq!(DESTROY_COUNT.load(Ordering::Relaxed), 0);
+
+        hierarchy.destroy_node(other_scene);
+        hierarchy.run_frame();
+        assert_eq!(DESTROY_COUNT.load(Ordering::Relaxed), 3);
+
+        let immediate_scene = hierarchy.spawn_object("Scene", hierarchy.root);
+        hierarchy.destroy_node_immediate(immediate_scene);
+        assert_eq!(DESTROY_COUNT.load(Ordering::Relaxed), 6);
+        hierarchy.run_frame();
+        assert_eq!(DESTROY_COUNT.load(Ordering::Relaxed), 6);
+
+        // Shutting down a second time, or dropping afterwards, doesn't run them again
+        hierarchy.shutdown();
+        assert_eq!(DESTROY_COUNT.load(Ordering::Relaxed), 9);
+        hierarchy.shutdown();
+        assert!(hierarchy.try_borrow(scene).is_some());
+        drop(hierarchy);
+        assert_eq!(DESTROY_COUNT.load(Ordering::Relaxed), 9);
+    }
+
+    #[test]
+    fn subtree_handles_parents_first() {
+        let mut nodes = vec![saved_node("Scene", 0), saved_node("A", 0), saved_node("A1", 0), saved_node("B", 0)];
+        (nodes[0].child_index, nodes[1].parent_index, nodes[1].sibling_index) = (NonZeroU32::new(1), Some(0), NonZeroU32::new(3));
+        (nodes[1].child_index, nodes[2].parent_index) = (NonZeroU32::new(2), Some(1));
+        nodes[3].parent_index = Some(0);
+        let mut hierarchy = test_hierarchy(vec![("Scene", SavedNodeGraph { nodes, backdrop_colour: None })]);
+        let scene = hierarchy.spawn_object("Scene", hierarchy.root);
+        let names: Vec<&str> = hierarchy.subtree_handles(scene).into_iter().map(|h| hierarchy.node_name(h)).collect();
+        assert_eq!(names, ["Scene", "A", "A1", "B"]);
+    }
+
+    #[test]
+    fn find_from_root_searches_every_node() {
+        let mut nodes = vec![saved_node("Scene", 0), saved_node("Player", 0), saved_node("Gun", 0)];
+        (nodes[0].child_index, nodes[1].parent_index) = (NonZeroU32::new(1), Some(0));
+        (nodes[1].child_index, nodes[2].parent_index) = (NonZeroU32::new(2), Some(1));
+        let mut hierarchy = test_hierarchy(vec![("Scene", SavedNodeGraph { nodes, backdrop_colour: None })]);
+        let scene = hierarchy.spawn_object("Scene", hierarchy.root);
+        let player = hierarchy.first_child(scene).unwrap();
+        let gun = hierarchy.first_child(player).unwrap();
+
+        let root = hierarchy.root;
+        assert_eq!(hierarchy.find_by_name(root, "Gun"), Some(gun));
+        assert_eq!(hierarchy.find_by_name(root, "Scene"), Some(scene));
+        // Other search roots only look at direct children
+        assert_eq!(hierarchy.find_by_name(scene, "Gun"), None);
+        assert_eq!(hierarchy.find_by_name(player, "Gun"), Some(gun));
+        // Destroyed nodes aren't found
+        hierarchy.destroy_node(player);
+        hierarchy.run_frame();
+        assert_eq!(hierarchy.find_by_name(root, "Gun"), None);
+    }
+
+    #[test]
+    fn unregistered_script_id_spawns_scriptless_node() {
+        let mut hierarchy = test_hierarchy(vec![
+            ("Registered", SavedNodeGraph { nodes: vec![saved_node("Registered", 1)], backdrop_colour: None }),
+            ("Unregistered", SavedNodeGraph { nodes: vec![saved_node("Unregistered", 99)], backdrop_colour: None }),
+        ]);
+        let registered = hierarchy.spawn_object("Registered", hierarchy.root);
+        let unregistered = hierarchy.spawn_object("Unregistered", hierarchy.root);
+        hierarchy.run_frame();
+
+        assert_eq!(hierarchy.borrow(registered).scripts.len(), 1);
+        assert!(hierarchy.borrow(unregistered).scripts.is_empty());
+    }
+
+    #[test]
+    fn script_name_from_type_id() {
+        let hierarchy = test_hierarchy(vec![]);
+        assert_eq!(hierarchy.script_name(NonZeroU32::new(1).unwrap()), Some("TestScript"));
+        assert_eq!(hierarchy.script_name(NonZeroU32::new(99).unwrap()), None);
+    }
+
+    #[test]
+    fn bad_borrows_log_and_continue() {
+        let mut hierarchy = test_hierarchy(vec![("Scene", tree_graph(vec![
+            saved_node("Scene", 0),
+            saved_node("Player", 1),
+        ]))]);
+        let scene = hierarchy.spawn_object("Scene", hierarchy.root);
+        let player = hierarchy.find_by_name(scene, "Player").unwrap();
+        crate::log::set_level(crate::log::Level::Error);
+        crate::log::take_written();
+
+        assert!(hierarchy.cast_script_or_log::<TestScript>(player).is_some());
+        assert!(hierarchy.cast_script_mut_or_log::<TestScript>(player).is_some());
+        assert!(crate::log::take_written().is_empty());
+
+        assert!(hierarchy.cast_script_or_log::<SelfDestructScript>(player).is_none());
+        let written = crate::log::take_written();
+        assert_eq!(written.len(), 1);
+        assert!(written[0].starts_with("[ERROR] Tried to cast the TestScript script on Root/Scene/Player to "));
+        assert!(written[0].contains("SelfDestructScript"));
+
+        assert!(hierarchy.cast_script_mut_or_log::<TestScript>(scene).is_none());
+        let written = crate::log::take_written();
+        assert!(written[0].contains("on Root/Scene to ") && written[0].contains("it has no script"));
+
+        hierarchy.destroy_node_immediate(player);
+        assert!(hierarchy.borrow_or_log(player).is_none());
+        assert!(hierarchy.borrow_mut_or_log(player).is_none());
+        assert!(hierarchy.cast_script_or_log::<TestScript>(player).is_none());
+        let written = crate::log::take_written();
+        assert_eq!(written.len(), 3);
+        assert!(written.iter().all(|line| line.contains("invalid node handle")));
+
+        // Still usable afterwards
+        hierarchy.run_frame();
+        assert!(hierarchy.borrow_or_log(scene).is_some());
+    }
+
+    #[test]
+    fn try_cast_script() {
+        let mut hierarchy = test_hierarchy(vec![("Scene", tree_graph(vec![
+            saved_node("Scene", 0),
+            saved_node("Player", 1),
+        ]))]);
+        let scene = hierarchy.spawn_object("Scene", hierarchy.root);
+        let player = hierarchy.find_by_name(scene, "Player").unwrap();
+
+        assert_eq!(hierarchy.borrow(scene).try_cast_script::<TestScript>().err(), Some(Error::NoScript));
+        assert_eq!(hierarchy.borrow_mut(scene).try_cast_script_mut::<TestScript>().err(), Some(Error::NoScript));
+        assert_eq!(hierarchy.borrow(player).try_cast_script::<SelfDestructScript>().err(), Some(Error::ScriptTypeMismatch));
+        assert_eq!(hierarchy.borrow_mut(player).try_cast_script_mut::<SelfDestructScript>().err(), Some(Error::ScriptTypeMismatch));
+        assert!(hierarchy.borrow(player).try_cast_script::<TestScript>().is_ok());
+        assert!(hierarchy.borrow_mut(player).try_cast_script_mut::<TestScript>().is_ok());
+    }
+
+    #[test]
+    fn typed_collider_handle() {
+        let mut hierarchy = test_hierarchy(vec![("Scene", tree_graph(vec![
+            saved_node("Scene", 0),
+            placed_node("Hitbox", 0, 0, collider(8)),
+            saved_node("Plain", 0),
+        ]))]);
+        let scene = hierarchy.spawn_object("Scene", hierarchy.root);
+        let hitbox = hierarchy.find_by_name(scene, "Hitbox").unwrap();
+
+        let typed: TypedHandle<rect_collider::RectColliderExtension> = hierarchy.find_by_name_typed(scene, "Hitbox").unwrap();
+        assert_eq!(typed.node(), hitbox);
+        assert_eq!(hierarchy.typed_handle(hitbox), Some(typed));
+        assert_eq!(hierarchy.get_extension(typed).width, I20F12::from_num(8));
+        hierarchy.get_extension_mut(typed).width = I20F12::from_num(4);
+        assert_eq!(hierarchy.get_extension(typed).node_handle, hitbox);
+        assert_eq!(hierarchy.get_extension(typed).width, I20F12::from_num(4));
+
+        assert!(hierarchy.find_by_name_typed::<crate::node::sprite::SpriteExtension>(scene, "Hitbox").is_none());
+        assert!(hierarchy.find_by_name_typed::<rect_collider::RectColliderExtension>(scene, "Plain").is_none());
+    }
+
+    #[test]
+    fn spawn_params_override_speed() {
+        let mut hierarchy = test_hierarchy(vec![("Mover", SavedNodeGraph { nodes: vec![saved_node("Mover", 4)], backdrop_colour: None })]);
+        let root = hierarchy.root;
+        let normal = hierarchy.spawn_object("Mover", root);
+        let fast = hierarchy.spawn_object_with_params("Mover", root, &[("speed", Value::Int(3)), ("unused", Value::Bool(true))]).unwrap();
+        hierarchy.run_pending_script_starts();
+        for _ in 0..2 {
+            hierarchy.run_frame();
+        }
+        assert_eq!(hierarchy.borrow(normal).transform.x, I20F12::from_num(2));
+        assert_eq!(hierarchy.borrow(fast).transform.x, I20F12::from_num(6));
+        assert!(hierarchy.pending_spawn_info.is_empty());
+        assert!(hierarchy.start_info.is_none());
+    }
+
+    #[test]
+    fn script_spawn_past_instance_limit() {
+        let mut hierarchy = test_hierarchy(vec![
+            ("Gun", SavedNodeGraph { nodes: vec![saved_node("Gun", 0)], backdrop_colour: None }),
+            ("Bullet", SavedNodeGraph { nodes: vec![saved_node("Bullet", 6)], backdrop_colour: None }),
+        ]);
+        let root = hierarchy.root;
+        let gun = hierarchy.spawn_object("Gun", root);
+        hierarchy.set_graph_instance_limit("Bullet", Some(2));
+        hierarchy.add_timer(gun, 1, Box::new(|context| {
+            let spawned: Vec<bool> = (0..3).map(|_| context.spawn_at_root("Bullet").is_some()).collect();
+            assert_eq!(spawned, [true, true, false]);
+            assert!(context.spawn_child("Missing").is_none());
+        }));
+        hierarchy.run_frame();
+        hierarchy.run_frame();
+        // The refused spawns are skipped, and the frames carry on as normal
+        assert_eq!(hierarchy.graph_instance_count("Bullet"), 2);
+        let bullet = hierarchy.find_by_name(root, "Bullet").unwrap();
+        assert_eq!(hierarchy.borrow(bullet).cast_script::<CounterScript>().count, 2);
+    }
+
+    #[test]
+    fn start_reads_spawner() {
+        let mut hierarchy = test_hierarchy(vec![
+            ("Gun", SavedNodeGraph { nodes: vec![saved_node("Gun", 1)], backdrop_colour: None }),
+            ("Bullet", SavedNodeGraph { nodes: vec![saved_node("Bullet", 5)], backdrop_colour: None }),
+        ]);
+        let root = hierarchy.root;
+        let gun = hierarchy.spawn_object("Gun", root);
+        let placed = hierarchy.spawn_object("Bullet", root);
+        hierarchy.add_timer(gun, 1, Box::new(|context| {
+            context.spawn_at_root("Bullet");
+        }));
+        hierarchy.run_frame();
+
+        let bullet = hierarchy.name_id("Bullet").unwrap();
+        let fired = hierarchy.find(root, |node| node.name == bullet && node.try_cast_script::<SpawnerRecordScript>()
+            .is_ok_and(|script| script.spawner.is_some())).unwrap();
+        assert_ne!(fired, placed);
+        assert_eq!(hierarchy.borrow(fired).cast_script::<SpawnerRecordScript>().spawner, Some(gun));
+        assert_eq!(hierarchy.borrow(placed).cast_script::<SpawnerRecordScript>().spawner, None);
+    }
+
+    #[test]
+    fn sub_screen_nodes_skip_main_oam() {
+        use crate::node::sprite::sprites_on_screen;
+        let sprite = || SavedNodeExtension::Sprite(SavedSpriteExtension {
+            graphic_asset: String::from("Box16"),
+            sprite_type: SavedSpriteType::Normal,
+        });
+        let mut hierarchy = test_hierarchy(vec![("Scene", tree_graph(vec![
+            saved_node("Scene", 0),
+            placed_node("Player", 0, 0, sprite()),
+            placed_node("Map", 0, 0, sprite()),
+        ]))]);
+        let scene = hierarchy.spawn_object("Scene", hierarchy.root);
+        let player = hierarchy.find_by_name(scene, "Player").unwrap();
+        let map = hierarchy.find_by_name(scene, "Map").unwrap();
+        let names = |hierarchy: &Hierarchy, screen| {
+            let mut names: Vec<String> = sprites_on_screen(hierarchy, screen)
+                .map(|s| String::from(hierarchy.node_name(s.node_handle)))
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(names(&hierarchy, Screen::Main), ["Map", "Player"]);
+
+        hierarchy.borrow_mut(map).screen = Some(Screen::Sub);
+        hierarchy.update_global_positions();
+        assert_eq!(names(&hierarchy, Screen::Main), ["Player"]);
+        assert_eq!(names(&hierarchy, Screen::Sub), ["Map", "Player"]);
+
+        // Children follow their parent's screen unless they set their own
+        hierarchy.borrow_mut(scene).screen = Some(Screen::Main);
+        hierarchy.update_global_positions();
+        assert_eq!(names(&hierarchy, Screen::Main), ["Player"]);
+        assert_eq!(names(&hierarchy, Screen::Sub), ["Map"]);
+        hierarchy.borrow_mut(player).screen = Some(Screen::Both);
+        hierarchy.update_global_positions();
+        assert_eq!(names(&hierarchy, Screen::Sub), ["Map", "Player"]);
+    }
+
+    #[test]
+    fn tween_position_lands_on_target() {
+        let mut hierarchy = test_hierarchy(vec![("Scene", tree_graph(vec![
+            saved_node("Scene", 0),
+            placed_node("Panel", 2, 0, SavedNodeExtension::None),
+        ]))]);
+        let scene = hierarchy.spawn_object("Scene", hierarchy.root);
+        let panel = hierarchy.find_by_name(scene, "Panel").unwrap();
+        let target = Vec2::new(I20F12::from_num(9), I20F12::from_num(-5.5));
+        hierarchy.tween_position(panel, target, 3, Easing::Linear, Some(Box::new(|context| {
+            context.hierarchy.borrow_mut(context.handle).user_data += 1;
+        })));
+
+        let mut positions = Vec::new();
+        for _ in 0..4 {
+            hierarchy.run_frame();
+            positions.push(hierarchy.borrow(panel).transform.position());
+        }
+        assert!(positions[0].x > I20F12::from_num(2) && positions[1].x < target.x);
+        assert_eq!(positions[2], target);
+        assert_eq!(positions[3], target);
+        assert_eq!(hierarchy.borrow(panel).user_data, 1);
+
+        // Destroying the node drops its tween without running the callback
+        hierarchy.tween_position(panel, Vec2::ZERO, 5, Easing::EaseIn, Some(Box::new(|_| panic!("Shouldn't complete"))));
+        hierarchy.destroy_node_immediate(panel);
+        for _ in 0..6 {
+            hierarchy.run_frame();
+        }
+    }
+
+    #[test]
+    fn active_in_hierarchy_chain() {
+        let mut hierarchy = test_hierarchy(vec![("Chain", SavedNodeGraph { nodes: vec![
+            SavedNode { child_index: NonZeroU32::new(1), ..saved_node("Top", 0) },
+            SavedNode { parent_index: Some(0), child_index: NonZeroU32::new(2), ..saved_node("Middle", 0) },
+            SavedNode { parent_index: Some(1), ..saved_node("Bottom", 0) },
+        ], backdrop_colour: None })]);
+        let top = hierarchy.spawn_object("Chain", hierarchy.root);
+        let middle = hierarchy.first_child(top).unwrap();
+        let bottom = hierarchy.first_child(middle).unwrap();
+        assert!([top, middle, bottom].iter().all(|&h| hierarchy.is_active_in_hierarchy(h)));
+
+        hierarchy.borrow_mut(middle).enabled = false;
+        assert!(hierarchy.is_active_in_hierarchy(top));
+        assert!(!hierarchy.is_active_in_hierarchy(middle));
+        assert!(!hierarchy.is_active_in_hierarchy(bottom));
+        // Its own flag is still set
+        assert!(hierarchy.borrow(bottom).enabled);
+
+        hierarchy.borrow_mut(middle).enabled = true;
+        hierarchy.borrow_mut(hierarchy.root).enabled = false;
+        assert!(!hierarchy.is_active_in_hierarchy(top));
+        assert!(!hierarchy.is_active_in_hierarchy(bottom));
+    }
+
+    #[test]
+    fn batch_spawn_in_a_row() {
+        let mut hierarchy = test_hierarchy(vec![
+            ("Row", tree_graph(vec![saved_node("Row", 0)])),
+            ("Coin", tree_graph(vec![placed_node("Coin", 0, 5, SavedNodeExtension::None), saved_node("Shine", 0)])),
+        ]);
+        let row = hierarchy.spawn_object("Row", hierarchy.root);
+        let coins = hierarchy.spawn_object_batch("Coin", row, 10, |i| Transform { x: I20F12::from_num(i as i32 * 16), y: I20F12::from_num(40) }).unwrap();
+        assert_eq!(coins.len(), 10);
+        assert_eq!(child_names(&hierarchy, row).len(), 10);
+        for (i, &coin) in coins.iter().enumerate() {
+            let node = hierarchy.borrow(coin);
+            assert_eq!(node.parent_index, Some(row.raw()));
+            assert_eq!(node.transform, Transform { x: I20F12::from_num(i as i32 * 16), y: I20F12::from_num(40) });
+            assert_eq!(node.global_transform, node.transform);
+            let shine = hierarchy.first_child(coin).unwrap();
+            assert_eq!(hierarchy.borrow(shine).global_transform, node.transform);
+        }
+        // The graph is still there to spawn again
+        hierarchy.spawn_object("Coin", hierarchy.root);
+    }
+
+    #[test]
+    fn collider_outline_vertices() {
+        let mut hierarchy = test_hierarchy(vec![("Box", tree_graph(vec![
+            placed_node("Box", 40, 30, SavedNodeExtension::None),
+            placed_node("Hitbox", 4, -2, collider(16)),
+        ]))]);
+        let root = hierarchy.spawn_object("Box", hierarchy.root);
+        let hitbox = hierarchy.first_child(root).unwrap();
+        let NodeExtensionHandle::RectCollider(col) = hierarchy.borrow(hitbox).node_extension else { panic!() };
+        let col = hierarchy.borrow(col);
+        assert_eq!(rect_collider::collider_outline(&hierarchy, col, (I20F12::ZERO, I20F12::ZERO)),
+            [(44, 28), (60, 28), (60, 44), (44, 44)]);
+        // Relative to the camera
+        assert_eq!(rect_collider::collider_outline(&hierarchy, col, (I20F12::from_num(50), I20F12::from_num(-10))),
+            [(-6, 38), (10, 38), (10, 54), (-6, 54)]);
+    }
+
+    #[test]
+    fn offset_collider_intersects_at_offset() {
+        let offset_collider = SavedNodeExtension::RectCollider(SavedRectColliderExtension {
+            width: I20F12::from_num(8),
+            height: I20F12::from_num(8),
+            offset_x: I20F12::from_num(20),
+            offset_y: I20F12::ZERO,
+            response: CollisionResponse::Trigger,
+            is_static: false,
+            continuous: false,
+        });
+        let mut hierarchy = test_hierarchy(vec![("Scene", tree_graph(vec![
+            placed_node("Scene", 10, 0, SavedNodeExtension::None),
+            placed_node("Ship", 90, 50, offset_collider),
+            placed_node("Target", 112, 52, collider(4)),
+            // Where the ship's collider would be without its offset
+            placed_node("Decoy", 92, 52, collider(4)),
+        ]))]);
+        let scene = hierarchy.spawn_object("Scene", hierarchy.root);
+        hierarchy.run_frame();
+        let ship = hierarchy.find_by_name(scene, "Ship").unwrap();
+        let target = hierarchy.find_by_name(scene, "Target").unwrap();
+        let intersecting = |hierarchy: &Hierarchy| {
+            let NodeExtensionHandle::RectCollider(col) = hierarchy.borrow(ship).node_extension else { panic!() };
+            hierarchy.borrow(col).intersect_list.clone()
+        };
+        assert_eq!(intersecting(&hierarchy), [target]);
+        assert_eq!(hierarchy.world_bounds(ship), Rect::from_pos_size(Vec2::new(I20F12::from_num(120), I20F12::from_num(50)), Vec2::new(I20F12::from_num(8), I20F12::from_num(8))));
+
+        // Moving the parent moves the collider, offset included
+        hierarchy.borrow_mut(scene).transform.y = I20F12::from_num(20);
+        hierarchy.run_frame();
+        assert_eq!(hierarchy.world_bounds(ship).min, Vec2::new(I20F12::from_num(120), I20F12::from_num(70)));
+        assert_eq!(intersecting(&hierarchy), [target]);
+        hierarchy.borrow_mut(ship).transform.x = I20F12::from_num(70);
+        hierarchy.run_frame();
+        assert_eq!(intersecting(&hierarchy), [hierarchy.find_by_name(scene, "Decoy").unwrap()]);
+    }
+
+    #[test]
+    fn static_collider_not_rebucketed() {
+        let mut hierarchy = test_hierarchy(vec![("Scene", tree_graph(vec![
+            saved_node("Scene", 0),
+            placed_node("Wall", 100, 0, collider(16)),
+            placed_node("Bullet", 60, 4, collider(4)),
+        ]))]);
+        let scene = hierarchy.spawn_object("Scene", hierarchy.root);
+        let wall = hierarchy.find_by_name_typed::<rect_collider::RectColliderExtension>(scene, "Wall").unwrap();
+        let bullet = hierarchy.find_by_name(scene, "Bullet").unwrap();
+        hierarchy.get_extension_mut(wall).is_static = true;
+        hierarchy.run_frame();
+        assert_eq!(hierarchy.collision_grid.static_rebuckets, 1);
+
+        let mut hit_frames = Vec::new();
+        for frame in 0..8 {
+            hierarchy.borrow_mut(bullet).transform.x += I20F12::from_num(10);
+            hierarchy.run_frame();
+            if hierarchy.get_extension(wall).intersect_list.contains(&bullet) {
+                hit_frames.push(frame);
+            }
+        }
+        // At x = 100 and 110
+        assert_eq!(hit_frames, [3, 4]);
+        assert_eq!(hierarchy.collision_grid.static_rebuckets, 1);
+
+        // Moving it puts it in its new cells
+        hierarchy.borrow_mut(wall.node()).transform.y = I20F12::from_num(40);
+        hierarchy.run_frame();
+        assert_eq!(hierarchy.collision_grid.static_rebuckets, 2);
+        hierarchy.borrow_mut(bullet).transform = Transform { x: I20F12::from_num(104), y: I20F12::from_num(50) };
+        hierarchy.run_frame();
+        assert!(hierarchy.get_extension(wall).intersect_list.contains(&bullet));
+        assert_eq!(hierarchy.collision_grid.static_rebuckets, 2);
+    }
+
+    #[test]
+    fn continuous_collider_does_not_tunnel() {
+        let frames_hit = |continuous: bool| {
+            let thin_wall = SavedNodeExtension::RectCollider(SavedRectColliderExtension {
+                width: I20F12::from_num(2),
+                height: I20F12::from_num(32),
+                offset_x: I20F12::ZERO,
+                offset_y: I20F12::ZERO,
+                response: CollisionResponse::Trigger,
+                is_static: false,
+                continuous: false,
+            });
+            let mut hierarchy = test_hierarchy(vec![("Scene", tree_graph(vec![
+                saved_node("Scene", 0),
+                placed_node("Enemy", 100, 0, thin_wall),
+                placed_node("Bullet", 70, 8, collider(4)),
+            ]))]);
+            let scene = hierarchy.spawn_object("Scene", hierarchy.root);
+            let enemy = hierarchy.find_by_name_typed::<rect_collider::RectColliderExtension>(scene, "Enemy").unwrap();
+            let bullet = hierarchy.find_by_name_typed::<rect_collider::RectColliderExtension>(scene, "Bullet").unwrap();
+            hierarchy.get_extension_mut(bullet).continuous = continuous;
+            let mut hits = Vec::new();
+            for frame in 0..4 {
+                hierarchy.run_frame();
+                if hierarchy.get_extension(enemy).intersect_list.contains(&bullet.node()) {
+                    hits.push(frame);
+                }
+                // At 20 pixels a frame it's at 90 and then 110, never overlapping the wall
+                hierarchy.borrow_mut(bullet.node()).transform.x += I20F12::from_num(20);
+            }
+            hits
+        };
+        assert!(frames_hit(false).is_empty());
+        assert_eq!(frames_hit(true), [2]);
+    }
+
+    #[test]
+    fn restart_scene_resets_scripts() {
+        let mut hierarchy = test_hierarchy(vec![("Level", tree_graph(vec![saved_node("Level", 0), saved_node("Counter", 6)]))]);
+        assert_eq!(hierarchy.current_scene_name(), None);
+        hierarchy.restart_scene();
+        hierarchy.set_scene("Level");
+        hierarchy.process_pending_scene_change();
+        assert_eq!(hierarchy.current_scene_name(), Some("Level"));
+        let count = |hierarchy: &mut Hierarchy| {
+            let level = hierarchy.first_child(hierarchy.root).unwrap();
+            let counter = hierarchy.find_by_name(level, "Counter").unwrap();
+            hierarchy.borrow(counter).cast_script::<CounterScript>().count
+        };
+        for _ in 0..3 {
+            hierarchy.run_frame();
+        }
+        assert_eq!(count(&mut hierarchy), 3);
+
+        hierarchy.restart_scene();
+        hierarchy.process_pending_scene_change();
+        assert_eq!(count(&mut hierarchy), 0);
+        assert_eq!(hierarchy.current_scene_name(), Some("Level"));
+        assert_eq!(child_names(&hierarchy, hierarchy.root), ["Level"]);
+    }
+
+    #[test]
+    fn restart_scene_keeps_root_spawns() {
+        let mut hierarchy = test_hierarchy(vec![
+            ("Level", tree_graph(vec![saved_node("Level", 0), saved_node("Counter", 6)])),
+            ("Music", tree_graph(vec![saved_node("Music", 0)])),
+        ]);
+        hierarchy.set_scene("Level");
+        hierarchy.run_frame();
+        // Spawned after the scene, so it's the root's first child
+        let music = hierarchy.spawn_object("Music", hierarchy.root);
+        hierarchy.run_frame();
+
+        hierarchy.restart_scene();
+        hierarchy.run_frame();
+        assert!(hierarchy.try_borrow(music).is_some());
+        let mut names = child_names(&hierarchy, hierarchy.root);
+        names.sort();
+        assert_eq!(names, ["Level", "Music"]);
+
+        // Still the scene that's replaced after a snapshot is restored
+        let snapshot = hierarchy.snapshot();
+        hierarchy.restore(&snapshot).unwrap();
+        hierarchy.set_scene("Level");
+        hierarchy.run_frame();
+        let mut names = child_names(&hierarchy, hierarchy.root);
+        names.sort();
+        assert_eq!(names, ["Level", "Music"]);
+    }
+
+    #[test]
+    fn scene_root_follows_scene_changes() {
+        let mut hierarchy = test_hierarchy(vec![
+            ("Title", tree_graph(vec![saved_node("Title", 0)])),
+            ("Level", tree_graph(vec![saved_node("Level", 0)])),
+        ]);
+        assert_eq!(hierarchy.scene_root(), None);
+        hierarchy.set_scene("Title");
+        hierarchy.run_frame();
+        let title = hierarchy.scene_root().unwrap();
+        assert_eq!(hierarchy.to_saved_graph(title).nodes[0].name, "Title");
+
+        hierarchy.set_scene("Level");
+        hierarchy.run_frame();
+        let level = hierarchy.scene_root().unwrap();
+        assert_eq!(hierarchy.to_saved_graph(level).nodes[0].name, "Level");
+
+        hierarchy.destroy_node(level);
+        hierarchy.run_frame();
+        assert_eq!(hierarchy.scene_root(), None);
+    }
+
+    #[test]
+    fn set_scene_main_loads_main_graph() {
+        let mut hierarchy = test_hierarchy(vec![
+            ("Title", tree_graph(vec![saved_node("Title", 0)])),
+            ("Level", tree_graph(vec![saved_node("Level", 0)])),
+        ]);
+        hierarchy.game_data.main_graph = String::from("Title");
+        hierarchy.set_scene("Level");
+        hierarchy.run_frame();
+        assert_eq!(child_names(&hierarchy, hierarchy.root), ["Level"]);
+
+        hierarchy.set_scene_main();
+        hierarchy.run_frame();
+        assert_eq!(hierarchy.current_scene_name(), Some("Title"));
+        assert_eq!(child_names(&hierarchy, hierarchy.root), ["Title"]);
+        // The name is copied rather than taken, so the main scene can be loaded again
+        assert_eq!(hierarchy.game_data.main_graph, "Title");
+    }
+
+    #[test]
+    fn scene_name_after_set_scene() {
+        let mut hierarchy = test_hierarchy(vec![
+            ("GameScene", tree_graph(vec![saved_node("Game", 0)])),
+            ("TitleScene", tree_graph(vec![saved_node("Title", 0)])),
+        ]);
+        hierarchy.set_scene("GameScene");
+        // Not loaded until the scene change is processed
+        assert_eq!(hierarchy.current_scene_name(), None);
+        hierarchy.run_frame();
+        assert_eq!(hierarchy.current_scene_name(), Some("GameScene"));
+        hierarchy.set_scene("TitleScene");
+        hierarchy.run_frame();
+        assert_eq!(hierarchy.current_scene_name(), Some("TitleScene"));
+    }
+
+    #[test]
+    fn scene_start_order() {
+        // Saved out of hierarchy order, which is Level, A, A1, B
+        let mut hierarchy = test_hierarchy(vec![("Level", SavedNodeGraph { nodes: vec![
+            SavedNode { child_index: NonZeroU32::new(3), ..saved_node("Level", 7) },
+            SavedNode { parent_index: Some(0), ..saved_node("B", 7) },
+            SavedNode { parent_index: Some(3), ..saved_node("A1", 7) },
+            SavedNode { parent_index: Some(0), sibling_index: NonZeroU32::new(1), child_index: NonZeroU32::new(2), ..saved_node("A", 7) },
+        ], backdrop_colour: None })]);
+        CALL_ORDER.with(|o| o.borrow_mut().clear());
+        hierarchy.set_scene("Level");
+        hierarchy.run_frame();
+        hierarchy.run_frame();
+        let order = CALL_ORDER.with(|o| o.take());
+        assert_eq!(order[..4], ["start Level", "start A", "start A1", "start B"]);
+        // Updates are in pool order, but they all come after every start
+        assert_eq!(order.len(), 8);
+        assert!(order[4..].iter().all(|call| call.starts_with("update")));
+    }
+
+    #[test]
+    fn start_follows_spawn_order() {
+        let mut hierarchy = test_hierarchy(vec![
+            ("Squad", tree_graph(vec![saved_node("Leader", 7), saved_node("Grunt1", 7), saved_node("Grunt2", 7)])),
+            ("Solo", tree_graph(vec![saved_node("Solo", 7)])),
+        ]);
+        CALL_ORDER.with(|o| o.borrow_mut().clear());
+        let squad = hierarchy.spawn_object("Squad", hierarchy.root);
+        hierarchy.spawn_object("Solo", hierarchy.root);
+        hierarchy.spawn_object("Squad", hierarchy.root);
+        hierarchy.clone_subtree(squad, hierarchy.root);
+        hierarchy.run_pending_script_starts();
+        let squad_starts = ["start Leader", "start Grunt1", "start Grunt2"];
+        let expected: Vec<&str> = squad_starts.iter().chain(&["start Solo"]).chain(&squad_starts).chain(&squad_starts).copied().collect();
+        assert_eq!(CALL_ORDER.with(|o| o.take()), expected);
+    }
+
+    #[test]
+    fn hierarchy_from_serialized_data() {
+        let game_data = SavedGameData {
+            main_graph: String::from("Level"),
+            graphs: [(String::from("Level"), tree_graph(vec![saved_node("Level", 0), saved_node("Counter", 6)]))].into_iter().collect(),
+            graphics: Default::default(),
+        };
+        let mut hierarchy = Hierarchy::new(&sandstone_common::serialize(&game_data), TEST_SCRIPTS);
+        hierarchy.set_scene_main();
+        hierarchy.run_frame();
+        hierarchy.run_frame();
+        let level = hierarchy.first_child(hierarchy.root).unwrap();
+        let counter = hierarchy.find_by_name(level, "Counter").unwrap();
+        assert_eq!(hierarchy.borrow(counter).cast_script::<CounterScript>().count, 1);
+    }
+
+    #[test]
+    fn iter_colliders_tracks_spawns_and_destroys() {
+        let mut hierarchy = test_hierarchy(vec![
+            ("Scene", tree_graph(vec![
+                saved_node("Scene", 0),
+                placed_node("Wall", 0, 0, collider(16)),
+                saved_node("Plain", 0),
+                placed_node("Enemy", 32, 0, collider(8)),
+            ])),
+            ("Bullet", tree_graph(vec![placed_node("Bullet", 0, 0, collider(2))])),
+        ]);
+        let collider_names = |hierarchy: &Hierarchy| -> Vec<String> {
+            let mut names: Vec<String> = hierarchy.iter_colliders().map(|c| String::from(hierarchy.node_name(c.node_handle))).collect();
+            names.sort();
+            names
+        };
+        assert!(collider_names(&hierarchy).is_empty());
+        let scene = hierarchy.spawn_object("Scene", hierarchy.root);
+        assert_eq!(collider_names(&hierarchy), ["Enemy", "Wall"]);
+        let bullet = hierarchy.spawn_object("Bullet", scene);
+        assert_eq!(collider_names(&hierarchy), ["Bullet", "Enemy", "Wall"]);
+
+        let enemy = hierarchy.find_by_name(scene, "Enemy").unwrap();
+        hierarchy.destroy_node(enemy);
+        hierarchy.destroy_node(bullet);
+        hierarchy.process_pending_destroys();
+        assert_eq!(collider_names(&hierarchy), ["Wall"]);
+        hierarchy.spawn_object("Bullet", scene);
+        assert_eq!(collider_names(&hierarchy), ["Bullet", "Wall"]);
+        hierarchy.destroy_node(scene);
+        hierarchy.process_pending_destroys();
+        assert!(collider_names(&hierarchy).is_empty());
+    }
+
+    #[test]
+    fn enter_and_exit_screen_events() {
+        let mut hierarchy = test_hierarchy(vec![("Scene", tree_graph(vec![
+            saved_node("Scene", 0),
+            placed_node("Camera", 0, 0, SavedNodeExtension::Camera(SavedCameraExtension { active_main: true, active_sub: false })),
+            SavedNode { script_type_id: NonZeroU32::new(8), ..placed_node("Enemy", -40, 50, collider(16)) },
+        ]))]);
+        CALL_ORDER.with(|o| o.borrow_mut().clear());
+        let scene = hierarchy.spawn_object("Scene", hierarchy.root);
+        let enemy = hierarchy.find_by_name(scene, "Enemy").unwrap();
+        hierarchy.run_frame();
+        assert!(CALL_ORDER.with(|o| o.take()).is_empty());
+
+        for _ in 0..4 {
+            hierarchy.borrow_mut(enemy).transform.x += I20F12::from_num(10);
+            hierarchy.run_frame();
+        }
+        assert_eq!(CALL_ORDER.with(|o| o.take()), ["enter Enemy"]);
+
+        hierarchy.borrow_mut(enemy).transform.x = I20F12::from_num(300);
+        hierarchy.run_frame();
+        hierarchy.run_frame();
+        assert_eq!(CALL_ORDER.with(|o| o.take()), ["exit Enemy"]);
+    }
+
+    #[test]
+    fn damage_clamps_at_zero() {
+        let mut hierarchy = test_hierarchy(vec![("Enemy", tree_graph(vec![saved_node("Enemy", 0)]))]);
+        let enemy = hierarchy.spawn_object("Enemy", hierarchy.root);
+        assert!(!hierarchy.apply_damage(enemy, 5));
+        hierarchy.borrow_mut(enemy).health = Some(crate::node::Health::new(5));
+        assert!(!hierarchy.apply_damage(enemy, 3));
+        assert_eq!(hierarchy.borrow(enemy).health.unwrap().current, 2);
+        assert!(hierarchy.apply_damage(enemy, 10));
+        assert_eq!(hierarchy.borrow(enemy).health.unwrap().current, 0);
+        // Already dead
+        assert!(!hierarchy.apply_damage(enemy, 1));
+    }
+
+    #[test]
+    fn death_runs_on_death_then_destroys() {
+        let mut hierarchy = test_hierarchy(vec![("Scene", tree_graph(vec![
+            saved_node("Scene", 0),
+            saved_node("Grunt", 9),
+            saved_node("Boss", 9),
+        ]))]);
+        CALL_ORDER.with(|o| o.borrow_mut().clear());
+        let scene = hierarchy.spawn_object("Scene", hierarchy.root);
+        let [grunt, boss] = ["Grunt", "Boss"].map(|name| hierarchy.find_by_name(scene, name).unwrap());
+        hierarchy.borrow_mut(grunt).health = Some(crate::node::Health::new(1));
+        hierarchy.borrow_mut(boss).health = Some(crate::node::Health { destroy_on_death: false, ..crate::node::Health::new(2) });
+        hierarchy.run_frame();
+
+        hierarchy.apply_damage(grunt, 1);
+        hierarchy.apply_damage(boss, 1);
+        assert!(CALL_ORDER.with(|o| o.take()).is_empty());
+        hierarchy.run_frame();
+        assert_eq!(CALL_ORDER.with(|o| o.take()), ["death Grunt"]);
+        assert!(hierarchy.try_borrow(grunt).is_none());
+
+        hierarchy.apply_damage(boss, 1);
+        hierarchy.run_frame();
+        assert_eq!(CALL_ORDER.with(|o| o.take()), ["death Boss"]);
+        assert!(hierarchy.borrow(boss).health.unwrap().is_dead());
+    }
+
+    #[test]
+    fn follow_tracks_target() {
+        let mut hierarchy = test_hierarchy(vec![("Scene", SavedNodeGraph { nodes: vec![
+            SavedNode { child_index: NonZeroU32::new(1), ..saved_node("Scene", 0) },
+            SavedNode { parent_index: Some(0), sibling_index: NonZeroU32::new(2), ..placed_node("Player", 50, 50, SavedNodeExtension::None) },
+            SavedNode { parent_index: Some(0), child_index: NonZeroU32::new(3), ..placed_node("Effects", 10, 10, SavedNodeExtension::None) },
+            SavedNode { parent_index: Some(2), ..saved_node("Shield", 0) },
+        ], backdrop_colour: None })]);
+        let scene = hierarchy.spawn_object("Scene", hierarchy.root);
+        let player = hierarchy.find_by_name(scene, "Player").unwrap();
+        let shield = hierarchy.find_by_name(hierarchy.root, "Shield").unwrap();
+        let pos = |x: i32, y: i32| Vec2::new(I20F12::from_num(x), I20F12::from_num(y));
+        hierarchy.borrow_mut(shield).follow = Some(crate::node::Follow { target: player, offset: pos(0, -8) });
+        hierarchy.run_frame();
+        assert_eq!(hierarchy.borrow(shield).global_transform.position(), pos(50, 42));
+        assert_eq!(hierarchy.borrow(shield).transform.position(), pos(40, 32));
+
+        hierarchy.borrow_mut(player).transform.x = I20F12::from_num(80);
+        hierarchy.run_frame();
+        assert_eq!(hierarchy.borrow(shield).global_transform.position(), pos(80, 42));
+
+        // Broken, so it stays behind
+        hierarchy.borrow_mut(shield).follow = None;
+        hierarchy.borrow_mut(player).transform.x = I20F12::from_num(100);
+        hierarchy.run_frame();
+        assert_eq!(hierarchy.borrow(shield).global_transform.position(), pos(80, 42));
+
+        hierarchy.borrow_mut(shield).follow = Some(crate::node::Follow { target: player, offset: pos(0, -8) });
+        hierarchy.destroy_node(player);
+        hierarchy.run_frame();
+        hierarchy.run_frame();
+        assert_eq!(hierarchy.borrow(shield).follow, None);
+    }
+
+    #[test]
+    fn on_screen_query() {
+        let sprite = || SavedNodeExtension::Sprite(SavedSpriteExtension {
+            graphic_asset: String::from("Box16"),
+            sprite_type: SavedSpriteType::Normal,
+        });
+        let mut hierarchy = test_hierarchy(vec![("Scene", tree_graph(vec![
+            saved_node("Scene", 0),
+            placed_node("Camera", 0, 0, SavedNodeExtension::Camera(SavedCameraExtension { active_main: true, active_sub: false })),
+            placed_node("Inside", 100, 100, sprite()),
+            placed_node("Partial", -8, 50, sprite()),
+            placed_node("Left", -40, 50, sprite()),
+            placed_node("Below", 0, 200, sprite()),
+        ]))]);
+        let scene = hierarchy.spawn_object("Scene", hierarchy.root);
+        let handles = ["Inside", "Partial", "Left", "Below"].map(|name| hierarchy.find_by_name(scene, name).unwrap());
+        assert_eq!(handles.map(|handle| hierarchy.is_on_screen(handle)), [true, true, false, false]);
+
+        // Moving the camera changes what's on screen
+        let camera = hierarchy.find_by_name(scene, "Camera").unwrap();
+        hierarchy.borrow_mut(camera).transform.x = I20F12::from_num(-100);
+        hierarchy.run_frame();
+        let [inside, _, left, _] = handles;
+        assert!(hierarchy.is_on_screen(left));
+        assert!(hierarchy.is_on_screen(inside));
+
+        // Nothing is being shown without an active camera
+        hierarchy.borrow_mut(camera).enabled = false;
+        hierarchy.run_frame();
+        assert!(!hierarchy.is_on_screen(inside));
+    }
+
+    #[test]
+    fn recycled_graph_reuses_slots() {
+        let mut hierarchy = test_hierarchy(vec![
+            ("Bullet", tree_graph(vec![saved_node("Bullet", 6), placed_node("Hitbox", 0, 0, collider(4))])),
+        ]);
+        hierarchy.set_graph_recycling("Bullet", true);
+        let bullet = hierarchy.spawn_object("Bullet", hierarchy.root);
+        for _ in 0..3 {
+            hierarchy.run_frame();
+        }
+        assert_eq!(hierarchy.borrow(bullet).cast_script::<CounterScript>().count, 3);
+        let pool_len = hierarchy.object_pool.vec_len();
+
+        hierarchy.destroy_node(bullet);
+        hierarchy.run_frame();
+        let new_bullet = hierarchy.spawn_object("Bullet", hierarchy.root);
+        assert_eq!(hierarchy.object_pool.vec_len(), pool_len);
+        // Same slot, but a new node - the old handle doesn't point at it
+        assert!(hierarchy.try_borrow(bullet).is_none());
+        let hitbox = hierarchy.first_child(new_bullet).unwrap();
+        let slots: Vec<_> = (1..pool_len).filter_map(|i| hierarchy.object_pool.handle_from_index_checked(i)).collect();
+        assert!(slots.len() == 2 && slots.contains(&new_bullet) && slots.contains(&hitbox));
+        assert_eq!(hierarchy.borrow(new_bullet).cast_script::<CounterScript>().count, 0);
+        hierarchy.run_frame();
+        assert_eq!(hierarchy.borrow(new_bullet).cast_script::<CounterScript>().count, 1);
+        assert_eq!(child_names(&hierarchy, new_bullet), ["Hitbox"]);
+
+        // Without recycling, the slots aren't reused
+        hierarchy.set_graph_recycling("Bullet", false);
+        hierarchy.destroy_node(new_bullet);
+        hierarchy.run_frame();
+        hierarchy.spawn_object("Bullet", hierarchy.root);
+        hierarchy.spawn_object("Bullet", hierarchy.root);
+        assert_eq!(hierarchy.object_pool.vec_len(), pool_len + 2);
+    }
+
+    #[test]
+    fn instance_limit_refuses_extra_spawns() {
+        let mut hierarchy = test_hierarchy(vec![("Bullet", tree_graph(vec![saved_node("Bullet", 0)]))]);
+        hierarchy.set_graph_instance_limit("Bullet", Some(3));
+        let bullets: Vec<_> = (0..3).map(|_| hierarchy.try_spawn_object("Bullet", hierarchy.root)).collect();
+        assert!(bullets.iter().all(Result::is_ok));
+        assert_eq!(hierarchy.graph_instance_count("Bullet"), 3);
+        assert_eq!(hierarchy.try_spawn_object("Bullet", hierarchy.root), Err(Error::InstanceLimitReached(String::from("Bullet"))));
+        assert_eq!(child_names(&hierarchy, hierarchy.root).len(), 3);
+        // Batches stop at the limit too
+        assert!(hierarchy.spawn_object_batch("Bullet", hierarchy.root, 2, |_| Transform::default()).unwrap().is_empty());
+
+        // Destroying one makes room for another
+        hierarchy.destroy_node(*bullets[0].as_ref().unwrap());
+        hierarchy.run_frame();
+        assert_eq!(hierarchy.graph_instance_count("Bullet"), 2);
+        assert_eq!(hierarchy.spawn_object_batch("Bullet", hierarchy.root, 2, |_| Transform::default()).unwrap().len(), 1);
+        assert_eq!(hierarchy.graph_instance_count("Bullet"), 3);
+
+        hierarchy.set_graph_instance_limit("Bullet", None);
+        assert!(hierarchy.try_spawn_object("Bullet", hierarchy.root).is_ok());
+    }
+
+    #[test]
+    fn replayed_inputs_give_same_positions() {
+        use crate::input::{Buttons, FrameInput};
+        let play = |inputs: &[FrameInput]| {
+            let mut hierarchy = test_hierarchy(vec![("Player", tree_graph(vec![saved_node("Player", 10)]))]);
+            let player = hierarchy.spawn_object("Player", hierarchy.root);
+            inputs.iter().map(|&input| {
+                hierarchy.step(input);
+                hierarchy.borrow(player).transform.position()
+            }).collect::<Vec<_>>()
+        };
+        let recorded: Vec<FrameInput> = (0..60u32).map(|i| FrameInput {
+            keys: match i % 7 {
+                0 | 1 => Buttons::RIGHT,
+                2 => Buttons::RIGHT | Buttons::DOWN,
+                5 => Buttons::DOWN,
+                _ => Buttons::empty(),
+            },
+        }).collect();
+        let positions = play(&recorded);
+        assert_eq!(play(&recorded), positions);
+        assert!(positions.last().unwrap().x > 0 && positions.last().unwrap().y > 0);
+    }
+
+    #[test]
+    fn recorded_input_replays_to_same_state() {
+        use crate::input::{Buttons, FrameInput, InputReplay};
+        let new_game = || {
+            let mut hierarchy = test_hierarchy(vec![("Player", tree_graph(vec![saved_node("Player", 10)]))]);
+            let player = hierarchy.spawn_object("Player", hierarchy.root);
+            (hierarchy, player)
+        };
+        let (mut hierarchy, player) = new_game();
+        let mut frame = 0u32;
+        hierarchy.set_input_provider(Box::new(move || {
+            frame += 1;
+            FrameInput {
+                keys: match frame % 6 {
+                    0 | 3 => Buttons::RIGHT | Buttons::DOWN,
+                    2 | 4 => Buttons::RIGHT,
+                    _ => Buttons::empty(),
+                },
+            }
+        }));
+        hierarchy.start_input_recording();
+        let positions: Vec<_> = (0..30).map(|_| {
+            hierarchy.run_frame();
+            hierarchy.borrow(player).transform.position()
+        }).collect();
+        let recording = hierarchy.stop_input_recording().unwrap();
+        assert_eq!(recording.frames().len(), 30);
+        assert!(hierarchy.stop_input_recording().is_none());
+
+        let (mut replayed, player) = new_game();
+        assert!(matches!(InputReplay::from_bytes(&[0xFF; 4]), Err(Error::SerializationError(_))));
+        let replay = InputReplay::from_bytes(&recording.to_bytes()).unwrap();
+        assert!(!replay.finished());
+        replayed.set_input_provider(Box::new(replay));
+        let replayed_positions: Vec<_> = (0..30).map(|_| {
+            replayed.run_frame();
+            replayed.borrow(player).transform.position()
+        }).collect();
+        assert_eq!(replayed_positions, positions);
+
+        // After the end, nothing is held (without hardware) and the player stays still
+        crate::input::set_keys(Buttons::empty());
+        replayed.run_frame();
+        assert_eq!(replayed.input(), FrameInput::default());
+        assert_eq!(replayed.borrow(player).transform.position(), positions[29]);
+    }
+
+    #[test]
+    fn restore_returns_to_snapshot() {
+        let mut health_node = placed_node("Tough", 0, 0, collider(8));
+        health_node.script_type_id = NonZeroU32::new(6);
+        let mut hierarchy = test_hierarchy(vec![
+            ("Level", tree_graph(vec![saved_node("Level", 0), saved_node("Mover", 4), saved_node("Counter", 6), health_node])),
+            ("Extra", tree_graph(vec![saved_node("Extra", 0)])),
+        ]);
+        hierarchy.set_scene("Level");
+        hierarchy.run_frame();
+        let tough = hierarchy.find_by_name(hierarchy.root, "Tough").unwrap();
+        hierarchy.borrow_mut(tough).health = Some(Health::new(5));
+        hierarchy.apply_damage(tough, 2);
+        for _ in 0..3 {
+            hierarchy.run_frame();
+        }
+        // Not started yet when the snapshot is taken
+        hierarchy.spawn_object("Level", hierarchy.root);
+
+        // What the nodes under the root look like: name, position, health, counter script count and started
+        let state = |hierarchy: &Hierarchy| {
+            hierarchy.subtree_handles(hierarchy.root).into_iter().skip(1).map(|h| {
+                let node = hierarchy.borrow(h);
+                let count = node.scripts.first().filter(|s| s.type_id.get() == 6).map(|_| node.cast_script::<CounterScript>().count);
+                (String::from(hierarchy.node_name(h)), node.transform.position(), node.health, count, node.started)
+            }).collect::<Vec<_>>()
+        };
+        let snapshot = hierarchy.snapshot();
+        let before = state(&hierarchy);
+        let frame_count = hierarchy.frame_count();
+        hierarchy.run_frame();
+        let after_one_frame = state(&hierarchy);
+
+        for _ in 0..5 {
+            hierarchy.run_frame();
+        }
+        hierarchy.spawn_object("Extra", hierarchy.root);
+        hierarchy.apply_damage(tough, 10);
+        hierarchy.run_frame();
+        assert_ne!(state(&hierarchy), before);
+
+        hierarchy.restore(&snapshot).unwrap();
+        assert_eq!(state(&hierarchy), before);
+        assert_eq!(hierarchy.frame_count(), frame_count);
+        assert_eq!(hierarchy.current_scene_name(), Some("Level"));
+        // Handles from before don't point at the restored nodes
+        assert!(hierarchy.try_borrow(tough).is_none());
+        // Carries on the same as it did the first time
+        hierarchy.run_frame();
+        assert_eq!(state(&hierarchy), after_one_frame);
+    }
+
+    #[test]
+    fn restore_keeps_node_settings() {
+        let mut hierarchy = test_hierarchy(vec![("Level", tree_graph(vec![
+            saved_node("Level", 0),
+            placed_node("Camera", 0, 0, SavedNodeExtension::Camera(SavedCameraExtension { active_main: true, active_sub: false })),
+            placed_node("Player", 20, 0, SavedNodeExtension::Sprite(SavedSpriteExtension { graphic_asset: String::from("Box16"), sprite_type: SavedSpriteType::Normal })),
+            placed_node("Floor", 0, 40, collider(64)),
+            saved_node("Shield", 0),
+        ]))]);
+        hierarchy.set_scene("Level");
+        hierarchy.run_frame();
+        let find = |hierarchy: &Hierarchy, name: &str| hierarchy.find_by_name(hierarchy.root, name).unwrap();
+        let (camera, player, floor, shield) = (find(&hierarchy, "Camera"), find(&hierarchy, "Player"), find(&hierarchy, "Floor"), find(&hierarchy, "Shield"));
+        let mut body = Body::new(I20F12::ONE);
+        body.velocity.x = I20F12::from_num(2);
+        body.set_contacts(None, Some(Contact { node: floor, normal: Vec2::new(I20F12::ZERO, I20F12::from_num(-1)) }));
+        let player_node = hierarchy.borrow_mut(player);
+        player_node.body = Some(body);
+        player_node.parallax = Some(I20F12::from_num(0.5));
+        player_node.spawn_animation = Some(SpawnAnimation::new(SpawnEffect::Mosaic, 10));
+        hierarchy.borrow_mut(shield).follow = Some(Follow { target: player, offset: Vec2::new(I20F12::ZERO, I20F12::from_num(-8)) });
+        let NodeExtensionHandle::Sprite(sprite) = hierarchy.borrow(player).node_extension else { panic!() };
+        let sprite = hierarchy.borrow_mut(sprite);
+        sprite.palette_override = Some(3);
+        sprite.blend_mode = BlendMode::Additive;
+        sprite.flash(2, 5);
+        let NodeExtensionHandle::Camera(camera_ext) = hierarchy.borrow(camera).node_extension else { panic!() };
+        hierarchy.borrow_mut(camera_ext).follow = Some(CameraFollow::new(player));
+        hierarchy.borrow_mut(camera_ext).shake(I20F12::from_num(4), 20);
+        hierarchy.set_sprite_mosaic(4, 2);
+
+        let snapshot = hierarchy.snapshot();
+        hierarchy.set_sprite_mosaic(1, 1);
+        hierarchy.restore(&snapshot).unwrap();
+        let (camera, player, floor, shield) = (find(&hierarchy, "Camera"), find(&hierarchy, "Player"), find(&hierarchy, "Floor"), find(&hierarchy, "Shield"));
+        let player_node = hierarchy.borrow(player);
+        let body = player_node.body.unwrap();
+        assert_eq!(body.velocity.x, I20F12::from_num(2));
+        assert_eq!(body.gravity, I20F12::ONE);
+        // References to other nodes point at the restored ones
+        assert_eq!(body.contacts().next().map(|c| c.node), Some(floor));
+        assert_eq!(player_node.parallax, Some(I20F12::from_num(0.5)));
+        assert_eq!(player_node.spawn_animation, Some(SpawnAnimation::new(SpawnEffect::Mosaic, 10)));
+        assert_eq!(hierarchy.borrow(shield).follow.map(|f| f.target), Some(player));
+        let NodeExtensionHandle::Sprite(sprite) = player_node.node_extension else { panic!() };
+        let sprite = hierarchy.borrow(sprite);
+        assert_eq!((sprite.palette_override, sprite.blend_mode, sprite.is_flashing()), (Some(3), BlendMode::Additive, true));
+        let NodeExtensionHandle::Camera(camera_ext) = hierarchy.borrow(camera).node_extension else { panic!() };
+        assert_eq!(hierarchy.borrow(camera_ext).follow.map(|f| f.target), Some(player));
+        assert!(hierarchy.borrow(camera_ext).shake.is_some());
+        assert_eq!(hierarchy.sprite_handler.mosaic_size, (4, 2));
+    }
+
+    #[test]
+    fn restore_refuses_malformed_snapshot() {
+        let mut hierarchy = test_hierarchy(vec![("Level", tree_graph(vec![saved_node("Level", 0), saved_node("Counter", 6)]))]);
+        let level = hierarchy.spawn_object("Level", hierarchy.root);
+        hierarchy.run_frame();
+        let snapshot = hierarchy.snapshot();
+
+        // A node without a state
+        let mut saved: SavedSnapshot = sandstone_common::deserialize(&snapshot);
+        saved.node_states.pop();
+        assert_eq!(hierarchy.restore(&sandstone_common::serialize(&saved)), Err(Error::MalformedSnapshot));
+        // Links that don't form a tree
+        let mut saved: SavedSnapshot = sandstone_common::deserialize(&snapshot);
+        saved.graphs[0].nodes[1].parent_index = None;
+        assert_eq!(hierarchy.restore(&sandstone_common::serialize(&saved)),
+            Err(Error::MalformedGraph(String::from("snapshot graph 0"), GraphError::MultipleRoots(1))));
+        // A follow target past the end
+        let mut saved: SavedSnapshot = sandstone_common::deserialize(&snapshot);
+        saved.node_states[1].follow = Some((2, (I20F12::ZERO, I20F12::ZERO)));
+        assert_eq!(hierarchy.restore(&sandstone_common::serialize(&saved)), Err(Error::MalformedSnapshot));
+
+        // None of them touched the hierarchy
+        assert!(hierarchy.try_borrow(level).is_some());
+        assert_eq!(hierarchy.subtree_handles(hierarchy.root).len(), 3);
+    }
+
+    #[test]
+    fn restore_pairs_script_states_by_type() {
+        let mut hierarchy = test_hierarchy(vec![("Player", tree_graph(vec![saved_node("Player", 6)]))]);
+        hierarchy.spawn_object("Player", hierarchy.root);
+        for _ in 0..3 {
+            hierarchy.run_frame();
+        }
+        // A first script that isn't registered any more, ahead of the counter
+        let mut saved: SavedSnapshot = sandstone_common::deserialize(&hierarchy.snapshot());
+        saved.node_states[0].scripts.insert(0, SavedScriptState { type_id: NonZeroU32::new(99).unwrap(), started: true, state: Some(vec![0xFF]) });
+        hierarchy.restore(&sandstone_common::serialize(&saved)).unwrap();
+        let player = hierarchy.first_child(hierarchy.root).unwrap();
+        assert_eq!(hierarchy.borrow(player).scripts.len(), 1);
+        assert_eq!(hierarchy.borrow(player).cast_script::<CounterScript>().count, 3);
+    }
+
+    #[test]
+    fn spawn_animation_holds_back_update() {
+        let mut counter = placed_node("Counter", 0, 0, SavedNodeExtension::Sprite(SavedSpriteExtension {
+            graphic_asset: String::from("Box16"),
+            sprite_type: SavedSpriteType::Affine(sandstone_common::SavedAffineSpriteData {
+                rotation: I20F12::ZERO,
+                scale_x: I20F12::from_num(2),
+                scale_y: I20F12::ONE,
+            }),
+        }));
+        counter.script_type_id = NonZeroU32::new(6);
+        let mut hierarchy = test_hierarchy(vec![("Scene", tree_graph(vec![saved_node("Scene", 0), counter]))]);
+        let scene = hierarchy.spawn_object("Scene", hierarchy.root);
+        let counter = hierarchy.find_by_name(scene, "Counter").unwrap();
+        hierarchy.borrow_mut(counter).spawn_animation = Some(SpawnAnimation::new(SpawnEffect::ScaleUp, 3));
+        let count = |hierarchy: &Hierarchy| hierarchy.borrow(counter).cast_script::<CounterScript>().count;
+        let scale = |hierarchy: &Hierarchy| {
+            let NodeExtensionHandle::Sprite(sprite) = hierarchy.borrow(counter).node_extension else { panic!() };
+            let SavedSpriteType::Affine(affine) = hierarchy.node_ext_pools.sprite_pool.borrow(sprite).sprite_type else { panic!() };
+            (affine.scale_x, affine.scale_y)
+        };
+        // Being spawned enabled doesn't count as becoming enabled
+        hierarchy.run_frame();
+        assert_eq!(count(&hierarchy), 1);
+
+        hierarchy.borrow_mut(scene).enabled = false;
+        hierarchy.run_frame();
+        hierarchy.borrow_mut(scene).enabled = true;
+        for frame in 1..=3 {
+            hierarchy.run_frame();
+            assert_eq!(count(&hierarchy), 1);
+            assert!(hierarchy.borrow(counter).spawn_animation.unwrap().is_playing());
+            assert_eq!(scale(&hierarchy).0, I20F12::from_num(2) * (I20F12::from_num(frame) / 3));
+        }
+        hierarchy.run_frame();
+        assert_eq!(count(&hierarchy), 2);
+        assert!(!hierarchy.borrow(counter).spawn_animation.unwrap().is_playing());
+        assert_eq!(scale(&hierarchy), (I20F12::from_num(2), I20F12::ONE));
+    }
+
+    #[test]
+    fn scene_backdrop_colour() {
+        let mut dark = tree_graph(vec![saved_node("Dark", 0)]);
+        dark.backdrop_colour = Some(0x101010);
+        let mut hierarchy = test_hierarchy(vec![
+            ("DarkScene", dark),
+            ("PlainScene", tree_graph(vec![saved_node("Plain", 0)])),
+        ]);
+        assert_eq!(hierarchy.backdrop_colour(), None);
+        hierarchy.set_scene("DarkScene");
+        hierarchy.run_frame();
+        assert_eq!(hierarchy.backdrop_colour(), Some(0x101010));
+
+        // A scene without a colour keeps whatever was set last
+        hierarchy.set_backdrop_colour(0x3A2E3F);
+        hierarchy.set_scene("PlainScene");
+        hierarchy.run_frame();
+        assert_eq!(hierarchy.backdrop_colour(), Some(0x3A2E3F));
+    }
+
+    #[test]
+    fn graphic_unloaded_after_last_sprite() {
+        let sprite = || SavedNodeExtension::Sprite(SavedSpriteExtension {
+            graphic_asset: String::from("Box16"),
+            sprite_type: SavedSpriteType::Normal,
+        });
+        let mut hierarchy = test_hierarchy(vec![("Box", tree_graph(vec![placed_node("Box", 0, 0, sprite())]))]);
+        // Nothing is loaded until a sprite needs it
+        assert_eq!(hierarchy.graphic_vram_slot("Box16"), None);
+        let first = hierarchy.spawn_object("Box", hierarchy.root);
+        let second = hierarchy.spawn_object("Box", hierarchy.root);
+        assert_eq!(hierarchy.graphic_ref_count("Box16"), 2);
+
+        hierarchy.destroy_node(first);
+        hierarchy.run_frame();
+        assert_eq!(hierarchy.graphic_ref_count("Box16"), 1);
+        assert!(hierarchy.graphic_vram_slot("Box16").is_some());
+
+        hierarchy.destroy_node(second);
+        hierarchy.run_frame();
+        assert_eq!(hierarchy.graphic_ref_count("Box16"), 0);
+        assert_eq!(hierarchy.graphic_vram_slot("Box16"), None);
+    }
+
+    #[test]
+    fn pinned_graphic_stays_loaded() {
+        let sprite = SavedNodeExtension::Sprite(SavedSpriteExtension {
+            graphic_asset: String::from("Box16"),
+            sprite_type: SavedSpriteType::Normal,
+        });
+        let mut hierarchy = test_hierarchy(vec![("Box", tree_graph(vec![placed_node("Box", 0, 0, sprite)]))]);
+        assert_eq!(hierarchy.pin_graphic("Box16"), Ok(()));
+        let slot = hierarchy.graphic_vram_slot("Box16");
+        assert!(slot.is_some());
+
+        let box_node = hierarchy.spawn_object("Box", hierarchy.root);
+        hierarchy.destroy_node(box_node);
+        hierarchy.run_frame();
+        assert_eq!(hierarchy.graphic_vram_slot("Box16"), slot);
+
+        hierarchy.unpin_graphic("Box16");
+        assert_eq!(hierarchy.graphic_vram_slot("Box16"), None);
+        assert_eq!(hierarchy.pin_graphic("Missing"), Err(Error::UnknownGraphic(String::from("Missing"))));
+    }
+
+    #[test]
+    fn sprites_share_graphic_upload() {
+        let sprite = || SavedNodeExtension::Sprite(SavedSpriteExtension {
+            graphic_asset: String::from("Box16"),
+            sprite_type: SavedSpriteType::Normal,
+        });
+        let mut hierarchy = test_hierarchy(vec![("Scene", tree_graph(vec![
+            saved_node("Scene", 0),
+            placed_node("Left", 0, 0, sprite()),
+            placed_node("Right", 32, 0, sprite()),
+        ]))]);
+        hierarchy.spawn_object("Scene", hierarchy.root);
+        assert_eq!(hierarchy.graphic_upload_count(), 1);
+        assert_eq!(hierarchy.graphic_ref_count("Box16"), 2);
+    }
+
+    #[test]
+    fn swap_sprite_graphic() {
+        let sprite = SavedNodeExtension::Sprite(SavedSpriteExtension {
+            graphic_asset: String::from("Box16"),
+            sprite_type: SavedSpriteType::Normal,
+        });
+        let mut hierarchy = test_hierarchy(vec![("Player", tree_graph(vec![placed_node("Player", 0, 0, sprite)]))]);
+        let player = hierarchy.spawn_object("Player", hierarchy.root);
+        assert_eq!(hierarchy.graphic_vram_slot("Box16").unwrap().tile_index, 0);
+
+        assert_eq!(hierarchy.set_sprite_graphic(player, "Box32"), Ok(()));
+        // The new graphic is loaded before the old one is let go, so it goes after it
+        assert_eq!(hierarchy.graphic_vram_slot("Box32").unwrap().tile_index, 4);
+        assert_eq!(hierarchy.graphic_ref_count("Box32"), 1);
+        assert_eq!(hierarchy.graphic_ref_count("Box16"), 0);
+        assert_eq!(hierarchy.graphic_vram_slot("Box16"), None);
+        assert_eq!(hierarchy.world_bounds(player).size(), Vec2::new(I20F12::from_num(32), I20F12::from_num(32)));
+
+        // Unknown graphics leave the sprite alone
+        assert_eq!(hierarchy.set_sprite_graphic(player, "Missing"), Err(Error::UnknownGraphic(String::from("Missing"))));
+        hierarchy.run_frame();
+        assert_eq!(hierarchy.graphic_ref_count("Box32"), 1);
+    }
+
+    #[test]
+    fn sprite_with_missing_graphic_has_point_bounds() {
+        let sprite = SavedNodeExtension::Sprite(SavedSpriteExtension {
+            graphic_asset: String::from("Missing"),
+            sprite_type: SavedSpriteType::Normal,
+        });
+        let mut hierarchy = test_hierarchy(vec![("Scene", tree_graph(vec![
+            saved_node("Scene", 0),
+            placed_node("Camera", 0, 0, SavedNodeExtension::Camera(SavedCameraExtension { active_main: true, active_sub: false })),
+            placed_node("Player", 10, 20, sprite),
+        ]))]);
+        let scene = hierarchy.spawn_object("Scene", hierarchy.root);
+        let player = hierarchy.find_by_name(scene, "Player").unwrap();
+        hierarchy.borrow_mut(player).offscreen_destroy_margin = Some(I20F12::from_num(8));
+        assert_eq!(hierarchy.world_bounds(player), Rect::from_pos_size(Vec2::new(I20F12::from_num(10), I20F12::from_num(20)), Vec2::ZERO));
+        // Everything that looks at bounds every frame carries on, and the point is on screen so it isn't destroyed
+        for _ in 0..3 {
+            hierarchy.run_frame();
+        }
+        assert!(hierarchy.try_borrow(player).is_some());
+        assert!(hierarchy.query_aabb(Rect::from_pos_size(Vec2::ZERO, Vec2::new(I20F12::from_num(32), I20F12::from_num(32)))).contains(&player));
+    }
+
+    #[test]
+    fn body_free_fall() {
+        let mut hierarchy = test_hierarchy(vec![("Ball", tree_graph(vec![saved_node("Ball", 0)]))]);
+        let ball = hierarchy.spawn_object("Ball", hierarchy.root);
+        hierarchy.borrow_mut(ball).body = Some(crate::node::Body::new(I20F12::ONE));
+        for _ in 0..3 {
+            hierarchy.run_frame();
+        }
+        // Velocity goes 1, 2, 3, so it's fallen 1 + 2 + 3
+        assert_eq!(hierarchy.borrow(ball).transform.y, I20F12::from_num(6));
+        assert_eq!(hierarchy.borrow(ball).body.unwrap().velocity.y, I20F12::from_num(3));
+
+        // Paused bodies don't move
+        hierarchy.set_paused(true);
+        hierarchy.run_frame();
+        assert_eq!(hierarchy.borrow(ball).transform.y, I20F12::from_num(6));
+    }
+
+    #[test]
+    fn body_lands_on_solid_floor() {
+        let mut hierarchy = test_hierarchy(vec![("Level", tree_graph(vec![
+            saved_node("Level", 0),
+            placed_node("Player", 0, 0, collider(8)),
+            placed_node("Floor", -50, 20, SavedNodeExtension::RectCollider(SavedRectColliderExtension {
+                width: I20F12::from_num(100), height: I20F12::from_num(8), offset_x: I20F12::ZERO, offset_y: I20F12::ZERO,
+                response: CollisionResponse::Solid, is_static: false, continuous: false,
+            })),
+        ]))]);
+        let level = hierarchy.spawn_object("Level", hierarchy.root);
+        let player = hierarchy.find_by_name(level, "Player").unwrap();
+        let mut body = crate::node::Body::new(I20F12::ONE);
+        body.velocity.x = I20F12::ONE;
+        hierarchy.borrow_mut(player).body = Some(body);
+
+        for _ in 0..10 {
+            hierarchy.run_frame();
+        }
+        // Resting with its bottom on the floor's top, still sliding along it
+        let player_node = hierarchy.borrow(player);
+        assert_eq!(player_node.transform.y, I20F12::from_num(12));
+        assert_eq!(player_node.transform.x, I20F12::from_num(10));
+        assert_eq!(player_node.body.unwrap().velocity, Vec2::new(I20F12::ONE, I20F12::ZERO));
+    }
+
+    #[test]
+    fn one_way_platform() {
+        let platform = SavedNodeExtension::RectCollider(SavedRectColliderExtension {
+            width: I20F12::from_num(100), height: I20F12::from_num(4), offset_x: I20F12::ZERO, offset_y: I20F12::ZERO,
+            response: CollisionResponse::OneWayUp, is_static: false, continuous: false,
+        });
+        let mut hierarchy = test_hierarchy(vec![("Level", tree_graph(vec![
+            saved_node("Level", 0),
+            placed_node("Faller", 0, 0, collider(8)),
+            placed_node("Jumper", 20, 40, collider(8)),
+            placed_node("Platform", -50, 20, platform),
+            placed_node("Coin", 20, 10, collider(8)),
+        ]))]);
+        let level = hierarchy.spawn_object("Level", hierarchy.root);
+        let faller = hierarchy.find_by_name(level, "Faller").unwrap();
+        let jumper = hierarchy.find_by_name(level, "Jumper").unwrap();
+        hierarchy.borrow_mut(faller).body = Some(crate::node::Body::new(I20F12::ONE));
+        let mut jump = crate::node::Body::new(I20F12::ZERO);
+        jump.velocity.y = I20F12::from_num(-4);
+        hierarchy.borrow_mut(jumper).body = Some(jump);
+
+        let coin = hierarchy.find_by_name(level, "Coin").unwrap();
+        let NodeExtensionHandle::RectCollider(coin_collider) = hierarchy.borrow(coin).node_extension else { panic!() };
+
+        let mut coin_touched = false;
+        for _ in 0..10 {
+            hierarchy.run_frame();
+            coin_touched |= hierarchy.borrow(coin_collider).intersect_list.contains(&jumper);
+        }
+        // The faller lands on top, and the jumper goes straight up through it
+        assert_eq!(hierarchy.borrow(faller).transform.y, I20F12::from_num(12));
+        assert_eq!(hierarchy.borrow(jumper).transform.y, I20F12::from_num(0));
+        // The coin is a trigger, so it didn't stop the jumper but still saw it go past
+        assert!(coin_touched);
+    }
+
+    #[test]
+    fn body_contact_normals() {
+        let wide = |width: i32| SavedNodeExtension::RectCollider(SavedRectColliderExtension {
+            width: I20F12::from_num(width), height: I20F12::from_num(8), offset_x: I20F12::ZERO, offset_y: I20F12::ZERO,
+            response: CollisionResponse::Solid, is_static: false, continuous: false,
+        });
+        let mut hierarchy = test_hierarchy(vec![("Level", tree_graph(vec![
+            saved_node("Level", 0),
+            placed_node("Player", 0, 0, collider(8)),
+            placed_node("Floor", -50, 20, wide(100)),
+            placed_node("Wall", 20, -40, wide(8)),
+        ]))]);
+        let level = hierarchy.spawn_object("Level", hierarchy.root);
+        let player = hierarchy.find_by_name(level, "Player").unwrap();
+        let floor = hierarchy.find_by_name(level, "Floor").unwrap();
+        let wall = hierarchy.find_by_name(level, "Wall").unwrap();
+        hierarchy.borrow_mut(player).body = Some(crate::node::Body::new(I20F12::ONE));
+        for _ in 0..10 {
+            hierarchy.run_frame();
+        }
+        assert!(hierarchy.grounded(player));
+        let contacts: Vec<Contact> = hierarchy.borrow(player).body.unwrap().contacts().copied().collect();
+        assert_eq!(contacts, vec![Contact { node: floor, normal: Vec2::new(I20F12::ZERO, I20F12::from_num(-1)) }]);
+
+        // Keep walking right into the wall, which is moved down to the player's height
+        hierarchy.borrow_mut(wall).transform.y = I20F12::from_num(12);
+        for _ in 0..5 {
+            hierarchy.borrow_mut(player).body.as_mut().unwrap().velocity.x = I20F12::from_num(4);
+            hierarchy.run_frame();
+        }
+        let body = hierarchy.borrow(player).body.unwrap();
+        assert_eq!(hierarchy.borrow(player).transform.x, I20F12::from_num(12));
+        assert_eq!(body.contacts().next(), Some(&Contact { node: wall, normal: Vec2::new(I20F12::from_num(-1), I20F12::ZERO) }));
+        assert!(body.is_grounded());
+    }
+
+    #[test]
+    fn camera_follow_dead_zone() {
+        use crate::node::camera::CameraFollow;
+        let mut hierarchy = test_hierarchy(vec![("Scene", tree_graph(vec![
+            saved_node("Scene", 0),
+            placed_node("Camera", 0, 0, SavedNodeExtension::Camera(SavedCameraExtension { active_main: true, active_sub: false })),
+            placed_node("Player", 128, 96, SavedNodeExtension::None),
+        ]))]);
+        let scene = hierarchy.spawn_object("Scene", hierarchy.root);
+        let camera = hierarchy.find_by_name(scene, "Camera").unwrap();
+        let player = hierarchy.find_by_name(scene, "Player").unwrap();
+        let NodeExtensionHandle::Camera(camera_ext) = hierarchy.borrow(camera).node_extension else { panic!() };
+        hierarchy.borrow_mut(camera_ext).follow = Some(CameraFollow {
+            stiffness: I20F12::from_num(0.5),
+            dead_zone: Some(Vec2::new(I20F12::from_num(16), I20F12::from_num(16))),
+            ..CameraFollow::new(player)
+        });
+
+        // Moving around inside the dead zone doesn't scroll
+        hierarchy.borrow_mut(player).transform.x = I20F12::from_num(140);
+        hierarchy.run_frame();
+        assert_eq!(hierarchy.borrow(camera).transform.x, I20F12::ZERO);
+
+        // Leaving it eases the camera along until the player is back at the edge of the dead zone
+        hierarchy.borrow_mut(player).transform.x = I20F12::from_num(176);
+        let mut xs = Vec::new();
+        for _ in 0..3 {
+            hierarchy.run_frame();
+            xs.push(hierarchy.borrow(camera).transform.x);
+        }
+        assert_eq!(xs, vec![I20F12::from_num(16), I20F12::from_num(24), I20F12::from_num(28)]);
+        assert_eq!(hierarchy.borrow(camera).transform.y, I20F12::ZERO);
+    }
+
+    #[test]
+    fn camera_shake_decays() {
+        let mut hierarchy = test_hierarchy(vec![("Scene", tree_graph(vec![
+            saved_node("Scene", 0),
+            placed_node("Camera", 10, 20, SavedNodeExtension::Camera(SavedCameraExtension { active_main: true, active_sub: false })),
+        ]))]);
+        let scene = hierarchy.spawn_object("Scene", hierarchy.root);
+        let camera = hierarchy.find_by_name(scene, "Camera").unwrap();
+        let NodeExtensionHandle::Camera(camera_ext) = hierarchy.borrow(camera).node_extension else { panic!() };
+        hierarchy.run_frame();
+
+        hierarchy.shake_camera(I20F12::from_num(8), 4);
+        let mut shook = false;
+        for frames_left in (0..4).rev() {
+            hierarchy.run_frame();
+            let offset = hierarchy.borrow(camera_ext).shake_offset();
+            let range = I20F12::from_num(2 * frames_left);
+            assert!(offset.x.abs() <= range && offset.y.abs() <= range);
+            shook |= offset != Vec2::ZERO;
+        }
+        assert!(shook);
+        // Back to where it started, and the camera itself never moved
+        assert_eq!(hierarchy.borrow(camera_ext).shake_offset(), Vec2::ZERO);
+        assert_eq!(hierarchy.borrow(camera_ext).draw_position(&hierarchy), (I20F12::from_num(10), I20F12::from_num(20)));
+        assert_eq!(hierarchy.borrow(camera).transform.position(), Vec2::new(I20F12::from_num(10), I20F12::from_num(20)));
+    }
+
+    #[test]
+    fn parallax_scrolls_slower() {
+        let mut hierarchy = test_hierarchy(vec![("Scene", tree_graph(vec![
+            saved_node("Scene", 0),
+            placed_node("Camera", 0, 0, SavedNodeExtension::Camera(SavedCameraExtension { active_main: true, active_sub: false })),
+            placed_node("Background", 0, 0, SavedNodeExtension::None),
+            placed_node("Foreground", 0, 0, SavedNodeExtension::None),
+        ]))]);
+        let scene = hierarchy.spawn_object("Scene", hierarchy.root);
+        let camera = hierarchy.find_by_name(scene, "Camera").unwrap();
+        let background = hierarchy.find_by_name(scene, "Background").unwrap();
+        let foreground = hierarchy.find_by_name(scene, "Foreground").unwrap();
+        hierarchy.borrow_mut(background).parallax = Some(I20F12::from_num(0.5));
+        hierarchy.run_frame();
+        let origin = Some(Vec2::new(I20F12::ZERO, I20F12::ZERO));
+        assert_eq!((hierarchy.screen_position(background), hierarchy.screen_position(foreground)), (origin, origin));
+
+        hierarchy.borrow_mut(camera).transform.x = I20F12::from_num(40);
+        hierarchy.run_frame();
+        assert_eq!(hierarchy.screen_position(foreground), Some(Vec2::new(I20F12::from_num(-40), I20F12::ZERO)));
+        assert_eq!(hierarchy.screen_position(background), Some(Vec2::new(I20F12::from_num(-20), I20F12::ZERO)));
+    }
+
+    #[test]
+    fn node_names_are_interned() {
+        let mut hierarchy = test_hierarchy(vec![("Scene", tree_graph(vec![
+            saved_node("Scene", 0),
+            saved_node("Enemy", 0),
+            saved_node("Enemy", 0),
+            saved_node("Player", 0),
+        ]))]);
+        let scene = hierarchy.spawn_object("Scene", hierarchy.root);
+        let children = hierarchy.subtree_handles(scene);
+        let (first, second, player) = (children[1], children[2], children[3]);
+        assert_eq!(hierarchy.borrow(first).name, hierarchy.borrow(second).name);
+        assert_ne!(hierarchy.borrow(first).name, hierarchy.borrow(player).name);
+        assert_eq!(hierarchy.name_id("Enemy"), Some(hierarchy.borrow(first).name));
+        assert_eq!(hierarchy.node_name(second), "Enemy");
+
+        // Renaming interns the new name, and saving turns it back into a string
+        hierarchy.set_node_name(second, "Player");
+        assert_eq!(hierarchy.borrow(second).name, hierarchy.borrow(player).name);
+        assert_eq!(hierarchy.to_saved_graph(second).nodes[0].name, "Player");
+        assert_eq!(hierarchy.name_id("Boss"), None);
+        assert_eq!(hierarchy.find_by_name(scene, "Boss"), None);
+    }
+
+    #[test]
+    fn tree_links_survive_slot_reuse() {
+        let mut hierarchy = test_hierarchy(vec![
+            ("Scene", tree_graph(vec![saved_node("Scene", 0), saved_node("Wall", 0)])),
+            ("Enemy", tree_graph(vec![saved_node("Enemy", 0), saved_node("Gun", 0), saved_node("Shield", 0)])),
+        ]);
+        hierarchy.set_graph_recycling("Enemy", true);
+        let scene = hierarchy.spawn_object("Scene", hierarchy.root);
+        let old_enemy = hierarchy.spawn_object("Enemy", scene);
+        hierarchy.destroy_node(old_enemy);
+        hierarchy.run_frame();
+        let pool_len = hierarchy.object_pool.vec_len();
+        let enemy = hierarchy.spawn_object("Enemy", scene);
+
+        // The new copy is in the old one's slots, but the old handle is still stale
+        assert_eq!(hierarchy.object_pool.vec_len(), pool_len);
+        assert!(hierarchy.try_borrow(old_enemy).is_none());
+        assert_eq!(hierarchy.parent(enemy), Some(scene));
+        assert_eq!(hierarchy.first_child(scene), Some(enemy));
+        let gun = hierarchy.first_child(enemy).unwrap();
+        assert_eq!(hierarchy.node_name(hierarchy.next_sibling(gun).unwrap()), "Shield");
+        assert!(hierarchy.is_ancestor_of(scene, gun));
+        let names: Vec<&str> = hierarchy.subtree_handles(scene).into_iter().map(|h| hierarchy.node_name(h)).collect();
+        assert_eq!(names, ["Scene", "Enemy", "Gun", "Shield", "Wall"]);
+    }
+
+    #[test]
+    fn recoverable_failures_return_errors() {
+        let mut broken = tree_graph(vec![saved_node("Broken", 0), saved_node("Child", 0)]);
+        broken.nodes[1].parent_index = None;
+        let mut hierarchy = test_hierarchy(vec![
+            ("Scene", tree_graph(vec![saved_node("Scene", 0)])),
+            ("Broken", broken),
+        ]);
+        let root = hierarchy.root;
+        assert_eq!(hierarchy.try_spawn_object("Missing", root), Err(Error::UnknownGraph(String::from("Missing"))));
+        assert_eq!(hierarchy.spawn_object_batch("Missing", root, 2, |_| Transform::default()), Err(Error::UnknownGraph(String::from("Missing"))));
+        assert_eq!(hierarchy.try_spawn_object("Broken", root), Err(Error::MalformedGraph(String::from("Broken"), GraphError::MultipleRoots(1))));
+
+        let scene = hierarchy.spawn_object("Scene", root);
+        assert_eq!(hierarchy.set_sprite_graphic(scene, "Box16"), Err(Error::NotASprite));
+        hierarchy.destroy_node(scene);
+        hierarchy.run_frame();
+        assert_eq!(hierarchy.set_sprite_graphic(scene, "Box16"), Err(Error::InvalidHandle));
+
+        // A bad snapshot is refused without touching the hierarchy
+        let kept = hierarchy.spawn_object("Scene", root);
+        assert!(matches!(hierarchy.restore(&[0xFF; 4]), Err(Error::SerializationError(_))));
+        assert!(hierarchy.try_borrow(kept).is_some());
+    }
+
+    #[test]
+    fn build_node_under_parent() {
+        let mut hierarchy = test_hierarchy(vec![("Scene", tree_graph(vec![saved_node("Scene", 0), saved_node("Wall", 0)]))]);
+        let scene = hierarchy.spawn_object("Scene", hierarchy.root);
+        let player = hierarchy.build_node("Player")
+            .transform(Transform { x: I20F12::from_num(24), y: I20F12::from_num(8) })
+            .extension(SavedNodeExtension::Sprite(SavedSpriteExtension {
+                graphic_asset: String::from("Box16"),
+                sprite_type: SavedSpriteType::Normal,
+            }))
+            .script_id(NonZeroU32::new(1).unwrap())
+            .user_data(7)
+            .build_under(scene);
+
+        assert_eq!(hierarchy.parent(player), Some(scene));
+        assert_eq!(hierarchy.first_child(scene), Some(player));
+        assert_eq!(hierarchy.node_name(player), "Player");
+        let node = hierarchy.borrow(player);
+        assert_eq!(node.transform.position(), Vec2::new(I20F12::from_num(24), I20F12::from_num(8)));
+        assert_eq!(node.user_data, 7);
+        assert!(node.try_cast_script::<TestScript>().is_ok());
+        let NodeExtensionHandle::Sprite(sprite) = node.node_extension else { panic!() };
+        assert_eq!(hierarchy.borrow(sprite).node_handle, player);
+        assert_eq!(hierarchy.graphic_ref_count("Box16"), 1);
+        assert_eq!(hierarchy.world_bounds(player).size(), Vec2::new(I20F12::from_num(16), I20F12::from_num(16)));
+    }
+
+    #[test]
+    fn multiple_scripts_all_update() {
+        CALL_ORDER.with(|o| o.borrow_mut().clear());
+        let mut hierarchy = test_hierarchy(vec![("Player", tree_graph(vec![saved_node("Player", 7)]))]);
+        let player = hierarchy.spawn_object("Player", hierarchy.root);
+        assert_eq!(hierarchy.add_script(player, CounterScript::type_id()), Ok(()));
+        assert_eq!(hierarchy.add_script(player, NonZeroU32::new(99).unwrap()), Err(Error::UnknownScript(NonZeroU32::new(99).unwrap())));
+        for _ in 0..3 {
+            hierarchy.run_frame();
+        }
+        assert_eq!(hierarchy.borrow(player).scripts.len(), 2);
+        assert_eq!(hierarchy.borrow(player).cast_script::<CounterScript>().count, 3);
+        assert_eq!(CALL_ORDER.with(|o| o.borrow().clone()), ["start Player", "update Player", "update Player", "update Player"]);
+
+        // Snapshots keep the extra scripts and their state
+        let snapshot = hierarchy.snapshot();
+        hierarchy.restore(&snapshot).unwrap();
+        let player = hierarchy.first_child(hierarchy.root).unwrap();
+        assert_eq!(hierarchy.borrow(player).scripts.len(), 2);
+        assert_eq!(hierarchy.borrow(player).cast_script::<CounterScript>().count, 3);
+        hierarchy.run_frame();
+        assert_eq!(hierarchy.borrow(player).cast_script::<CounterScript>().count, 4);
+    }
+
+    #[test]
+    fn script_added_after_start_gets_own_start() {
+        CALL_ORDER.with(|o| o.borrow_mut().clear());
+        let mut hierarchy = test_hierarchy(vec![("Player", tree_graph(vec![saved_node("Player", 6)]))]);
+        let player = hierarchy.spawn_object("Player", hierarchy.root);
+        hierarchy.run_frame();
+        hierarchy.run_frame();
+        hierarchy.add_script(player, NonZeroU32::new(7).unwrap()).unwrap();
+        hierarchy.run_frame();
+        hierarchy.run_frame();
+
+        // The first script isn't started again, and the new one starts before it updates
+        assert_eq!(hierarchy.borrow(player).cast_script::<CounterScript>().count, 4);
+        assert_eq!(CALL_ORDER.with(|o| o.borrow().clone()), ["start Player", "update Player", "update Player"]);
+    }
 }
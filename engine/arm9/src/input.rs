@@ -0,0 +1,372 @@
+//! Reading the DS buttons.
+//! Each frame's input comes from the hierarchy's `InputProvider`, which reads the real buttons unless it's
+//! been replaced, e.g. to replay recorded input. Scripts see that frame's input through `read_keys`.
+//! With the `no-hardware` feature, there are no real buttons - use `set_keys` to say which are held.
+
+#[cfg(feature = "hardware")]
+pub use ironds::input::Buttons;
+
+#[cfg(feature = "no-hardware")]
+pub use fake::{Buttons, set_keys};
+
+use alloc::vec::Vec;
+use crate::Error;
+
+#[cfg(feature = "hardware")]
+static FRAME_KEYS: core::sync::atomic::AtomicU16 = core::sync::atomic::AtomicU16::new(0);
+
+#[cfg(feature = "no-hardware")]
+std::thread_local! {
+    // Thread local so that tests running in parallel don't see each other's frames
+    static FRAME_KEYS: core::cell::Cell<u16> = const { core::cell::Cell::new(0) };
+}
+
+/// Buttons held in the frame that's running, as given by the input provider.
+#[must_use]
+pub fn read_keys() -> Buttons {
+    #[cfg(feature = "hardware")]
+    return Buttons::from_bits_truncate(FRAME_KEYS.load(core::sync::atomic::Ordering::Relaxed));
+    #[cfg(feature = "no-hardware")]
+    return Buttons::from_bits_truncate(FRAME_KEYS.with(core::cell::Cell::get));
+}
+
+// Makes this the input that read_keys gives, until the next frame
+pub(crate) fn set_frame_input(input: FrameInput) {
+    #[cfg(feature = "hardware")]
+    FRAME_KEYS.store(input.keys.bits(), core::sync::atomic::Ordering::Relaxed);
+    #[cfg(feature = "no-hardware")]
+    FRAME_KEYS.with(|k| k.set(input.keys.bits()));
+}
+
+/// Where the hierarchy gets each frame's input from. See `Hierarchy::set_input_provider`.
+/// Closures returning a `FrameInput` can be used as providers.
+pub trait InputProvider {
+    /// Input for the next frame. Called once at the start of every `Hierarchy::run_frame`.
+    fn next_frame(&mut self) -> FrameInput;
+}
+
+impl<F: FnMut() -> FrameInput> InputProvider for F {
+    fn next_frame(&mut self) -> FrameInput {
+        self()
+    }
+}
+
+/// Reads the real buttons each frame. This is the provider a hierarchy starts with.
+pub struct HardwareInput;
+
+impl InputProvider for HardwareInput {
+    fn next_frame(&mut self) -> FrameInput {
+        FrameInput::read()
+    }
+}
+
+/// Everything the player did in a frame, as given to `Hierarchy::step`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct FrameInput {
+    /// Buttons held down this frame.
+    pub keys: Buttons,
+}
+
+impl FrameInput {
+    /// The current state of the real buttons, rather than the frame's input.
+    #[must_use]
+    pub fn read() -> Self {
+        #[cfg(feature = "hardware")]
+        return Self { keys: ironds::input::read_keys() };
+        #[cfg(feature = "no-hardware")]
+        return Self { keys: fake::held_keys() };
+    }
+}
+
+/// Every frame's input, in order, for replaying later with `InputReplay` to reproduce a bug or play a demo.
+/// Started with `Hierarchy::start_input_recording`.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct InputRecorder {
+    frames: Vec<FrameInput>,
+}
+
+impl InputRecorder {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self { frames: Vec::new() }
+    }
+
+    pub fn record(&mut self, input: FrameInput) {
+        self.frames.push(input);
+    }
+
+    #[must_use]
+    pub fn frames(&self) -> &[FrameInput] {
+        &self.frames
+    }
+
+    /// The recording, serialized so it can be saved.
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let keys: Vec<u16> = self.frames.iter().map(|f| f.keys.bits()).collect();
+        sandstone_common::serialize(&keys)
+    }
+}
+
+/// An input provider that plays back a recording, a frame at a time.
+/// Once it runs out, it goes back to reading the real buttons, so the player can take over.
+pub struct InputReplay {
+    frames: Vec<FrameInput>,
+    next: usize,
+}
+
+impl InputReplay {
+    #[must_use]
+    pub fn new(recording: InputRecorder) -> Self {
+        Self { frames: recording.frames, next: 0 }
+    }
+
+    /// Plays back a recording from `InputRecorder::to_bytes`.
+    /// If the bytes aren't a valid recording, an error is returned instead.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let keys: Vec<u16> = sandstone_common::try_deserialize(bytes)?;
+        let frames = keys.into_iter().map(|k| FrameInput { keys: Buttons::from_bits_truncate(k) }).collect();
+        Ok(Self { frames, next: 0 })
+    }
+
+    /// True once every recorded frame has been played.
+    #[must_use]
+    pub fn finished(&self) -> bool {
+        self.next >= self.frames.len()
+    }
+}
+
+impl InputProvider for InputReplay {
+    fn next_frame(&mut self) -> FrameInput {
+        let Some(&input) = self.frames.get(self.next) else {
+            return FrameInput::read();
+        };
+        self.next += 1;
+        if self.finished() {
+            crate::log::info!("Input replay finished after {} frames", self.frames.len());
+        }
+        input
+    }
+}
+
+/// Remembers button presses for a few frames, so a press that comes slightly too early
+/// (e.g. shooting during a cooldown) still counts. Call `update` once per frame.
+pub struct InputBuffer {
+    previous: u16,
+    frame: u32,
+    // Frame each button bit was last pressed on, if it hasn't been consumed
+    presses: [Option<u32>; 16],
+}
+
+impl InputBuffer {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self { previous: 0, frame: 0, presses: [None; 16] }
+    }
+
+    /// Moves on a frame, recording any buttons that were pressed since the last call.
+    pub fn update(&mut self, held: Buttons) {
+        self.frame = self.frame.wrapping_add(1);
+        let just_pressed = held.bits() & !self.previous;
+        self.previous = held.bits();
+        for (bit, press) in self.presses.iter_mut().enumerate() {
+            if just_pressed & (1 << bit) != 0 {
+                *press = Some(self.frame);
+            }
+        }
+    }
+
+    /// True if any of the buttons were pressed within the last `window` frames, counting the current one.
+    /// The press is used up, so it only triggers one action.
+    pub fn consume_buffered(&mut self, buttons: Buttons, window: u32) -> bool {
+        let mut found = false;
+        for (bit, press) in self.presses.iter_mut().enumerate() {
+            if buttons.bits() & (1 << bit) == 0 {
+                continue;
+            }
+            if let Some(frame) = *press {
+                if self.frame.wrapping_sub(frame) < window {
+                    *press = None;
+                    found = true;
+                }
+            }
+        }
+        found
+    }
+}
+
+impl Default for InputBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Key repeat for menus - a held button triggers once when pressed,
+/// then again every `interval` frames after `initial_delay`. Call `update` once per frame.
+pub struct KeyRepeat {
+    // Number of frames each button bit has been held for, including this one
+    held_frames: [u32; 16],
+}
+
+impl KeyRepeat {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self { held_frames: [0; 16] }
+    }
+
+    pub fn update(&mut self, held: Buttons) {
+        for (bit, frames) in self.held_frames.iter_mut().enumerate() {
+            *frames = if held.bits() & (1 << bit) != 0 { frames.saturating_add(1) } else { 0 };
+        }
+    }
+
+    /// True on the frame any of the buttons is pressed, and on each repeat while it's held.
+    #[must_use]
+    pub fn repeat(&self, buttons: Buttons, initial_delay: u32, interval: u32) -> bool {
+        self.held_frames.iter().enumerate()
+            .filter(|(bit, _)| buttons.bits() & (1 << bit) != 0)
+            .any(|(_, &frames)| match frames {
+                0 => false,
+                1 => true,
+                _ => frames > initial_delay && (frames - 1 - initial_delay) % interval.max(1) == 0,
+            })
+    }
+}
+
+impl Default for KeyRepeat {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "no-hardware")]
+mod fake {
+    use core::cell::Cell;
+
+    std::thread_local! {
+        // Thread local so that tests running in parallel don't press each other's buttons
+        static HELD_KEYS: Cell<Buttons> = const { Cell::new(Buttons::empty()) };
+    }
+
+    /// Same layout as the KEYINPUT register, but with 1 meaning pressed.
+    #[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+    pub struct Buttons(u16);
+
+    impl Buttons {
+        pub const A: Self = Self(1 << 0);
+        pub const B: Self = Self(1 << 1);
+        pub const SELECT: Self = Self(1 << 2);
+        pub const START: Self = Self(1 << 3);
+        pub const RIGHT: Self = Self(1 << 4);
+        pub const LEFT: Self = Self(1 << 5);
+        pub const UP: Self = Self(1 << 6);
+        pub const DOWN: Self = Self(1 << 7);
+        pub const R: Self = Self(1 << 8);
+        pub const L: Self = Self(1 << 9);
+
+        #[must_use]
+        pub const fn empty() -> Self {
+            Self(0)
+        }
+
+        #[must_use]
+        pub const fn bits(&self) -> u16 {
+            self.0
+        }
+
+        #[must_use]
+        pub const fn from_bits_truncate(bits: u16) -> Self {
+            Self(bits)
+        }
+
+        #[must_use]
+        pub const fn contains(&self, other: Self) -> bool {
+            self.0 & other.0 == other.0
+        }
+
+        #[must_use]
+        pub const fn intersects(&self, other: Self) -> bool {
+            self.0 & other.0 != 0
+        }
+
+        #[must_use]
+        pub const fn is_empty(&self) -> bool {
+            self.0 == 0
+        }
+    }
+
+    impl core::ops::BitOr for Buttons {
+        type Output = Self;
+        fn bitor(self, rhs: Self) -> Self {
+            Self(self.0 | rhs.0)
+        }
+    }
+
+    impl core::ops::BitOrAssign for Buttons {
+        fn bitor_assign(&mut self, rhs: Self) {
+            self.0 |= rhs.0;
+        }
+    }
+
+    impl core::ops::BitAnd for Buttons {
+        type Output = Self;
+        fn bitand(self, rhs: Self) -> Self {
+            Self(self.0 & rhs.0)
+        }
+    }
+
+    #[must_use]
+    pub(super) fn held_keys() -> Buttons {
+        HELD_KEYS.with(Cell::get)
+    }
+
+    /// Sets which buttons are held, until it's called again. Like real buttons,
+    /// scripts see them through `read_keys` from the next `Hierarchy::run_frame`.
+    pub fn set_keys(keys: Buttons) {
+        HELD_KEYS.with(|k| k.set(keys));
+    }
+}
+
+#[cfg(all(test, feature = "no-hardware"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buffered_press_consumed_once() {
+        let mut buffer = InputBuffer::new();
+        buffer.update(Buttons::A);
+        buffer.update(Buttons::A);
+        buffer.update(Buttons::empty());
+        // Pressed two frames ago
+        assert!(!buffer.consume_buffered(Buttons::B, 3));
+        assert!(!buffer.consume_buffered(Buttons::A, 2));
+        assert!(buffer.consume_buffered(Buttons::A, 3));
+        assert!(!buffer.consume_buffered(Buttons::A, 3));
+
+        buffer.update(Buttons::B);
+        buffer.update(Buttons::empty());
+        buffer.update(Buttons::empty());
+        assert!(!buffer.consume_buffered(Buttons::A | Buttons::B, 2));
+        assert!(buffer.consume_buffered(Buttons::A | Buttons::B, 3));
+    }
+
+    #[test]
+    fn key_repeat_cadence() {
+        let mut repeat = KeyRepeat::new();
+        let mut triggered = alloc::vec::Vec::new();
+        for frame in 1..=100 {
+            repeat.update(Buttons::DOWN);
+            if repeat.repeat(Buttons::DOWN, 20, 5) {
+                triggered.push(frame);
+            }
+            assert!(!repeat.repeat(Buttons::UP, 20, 5));
+        }
+        assert_eq!(triggered, [1, 21, 26, 31, 36, 41, 46, 51, 56, 61, 66, 71, 76, 81, 86, 91, 96]);
+
+        // Letting go resets it
+        repeat.update(Buttons::empty());
+        assert!(!repeat.repeat(Buttons::DOWN, 20, 5));
+        repeat.update(Buttons::DOWN);
+        assert!(repeat.repeat(Buttons::DOWN, 20, 5));
+    }
+}
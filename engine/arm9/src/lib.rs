@@ -4,20 +4,40 @@
 #![feature(error_in_core)]
 #![feature(decl_macro)]
 
+#[cfg(all(feature = "hardware", feature = "no-hardware"))]
+compile_error!("The hardware and no-hardware features can't be used together - use default-features = false with no-hardware.");
+#[cfg(not(any(feature = "hardware", feature = "no-hardware")))]
+compile_error!("Either the hardware or the no-hardware feature must be enabled.");
+
 extern crate alloc;
+#[cfg(feature = "no-hardware")]
+extern crate std;
 use core::num::NonZeroU32;
-use alloc::boxed::Box;
+use alloc::{boxed::Box, vec::Vec};
 use crate::{hierarchy::Hierarchy, pool::Handle, node::Node};
+#[cfg(feature = "hardware")]
 use ironds as nds;
 
 pub mod pool;
 pub mod hierarchy;
 pub mod node;
 pub mod random;
+pub mod math;
+pub mod input;
+pub mod console;
+pub mod log;
+pub mod text;
+pub mod debug_draw;
+pub mod vram;
+pub mod names;
+pub mod error;
 
+#[cfg(feature = "hardware")]
 pub use ironds; // re-export
+pub use sandstone_common;
 pub use fixed;
 pub use cordic;
+pub use error::Error;
 
 /// Type alias for using a Hashbrown HashMap with FxHash
 pub type HashMap<K, V> = sandstone_common::HashMap<K, V>;
@@ -25,7 +45,17 @@ pub type HashMap<K, V> = sandstone_common::HashMap<K, V>;
 /// Type alias for using a Hashbrown HashSet with FxHash
 pub type HashSet<V> = sandstone_common::HashSet<V>;
 
-pub fn main_loop(game_data_raw: &[u8], script_factory: fn(NonZeroU32) -> Box<dyn Script>) -> ! {
+/// The scripts that can be attached to nodes, generated by the editor from the register_script! calls.
+#[derive(Clone, Copy)]
+pub struct ScriptRegistry {
+    /// Creates the script with the given type ID, or None if no script has that ID.
+    pub factory: fn(NonZeroU32) -> Option<Box<dyn Script>>,
+    /// Name of the script with the given type ID, for debug output.
+    pub name: fn(NonZeroU32) -> Option<&'static str>,
+}
+
+#[cfg(feature = "hardware")]
+pub fn main_loop(game_data_raw: &[u8], scripts: ScriptRegistry) -> ! {
     nds::interrupt::irq_set_handler(Some(inter));
     nds::interrupt::irq_enable(nds::interrupt::IRQFlags::VBLANK);
 
@@ -70,25 +100,20 @@ pub fn main_loop(game_data_raw: &[u8], script_factory: fn(NonZeroU32) -> Box<dyn
         .with_display_mode(1) // normal BG / OBJ display
     );
 
-    let mut hierarchy = Hierarchy::new(game_data_raw, script_factory);
-    hierarchy.run_extension_init();
+    let mut hierarchy = Hierarchy::new(game_data_raw, scripts);
 
     // Load main scene
     hierarchy.set_scene_main();
     hierarchy.process_pending_scene_change();
 
     loop {
-        hierarchy.update_global_positions();
-        hierarchy.run_extension_update();
-        hierarchy.run_script_update();
-        hierarchy.run_pending_script_starts();
-        hierarchy.process_pending_destroys();
-        hierarchy.process_pending_scene_change();
-
+        hierarchy.run_frame();
         nds::interrupt::wait_for_vblank();
+        hierarchy.vblank();
     }
 }
 
+#[cfg(feature = "hardware")]
 extern "C" fn inter (f: nds::interrupt::IRQFlags) {
     if f.contains(nds::interrupt::IRQFlags::VBLANK) {
     }
@@ -97,25 +122,140 @@ extern "C" fn inter (f: nds::interrupt::IRQFlags) {
 pub struct ScriptContext<'a> {
     pub hierarchy: &'a mut Hierarchy,
     pub handle: Handle<Node>,
+    pub rng: random::GlobalRng,
+}
+
+impl<'a> ScriptContext<'a> {
+    /// Spawns a graph as a child of this script's node, so it moves along with it.
+    /// This node is its spawner. Returns None, and logs why, if the graph doesn't exist or its instance limit has been reached.
+    pub fn spawn_child(&mut self, graph_name: &str) -> Option<Handle<Node>> {
+        self.spawn_under(graph_name, self.handle)
+    }
+
+    /// Spawns a graph as a child of the hierarchy root, independent of this script's node.
+    /// This node is its spawner. Returns None, and logs why, if the graph doesn't exist or its instance limit has been reached.
+    pub fn spawn_at_root(&mut self, graph_name: &str) -> Option<Handle<Node>> {
+        self.spawn_under(graph_name, self.hierarchy.root)
+    }
+
+    fn spawn_under(&mut self, graph_name: &str, parent: Handle<Node>) -> Option<Handle<Node>> {
+        self.hierarchy.spawn_object_from(graph_name, parent, self.handle)
+            .inspect_err(|e| {
+                // Reaching the instance limit has already been logged as a warning
+                if !matches!(e, crate::error::Error::InstanceLimitReached(_)) {
+                    crate::log::error!("Couldn't spawn {graph_name}: {e}");
+                }
+            })
+            .ok()
+    }
+
+    /// Destroys the node and its children at the end of the frame.
+    pub fn destroy(&mut self, handle: Handle<Node>) {
+        self.hierarchy.destroy_node(handle);
+    }
+
+    /// Runs the callback after the given number of frames (at least one).
+    /// It's bound to this script's node, so it won't run if the node is destroyed first.
+    pub fn after<F>(&mut self, frames: u32, callback: F)
+    where F: FnOnce(&mut ScriptContext) + 'static {
+        self.hierarchy.add_timer(self.handle, frames, Box::new(callback));
+    }
+
+    /// Moves this node to `target` over the given number of frames. See `Hierarchy::tween_position`.
+    pub fn tween_to(&mut self, target: math::Vec2, frames: u32, easing: math::tween::Easing) {
+        self.hierarchy.tween_position(self.handle, target, frames, easing, None);
+    }
+
+    /// A parameter this node was spawned with by `Hierarchy::spawn_object_with_params`.
+    /// Only available during `start` - copy anything that's needed later into the script.
+    #[must_use]
+    pub fn param(&self, name: &str) -> Option<hierarchy::Value> {
+        self.hierarchy.start_param(self.handle, name)
+    }
+
+    /// The node that spawned this one with `spawn_child`, `spawn_at_root` or `Hierarchy::spawn_object_from`.
+    /// Only available during `start`, and None for nodes placed in the editor.
+    /// The spawner may have been destroyed since, so check it with `try_borrow`.
+    #[must_use]
+    pub fn spawner(&self) -> Option<Handle<Node>> {
+        self.hierarchy.start_spawner(self.handle)
+    }
+
+    /// Shakes the screen, e.g. for explosions. See `CameraExtension::shake`.
+    pub fn shake_camera(&mut self, magnitude: fixed::types::I20F12, frames: u32) {
+        self.hierarchy.shake_camera(magnitude, frames);
+    }
+
+    /// What the player is doing this frame, as given to `Hierarchy::step`.
+    #[must_use]
+    pub fn input(&self) -> input::FrameInput {
+        self.hierarchy.input()
+    }
+
+    /// Number of frames that have been updated so far. This frame's updates see the same count,
+    /// which goes up by one after they've all run. Wraps back to 0 after u32::MAX.
+    #[must_use]
+    pub fn frame_count(&self) -> u32 {
+        self.hierarchy.frame_count()
+    }
 }
 
 pub trait Script: {
     fn update(&mut self, context: &mut ScriptContext);
+    /// Called before the node's first update. Nodes start in the order they were spawned, and the nodes of a graph
+    /// start in hierarchy order - parents before their children, and children in order.
+    /// When a scene loads, every node in it has started before any of them update.
     fn start(&mut self, context: &mut ScriptContext);
+    /// Called instead of `update` while the hierarchy is paused, e.g. to run a pause menu.
+    fn paused_update(&mut self, _context: &mut ScriptContext) {}
+    /// Called when the node becomes enabled, either directly or because an ancestor did.
+    fn on_enable(&mut self, _context: &mut ScriptContext) {}
+    /// Called when the node becomes disabled, either directly or because an ancestor did.
+    fn on_disable(&mut self, _context: &mut ScriptContext) {}
+    /// Called when the node's world bounds come into view of an active camera, including when it starts in view.
+    fn on_enter_screen(&mut self, _context: &mut ScriptContext) {}
+    /// Called when the node's world bounds go out of view of every active camera.
+    fn on_exit_screen(&mut self, _context: &mut ScriptContext) {}
+    /// Called when the node's health runs out from `Hierarchy::apply_damage`, at the end of that frame's updates.
+    fn on_death(&mut self, _context: &mut ScriptContext) {}
+    /// Called just before the node is destroyed, or when the hierarchy shuts down.
+    /// Parents get this before their children.
+    fn on_destroy(&mut self, _context: &mut ScriptContext) {}
+    /// The script's state for `Hierarchy::snapshot`, in any format. Scripts that return None (the default)
+    /// come back from a restore as freshly created by the script factory.
+    fn save_state(&self) -> Option<Vec<u8>> { None }
+    /// Puts back what `save_state` returned, when a snapshot is restored.
+    /// For nodes that had already started, this is called instead of `start`.
+    fn load_state(&mut self, _state: &[u8]) {}
 }
 
-pub macro register_script ($script:ident, $num:literal) {
-    #[doc = concat!("{script_type_id=", $num, "}")]
-    impl sandstone::hierarchy::HasTypeId for $script {
-        fn type_id() -> core::num::NonZeroU32 {
-            core::num::NonZeroU32::new($num).unwrap()
+/// Registers a script so it can be attached to nodes in the editor.
+/// Without an ID, one is made from the script's name, so renaming the script changes its ID.
+pub macro register_script {
+    ($script:ident, $num:literal) => {
+        #[doc = concat!("{script_type_id=", $num, "}")]
+        impl sandstone::hierarchy::HasTypeId for $script {
+            fn type_id() -> core::num::NonZeroU32 {
+                core::num::NonZeroU32::new($num).unwrap()
+            }
         }
-    }
+    },
+    ($script:ident) => {
+        #[doc = "{script_type_id=auto}"]
+        impl sandstone::hierarchy::HasTypeId for $script {
+            fn type_id() -> core::num::NonZeroU32 {
+                const ID: core::num::NonZeroU32 = sandstone::sandstone_common::script_id_from_name(stringify!($script));
+                ID
+            }
+        }
+    },
 }
 
 // Could make this an attribute of the camera
 #[inline(always)]
+#[cfg_attr(feature = "no-hardware", allow(unused_variables))]
 pub fn set_bg_colour(colour: u32) {
+    #[cfg(feature = "hardware")]
     unsafe {
         core::ptr::write_volatile(
             ironds::mmio::BG_PALETTE_RAM_BASE_SUB as *mut u16,
@@ -127,3 +267,11 @@ pub fn set_bg_colour(colour: u32) {
         );
     }
 }
+
+/// Prints to the emulator's debug output (e.g. the No$GBA or melonDS log).
+/// Does nothing with the `no-hardware` feature.
+#[cfg_attr(feature = "no-hardware", allow(unused_variables))]
+pub fn debug_print(s: &str) {
+    #[cfg(feature = "hardware")]
+    ironds::nocash::print(s);
+}
@@ -0,0 +1,130 @@
+//! Levelled logging to the emulator's debug output.
+//! Messages below the current level are thrown away without being formatted.
+
+use core::fmt;
+
+/// How important a message is. Each level includes the ones above it.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[repr(u8)]
+pub enum Level {
+    Error = 0,
+    Warn = 1,
+    Info = 2,
+    Debug = 3,
+}
+
+impl Level {
+    const fn from_u8(value: u8) -> Self {
+        match value {
+            0 => Self::Error,
+            1 => Self::Warn,
+            2 => Self::Info,
+            _ => Self::Debug,
+        }
+    }
+
+    const fn prefix(self) -> &'static str {
+        match self {
+            Self::Error => "[ERROR]",
+            Self::Warn => "[WARN]",
+            Self::Info => "[INFO]",
+            Self::Debug => "[DEBUG]",
+        }
+    }
+}
+
+#[cfg(debug_assertions)]
+const DEFAULT_LEVEL: Level = Level::Debug;
+#[cfg(not(debug_assertions))]
+const DEFAULT_LEVEL: Level = Level::Warn;
+
+#[cfg(feature = "hardware")]
+static LEVEL: core::sync::atomic::AtomicU8 = core::sync::atomic::AtomicU8::new(DEFAULT_LEVEL as u8);
+
+#[cfg(feature = "no-hardware")]
+std::thread_local! {
+    // Thread local so that tests running in parallel don't change each other's level
+    static LEVEL: core::cell::Cell<u8> = const { core::cell::Cell::new(DEFAULT_LEVEL as u8) };
+}
+
+#[cfg(all(test, feature = "no-hardware"))]
+std::thread_local! {
+    static WRITTEN: core::cell::RefCell<alloc::vec::Vec<alloc::string::String>> = const { core::cell::RefCell::new(alloc::vec::Vec::new()) };
+}
+
+/// Sets the least important level that still gets logged.
+/// Defaults to `Debug` in debug builds and `Warn` in release builds.
+pub fn set_level(level: Level) {
+    #[cfg(feature = "hardware")]
+    LEVEL.store(level as u8, core::sync::atomic::Ordering::Relaxed);
+    #[cfg(feature = "no-hardware")]
+    LEVEL.with(|l| l.set(level as u8));
+}
+
+#[must_use]
+pub fn level() -> Level {
+    #[cfg(feature = "hardware")]
+    return Level::from_u8(LEVEL.load(core::sync::atomic::Ordering::Relaxed));
+    #[cfg(feature = "no-hardware")]
+    return Level::from_u8(LEVEL.with(|l| l.get()));
+}
+
+/// Whether a message at this level would be logged.
+#[must_use]
+pub fn enabled(level: Level) -> bool {
+    level <= self::level()
+}
+
+/// Logs a message, if its level is enabled. Usually called through the `error!`, `warn!`, `info!` and `debug!` macros.
+pub fn log(level: Level, args: fmt::Arguments) {
+    if !enabled(level) {
+        return;
+    }
+    let line = alloc::format!("{} {}", level.prefix(), args);
+    crate::debug_print(&line);
+    #[cfg(all(test, feature = "no-hardware"))]
+    WRITTEN.with(|w| w.borrow_mut().push(line));
+}
+
+pub macro error($($arg:tt)*) {
+    crate::log::log(crate::log::Level::Error, format_args!($($arg)*))
+}
+
+pub macro warn($($arg:tt)*) {
+    crate::log::log(crate::log::Level::Warn, format_args!($($arg)*))
+}
+
+pub macro info($($arg:tt)*) {
+    crate::log::log(crate::log::Level::Info, format_args!($($arg)*))
+}
+
+pub macro debug($($arg:tt)*) {
+    crate::log::log(crate::log::Level::Debug, format_args!($($arg)*))
+}
+
+// Lines logged on this thread since the last call
+#[cfg(all(test, feature = "no-hardware"))]
+pub(crate) fn take_written() -> alloc::vec::Vec<alloc::string::String> {
+    WRITTEN.with(|w| core::mem::take(&mut *w.borrow_mut()))
+}
+
+#[cfg(all(test, feature = "no-hardware"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn below_threshold_suppressed() {
+        take_written();
+        set_level(Level::Warn);
+        error!("bad {}", 1);
+        warn!("careful");
+        info!("hidden");
+        debug!("hidden");
+        assert_eq!(take_written(), ["[ERROR] bad 1", "[WARN] careful"]);
+
+        set_level(Level::Debug);
+        debug!("shown");
+        assert_eq!(take_written(), ["[DEBUG] shown"]);
+        assert!(enabled(Level::Info));
+    }
+}
@@ -0,0 +1,239 @@
+use core::ops::{Add, AddAssign, Sub, SubAssign, Neg, Mul, MulAssign, Div, DivAssign};
+use fixed::types::I20F12;
+
+pub mod trig;
+pub mod tween;
+
+/// A 2D vector / point made of two I20F12 components.
+#[derive(Eq, PartialEq, Clone, Copy, Default, Debug)]
+pub struct Vec2 {
+    pub x: I20F12,
+    pub y: I20F12,
+}
+
+impl Vec2 {
+    pub const ZERO: Self = Self::new(I20F12::ZERO, I20F12::ZERO);
+
+    #[inline]
+    #[must_use]
+    pub const fn new(x: I20F12, y: I20F12) -> Self {
+        Self { x, y }
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn dot(self, other: Self) -> I20F12 {
+        self.x * other.x + self.y * other.y
+    }
+
+    /// Note that this overflows for components larger than ~724, use `length` for big vectors.
+    #[inline]
+    #[must_use]
+    pub fn length_sq(self) -> I20F12 {
+        self.dot(self)
+    }
+
+    #[must_use]
+    pub fn length(self) -> I20F12 {
+        // Square the raw bits in 64 bit, so the intermediate can't overflow and we keep the full precision.
+        // The square has 24 fractional bits, so the square root ends up with 12 again.
+        let (x, y) = (self.x.to_bits() as i64, self.y.to_bits() as i64);
+        I20F12::from_bits(isqrt((x * x + y * y) as u64) as i32)
+    }
+
+    /// Unit vector pointing in the direction of the angle, with 0 pointing right.
+    #[must_use]
+    pub fn from_angle(angle: trig::Angle) -> Self {
+        Self::new(angle.cos(), angle.sin())
+    }
+
+    /// Returns a vector with the same direction and a length of 1.
+    /// The zero vector has no direction, so it is returned unchanged.
+    #[must_use]
+    pub fn normalize(self) -> Self {
+        let length = self.length();
+        if length == 0 {
+            return Self::ZERO;
+        }
+        Self::new(self.x / length, self.y / length)
+    }
+}
+
+/// Axis-aligned rectangle, stored as its top-left and bottom-right corners.
+#[derive(Eq, PartialEq, Clone, Copy, Default, Debug)]
+pub struct Rect {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl Rect {
+    #[inline]
+    #[must_use]
+    pub const fn new(min: Vec2, max: Vec2) -> Self {
+        Self { min, max }
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn from_pos_size(position: Vec2, size: Vec2) -> Self {
+        Self::new(position, position + size)
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn size(&self) -> Vec2 {
+        self.max - self.min
+    }
+
+    /// Grows the rectangle by the margin on every side.
+    #[inline]
+    #[must_use]
+    pub fn expand(&self, margin: I20F12) -> Self {
+        let margin = Vec2::new(margin, margin);
+        Self::new(self.min - margin, self.max + margin)
+    }
+
+    /// Touching edges count as intersecting.
+    #[inline]
+    #[must_use]
+    pub fn intersects(&self, other: &Rect) -> bool {
+        !(self.min.x > other.max.x || self.max.x < other.min.x || self.min.y > other.max.y || self.max.y < other.min.y)
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn contains_point(&self, point: Vec2) -> bool {
+        point.x >= self.min.x && point.x <= self.max.x && point.y >= self.min.y && point.y <= self.max.y
+    }
+
+    /// Distance along the ray to where it enters the rectangle, or 0 if it starts inside.
+    /// `dir` should be normalized, so that the distance is in pixels.
+    #[must_use]
+    pub fn ray_intersection(&self, origin: Vec2, dir: Vec2, max_dist: I20F12) -> Option<I20F12> {
+        // Slab method - find the part of the ray between the edges on each axis, and take the overlap.
+        let mut t_min = I20F12::ZERO;
+        let mut t_max = max_dist;
+        for (o, d, min, max) in [(origin.x, dir.x, self.min.x, self.max.x), (origin.y, dir.y, self.min.y, self.max.y)] {
+            if d == 0 {
+                if o < min || o > max {
+                    return None;
+                }
+            } else {
+                // A tiny direction component can push these out of range, so saturate
+                let t1 = (min - o).saturating_div(d);
+                let t2 = (max - o).saturating_div(d);
+                t_min = t_min.max(t1.min(t2));
+                t_max = t_max.min(t1.max(t2));
+                if t_min > t_max {
+                    return None;
+                }
+            }
+        }
+        Some(t_min)
+    }
+}
+
+// Integer square root (rounded down), using the binary digit-by-digit method.
+fn isqrt(mut n: u64) -> u64 {
+    let mut result = 0;
+    let mut bit = 1 << 62;
+    while bit > n {
+        bit >>= 2;
+    }
+    while bit != 0 {
+        if n >= result + bit {
+            n -= result + bit;
+            result = (result >> 1) + bit;
+        } else {
+            result >>= 1;
+        }
+        bit >>= 2;
+    }
+    result
+}
+
+impl Add for Vec2 {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.x + rhs.x, self.y + rhs.y)
+    }
+}
+
+impl AddAssign for Vec2 {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub for Vec2 {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.x - rhs.x, self.y - rhs.y)
+    }
+}
+
+impl SubAssign for Vec2 {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
+impl Neg for Vec2 {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Self::new(-self.x, -self.y)
+    }
+}
+
+impl Mul<I20F12> for Vec2 {
+    type Output = Self;
+    fn mul(self, rhs: I20F12) -> Self {
+        Self::new(self.x * rhs, self.y * rhs)
+    }
+}
+
+impl MulAssign<I20F12> for Vec2 {
+    fn mul_assign(&mut self, rhs: I20F12) {
+        *self = *self * rhs;
+    }
+}
+
+impl Div<I20F12> for Vec2 {
+    type Output = Self;
+    fn div(self, rhs: I20F12) -> Self {
+        Self::new(self.x / rhs, self.y / rhs)
+    }
+}
+
+impl DivAssign<I20F12> for Vec2 {
+    fn div_assign(&mut self, rhs: I20F12) {
+        *self = *self / rhs;
+    }
+}
+
+#[cfg(all(test, feature = "no-hardware"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn length_sq_of_3_4_5_triangle() {
+        let v = Vec2::new(I20F12::from_num(3), I20F12::from_num(-4));
+        assert_eq!(v.length_sq(), I20F12::from_num(25));
+        assert_eq!(v.length(), I20F12::from_num(5));
+    }
+
+    #[test]
+    fn normalize_is_unit_length() {
+        // Fixed point division rounds down, so allow being a couple of LSBs out
+        let tolerance = I20F12::from_bits(2);
+        let v = Vec2::new(I20F12::from_num(3), I20F12::from_num(4)).normalize();
+        assert!((v.x - I20F12::from_num(0.6)).abs() <= tolerance);
+        assert!((v.y - I20F12::from_num(0.8)).abs() <= tolerance);
+
+        for (x, y) in [(300, -500), (1, 1), (-7, 2), (0, 9), (2000, 1)] {
+            let v = Vec2::new(I20F12::from_num(x), I20F12::from_num(y)).normalize();
+            assert!((v.length() - I20F12::ONE).abs() <= tolerance, "{x}, {y} gave {v:?}");
+        }
+        assert_eq!(Vec2::ZERO.normalize(), Vec2::ZERO);
+    }
+}
@@ -0,0 +1,147 @@
+use core::ops::{Add, AddAssign, Sub, SubAssign, Neg};
+use fixed::types::I20F12;
+
+const TABLE_BITS: u32 = 8;
+const TABLE_SHIFT: u32 = 16 - TABLE_BITS;
+const TABLE_MASK: usize = (1 << TABLE_BITS) - 1;
+
+// sin(i / 256 turns) for i in 0..256, as raw I20F12 bits.
+static SIN_TABLE: [i16; 1 << TABLE_BITS] = [
+    0, 101, 201, 301, 401, 501, 601, 700, 799, 897, 995, 1092, 1189, 1285, 1380, 1474,
+    1567, 1660, 1751, 1842, 1931, 2019, 2106, 2191, 2276, 2359, 2440, 2520, 2598, 2675, 2751, 2824,
+    2896, 2967, 3035, 3102, 3166, 3229, 3290, 3349, 3406, 3461, 3513, 3564, 3612, 3659, 3703, 3745,
+    3784, 3822, 3857, 3889, 3920, 3948, 3973, 3996, 4017, 4036, 4052, 4065, 4076, 4085, 4091, 4095,
+    4096, 4095, 4091, 4085, 4076, 4065, 4052, 4036, 4017, 3996, 3973, 3948, 3920, 3889, 3857, 3822,
+    3784, 3745, 3703, 3659, 3612, 3564, 3513, 3461, 3406, 3349, 3290, 3229, 3166, 3102, 3035, 2967,
+    2896, 2824, 2751, 2675, 2598, 2520, 2440, 2359, 2276, 2191, 2106, 2019, 1931, 1842, 1751, 1660,
+    1567, 1474, 1380, 1285, 1189, 1092, 995, 897, 799, 700, 601, 501, 401, 301, 201, 101,
+    0, -101, -201, -301, -401, -501, -601, -700, -799, -897, -995, -1092, -1189, -1285, -1380, -1474,
+    -1567, -1660, -1751, -1842, -1931, -2019, -2106, -2191, -2276, -2359, -2440, -2520, -2598, -2675, -2751, -2824,
+    -2896, -2967, -3035, -3102, -3166, -3229, -3290, -3349, -3406, -3461, -3513, -3564, -3612, -3659, -3703, -3745,
+    -3784, -3822, -3857, -3889, -3920, -3948, -3973, -3996, -4017, -4036, -4052, -4065, -4076, -4085, -4091, -4095,
+    -4096, -4095, -4091, -4085, -4076, -4065, -4052, -4036, -4017, -3996, -3973, -3948, -3920, -3889, -3857, -3822,
+    -3784, -3745, -3703, -3659, -3612, -3564, -3513, -3461, -3406, -3349, -3290, -3229, -3166, -3102, -3035, -2967,
+    -2896, -2824, -2751, -2675, -2598, -2520, -2440, -2359, -2276, -2191, -2106, -2019, -1931, -1842, -1751, -1660,
+    -1567, -1474, -1380, -1285, -1189, -1092, -995, -897, -799, -700, -601, -501, -401, -301, -201, -101,
+];
+
+/// An angle stored in binary units, where 65536 is one full turn.
+/// Arithmetic wraps around, so angles are always kept within a single turn.
+#[derive(Eq, PartialEq, Clone, Copy, Default, Debug)]
+pub struct Angle(pub u16);
+
+impl Angle {
+    pub const ZERO: Self = Self(0);
+    pub const QUARTER_TURN: Self = Self(0x4000);
+    pub const HALF_TURN: Self = Self(0x8000);
+    pub const THREE_QUARTER_TURN: Self = Self(0xC000);
+
+    #[inline]
+    #[must_use]
+    pub const fn from_brads(brads: u16) -> Self {
+        Self(brads)
+    }
+
+    #[must_use]
+    pub const fn from_degrees(degrees: i32) -> Self {
+        Self(((degrees.rem_euclid(360) as u32) * 0x10000 / 360) as u16)
+    }
+
+    /// Only the fractional part is used, as whole turns don't change the angle.
+    #[must_use]
+    pub fn from_turns(turns: I20F12) -> Self {
+        Self((turns.frac().to_bits() << (16 - I20F12::FRAC_NBITS)) as u16)
+    }
+
+    /// For converting from the radian angles used by affine sprites.
+    #[must_use]
+    pub fn from_radians(radians: I20F12) -> Self {
+        Self::from_turns(radians / I20F12::TAU)
+    }
+
+    #[must_use]
+    pub fn to_radians(self) -> I20F12 {
+        I20F12::from_bits(((self.0 as i64 * I20F12::TAU.to_bits() as i64) >> 16) as i32)
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn sin(self) -> I20F12 {
+        sin(self)
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn cos(self) -> I20F12 {
+        cos(self)
+    }
+}
+
+/// Looks up the sine in the table, linearly interpolating between the two closest entries.
+#[must_use]
+pub fn sin(angle: Angle) -> I20F12 {
+    let index = (angle.0 >> TABLE_SHIFT) as usize;
+    let weight = (angle.0 & ((1 << TABLE_SHIFT) - 1)) as i32;
+    let a = SIN_TABLE[index] as i32;
+    let b = SIN_TABLE[(index + 1) & TABLE_MASK] as i32;
+    I20F12::from_bits(a + (((b - a) * weight) >> TABLE_SHIFT))
+}
+
+#[inline]
+#[must_use]
+pub fn cos(angle: Angle) -> I20F12 {
+    sin(angle + Angle::QUARTER_TURN)
+}
+
+impl Add for Angle {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Self(self.0.wrapping_add(rhs.0))
+    }
+}
+
+impl AddAssign for Angle {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub for Angle {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        Self(self.0.wrapping_sub(rhs.0))
+    }
+}
+
+impl SubAssign for Angle {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
+impl Neg for Angle {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Self(self.0.wrapping_neg())
+    }
+}
+
+#[cfg(all(test, feature = "no-hardware"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sin_cos_at_quarter_turns() {
+        let tolerance = I20F12::from_bits(1);
+        let near = |a: I20F12, b: i32| (a - I20F12::from_num(b)).abs() <= tolerance;
+        for (degrees, sin, cos) in [(0, 0, 1), (90, 1, 0), (180, 0, -1), (270, -1, 0)] {
+            let angle = Angle::from_degrees(degrees);
+            assert!(near(angle.sin(), sin), "sin {degrees} gave {}", angle.sin());
+            assert!(near(angle.cos(), cos), "cos {degrees} gave {}", angle.cos());
+        }
+        assert_eq!(Angle::from_degrees(90), Angle::QUARTER_TURN);
+        assert_eq!(Angle::from_degrees(-90), Angle::THREE_QUARTER_TURN);
+        // Between table entries
+        assert!((sin(Angle::from_degrees(30)) - I20F12::from_num(0.5)).abs() <= I20F12::from_bits(2));
+    }
+}
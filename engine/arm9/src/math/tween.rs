@@ -0,0 +1,74 @@
+use fixed::types::I20F12;
+use super::Vec2;
+
+/// Shape of a tween's motion over time.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Easing {
+    #[default]
+    Linear,
+    /// Starts slow and speeds up.
+    EaseIn,
+    /// Starts fast and slows down.
+    EaseOut,
+    /// Slow at both ends.
+    EaseInOut,
+}
+
+impl Easing {
+    /// Maps progress from 0 to 1 onto the eased progress, which also goes from 0 to 1.
+    #[must_use]
+    pub fn apply(self, t: I20F12) -> I20F12 {
+        let t = t.clamp(I20F12::ZERO, I20F12::ONE);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (I20F12::from_num(2) - t),
+            Easing::EaseInOut => {
+                if t < I20F12::from_num(0.5) {
+                    2 * t * t
+                } else {
+                    let inv = I20F12::ONE - t;
+                    I20F12::ONE - 2 * inv * inv
+                }
+            }
+        }
+    }
+}
+
+/// Position `frame` frames into a tween lasting `frames` frames.
+/// Gives exactly `to` on the last frame, so rounding never leaves it slightly off.
+#[must_use]
+pub fn tween(from: Vec2, to: Vec2, frame: u32, frames: u32, easing: Easing) -> Vec2 {
+    if frame >= frames {
+        return to;
+    }
+    let t = easing.apply(I20F12::from_num(frame) / I20F12::from_num(frames));
+    from + (to - from) * t
+}
+
+#[cfg(all(test, feature = "no-hardware"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn easing_end_points() {
+        for easing in [Easing::Linear, Easing::EaseIn, Easing::EaseOut, Easing::EaseInOut] {
+            assert_eq!(easing.apply(I20F12::ZERO), I20F12::ZERO);
+            assert_eq!(easing.apply(I20F12::ONE), I20F12::ONE);
+        }
+        let half = I20F12::from_num(0.5);
+        assert_eq!(Easing::EaseIn.apply(half), I20F12::from_num(0.25));
+        assert_eq!(Easing::EaseOut.apply(half), I20F12::from_num(0.75));
+        assert_eq!(Easing::EaseInOut.apply(half), half);
+    }
+
+    #[test]
+    fn linear_tween_reaches_target_on_last_frame() {
+        let from = Vec2::new(I20F12::from_num(1), I20F12::from_num(-3));
+        let to = Vec2::new(I20F12::from_num(11), I20F12::from_num(7.3));
+        assert_eq!(tween(from, to, 0, 3, Easing::Linear), from);
+        assert_ne!(tween(from, to, 2, 3, Easing::Linear), to);
+        assert_eq!(tween(from, to, 3, 3, Easing::Linear), to);
+        assert_eq!(tween(from, to, 0, 0, Easing::Linear), to);
+    }
+}
@@ -0,0 +1,62 @@
+//! Node names are stored once, in a table owned by the hierarchy, and nodes hold an id into it.
+//! Nodes with the same name share an id, so comparing names is an integer compare.
+
+use alloc::{string::String, vec::Vec};
+use crate::HashMap;
+
+/// A name in a `NameTable`. The default id is the empty name.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct NameId(u32);
+
+pub struct NameTable {
+    names: Vec<String>,
+    ids: HashMap<String, NameId>,
+}
+
+impl NameTable {
+    #[must_use]
+    pub fn new() -> Self {
+        let mut table = Self { names: Vec::new(), ids: HashMap::default() };
+        table.intern("");
+        table
+    }
+
+    /// The id for the name, adding it to the table if it isn't there yet.
+    pub fn intern(&mut self, name: &str) -> NameId {
+        if let Some(&id) = self.ids.get(name) {
+            return id;
+        }
+        let id = NameId(self.names.len() as u32);
+        self.names.push(String::from(name));
+        self.ids.insert(String::from(name), id);
+        id
+    }
+
+    /// The id for the name, or None if no node has ever had it.
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<NameId> {
+        self.ids.get(name).copied()
+    }
+
+    #[must_use]
+    pub fn resolve(&self, id: NameId) -> &str {
+        &self.names[id.0 as usize]
+    }
+
+    /// Number of different names, including the empty one.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+impl Default for NameTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,9 +1,91 @@
-use crate::{pool::Handle, node::Node, hierarchy::{Hierarchy, HierarchyPoolTrait}};
+use fixed::types::I20F12;
+use crate::{pool::Handle, node::Node, hierarchy::{Hierarchy, HierarchyPoolTrait}, math::Vec2};
 
 pub struct CameraExtension {
     pub node_handle: Handle<Node>,
     pub active_main: bool,
     pub active_sub: bool,
+    /// Eases the camera toward a node every frame. Set to None to stop following, leaving the camera where it is.
+    pub follow: Option<CameraFollow>,
+    // Magnitude, frames left and total frames of the current shake
+    pub(crate) shake: Option<(I20F12, u32, u32)>,
+    pub(crate) shake_offset: Vec2,
+}
+
+impl CameraExtension {
+    pub(crate) fn new(node_handle: Handle<Node>, active_main: bool, active_sub: bool) -> Self {
+        Self { node_handle, active_main, active_sub, follow: None, shake: None, shake_offset: Vec2::ZERO }
+    }
+
+    /// Shakes the view by up to `magnitude` pixels, dying down over the given number of frames.
+    /// Only what's drawn is moved - the camera node stays where it is. Starting a new shake replaces the current one.
+    pub fn shake(&mut self, magnitude: I20F12, frames: u32) {
+        self.shake = (frames > 0).then_some((magnitude.abs(), frames, frames));
+    }
+
+    /// How far the current shake has moved the view this frame.
+    #[must_use]
+    pub fn shake_offset(&self) -> Vec2 {
+        self.shake_offset
+    }
+
+    /// Where the view's top left corner is drawn from, including any shake.
+    #[must_use]
+    pub(crate) fn draw_position(&self, hierarchy: &Hierarchy) -> (I20F12, I20F12) {
+        let position = hierarchy.borrow(self.node_handle).global_transform.position() + self.shake_offset;
+        (position.x, position.y)
+    }
+
+    // Picks this frame's shake offset. The range shrinks each frame, so it's back at 0 on the last one.
+    pub(crate) fn update_shake(&mut self) {
+        let Some((magnitude, frames_left, total)) = &mut self.shake else { return; };
+        *frames_left -= 1;
+        let range = *magnitude * I20F12::from_num(*frames_left) / I20F12::from_num(*total);
+        self.shake_offset = if range > 0 {
+            let rng = crate::random::GlobalRng;
+            Vec2::new(rng.range_fixed(-range, range), rng.range_fixed(-range, range))
+        } else {
+            Vec2::ZERO
+        };
+        if *frames_left == 0 {
+            self.shake = None;
+        }
+    }
+}
+
+/// Smoothly keeps a node in view, for `CameraExtension::follow`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CameraFollow {
+    pub target: Handle<Node>,
+    /// Where the target is kept on screen, e.g. the middle of the screen.
+    pub screen_pos: Vec2,
+    /// Fraction of the way to the target that the camera moves each frame, from 0 to 1. 1 follows it exactly.
+    pub stiffness: I20F12,
+    /// Half the size of a box around `screen_pos` that the target can move around in without the camera moving.
+    pub dead_zone: Option<Vec2>,
+}
+
+impl CameraFollow {
+    /// Keeps the target in the middle of the screen, moving a quarter of the way there each frame.
+    #[must_use]
+    pub fn new(target: Handle<Node>) -> Self {
+        Self {
+            target,
+            screen_pos: Vec2::new(I20F12::from_num(crate::hierarchy::SCREEN_WIDTH / 2), I20F12::from_num(crate::hierarchy::SCREEN_HEIGHT / 2)),
+            stiffness: I20F12::from_num(0.25),
+            dead_zone: None,
+        }
+    }
+
+    /// How far the camera should move this frame, with it at `camera_pos` and the target at `target_pos`.
+    #[must_use]
+    pub fn step(&self, camera_pos: Vec2, target_pos: Vec2) -> Vec2 {
+        let dead_zone = self.dead_zone.unwrap_or(Vec2::ZERO);
+        let error = target_pos - camera_pos - self.screen_pos;
+        // Only the part of the error outside of the dead zone gets corrected
+        let outside = |error: I20F12, half_size: I20F12| error - error.clamp(-half_size, half_size);
+        Vec2::new(outside(error.x, dead_zone.x), outside(error.y, dead_zone.y)) * self.stiffness.clamp(I20F12::ZERO, I20F12::ONE)
+    }
 }
 
 #[derive(Clone, Copy)]
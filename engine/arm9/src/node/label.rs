@@ -0,0 +1,103 @@
+use alloc::{string::String, vec::Vec};
+use fixed::types::I20F12;
+use crate::{pool::Handle, node::{Node, Screen, camera::ActiveCameras}, hierarchy::{Hierarchy, HierarchyPoolTrait}};
+pub use sandstone_common::TextAlign;
+
+// The console covers the sub screen in 8x8 character cells
+const CONSOLE_COLUMNS: i32 = 32;
+const CONSOLE_ROWS: i32 = 24;
+const CELL_SIZE: i32 = 8;
+
+/// Text drawn with the console on the sub screen, at the node's position relative to the sub camera.
+/// The position is rounded down to the 8x8 character grid. `\n` starts a new line.
+pub struct LabelExtension {
+    pub node_handle: Handle<Node>,
+    pub text: String,
+    pub align: TextAlign,
+}
+
+// Console cells (column, row, character) covered by text placed at the given screen position.
+// Each line is aligned around x, and characters off the edge of the console are dropped.
+pub(crate) fn label_cells(text: &str, align: TextAlign, x: i32, y: i32) -> Vec<(u8, u8, char)> {
+    let (base_col, base_row) = (x.div_euclid(CELL_SIZE), y.div_euclid(CELL_SIZE));
+    let mut cells = Vec::new();
+    for (line_index, line) in text.split('\n').enumerate() {
+        let row = base_row + line_index as i32;
+        if !(0..CONSOLE_ROWS).contains(&row) {
+            continue;
+        }
+        let len = line.chars().count() as i32;
+        let start_col = base_col - match align {
+            TextAlign::Left => 0,
+            TextAlign::Center => len / 2,
+            TextAlign::Right => len,
+        };
+        for (i, c) in line.chars().enumerate() {
+            let col = start_col + i as i32;
+            if (0..CONSOLE_COLUMNS).contains(&col) {
+                cells.push((col as u8, row as u8, c));
+            }
+        }
+    }
+    cells
+}
+
+// Remembers what was drawn last frame, so only changed characters are written to the console
+#[derive(Default)]
+pub(crate) struct LabelExtensionHandler {
+    drawn: Vec<(u8, u8, char)>,
+}
+
+impl LabelExtensionHandler {
+    pub fn label_update(&mut self, hierarchy: &Hierarchy, cameras: ActiveCameras) {
+        let (cam_x, cam_y) = match cameras.sub {
+            Some(camera) => hierarchy.borrow(camera).draw_position(hierarchy),
+            None => (I20F12::ZERO, I20F12::ZERO),
+        };
+
+        let mut cells = Vec::new();
+        for label in hierarchy.node_ext_pools.label_pool.iter() {
+            let node = hierarchy.borrow(label.node_handle);
+            if !node.global_enabled || !node.global_screen.shows_on(Screen::Sub) {
+                continue;
+            }
+            let (x, y) = node.draw_position((cam_x, cam_y));
+            let (x, y) = (x.to_num::<i32>(), y.to_num::<i32>());
+            cells.extend(label_cells(&label.text, label.align, x, y));
+        }
+
+        // Blank out characters that aren't covered any more
+        for &(col, row, _) in self.drawn.iter() {
+            if !cells.iter().any(|&(c, r, _)| (c, r) == (col, row)) {
+                crate::console::set_cursor_pos(col, row);
+                crate::console::print(" ");
+            }
+        }
+        let mut buf = [0; 4];
+        for &cell in cells.iter() {
+            if !self.drawn.contains(&cell) {
+                let (col, row, c) = cell;
+                crate::console::set_cursor_pos(col, row);
+                crate::console::print(c.encode_utf8(&mut buf));
+            }
+        }
+        self.drawn = cells;
+    }
+}
+
+#[cfg(all(test, feature = "no-hardware"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn label_cell_mapping() {
+        assert_eq!(label_cells("Hi", TextAlign::Left, 16, 12), [(2, 1, 'H'), (3, 1, 'i')]);
+        assert_eq!(label_cells("Score", TextAlign::Center, 80, 0), [(8, 0, 'S'), (9, 0, 'c'), (10, 0, 'o'), (11, 0, 'r'), (12, 0, 'e')]);
+        assert_eq!(label_cells("AB", TextAlign::Right, 256, 184), [(30, 23, 'A'), (31, 23, 'B')]);
+        assert_eq!(label_cells("A\nBC", TextAlign::Left, 0, 0), [(0, 0, 'A'), (0, 1, 'B'), (1, 1, 'C')]);
+        // Clipped at the edges of the console
+        assert_eq!(label_cells("XYZ", TextAlign::Left, -8, 0), [(0, 0, 'Y'), (1, 0, 'Z')]);
+        assert_eq!(label_cells("XYZ", TextAlign::Left, 248, 0), [(31, 0, 'X')]);
+        assert!(label_cells("Gone", TextAlign::Left, 0, 192).is_empty());
+    }
+}
@@ -1,10 +1,12 @@
 use core::num::NonZeroU32;
-use alloc::{string::String, boxed::Box, vec::Vec};
-use crate::{Script, pool::{Pool, Handle}, hierarchy::HasTypeId};
+use alloc::{boxed::Box, vec::Vec};
+use crate::{Script, pool::{Pool, Handle, RawIndex}, hierarchy::HasTypeId, math::Vec2, names::NameId, Error};
+pub use sandstone_common::{Screen, SpawnEffect};
 
 pub mod sprite;
 pub mod camera;
 pub mod rect_collider;
+pub mod label;
 
 #[derive(Eq, PartialEq, Clone, Copy, Default, Debug)]
 pub struct Transform {
@@ -12,18 +14,206 @@ pub struct Transform {
     pub y: fixed::types::I20F12,
 }
 
+impl Transform {
+    #[inline]
+    #[must_use]
+    pub fn position(&self) -> Vec2 {
+        Vec2::new(self.x, self.y)
+    }
+
+    #[inline]
+    pub fn set_position(&mut self, position: Vec2) {
+        (self.x, self.y) = (position.x, position.y);
+    }
+}
+
+/// Keeps a node at another node's world position plus an offset, whatever its parent is.
+/// Applied every frame before drawing. Stops when the target is destroyed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Follow {
+    pub target: Handle<Node>,
+    pub offset: Vec2,
+}
+
+/// Velocity that the engine moves the node by every frame, before scripts update.
+/// If the node has a collider, it stops against colliders that block it instead of moving into them.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Body {
+    /// In pixels per frame.
+    pub velocity: Vec2,
+    /// Added to the vertical velocity every frame. Positive is down.
+    pub gravity: fixed::types::I20F12,
+    // What stopped it this frame, horizontally and vertically
+    pub(crate) contacts: [Option<Contact>; 2],
+}
+
+/// Something that stopped a body when it last moved.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Contact {
+    /// The node with the collider that was hit.
+    pub node: Handle<Node>,
+    /// Points away from the collider that was hit, so standing on a floor gives (0, -1).
+    pub normal: Vec2,
+}
+
+impl Body {
+    /// A body at rest, with the given gravity.
+    #[must_use]
+    pub const fn new(gravity: fixed::types::I20F12) -> Self {
+        Self { velocity: Vec2::ZERO, gravity, contacts: [None; 2] }
+    }
+
+    /// Colliders that stopped the body when it last moved, at most one per axis.
+    pub fn contacts(&self) -> impl Iterator<Item = &Contact> {
+        self.contacts.iter().flatten()
+    }
+
+    /// Whether the body is standing on something - it was stopped while moving down.
+    #[must_use]
+    pub fn is_grounded(&self) -> bool {
+        self.contacts[1].is_some_and(|c| c.normal.y < 0)
+    }
+
+    pub(crate) fn set_contacts(&mut self, horizontal: Option<Contact>, vertical: Option<Contact>) {
+        self.contacts = [horizontal, vertical];
+    }
+}
+
+/// A short effect played whenever the node becomes enabled, e.g. so enemies don't just pop in.
+/// The node's script doesn't get updates until it's done.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SpawnAnimation {
+    pub effect: SpawnEffect,
+    /// Number of frames it lasts, and that updates are held back for.
+    pub frames: u32,
+    // Frames played so far, and the sprite's scale and mosaic to go back to, while it's playing
+    pub(crate) playing: Option<(u32, (fixed::types::I20F12, fixed::types::I20F12), bool)>,
+}
+
+impl SpawnAnimation {
+    #[must_use]
+    pub const fn new(effect: SpawnEffect, frames: u32) -> Self {
+        Self { effect, frames, playing: None }
+    }
+
+    /// Whether it's playing right now, holding back the script's updates.
+    #[must_use]
+    pub const fn is_playing(&self) -> bool {
+        self.playing.is_some()
+    }
+}
+
+/// Hit points, taken away with `Hierarchy::apply_damage`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Health {
+    pub current: u32,
+    pub max: u32,
+    /// Whether the node is destroyed once its health runs out, after its script's `on_death`.
+    pub destroy_on_death: bool,
+}
+
+impl Health {
+    /// Full health, which destroys the node when it runs out.
+    #[must_use]
+    pub const fn new(max: u32) -> Self {
+        Self { current: max, max, destroy_on_death: true }
+    }
+
+    #[must_use]
+    pub const fn is_dead(&self) -> bool {
+        self.current == 0
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum NodeExtensionHandle {
     None,
     Sprite(Handle<sprite::SpriteExtension>),
     Camera(Handle<camera::CameraExtension>),
     RectCollider(Handle<rect_collider::RectColliderExtension>),
+    Label(Handle<label::LabelExtension>),
+}
+
+/// Extension types that a node can have, so they can be used with `TypedHandle`.
+pub trait ExtensionType: Sized {
+    /// The handle to this extension type, if the node extension is one.
+    fn from_extension_handle(handle: NodeExtensionHandle) -> Option<Handle<Self>>;
+}
+
+impl ExtensionType for sprite::SpriteExtension {
+    fn from_extension_handle(handle: NodeExtensionHandle) -> Option<Handle<Self>> {
+        if let NodeExtensionHandle::Sprite(h) = handle { Some(h) } else { None }
+    }
+}
+
+impl ExtensionType for camera::CameraExtension {
+    fn from_extension_handle(handle: NodeExtensionHandle) -> Option<Handle<Self>> {
+        if let NodeExtensionHandle::Camera(h) = handle { Some(h) } else { None }
+    }
+}
+
+impl ExtensionType for rect_collider::RectColliderExtension {
+    fn from_extension_handle(handle: NodeExtensionHandle) -> Option<Handle<Self>> {
+        if let NodeExtensionHandle::RectCollider(h) = handle { Some(h) } else { None }
+    }
+}
+
+impl ExtensionType for label::LabelExtension {
+    fn from_extension_handle(handle: NodeExtensionHandle) -> Option<Handle<Self>> {
+        if let NodeExtensionHandle::Label(h) = handle { Some(h) } else { None }
+    }
+}
+
+/// Handle to a node that's known to have an extension of type `E`,
+/// so the extension can be fetched with `Hierarchy::get_extension` without matching on it.
+/// Get one from `Hierarchy::typed_handle` or `Hierarchy::find_by_name_typed`.
+pub struct TypedHandle<E> {
+    pub(crate) node: Handle<Node>,
+    pub(crate) extension: Handle<E>,
+}
+
+impl<E> TypedHandle<E> {
+    #[inline]
+    #[must_use]
+    pub fn node(&self) -> Handle<Node> {
+        self.node
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn extension(&self) -> Handle<E> {
+        self.extension
+    }
+}
+
+impl<E> Clone for TypedHandle<E> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<E> Copy for TypedHandle<E> {}
+
+impl<E> PartialEq for TypedHandle<E> {
+    fn eq(&self, other: &Self) -> bool {
+        self.node == other.node && self.extension == other.extension
+    }
+}
+impl<E> Eq for TypedHandle<E> {}
+
+impl<E> core::fmt::Debug for TypedHandle<E> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("TypedHandle")
+            .field("node", &self.node)
+            .field("extension", &self.extension)
+            .finish()
+    }
 }
 
 pub(crate) struct NodeExtensionPools {
     pub sprite_pool: Pool<sprite::SpriteExtension>,
     pub camera_pool: Pool<camera::CameraExtension>,
     pub rect_collider_pool: Pool<rect_collider::RectColliderExtension>,
+    pub label_pool: Pool<label::LabelExtension>,
 }
 
 impl NodeExtensionPools {
@@ -32,6 +222,7 @@ impl NodeExtensionPools {
             sprite_pool: Pool::new(),
             camera_pool: Pool::new(),
             rect_collider_pool: Pool::new(),
+            label_pool: Pool::new(),
         }
     }
 
@@ -43,26 +234,117 @@ impl NodeExtensionPools {
         match saved_extension {
             sandstone_common::SavedNodeExtension::None => NodeExtensionHandle::None,
             sandstone_common::SavedNodeExtension::Sprite(s) => {
-                NodeExtensionHandle::Sprite(self.sprite_pool.add(sprite::SpriteExtension {
+                NodeExtensionHandle::Sprite(self.sprite_pool.add(
+                    sprite::SpriteExtension::new(node_handle, s.graphic_asset.clone(), s.sprite_type)
+                ))
+            },
+            sandstone_common::SavedNodeExtension::Camera(c) => {
+                NodeExtensionHandle::Camera(self.camera_pool.add(camera::CameraExtension::new(node_handle, c.active_main, c.active_sub)))
+            },
+            sandstone_common::SavedNodeExtension::RectCollider(c) => {
+                NodeExtensionHandle::RectCollider(self.rect_collider_pool.add(rect_collider::RectColliderExtension {
                     node_handle,
-                    graphic_asset: s.graphic_asset.clone(),
-                    sprite_type: s.sprite_type,
+                    width: c.width,
+                    height: c.height,
+                    offset_x: c.offset_x,
+                    offset_y: c.offset_y,
+                    is_static: c.is_static,
+                    continuous: c.continuous,
+                    response: c.response,
+                    previous_position: None,
+                    intersect_list: Vec::new(),
                 }))
             },
-            sandstone_common::SavedNodeExtension::Camera(c) => {
-                NodeExtensionHandle::Camera(self.camera_pool.add(camera::CameraExtension {
+            sandstone_common::SavedNodeExtension::Label(l) => {
+                NodeExtensionHandle::Label(self.label_pool.add(label::LabelExtension {
                     node_handle,
+                    text: l.text.clone(),
+                    align: l.align,
+                }))
+            },
+        }
+    }
+
+    pub(crate) fn to_saved(&self, handle: NodeExtensionHandle) -> sandstone_common::SavedNodeExtension {
+        match handle {
+            NodeExtensionHandle::None => sandstone_common::SavedNodeExtension::None,
+            NodeExtensionHandle::Sprite(h) => {
+                let s = self.sprite_pool.borrow(h);
+                sandstone_common::SavedNodeExtension::Sprite(sandstone_common::SavedSpriteExtension {
+                    graphic_asset: s.graphic_asset.clone(),
+                    sprite_type: s.sprite_type,
+                })
+            },
+            NodeExtensionHandle::Camera(h) => {
+                let c = self.camera_pool.borrow(h);
+                sandstone_common::SavedNodeExtension::Camera(sandstone_common::SavedCameraExtension {
                     active_main: c.active_main,
                     active_sub: c.active_sub,
-                }))
+                })
             },
-            sandstone_common::SavedNodeExtension::RectCollider(c) => {
-                NodeExtensionHandle::RectCollider(self.rect_collider_pool.add(rect_collider::RectColliderExtension {
+            NodeExtensionHandle::RectCollider(h) => {
+                let c = self.rect_collider_pool.borrow(h);
+                sandstone_common::SavedNodeExtension::RectCollider(sandstone_common::SavedRectColliderExtension {
+                    width: c.width,
+                    height: c.height,
+                    offset_x: c.offset_x,
+                    offset_y: c.offset_y,
+                    response: c.response,
+                    is_static: c.is_static,
+                    continuous: c.continuous,
+                })
+            },
+            NodeExtensionHandle::Label(h) => {
+                let l = self.label_pool.borrow(h);
+                sandstone_common::SavedNodeExtension::Label(sandstone_common::SavedLabelExtension {
+                    text: l.text.clone(),
+                    align: l.align,
+                })
+            },
+        }
+    }
+
+    pub(crate) fn clone_extension(&mut self, node_handle: Handle<Node>, handle: NodeExtensionHandle) -> NodeExtensionHandle {
+        match handle {
+            NodeExtensionHandle::None => NodeExtensionHandle::None,
+            NodeExtensionHandle::Sprite(h) => {
+                let s = self.sprite_pool.borrow(h);
+                let mut new_sprite = sprite::SpriteExtension::new(node_handle, s.graphic_asset.clone(), s.sprite_type);
+                new_sprite.palette_override = s.palette_override;
+                new_sprite.blend_mode = s.blend_mode;
+                new_sprite.mosaic = s.mosaic;
+                NodeExtensionHandle::Sprite(self.sprite_pool.add(new_sprite))
+            },
+            NodeExtensionHandle::Camera(h) => {
+                let c = self.camera_pool.borrow(h);
+                let mut new_camera = camera::CameraExtension::new(node_handle, c.active_main, c.active_sub);
+                new_camera.follow = c.follow;
+                NodeExtensionHandle::Camera(self.camera_pool.add(new_camera))
+            },
+            NodeExtensionHandle::RectCollider(h) => {
+                let c = self.rect_collider_pool.borrow(h);
+                let new_collider = rect_collider::RectColliderExtension {
                     node_handle,
                     width: c.width,
                     height: c.height,
+                    offset_x: c.offset_x,
+                    offset_y: c.offset_y,
+                    is_static: c.is_static,
+                    continuous: c.continuous,
+                    response: c.response,
+                    previous_position: None,
                     intersect_list: Vec::new(),
-                }))
+                };
+                NodeExtensionHandle::RectCollider(self.rect_collider_pool.add(new_collider))
+            },
+            NodeExtensionHandle::Label(h) => {
+                let l = self.label_pool.borrow(h);
+                let new_label = label::LabelExtension {
+                    node_handle,
+                    text: l.text.clone(),
+                    align: l.align,
+                };
+                NodeExtensionHandle::Label(self.label_pool.add(new_label))
             },
         }
     }
@@ -73,6 +355,7 @@ impl NodeExtensionPools {
             NodeExtensionHandle::Sprite(h) => { self.sprite_pool.try_remove(h) },
             NodeExtensionHandle::Camera(h) => { self.camera_pool.try_remove(h) },
             NodeExtensionHandle::RectCollider(h) => { self.rect_collider_pool.try_remove(h)},
+            NodeExtensionHandle::Label(h) => { self.label_pool.try_remove(h) },
         }.expect("Tried to destroy extension with invalid handle");
     }
 }
@@ -80,6 +363,8 @@ impl NodeExtensionPools {
 pub struct NodeScriptData {
     pub type_id: NonZeroU32,
     pub script: Box<dyn Script>,
+    // Scripts added to a node that has already started get their own start later
+    pub(crate) started: bool,
 }
 
 impl core::fmt::Debug for NodeScriptData {
@@ -90,32 +375,186 @@ impl core::fmt::Debug for NodeScriptData {
     }
 }
 
+/// The scripts on a node, which are run in the order they were added.
+/// Most nodes have one at most, so the first is kept inline and only the rest need a Vec.
+#[derive(Debug, Default)]
+pub struct NodeScripts {
+    first: Option<NodeScriptData>,
+    rest: Vec<NodeScriptData>,
+}
+
+impl NodeScripts {
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.first.is_none()
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        usize::from(self.first.is_some()) + self.rest.len()
+    }
+
+    #[must_use]
+    pub fn first(&self) -> Option<&NodeScriptData> {
+        self.first.as_ref()
+    }
+
+    /// The first script with this type ID.
+    #[must_use]
+    pub fn get(&self, type_id: NonZeroU32) -> Option<&NodeScriptData> {
+        self.iter().find(|s| s.type_id == type_id)
+    }
+
+    #[must_use]
+    pub fn get_mut(&mut self, type_id: NonZeroU32) -> Option<&mut NodeScriptData> {
+        self.iter_mut().find(|s| s.type_id == type_id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &NodeScriptData> {
+        self.first.iter().chain(self.rest.iter())
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut NodeScriptData> {
+        self.first.iter_mut().chain(self.rest.iter_mut())
+    }
+
+    pub fn push(&mut self, script: NodeScriptData) {
+        if self.first.is_none() {
+            self.first = Some(script);
+        } else {
+            self.rest.push(script);
+        }
+    }
+
+    // Puts scripts that were taken out to run back in front of any that were added meanwhile
+    pub(crate) fn put_back(&mut self, mut taken: Self) {
+        let added = core::mem::take(self);
+        added.first.into_iter().chain(added.rest).for_each(|s| taken.push(s));
+        *self = taken;
+    }
+}
+
+impl FromIterator<NodeScriptData> for NodeScripts {
+    fn from_iter<I: IntoIterator<Item = NodeScriptData>>(iter: I) -> Self {
+        let mut scripts = Self::default();
+        iter.into_iter().for_each(|s| scripts.push(s));
+        scripts
+    }
+}
+
 #[derive(Debug)]
 pub struct Node {
-    pub child_handle: Option<Handle<Node>>, // todo: maybe could be more efficient, could just be Index without Generation
-    pub parent_handle: Option<Handle<Node>>, // should only be None on root node
-    pub sibling_handle: Option<Handle<Node>>,
-    pub name: String,
+    // Tree links are raw indices, as the hierarchy keeps them valid. Use Hierarchy::parent etc. to get handles.
+    pub(crate) child_index: Option<RawIndex<Node>>,
+    pub(crate) parent_index: Option<RawIndex<Node>>, // should only be None on root node
+    pub(crate) sibling_index: Option<RawIndex<Node>>,
+    /// Interned in the hierarchy. Use `Hierarchy::node_name` to get it as a string.
+    pub name: NameId,
     pub transform: Transform,
     pub node_extension: NodeExtensionHandle,
-    pub script_data: Option<NodeScriptData>,
+    pub scripts: NodeScripts,
     pub enabled: bool,
+    /// Free for game code to use, for small bits of state (like HP) that don't need a whole script.
+    pub user_data: u32,
+    /// When false, the script stops getting updates but the node is otherwise unaffected.
+    pub script_enabled: bool,
+    /// Number of frames until the node is automatically destroyed. None means it lives forever.
+    pub lifetime: Option<u32>,
+    /// When set, the node is destroyed once it's entirely outside of the screen, plus this margin.
+    pub offscreen_destroy_margin: Option<fixed::types::I20F12>,
+    /// For nodes that can be damaged with `Hierarchy::apply_damage`.
+    pub health: Option<Health>,
+    /// Set to None to break the constraint, leaving the node where it was.
+    pub follow: Option<Follow>,
+    /// Played each time the node becomes enabled.
+    pub spawn_animation: Option<SpawnAnimation>,
+    /// For nodes moved by velocity and gravity. Doesn't move while the game is paused.
+    pub body: Option<Body>,
+    /// How much this node and its children scroll with the camera when drawn, for backgrounds.
+    /// 1 scrolls normally, 0.5 looks further away, and 0 stays put on screen. None means the same as the parent.
+    pub parallax: Option<fixed::types::I20F12>,
+    /// Screen to draw this node and its children on. None means the same as the parent.
+    pub screen: Option<Screen>,
     pub(crate) global_transform: Transform,
     pub(crate) global_enabled: bool,
+    pub(crate) global_screen: Screen,
+    pub(crate) global_parallax: fixed::types::I20F12,
+    // Set once the node has been through the start queue. Nodes don't get updates before this.
+    pub(crate) started: bool,
+    // Whether on_enter_screen was the last screen event the script got
+    pub(crate) on_screen: bool,
 }
 
 impl Node {
+    /// A node that isn't linked into the tree yet, enabled, with no scripts and none of the optional behaviours.
+    /// It hasn't started, and its global state is filled in once it's in the hierarchy.
+    #[must_use]
+    pub(crate) fn new(name: NameId, transform: Transform, node_extension: NodeExtensionHandle) -> Self {
+        Self {
+            child_index: None,
+            parent_index: None,
+            sibling_index: None,
+            name,
+            transform,
+            node_extension,
+            scripts: NodeScripts::default(),
+            enabled: true,
+            user_data: 0,
+            script_enabled: true,
+            lifetime: None,
+            offscreen_destroy_margin: None,
+            health: None,
+            follow: None,
+            spawn_animation: None,
+            body: None,
+            parallax: None,
+            screen: None,
+            global_transform: Transform::default(),
+            global_enabled: false,
+            global_screen: Screen::Both,
+            global_parallax: fixed::types::I20F12::ONE,
+            started: false,
+            on_screen: false,
+        }
+    }
+
+    // Screen position for a camera whose view starts at `camera`, after parallax
+    pub(crate) fn draw_position(&self, camera: (fixed::types::I20F12, fixed::types::I20F12)) -> (fixed::types::I20F12, fixed::types::I20F12) {
+        (self.global_transform.x - camera.0 * self.global_parallax, self.global_transform.y - camera.1 * self.global_parallax)
+    }
+
+    /// The node's script of type T. With several scripts, it's the first one of that type.
     pub fn cast_script<T>(&self) -> &T
     where T: Script + HasTypeId {
-        let s_data = self.script_data.as_ref().expect("Tried to cast_script on an object which has no Script");
-        assert_eq!(<T as HasTypeId>::type_id(), s_data.type_id, "Tried to cast_script with mismatching types");
+        assert!(!self.scripts.is_empty(), "Tried to cast_script on an object which has no Script");
+        let s_data = self.scripts.get(<T as HasTypeId>::type_id()).expect("Tried to cast_script with mismatching types");
         unsafe { &*(s_data.script.as_ref() as *const dyn Script as *const T) }
     }
 
     pub fn cast_script_mut<T>(&mut self) -> &mut T
     where T: Script + HasTypeId {
-        let s_data = self.script_data.as_mut().expect("Tried to cast_script on an object which has no Script");
-        assert_eq!(<T as HasTypeId>::type_id(), s_data.type_id, "Tried to cast_script with mismatching types");
+        assert!(!self.scripts.is_empty(), "Tried to cast_script on an object which has no Script");
+        let s_data = self.scripts.get_mut(<T as HasTypeId>::type_id()).expect("Tried to cast_script with mismatching types");
         unsafe { &mut *(s_data.script.as_mut() as *mut dyn Script as *mut T) }
     }
+
+    /// Like `cast_script`, but returns an error if there's no script or none of them are type T.
+    pub fn try_cast_script<T>(&self) -> Result<&T, Error>
+    where T: Script + HasTypeId {
+        if self.scripts.is_empty() {
+            return Err(Error::NoScript);
+        }
+        let s_data = self.scripts.get(<T as HasTypeId>::type_id()).ok_or(Error::ScriptTypeMismatch)?;
+        Ok(unsafe { &*(s_data.script.as_ref() as *const dyn Script as *const T) })
+    }
+
+    /// Like `cast_script_mut`, but returns an error if there's no script or none of them are type T.
+    pub fn try_cast_script_mut<T>(&mut self) -> Result<&mut T, Error>
+    where T: Script + HasTypeId {
+        if self.scripts.is_empty() {
+            return Err(Error::NoScript);
+        }
+        let s_data = self.scripts.get_mut(<T as HasTypeId>::type_id()).ok_or(Error::ScriptTypeMismatch)?;
+        Ok(unsafe { &mut *(s_data.script.as_mut() as *mut dyn Script as *mut T) })
+    }
 }
@@ -1,16 +1,160 @@
 use fixed::types::*;
 use alloc::vec::Vec;
-use crate::{pool::Handle, node::Node, hierarchy::{Hierarchy, HierarchyPoolTrait}};
+use crate::{pool::Handle, node::Node, hierarchy::{Hierarchy, HierarchyPoolTrait}, math::Vec2, HashMap};
+pub use sandstone_common::CollisionResponse;
 
+// Width and height of a broadphase grid cell, in pixels.
+const GRID_CELL_SIZE: i32 = 32;
+
+/// A rectangle with its top left corner at the node's position plus the offset.
 #[derive(Clone)]
 pub struct RectColliderExtension {
     pub node_handle: Handle<Node>,
     pub width: I20F12,
     pub height: I20F12,
+    pub offset_x: I20F12,
+    pub offset_y: I20F12,
+    /// For colliders that rarely move, like walls or parked enemies. A static collider is only put back into the
+    /// broadphase grid when it has moved, instead of every frame. It still collides with everything as normal.
+    pub is_static: bool,
+    /// For fast colliders like bullets, which could otherwise pass straight through thin colliders between frames.
+    /// Hits anything between where it was last frame and where it is now, so teleporting one hits everything in between.
+    pub continuous: bool,
+    /// Whether nodes with a `Body` stop when they run into this, instead of moving through it.
+    pub response: CollisionResponse,
+    // Top left corner last frame, for continuous colliders
+    pub(crate) previous_position: Option<(I20F12, I20F12)>,
     pub intersect_list: Vec<Handle<Node>>, // todo: put this on the stack?
 }
 
+/// Buckets colliders by the grid cells they cover, so only colliders sharing a cell get compared.
+/// Kept between frames so the buckets don't have to be reallocated every time.
+#[derive(Default)]
+pub(crate) struct CollisionGrid {
+    cells: HashMap<(i32, i32), Vec<usize>>,
+    // Static colliders stay in their cells until they move
+    static_cells: HashMap<(i32, i32), Vec<usize>>,
+    // The static collider at each pool index, and where it was bucketed
+    static_entries: Vec<Option<(Handle<RectColliderExtension>, RectExtents)>>,
+    extents: Vec<Option<RectExtents>>,
+    // Where each continuous collider moved from this frame
+    sweeps: Vec<Option<(I20F12, I20F12)>>,
+    candidates: Vec<usize>,
+    #[cfg(test)]
+    pub static_rebuckets: u32,
+}
+
+impl CollisionGrid {
+    fn remove_static(&mut self, index: usize) {
+        if let Some((_, old_extents)) = self.static_entries[index].take() {
+            for cell in old_extents.cells() {
+                if let Some(c) = self.static_cells.get_mut(&cell) {
+                    c.retain(|&j| j != index);
+                    if c.is_empty() {
+                        self.static_cells.remove(&cell);
+                    }
+                }
+            }
+        }
+    }
+}
+
 pub fn check_collisions(hierarchy: &mut Hierarchy) {
+    let mut grid = core::mem::take(&mut hierarchy.collision_grid);
+    let pool_len = hierarchy.node_ext_pools.rect_collider_pool.vec_len();
+
+    // Drop the cells nothing was in last frame, and empty the rest
+    grid.cells.retain(|_, c| !c.is_empty());
+    for cell in grid.cells.values_mut() {
+        cell.clear();
+    }
+    grid.extents.clear();
+    grid.sweeps.clear();
+    for i in pool_len..grid.static_entries.len() {
+        grid.remove_static(i);
+    }
+    grid.static_entries.resize(pool_len, None);
+    for i in 0..pool_len {
+        let handle = hierarchy.node_ext_pools.rect_collider_pool.handle_from_index_checked(i);
+        let mut is_static = false;
+        let mut sweep = None;
+        let extents = handle.and_then(|handle| {
+            let col = hierarchy.node_ext_pools.rect_collider_pool.borrow_mut(handle);
+            col.intersect_list.clear();
+            let col = hierarchy.node_ext_pools.rect_collider_pool.borrow(handle);
+            is_static = col.is_static;
+            let extents = hierarchy.borrow(col.node_handle).global_enabled.then(|| extents_of_collider(hierarchy, col));
+            let col = hierarchy.node_ext_pools.rect_collider_pool.borrow_mut(handle);
+            if col.continuous && !is_static {
+                sweep = col.previous_position.filter(|_| extents.is_some());
+            }
+            col.previous_position = extents.filter(|_| col.continuous).map(|e| (e.min_x, e.min_y));
+            extents
+        });
+        match (handle, &extents) {
+            (Some(handle), Some(e)) if is_static => {
+                let moved = grid.static_entries[i] != Some((handle, *e));
+                if moved {
+                    grid.remove_static(i);
+                    for cell in e.cells() {
+                        grid.static_cells.entry(cell).or_default().push(i);
+                    }
+                    grid.static_entries[i] = Some((handle, *e));
+                    #[cfg(test)]
+                    { grid.static_rebuckets += 1; }
+                }
+            }
+            _ => {
+                grid.remove_static(i);
+                if let Some(e) = &extents {
+                    for cell in e.swept_bounds(sweep).cells() {
+                        grid.cells.entry(cell).or_default().push(i);
+                    }
+                }
+            }
+        }
+        grid.extents.push(extents);
+        grid.sweeps.push(sweep);
+    }
+
+    // Each pair is only checked from the lower index, and in increasing order,
+    // so the intersect lists come out the same as comparing every collider against every other.
+    for i in 0..pool_len {
+        let Some(extents) = &grid.extents[i] else { continue; };
+        grid.candidates.clear();
+        for cell in extents.swept_bounds(grid.sweeps[i]).cells() {
+            for cells in [&grid.cells, &grid.static_cells] {
+                if let Some(c) = cells.get(&cell) {
+                    grid.candidates.extend(c.iter().filter(|&&j| j > i));
+                }
+            }
+        }
+        grid.candidates.sort_unstable();
+        grid.candidates.dedup();
+        for &j in &grid.candidates {
+            let Some(other_extents) = &grid.extents[j] else { continue; };
+            // When both are continuous, only the first one's movement is swept
+            let hit = match (grid.sweeps[i], grid.sweeps[j]) {
+                (Some(from), _) => extents.swept_intersects(from, other_extents),
+                (None, Some(from)) => other_extents.swept_intersects(from, extents),
+                (None, None) => extents.intersects(other_extents),
+            };
+            if hit {
+                let pool = &mut hierarchy.node_ext_pools.rect_collider_pool;
+                let handle = pool.handle_from_index(i).unwrap();
+                let handle_other = pool.handle_from_index(j).unwrap();
+                let [col, col_other] = pool.borrow_many_mut([handle, handle_other]);
+                col.intersect_list.push(col_other.node_handle);
+                col_other.intersect_list.push(col.node_handle);
+            }
+        }
+    }
+    hierarchy.collision_grid = grid;
+}
+
+// The original O(n²) version, used to check that the grid gives the same results.
+#[cfg(all(test, feature = "no-hardware"))]
+pub(crate) fn check_collisions_brute_force(hierarchy: &mut Hierarchy) {
     for col in hierarchy.node_ext_pools.rect_collider_pool.iter_mut() {
         col.intersect_list.clear();
     }
@@ -23,7 +167,7 @@ pub fn check_collisions(hierarchy: &mut Hierarchy) {
                     if let Some(handle_other) = hierarchy.node_ext_pools.rect_collider_pool.handle_from_index_checked(j) {
                         let (col_other_t, mut col_other) = hierarchy.node_ext_pools.rect_collider_pool.take(handle_other);
                         if hierarchy.borrow(col_other.node_handle).global_enabled {
-                            if check_collision(hierarchy, &col, &col_other) {
+                            if extents_of_collider(hierarchy, &col).intersects(&extents_of_collider(hierarchy, &col_other)) {
                                 col.intersect_list.push(col_other.node_handle);
                                 col_other.intersect_list.push(col.node_handle);
                             }
@@ -37,25 +181,130 @@ pub fn check_collisions(hierarchy: &mut Hierarchy) {
     }
 }
 
-fn check_collision(hierarchy: &Hierarchy, col1: &RectColliderExtension, col2: &RectColliderExtension) -> bool {
-    let r1 = extents_of_collider(hierarchy, col1);
-    let r2 = extents_of_collider(hierarchy, col2);
-    !(r1.min_x > r2.max_x || r1.max_x < r2.min_x || r1.min_y > r2.max_y || r1.max_y < r2.min_y)
+// How far the collider can move along one axis before it runs into a collider that blocks it, up to `distance`,
+// and the node with the collider that stopped it short, if any.
+// `moved` is added to its position first, for when it has already moved along the other axis.
+// Colliders it's already inside or only touching the side of don't block it, so it can slide along floors and walls.
+// This is also what lets bodies jump up through a one-way platform and then land on top of it.
+pub(crate) fn clamp_move(hierarchy: &Hierarchy, col: &RectColliderExtension, moved: Vec2, horizontal: bool, distance: I20F12) -> (I20F12, Option<Handle<Node>>) {
+    // Position along the axis of movement, and the range it covers across it
+    let axes = |e: RectExtents| if horizontal {
+        ((e.min_x, e.max_x), (e.min_y, e.max_y))
+    } else {
+        ((e.min_y, e.max_y), (e.min_x, e.max_x))
+    };
+    let e = extents_of_collider(hierarchy, col);
+    let ((min, max), (cross_min, cross_max)) = axes(RectExtents {
+        min_x: e.min_x + moved.x, max_x: e.max_x + moved.x, min_y: e.min_y + moved.y, max_y: e.max_y + moved.y,
+    });
+    let (mut allowed, mut blocker) = (distance, None);
+    for other in hierarchy.node_ext_pools.rect_collider_pool.iter() {
+        let blocks = match other.response {
+            CollisionResponse::Trigger => false,
+            CollisionResponse::Solid => true,
+            CollisionResponse::OneWayUp => !horizontal && distance > 0,
+        };
+        if !blocks || other.node_handle == col.node_handle || !hierarchy.borrow(other.node_handle).global_enabled {
+            continue;
+        }
+        let ((o_min, o_max), (o_cross_min, o_cross_max)) = axes(extents_of_collider(hierarchy, other));
+        if o_cross_min >= cross_max || o_cross_max <= cross_min {
+            continue;
+        }
+        let limit = if distance > 0 && o_min >= max {
+            o_min - max
+        } else if distance < 0 && o_max <= min {
+            o_max - min
+        } else {
+            continue;
+        };
+        if limit.abs() < allowed.abs() {
+            (allowed, blocker) = (limit, Some(other.node_handle));
+        }
+    }
+    (allowed, blocker)
 }
 
 fn extents_of_collider(hierarchy: &Hierarchy, col: &RectColliderExtension) -> RectExtents {
     let node = hierarchy.borrow(col.node_handle);
+    let (x, y) = (node.global_transform.x + col.offset_x, node.global_transform.y + col.offset_y);
     RectExtents {
-        min_x: node.global_transform.x,
-        max_x: node.global_transform.x + col.width,
-        min_y: node.global_transform.y,
-        max_y: node.global_transform.y + col.height,
+        min_x: x,
+        max_x: x + col.width,
+        min_y: y,
+        max_y: y + col.height,
     }
 }
 
+/// Screen positions of a collider's corners, clockwise from the top left, when the camera is at `camera`.
+/// These are the exact edges used for intersecting, so a collider touches whatever its outline touches.
+pub(crate) fn collider_outline(hierarchy: &Hierarchy, col: &RectColliderExtension, camera: (I20F12, I20F12)) -> [(i32, i32); 4] {
+    let e = extents_of_collider(hierarchy, col);
+    let (min_x, max_x) = ((e.min_x - camera.0).to_num::<i32>(), (e.max_x - camera.0).to_num::<i32>());
+    let (min_y, max_y) = ((e.min_y - camera.1).to_num::<i32>(), (e.max_y - camera.1).to_num::<i32>());
+    [(min_x, min_y), (max_x, min_y), (max_x, max_y), (min_x, max_y)]
+}
+
+#[derive(Clone, Copy, PartialEq)]
 struct RectExtents {
     min_x: I20F12,
     max_x: I20F12,
     min_y: I20F12,
     max_y: I20F12,
 }
+
+impl RectExtents {
+    fn intersects(&self, other: &RectExtents) -> bool {
+        !(self.min_x > other.max_x || self.max_x < other.min_x || self.min_y > other.max_y || self.max_y < other.min_y)
+    }
+
+    // Whether this would have touched other anywhere along the way, moving in a straight line
+    // from having its top left corner at `from` to where it is now.
+    fn swept_intersects(&self, from: (I20F12, I20F12), other: &RectExtents) -> bool {
+        if self.intersects(other) {
+            return true;
+        }
+        // Move the corner as a point against other grown by this one's size, clipping the
+        // fraction of the movement spent inside it on each axis.
+        let (mut t_min, mut t_max) = (I20F12::ZERO, I20F12::ONE);
+        let axes = [
+            (from.0, self.min_x, other.min_x - (self.max_x - self.min_x), other.max_x),
+            (from.1, self.min_y, other.min_y - (self.max_y - self.min_y), other.max_y),
+        ];
+        for (start, end, min, max) in axes {
+            let d = end - start;
+            if d == 0 {
+                if start < min || start > max {
+                    return false;
+                }
+                continue;
+            }
+            let (t0, t1) = ((min - start).saturating_div(d), (max - start).saturating_div(d));
+            t_min = t_min.max(t0.min(t1));
+            t_max = t_max.min(t0.max(t1));
+            if t_min > t_max {
+                return false;
+            }
+        }
+        true
+    }
+
+    // Covers both where it is now, and where its top left corner was at `from`.
+    fn swept_bounds(&self, from: Option<(I20F12, I20F12)>) -> RectExtents {
+        let Some((x, y)) = from else { return *self; };
+        RectExtents {
+            min_x: self.min_x.min(x),
+            max_x: self.max_x.max(x + (self.max_x - self.min_x)),
+            min_y: self.min_y.min(y),
+            max_y: self.max_y.max(y + (self.max_y - self.min_y)),
+        }
+    }
+
+    // Every grid cell this touches. Edges on a cell boundary count as in both cells, since touching rects intersect.
+    fn cells(&self) -> impl Iterator<Item = (i32, i32)> {
+        let to_cell = |v: I20F12| v.to_num::<i32>().div_euclid(GRID_CELL_SIZE);
+        let (min_x, max_x) = (to_cell(self.min_x), to_cell(self.max_x));
+        let (min_y, max_y) = (to_cell(self.min_y), to_cell(self.max_y));
+        (min_y..=max_y).flat_map(move |y| (min_x..=max_x).map(move |x| (x, y)))
+    }
+}
@@ -1,30 +1,250 @@
-use crate::{pool::Handle, node::{Node, camera::{ActiveCameras, CameraExtension}}, hierarchy::Hierarchy, HashMap};
+use crate::{pool::Handle, node::{Node, Screen, camera::ActiveCameras}, hierarchy::Hierarchy, vram::{VramAllocator, GraphicsCache, VramError, VramSlot}};
 use alloc::string::String;
-use fixed::types::*;
-use ironds::display::{obj, GfxEngine};
-use sandstone_common::{SavedGameData, SpriteSize};
+use sandstone_common::SavedGameData;
+pub use sandstone_common::BlendMode;
+// Only needed to actually draw the sprites
+#[cfg(feature = "hardware")]
+use {
+    crate::node::camera::CameraExtension,
+    fixed::types::*,
+    ironds::display::{obj, GfxEngine},
+    sandstone_common::SpriteSize,
+    crate::vram::{SIZEOF_TILE, SIZEOF_PALETTE},
+    core::cell::{Cell, RefCell},
+};
 
-// Assumes 16 palette / 16 colour mode.
-const SIZEOF_PALETTE: usize = 2 * 16;
-const SIZEOF_TILE: usize = (8 * 8) / 2;
+#[cfg_attr(feature = "no-hardware", allow(dead_code))]
+const OAM_SPRITE_COUNT: usize = 128;
+#[cfg_attr(feature = "no-hardware", allow(dead_code))]
+const OAM_AFFINE_COUNT: usize = 32;
+
+// Blending control and alpha coefficient registers, for each engine
+#[cfg(feature = "hardware")]
+const BLDCNT_MAIN: usize = 0x0400_0050;
+#[cfg(feature = "hardware")]
+const BLDALPHA_MAIN: usize = 0x0400_0052;
+#[cfg(feature = "hardware")]
+const MOSAIC_MAIN: usize = 0x0400_004C;
+#[cfg(feature = "hardware")]
+const SUB_ENGINE_REG_OFFSET: usize = 0x1000;
+// Every BG layer, OBJs and the backdrop can be blended onto
+#[cfg_attr(feature = "no-hardware", allow(dead_code))]
+const BLDCNT_SECOND_TARGET_ALL: u16 = 0x3F00;
+/// Maximum blend coefficient, which means fully opaque.
+pub const MAX_SPRITE_ALPHA: u8 = 16;
+
+/// Largest mosaic block size, in pixels.
+pub const MAX_MOSAIC_SIZE: u8 = 16;
+
+// MOSAIC value to write for a screen, which is 0 (no mosaic) if no sprites on it use mosaic.
+// The register is write-only, so it's written every frame and the BG mosaic bits get cleared - the engine doesn't use BG mosaic.
+#[cfg_attr(feature = "no-hardware", allow(dead_code))]
+pub(crate) fn mosaic_register(mosaic_used: bool, (width, height): (u8, u8)) -> u16 {
+    if mosaic_used { ((width as u16 - 1) << 8) | ((height as u16 - 1) << 12) } else { 0 }
+}
+
+// OBJ mode attribute for a blend mode - 1 is semi-transparent
+#[cfg_attr(feature = "no-hardware", allow(dead_code))]
+pub(crate) fn oam_mode(blend_mode: BlendMode) -> u8 {
+    match blend_mode {
+        BlendMode::Normal => 0,
+        BlendMode::Alpha | BlendMode::Additive => 1,
+    }
+}
+
+// BLDCNT and BLDALPHA values for a screen, based on which blend modes are on it.
+// Semi-transparent OBJs always blend as the first target, so only the second targets are set.
+#[cfg_attr(feature = "no-hardware", allow(dead_code))]
+pub(crate) fn blend_registers(alpha_used: bool, additive_used: bool, alpha: u8) -> (u16, u16) {
+    let (eva, evb) = if additive_used {
+        (MAX_SPRITE_ALPHA, MAX_SPRITE_ALPHA)
+    } else if alpha_used {
+        (alpha, MAX_SPRITE_ALPHA - alpha)
+    } else {
+        return (0, 0);
+    };
+    (BLDCNT_SECOND_TARGET_ALL, eva as u16 | (evb as u16) << 8)
+}
+
+// A pair of buffers - the back one is drawn into during the frame, then they're flipped
+// so the front one can be shown. The front is only handed out once per flip.
+#[cfg_attr(feature = "no-hardware", allow(dead_code))]
+pub(crate) struct DoubleBuffer<T> {
+    buffers: [T; 2],
+    front: usize,
+    front_pending: bool,
+}
+
+#[cfg_attr(feature = "no-hardware", allow(dead_code))]
+impl<T> DoubleBuffer<T> {
+    pub fn new(front: T, back: T) -> Self {
+        Self { buffers: [front, back], front: 0, front_pending: false }
+    }
+
+    #[cfg(test)]
+    #[must_use]
+    pub fn front_index(&self) -> usize {
+        self.front
+    }
+
+    pub fn back_mut(&mut self) -> &mut T {
+        &mut self.buffers[1 - self.front]
+    }
+
+    pub fn flip(&mut self) {
+        self.front = 1 - self.front;
+        self.front_pending = true;
+    }
+
+    // The front buffer, if it hasn't been taken since the last flip
+    pub fn take_front(&mut self) -> Option<&T> {
+        core::mem::take(&mut self.front_pending).then(|| &self.buffers[self.front])
+    }
+}
+
+// Hands out OAM entries, and affine parameters for affine sprites, in order until they run out.
+// Sprites that don't get one aren't drawn.
+#[cfg_attr(feature = "no-hardware", allow(dead_code))]
+#[derive(Default)]
+pub(crate) struct OamSlots {
+    sprites: usize,
+    affine: usize,
+    overflowed: bool,
+}
+
+#[cfg_attr(feature = "no-hardware", allow(dead_code))]
+impl OamSlots {
+    // The sprite's OAM index, and its affine parameter index if it's affine, or None if there's no room left
+    pub fn take(&mut self, affine: bool) -> Option<(usize, Option<usize>)> {
+        if self.sprites == OAM_SPRITE_COUNT || (affine && self.affine == OAM_AFFINE_COUNT) {
+            self.overflowed = true;
+            return None;
+        }
+        let affine_index = affine.then(|| {
+            self.affine += 1;
+            self.affine - 1
+        });
+        self.sprites += 1;
+        Some((self.sprites - 1, affine_index))
+    }
+}
+
+// Copy of OAM in main RAM, so sprites can be written at any time and copied over in VBlank.
+// The blend and mosaic registers go with it, so they change on the same frame as the sprites using them.
+#[cfg(feature = "hardware")]
+pub(crate) struct ShadowOam {
+    sprites: [obj::Sprite; OAM_SPRITE_COUNT],
+    affine_params: [(I8F8, I8F8, I8F8, I8F8); OAM_AFFINE_COUNT],
+    affine_count: usize,
+    // BLDCNT and BLDALPHA
+    blend: (u16, u16),
+    mosaic: u16,
+}
+
+#[cfg(feature = "hardware")]
+impl ShadowOam {
+    fn new() -> Self {
+        Self {
+            sprites: [obj::DISABLED_SPRITE; OAM_SPRITE_COUNT],
+            affine_params: [(I8F8::ZERO, I8F8::ZERO, I8F8::ZERO, I8F8::ZERO); OAM_AFFINE_COUNT],
+            affine_count: 0,
+            blend: (0, 0),
+            mosaic: 0,
+        }
+    }
+
+    fn copy_to_oam(&self, engine: GfxEngine) {
+        for (i, sprite) in self.sprites.iter().enumerate() {
+            obj::set_sprite(engine, i, *sprite);
+        }
+        for (i, &(pa, pb, pc, pd)) in self.affine_params[..self.affine_count].iter().enumerate() {
+            obj::set_affine_param(engine, i as u8, obj::AffineParameter { pa, pb, pc, pd });
+        }
+        let reg_offset = match engine {
+            GfxEngine::MAIN => 0,
+            GfxEngine::SUB => SUB_ENGINE_REG_OFFSET,
+        };
+        unsafe {
+            core::ptr::write_volatile((BLDCNT_MAIN + reg_offset) as *mut u16, self.blend.0);
+            core::ptr::write_volatile((BLDALPHA_MAIN + reg_offset) as *mut u16, self.blend.1);
+            core::ptr::write_volatile((MOSAIC_MAIN + reg_offset) as *mut u16, self.mosaic);
+        }
+    }
+}
+
+// Sprites that should be drawn on the given screen (Main or Sub), in OAM order
+#[cfg_attr(feature = "no-hardware", allow(dead_code))]
+pub(crate) fn sprites_on_screen(hierarchy: &Hierarchy, screen: Screen) -> impl Iterator<Item = &SpriteExtension> {
+    hierarchy.node_ext_pools.sprite_pool.iter().filter(move |sprite| {
+        let node = hierarchy.object_pool.borrow(sprite.node_handle);
+        node.global_enabled && node.global_screen.shows_on(screen)
+    })
+}
 
 pub type SpriteType = sandstone_common::SavedSpriteType;
 pub struct SpriteExtension {
     pub node_handle: Handle<Node>,
     pub graphic_asset: String,
     pub sprite_type: SpriteType,
+    /// Palette bank to draw with instead of the graphic's own, e.g. for team colours.
+    pub palette_override: Option<u8>,
+    pub blend_mode: BlendMode,
+    /// Draws the sprite in blocks, with the size set by `Hierarchy::set_sprite_mosaic`.
+    pub mosaic: bool,
+    // Palette bank and number of frames left for a flash
+    pub(crate) flash: Option<(u8, u32)>,
+    // The graphic last requested for this sprite, and whether it's holding a reference to it
+    pub(crate) loaded_graphic: Option<(String, bool)>,
 }
 
-pub(crate) struct SpriteExtensionHandler {
-    sprite_vram_map: HashMap<String, SpriteVramMapping>,
+impl SpriteExtension {
+    pub(crate) fn new(node_handle: Handle<Node>, graphic_asset: String, sprite_type: SpriteType) -> Self {
+        Self { node_handle, graphic_asset, sprite_type, palette_override: None, blend_mode: BlendMode::Normal, mosaic: false, flash: None, loaded_graphic: None }
+    }
+
+    /// Draws the sprite with another palette bank for the given number of frames, then goes back to normal.
+    /// Good for hit flashes. Starting a new flash replaces the current one.
+    pub fn flash(&mut self, palette_bank: u8, frames: u32) {
+        self.flash = (frames > 0).then_some((palette_bank, frames));
+    }
+
+    #[must_use]
+    pub fn is_flashing(&self) -> bool {
+        self.flash.is_some()
+    }
+
+    /// The palette bank to draw with, given the bank the graphic was loaded into.
+    /// Flashes take priority over the override.
+    #[must_use]
+    #[cfg_attr(feature = "no-hardware", allow(dead_code))]
+    pub(crate) fn palette_bank(&self, graphic_bank: u8) -> u8 {
+        self.flash.map(|(bank, _)| bank).or(self.palette_override).unwrap_or(graphic_bank)
+    }
+
+    pub(crate) fn update_flash(&mut self) {
+        if let Some((_, frames_left)) = &mut self.flash {
+            *frames_left -= 1;
+            if *frames_left == 0 {
+                self.flash = None;
+            }
+        }
+    }
 }
 
-#[derive(Copy, Clone)]
-struct SpriteVramMapping {
-    tile_index: u16,
-    pal_index: u8,
+pub(crate) struct SpriteExtensionHandler {
+    // Every live sprite holds a reference to its graphic in here
+    pub graphics: GraphicsCache,
+    pub blend_alpha: u8,
+    pub mosaic_size: (u8, u8),
+    // Main and sub engine. Written while the hierarchy is borrowed, so it needs a RefCell.
+    #[cfg(feature = "hardware")]
+    oam: RefCell<[DoubleBuffer<ShadowOam>; 2]>,
+    // Whether running out of OAM has been reported yet
+    #[cfg(feature = "hardware")]
+    oam_overflow_logged: Cell<bool>,
 }
 
+#[cfg(feature = "hardware")]
 fn sprite_size_to_shape_and_size(ss: SpriteSize) -> (u8, u8) {
     match ss {
         SpriteSize::_8x8 => (0, 0),
@@ -45,16 +265,84 @@ fn sprite_size_to_shape_and_size(ss: SpriteSize) -> (u8, u8) {
 impl SpriteExtensionHandler {
     pub fn new() -> Self {
         Self {
-            sprite_vram_map: HashMap::default(),
+            graphics: GraphicsCache::new(VramAllocator::new()),
+            blend_alpha: MAX_SPRITE_ALPHA / 2,
+            mosaic_size: (1, 1),
+            #[cfg(feature = "hardware")]
+            oam: RefCell::new([
+                DoubleBuffer::new(ShadowOam::new(), ShadowOam::new()),
+                DoubleBuffer::new(ShadowOam::new(), ShadowOam::new()),
+            ]),
+            #[cfg(feature = "hardware")]
+            oam_overflow_logged: Cell::new(false),
         }
     }
 
-    pub fn sprite_init(&mut self, game_data: &SavedGameData) {
-        self.sprite_init_for_engine(game_data, GfxEngine::MAIN);
-        self.sprite_init_for_engine(game_data, GfxEngine::SUB);
+    /// Loads the sprite's graphic if it's changed since the last call, and lets go of the old one.
+    /// Graphics are unloaded once no sprites use them, unless they're pinned.
+    pub fn sync_graphic(&mut self, sprite: &mut SpriteExtension, game_data: &SavedGameData) {
+        if sprite.loaded_graphic.as_ref().is_some_and(|(name, _)| *name == sprite.graphic_asset) {
+            return;
+        }
+        self.release_sprite_graphic(sprite);
+        let name = &sprite.graphic_asset;
+        let loaded = match self.load_graphic(name, game_data, false) {
+            Some(Ok(_)) => true,
+            // Failures are remembered, so they're only reported once
+            Some(Err(e)) => { crate::log::error!("Couldn't load graphic {name}: {e}"); false },
+            None => { crate::log::error!("Tried to load graphic {name}, which doesn't exist"); false },
+        };
+        sprite.loaded_graphic = Some((name.clone(), loaded));
+    }
+
+    /// Switches the sprite to another graphic, loading it before letting go of the old one.
+    /// If the new graphic can't be loaded, the sprite keeps the old one.
+    pub fn set_graphic(&mut self, sprite: &mut SpriteExtension, name: &str, game_data: &SavedGameData) -> Option<Result<VramSlot, VramError>> {
+        let result = self.load_graphic(name, game_data, false);
+        if let Some(Ok(_)) = result {
+            self.release_sprite_graphic(sprite);
+            sprite.graphic_asset = String::from(name);
+            sprite.loaded_graphic = Some((sprite.graphic_asset.clone(), true));
+        }
+        result
     }
 
-    // this really should be happening in Vblank handler, given that OAM is only accessible during vblank
+    /// Lets go of the sprite's graphic, e.g. when it's destroyed.
+    pub fn release_sprite_graphic(&mut self, sprite: &mut SpriteExtension) {
+        if let Some((name, true)) = sprite.loaded_graphic.take() {
+            self.graphics.release(&name);
+        }
+    }
+
+    /// Keeps the graphic loaded even when no sprites use it, e.g. for UI that comes and goes.
+    pub fn pin_graphic(&mut self, name: &str, game_data: &SavedGameData) -> Option<Result<VramSlot, VramError>> {
+        self.load_graphic(name, game_data, true)
+    }
+
+    // Pins the graphic or adds a reference to it, copying it into VRAM if it wasn't there already.
+    // Returns None if there's no graphic with this name.
+    #[cfg_attr(feature = "no-hardware", allow(unused_variables))]
+    fn load_graphic(&mut self, name: &str, game_data: &SavedGameData, pin: bool) -> Option<Result<VramSlot, VramError>> {
+        let graphic = game_data.graphics.get(name)?;
+        let result = if pin { self.graphics.pin(name, graphic) } else { self.graphics.acquire(name, graphic) };
+        Some(result.map(|(slot, is_new)| {
+            #[cfg(feature = "hardware")]
+            if is_new {
+                Self::upload_graphic(graphic, slot, GfxEngine::MAIN);
+                Self::upload_graphic(graphic, slot, GfxEngine::SUB);
+            }
+            slot
+        }))
+    }
+
+    #[cfg(feature = "no-hardware")]
+    pub fn sprite_update(&self, _hierarchy: &Hierarchy, _cameras: ActiveCameras) {}
+
+    #[cfg(feature = "no-hardware")]
+    pub fn vblank(&mut self) {}
+
+    // Draws into the back OAM buffers - they're copied to the real OAM by vblank
+    #[cfg(feature = "hardware")]
     pub fn sprite_update(&self, hierarchy: &Hierarchy, cameras: ActiveCameras) {
         if let Some(camera) = cameras.main {
             self.sprite_update_for_engine(hierarchy, GfxEngine::MAIN, camera);
@@ -64,58 +352,57 @@ impl SpriteExtensionHandler {
         }
     }
 
-    fn sprite_init_for_engine(&mut self, game_data: &SavedGameData, engine: GfxEngine) {
-        #[inline(always)]
-        fn align_to(ptr: *mut u8, align: usize) -> *mut u8 {
-            let align_mask = align - 1;
-            let ptr_u = ptr as usize;
-            if ptr_u & align_mask != 0 {
-                (ptr_u + (align - (ptr_u & align_mask))) as *mut u8
-            } else {
-                ptr
-            }
+    // OAM can only be written during VBlank, so this copies over the last finished frame's sprites, blending and mosaic.
+    #[cfg(feature = "hardware")]
+    pub fn vblank(&mut self) {
+        let [main, sub] = self.oam.get_mut();
+        if let Some(oam) = main.take_front() {
+            oam.copy_to_oam(GfxEngine::MAIN);
+        }
+        if let Some(oam) = sub.take_front() {
+            oam.copy_to_oam(GfxEngine::SUB);
         }
+    }
 
+    // Both engines get the same layout, so a sprite's slot is valid on either screen
+    #[cfg(feature = "hardware")]
+    fn upload_graphic(graphic: &sandstone_common::SavedGraphic, slot: VramSlot, engine: GfxEngine) {
         let (tile_ram_base, pal_ram_base) = match engine {
             GfxEngine::MAIN => (ironds::mmio::OBJ_RAM_BASE_MAIN as *mut u8, ironds::mmio::OBJ_PALETTE_RAM_BASE_MAIN as *mut u8),
             GfxEngine::SUB => (ironds::mmio::OBJ_RAM_BASE_SUB as *mut u8, ironds::mmio::OBJ_PALETTE_RAM_BASE_SUB as *mut u8),
         };
-        let mut cur_tile_ram_ptr = tile_ram_base;
-        let mut cur_pal_ram_ptr = pal_ram_base;
-        for (name, saved_graphic) in game_data.graphics.iter() {
-            self.sprite_vram_map.insert(name.clone(), SpriteVramMapping {
-                tile_index: ((cur_tile_ram_ptr as usize - tile_ram_base as usize) / SIZEOF_TILE) as u16,
-                pal_index: ((cur_pal_ram_ptr as usize - pal_ram_base as usize) / SIZEOF_PALETTE) as u8,
-            });
-            unsafe {
-                // todo: check for overflow of tile / palette ram
-                let tile_end = cur_tile_ram_ptr.add(saved_graphic.tiles.len());
-                let pal_end = cur_pal_ram_ptr.add(saved_graphic.palette.len());
-                core::ptr::copy_nonoverlapping(saved_graphic.tiles.as_ptr(), cur_tile_ram_ptr, saved_graphic.tiles.len());
-                core::ptr::copy_nonoverlapping(saved_graphic.palette.as_ptr(), cur_pal_ram_ptr, saved_graphic.palette.len());
-                cur_tile_ram_ptr = align_to(tile_end, SIZEOF_TILE);
-                cur_pal_ram_ptr = align_to(pal_end, SIZEOF_PALETTE);
-            }
+        unsafe {
+            // The allocator made sure both of these fit
+            let tile_ptr = tile_ram_base.add(slot.tile_index as usize * SIZEOF_TILE);
+            let pal_ptr = pal_ram_base.add(slot.pal_index as usize * SIZEOF_PALETTE);
+            core::ptr::copy_nonoverlapping(graphic.tiles.as_ptr(), tile_ptr, graphic.tiles.len());
+            core::ptr::copy_nonoverlapping(graphic.palette.as_ptr(), pal_ptr, graphic.palette.len());
         }
     }
 
+    #[cfg(feature = "hardware")]
     fn sprite_update_for_engine(&self, hierarchy: &Hierarchy, engine: GfxEngine, camera: Handle<CameraExtension>) {
-        let camera = hierarchy.node_ext_pools.camera_pool.borrow(camera);
-        let camera_node = hierarchy.object_pool.borrow(camera.node_handle);
-        let (cam_x, cam_y) = (camera_node.global_transform.x, camera_node.global_transform.y);
+        let (cam_x, cam_y) = hierarchy.node_ext_pools.camera_pool.borrow(camera).draw_position(hierarchy);
+
+        let (oam_index, screen) = match engine {
+            GfxEngine::MAIN => (0, Screen::Main),
+            GfxEngine::SUB => (1, Screen::Sub),
+        };
+        let mut oam_buffers = self.oam.borrow_mut();
+        let oam_buffer = &mut oam_buffers[oam_index];
+        let oam = oam_buffer.back_mut();
 
-        let mut cur_sprite_index = 0;
-        let mut cur_affine_index = 0;
-        for sprite in hierarchy.node_ext_pools.sprite_pool.iter() {
+        let mut slots = OamSlots::default();
+        let (mut alpha_used, mut additive_used, mut mosaic_used) = (false, false, false);
+        for sprite in sprites_on_screen(hierarchy, screen) {
             let node = hierarchy.object_pool.borrow(sprite.node_handle);
-            if !node.global_enabled { continue; }
 
-            let vram_mapping = self.sprite_vram_map[&sprite.graphic_asset];
-            let sprite_size = hierarchy.game_data.graphics[&sprite.graphic_asset].size;
+            // Graphics that failed to load were already reported, so they're just not drawn
+            let Some(vram_mapping) = self.graphics.get(&sprite.graphic_asset) else { continue; };
+            let sprite_size = vram_mapping.size;
             let (shape, size) = sprite_size_to_shape_and_size(sprite_size);
 
-            let mut screen_x_f = node.global_transform.x - cam_x;
-            let mut screen_y_f = node.global_transform.y - cam_y;
+            let (mut screen_x_f, mut screen_y_f) = node.draw_position((cam_x, cam_y));
             if let SpriteType::Affine(_) = sprite.sprite_type {
                 // Double-size sprites have the origin point moved to the center, so we must compensate
                 let (sz_x, sz_y) = sprite_size.to_dimensions();
@@ -127,62 +414,166 @@ impl SpriteExtensionHandler {
             if !(screen_y_f < 192 && screen_y_f > -64 && screen_x_f < 256 && screen_x_f > -128) {
                 continue;
             }
+            let Some((sprite_index, affine_index)) = slots.take(matches!(sprite.sprite_type, SpriteType::Affine(_))) else { continue; };
             let screen_x = (screen_x_f.to_num::<i32>() & 0x1FF) as u16;
             let screen_y = (screen_y_f.to_num::<i32>() & 0xFF) as u8;
+            alpha_used |= sprite.blend_mode == BlendMode::Alpha;
+            additive_used |= sprite.blend_mode == BlendMode::Additive;
+            mosaic_used |= sprite.mosaic;
 
-            match sprite.sprite_type {
-                SpriteType::Normal => {
-                    obj::set_sprite(engine, cur_sprite_index, obj::Sprite::NormalSprite(obj::NormalSprite::new()
-                        .with_x(screen_x)
-                        .with_y(screen_y)
-                        .with_disable(false)
-                        .with_h_flip(false)
-                        .with_v_flip(false)
-                        .with_mode(0) // Normal mode
-                        .with_mosaic(false)
-                        .with_palette_type(false) // 16/16
-                        .with_shape(shape)
-                        .with_size(size)
-                        .with_tile(vram_mapping.tile_index)
-                        .with_priority(0)
-                        .with_palette(vram_mapping.pal_index)
-                    ));
-                }
-                SpriteType::Affine(affine) => {
+            match (sprite.sprite_type, affine_index) {
+                (SpriteType::Affine(affine), Some(affine_index)) => {
                     // Construct an affine transformation matrix for rotation and scale:
                     // |pa, pb|    =     |cos(angle) / xscale, -sin(angle) / xscale|
                     // |pc, pd|          |sin(angle) / yscale, cos(angle) / yscale |
                     // https://www.coranac.com/tonc/text/affobj.htm
                     let (sin, cos) = cordic::sin_cos(-affine.rotation);
-                    obj::set_affine_param(engine, cur_affine_index, obj::AffineParameter {
-                        pa: I8F8::from_num(cos / affine.scale_x),
-                        pb: I8F8::from_num(-sin / affine.scale_x),
-                        pc: I8F8::from_num(sin / affine.scale_y),
-                        pd: I8F8::from_num(cos / affine.scale_y),
-                    });
-                    obj::set_sprite(engine, cur_sprite_index, obj::Sprite::AffineSprite(obj::AffineSprite::new()
+                    oam.affine_params[affine_index] = (
+                        I8F8::from_num(cos / affine.scale_x),
+                        I8F8::from_num(-sin / affine.scale_x),
+                        I8F8::from_num(sin / affine.scale_y),
+                        I8F8::from_num(cos / affine.scale_y),
+                    );
+                    oam.sprites[sprite_index] = obj::Sprite::AffineSprite(obj::AffineSprite::new()
                         .with_x(screen_x)
                         .with_y(screen_y)
                         .with_double_size(true)
-                        .with_mode(0)
-                        .with_mosaic(false)
+                        .with_mode(oam_mode(sprite.blend_mode))
+                        .with_mosaic(sprite.mosaic)
                         .with_palette_type(false)
                         .with_shape(shape)
                         .with_size(size)
                         .with_tile(vram_mapping.tile_index)
                         .with_priority(0)
-                        .with_palette(vram_mapping.pal_index)
-                        .with_affine_param(cur_affine_index)
-                    ));
-                    cur_affine_index += 1;
+                        .with_palette(sprite.palette_bank(vram_mapping.pal_index))
+                        .with_affine_param(affine_index as u8)
+                    );
+                }
+                _ => {
+                    oam.sprites[sprite_index] = obj::Sprite::NormalSprite(obj::NormalSprite::new()
+                        .with_x(screen_x)
+                        .with_y(screen_y)
+                        .with_disable(false)
+                        .with_h_flip(false)
+                        .with_v_flip(false)
+                        .with_mode(oam_mode(sprite.blend_mode))
+                        .with_mosaic(sprite.mosaic)
+                        .with_palette_type(false) // 16/16
+                        .with_shape(shape)
+                        .with_size(size)
+                        .with_tile(vram_mapping.tile_index)
+                        .with_priority(0)
+                        .with_palette(sprite.palette_bank(vram_mapping.pal_index))
+                    );
                 }
             }
-
-            cur_sprite_index += 1;
         }
-        for i in cur_sprite_index..128 {
-            obj::set_sprite(engine, i, obj::DISABLED_SPRITE);
+        oam.sprites[slots.sprites..].fill(obj::DISABLED_SPRITE);
+        oam.affine_count = slots.affine;
+        if slots.overflowed && !self.oam_overflow_logged.replace(true) {
+            crate::log::warn!("More sprites on screen than OAM can hold, so some aren't being drawn");
         }
+        oam.blend = blend_registers(alpha_used, additive_used, self.blend_alpha);
+        oam.mosaic = mosaic_register(mosaic_used, self.mosaic_size);
+        oam_buffer.flip();
     }
 }
 
+
+#[cfg(all(test, feature = "no-hardware"))]
+mod tests {
+    use super::*;
+    use crate::pool::Pool;
+
+    fn test_sprite() -> SpriteExtension {
+        let mut nodes: Pool<Node> = Pool::new();
+        let node_handle = nodes.add(Node::new(crate::names::NameId::default(), crate::node::Transform::default(), crate::node::NodeExtensionHandle::None));
+        SpriteExtension::new(node_handle, String::from("Enemy"), SpriteType::Normal)
+    }
+
+    #[test]
+    fn palette_override() {
+        let mut sprite = test_sprite();
+        assert_eq!(sprite.palette_bank(3), 3);
+        sprite.palette_override = Some(5);
+        assert_eq!(sprite.palette_bank(3), 5);
+        sprite.palette_override = None;
+        assert_eq!(sprite.palette_bank(3), 3);
+    }
+
+    #[test]
+    fn alpha_blend_registers() {
+        assert_eq!(oam_mode(BlendMode::Normal), 0);
+        assert_eq!(oam_mode(BlendMode::Alpha), 1);
+        assert_eq!(oam_mode(BlendMode::Additive), 1);
+
+        assert_eq!(blend_registers(false, false, 8), (0, 0));
+        assert_eq!(blend_registers(true, false, 4), (0x3F00, 0x0C04));
+        // Additive uses both coefficients at full, and wins over alpha
+        assert_eq!(blend_registers(false, true, 4), (0x3F00, 0x1010));
+        assert_eq!(blend_registers(true, true, 4), (0x3F00, 0x1010));
+    }
+
+    #[test]
+    fn mosaic_register_values() {
+        let mut sprite = test_sprite();
+        sprite.mosaic = true;
+        assert_eq!(mosaic_register(sprite.mosaic, (4, 2)), 0x1300);
+        assert_eq!(mosaic_register(true, (1, 1)), 0);
+        assert_eq!(mosaic_register(true, (16, 16)), 0xFF00);
+        // Still written, so a size from an earlier frame doesn't stay on
+        assert_eq!(mosaic_register(false, (4, 2)), 0);
+    }
+
+    #[test]
+    fn double_buffer_flips() {
+        let mut buffer = DoubleBuffer::new(0, 0);
+        assert_eq!(buffer.front_index(), 0);
+        assert_eq!(buffer.take_front(), None);
+
+        for frame in 1..=5 {
+            *buffer.back_mut() = frame;
+            buffer.flip();
+            assert_eq!(buffer.front_index(), frame as usize % 2);
+            assert_eq!(buffer.take_front(), Some(&frame));
+            // Only handed out once per flip
+            assert_eq!(buffer.take_front(), None);
+        }
+        assert_eq!(*buffer.back_mut(), 4);
+    }
+
+    #[test]
+    fn oam_slots_run_out() {
+        let mut slots = OamSlots::default();
+        let mut drawn = alloc::vec::Vec::new();
+        // Every other sprite is affine, so affine slots run out first
+        for i in 0..200 {
+            if let Some(slot) = slots.take(i % 2 == 1) {
+                drawn.push(slot);
+            }
+        }
+        assert!(slots.overflowed);
+        assert_eq!(drawn.len(), OAM_SPRITE_COUNT);
+        assert_eq!(drawn.iter().filter(|(_, affine)| affine.is_some()).count(), OAM_AFFINE_COUNT);
+        // Handed out in order, with no gaps
+        assert!(drawn.iter().enumerate().all(|(i, &(index, _))| index == i));
+        assert_eq!(drawn[63], (63, Some(31)));
+        assert_eq!(drawn[64], (64, None));
+        assert_eq!(slots.take(false), None);
+    }
+
+    #[test]
+    fn flash_then_restore() {
+        let mut sprite = test_sprite();
+        sprite.palette_override = Some(5);
+        sprite.flash(15, 2);
+        assert_eq!(sprite.palette_bank(3), 15);
+        sprite.update_flash();
+        assert_eq!(sprite.palette_bank(3), 15);
+        sprite.update_flash();
+        assert!(!sprite.is_flashing());
+        assert_eq!(sprite.palette_bank(3), 5);
+        sprite.flash(15, 0);
+        assert!(!sprite.is_flashing());
+    }
+}
@@ -15,6 +15,11 @@ struct PoolEntry<T> {
     data: Option<T>
 }
 
+/// A generational reference to an object in a `Pool`. Once the object is removed, the slot's
+/// generation goes up, so old handles stop working even if the slot is reused.
+///
+/// Layout: the slot index (a `usize`) then the generation (a `NonZeroU32`), so 8 bytes on the DS.
+/// The generation is never zero, so `Option<Handle>` is the same size.
 pub struct Handle<T> {
     index: usize,
     generation: NonZeroU32, // making this nonzero makes Option<Handle> more efficient using niche optimisation
@@ -43,6 +48,13 @@ impl<T> PartialEq for Handle<T> {
 }
 impl<T> Eq for Handle<T> {}
 
+impl<T> core::hash::Hash for Handle<T> {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+        self.generation.hash(state);
+    }
+}
+
 impl<T> Debug for Handle<T> {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("Handle")
@@ -52,6 +64,61 @@ impl<T> Debug for Handle<T> {
     }
 }
 
+impl<T> Handle<T> {
+    /// The slot this handle points to, without the generation.
+    #[inline]
+    #[must_use]
+    pub fn raw(self) -> RawIndex<T> {
+        RawIndex::new(self.index)
+    }
+}
+
+/// A slot index into a `Pool`, without a generation. Half the size of a `Handle`, but it can't tell
+/// if the object it pointed to has been removed, so it's only for links the engine keeps valid itself
+/// (like the tree links between nodes). Anything game code holds on to should be a `Handle`.
+///
+/// Layout: the slot index plus one, as a `NonZeroU32`, so 4 bytes, and `Option<RawIndex>` is too.
+pub struct RawIndex<T> {
+    index_plus_one: NonZeroU32,
+    phantom_type: PhantomData<T>
+}
+
+impl<T> RawIndex<T> {
+    #[inline]
+    fn new(index: usize) -> Self {
+        Self {
+            index_plus_one: NonZeroU32::new(index as u32 + 1).expect("Tried to make a raw index that's too big"),
+            phantom_type: PhantomData::<T>,
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn index(self) -> usize {
+        self.index_plus_one.get() as usize - 1
+    }
+}
+
+impl<T> Clone for RawIndex<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<T> Copy for RawIndex<T> {}
+
+impl<T> PartialEq for RawIndex<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index_plus_one == other.index_plus_one
+    }
+}
+impl<T> Eq for RawIndex<T> {}
+
+impl<T> Debug for RawIndex<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("RawIndex").field("index", &self.index()).finish()
+    }
+}
+
 pub struct Ticket<T> {
     index: usize,
     phantom_type: PhantomData<T>
@@ -66,6 +133,26 @@ impl<T> Pool<T> {
         }
     }
 
+    /// Makes a pool with room for `capacity` objects before it has to reallocate.
+    #[must_use]
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            data_vec: Vec::with_capacity(capacity),
+            free_stack: Vec::new()
+        }
+    }
+
+    /// Makes room for at least `additional` more objects, counting free slots that can be reused.
+    pub fn reserve(&mut self, additional: usize) {
+        self.data_vec.reserve(additional.saturating_sub(self.free_stack.len()));
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.data_vec.capacity()
+    }
+
     #[inline(always)]
     #[must_use]
     pub fn vec_len(&self) -> usize {
@@ -190,6 +277,54 @@ impl<T> Pool<T> {
         }
     }
 
+    /// Fills the emptied slot from a `take` with a new object. Unlike `put_back`,
+    /// this counts as a new object, so handles to the one that was taken stay invalid.
+    pub fn reuse(&mut self, ticket: Ticket<T>, value: T) -> Handle<T> {
+        let entry = self.data_vec.get_mut(ticket.index).expect("Tried to reuse out-of-bounds ticket index");
+        debug_assert!(entry.data.is_none(), "Tried to reuse an occupied slot");
+        entry.generation = entry.generation.checked_add(1).expect("Pool generation number overflow");
+        entry.data.replace(value);
+        Handle {
+            index: ticket.index,
+            generation: entry.generation,
+            phantom_type: PhantomData::<T>
+        }
+    }
+
+    /// Gives the emptied slot from a `take` back to the pool, for `add` to reuse.
+    pub fn release(&mut self, ticket: Ticket<T>) {
+        debug_assert!(self.data_vec.get(ticket.index).is_some_and(|e| e.data.is_none()), "Tried to release an occupied slot");
+        self.free_stack.push(ticket.index);
+    }
+
+    /// Turns a raw index back into a handle to whatever is in the slot now.
+    #[inline]
+    #[must_use]
+    pub fn handle_from_raw(&self, raw: RawIndex<T>) -> Handle<T> {
+        let index = raw.index();
+        let entry = self.data_vec.get(index).filter(|e| e.data.is_some())
+            .unwrap_or_else(|| panic!("Tried to use a raw index to an empty slot: {raw:?}"));
+        Handle {
+            index,
+            generation: entry.generation,
+            phantom_type: PhantomData::<T>,
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn borrow_raw(&self, raw: RawIndex<T>) -> &T {
+        self.data_vec.get(raw.index()).and_then(|e| e.data.as_ref())
+            .unwrap_or_else(|| panic!("Tried to borrow from pool with a raw index to an empty slot: {raw:?}"))
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn borrow_raw_mut(&mut self, raw: RawIndex<T>) -> &mut T {
+        self.data_vec.get_mut(raw.index()).and_then(|e| e.data.as_mut())
+            .unwrap_or_else(|| panic!("Tried to mutably borrow from pool with a raw index to an empty slot: {raw:?}"))
+    }
+
     // Only fails if index out of range.
     #[must_use]
     pub fn handle_from_index(&self, index: usize) -> Option<Handle<T>> {
@@ -369,3 +504,31 @@ impl<const N: usize> core::fmt::Display for PoolGetManyMutError<N> {
     }
 }
 impl<const N: usize> core::error::Error for PoolGetManyMutError<N> {}
+
+#[cfg(all(test, feature = "no-hardware"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raw_index_is_smaller() {
+        assert_eq!(core::mem::size_of::<Option<RawIndex<u8>>>(), 4);
+        assert_eq!(core::mem::size_of::<Option<Handle<u8>>>(), core::mem::size_of::<Handle<u8>>());
+        assert!(core::mem::size_of::<RawIndex<u8>>() < core::mem::size_of::<Handle<u8>>());
+    }
+
+    #[test]
+    fn handles_detect_reused_slots() {
+        let mut pool = Pool::new();
+        let old = pool.add(1);
+        let raw = old.raw();
+        pool.remove(old);
+        let new = pool.add(2);
+
+        // The raw index follows whatever is in the slot, but the old handle doesn't
+        assert_eq!(new.raw(), raw);
+        assert_eq!(pool.handle_from_raw(raw), new);
+        assert_eq!(*pool.borrow_raw(raw), 2);
+        assert_eq!(pool.try_borrow(old), None);
+        assert_eq!(pool.try_borrow(new), Some(&2));
+    }
+}
@@ -1,24 +1,117 @@
+use core::ops::DerefMut;
+#[cfg(feature = "hardware")]
 use ironds::sync::NdsMutex;
 use randomize::{PCG32, Gen32};
+use fixed::types::I20F12;
 
 // could improve this by making a version of LazyStatic / LazyCell for NdsMutex / NdsCell
-static RAND_GENERATOR: NdsMutex<PCG32> = NdsMutex::new(PCG32::seed(0, 0));
+#[cfg(feature = "hardware")]
+static RAND_GENERATOR: NdsMutex<Rng> = NdsMutex::new(Rng::new(0));
+#[cfg(feature = "no-hardware")]
+static RAND_GENERATOR: std::sync::Mutex<Rng> = std::sync::Mutex::new(Rng::new(0));
+
+#[cfg(feature = "hardware")]
+fn global_generator() -> impl DerefMut<Target = Rng> {
+    RAND_GENERATOR.lock()
+}
+
+#[cfg(feature = "no-hardware")]
+fn global_generator() -> impl DerefMut<Target = Rng> {
+    RAND_GENERATOR.lock().unwrap()
+}
 
 // Taken from randomize source
 const DEFAULT_PCG_INC: u128 = 34172814569070222299;
 
+/// Deterministic random number generator - the same seed always gives the same sequence.
+pub struct Rng {
+    gen: PCG32,
+}
+
+impl Rng {
+    #[must_use]
+    pub const fn new(seed: u64) -> Self {
+        Self { gen: PCG32::seed(seed, DEFAULT_PCG_INC as u64) }
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        self.gen.next_u32()
+    }
+
+    /// Random number from `lower` (inclusive) to `upper` (exclusive), or `lower` if they're equal.
+    pub fn range(&mut self, lower: i32, upper: i32) -> i32 {
+        assert!(upper >= lower);
+        if upper == lower {
+            return lower;
+        }
+        let range = upper.wrapping_sub(lower) as u32;
+        lower.wrapping_add(self.gen.next_bounded(range) as i32)
+    }
+
+    /// Random fixed point number from `lower` (inclusive) to `upper` (exclusive), or `lower` if they're equal.
+    pub fn range_fixed(&mut self, lower: I20F12, upper: I20F12) -> I20F12 {
+        I20F12::from_bits(self.range(lower.to_bits(), upper.to_bits()))
+    }
+}
+
+/// Gives scripts access to the global generator, as `context.rng`.
+#[derive(Clone, Copy)]
+pub struct GlobalRng;
+
+impl GlobalRng {
+    pub fn next_u32(&self) -> u32 {
+        rand_u32()
+    }
+
+    pub fn range(&self, lower: i32, upper: i32) -> i32 {
+        rand_i32_in_range(lower, upper)
+    }
+
+    pub fn range_fixed(&self, lower: I20F12, upper: I20F12) -> I20F12 {
+        global_generator().range_fixed(lower, upper)
+    }
+}
+
 pub fn seed(seed: u64) {
-    *RAND_GENERATOR.lock() = PCG32::seed(seed, DEFAULT_PCG_INC as u64);
+    *global_generator() = Rng::new(seed);
 }
 
 pub fn rand_u32() -> u32 {
-    RAND_GENERATOR.lock().next_u32()
+    global_generator().next_u32()
 }
 
 pub fn rand_i32_in_range(lower: i32, upper: i32) -> i32 {
-    assert!(upper >= lower);
-    let range = upper - lower;
-    let rand = RAND_GENERATOR.lock().next_bounded(range as u32);
-    rand as i32 + lower
+    global_generator().range(lower, upper)
 }
 
+#[cfg(all(test, feature = "no-hardware"))]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn same_seed_same_sequence() {
+        let (mut a, mut b, mut c) = (Rng::new(42), Rng::new(42), Rng::new(43));
+        let a: Vec<u32> = (0..16).map(|_| a.next_u32()).collect();
+        let b: Vec<u32> = (0..16).map(|_| b.next_u32()).collect();
+        let c: Vec<u32> = (0..16).map(|_| c.next_u32()).collect();
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn range_is_roughly_uniform() {
+        let mut rng = Rng::new(7);
+        let mut buckets = [0; 10];
+        for _ in 0..10000 {
+            let x = rng.range(-5, 5);
+            assert!((-5..5).contains(&x));
+            buckets[(x + 5) as usize] += 1;
+        }
+        // Each bucket expects 1000
+        assert!(buckets.iter().all(|&count| (850..1150).contains(&count)), "{buckets:?}");
+
+        assert_eq!(rng.range(3, 3), 3);
+        assert_eq!(rand_i32_in_range(-2, -2), -2);
+    }
+}
@@ -0,0 +1,89 @@
+//! Number formatting into stack buffers, for HUD text that changes every frame without allocating.
+//! The returned strings borrow from the buffer.
+
+use fixed::types::I20F12;
+
+/// Enough room for any i32, including the sign.
+pub const INT_BUFFER_LEN: usize = 11;
+/// Enough room for any I20F12 with up to `MAX_FIXED_DECIMALS` decimal places.
+pub const FIXED_BUFFER_LEN: usize = 13;
+/// More decimal places than this is more precision than an I20F12 has.
+pub const MAX_FIXED_DECIMALS: u8 = 4;
+
+// Writes the digits of value to the end of buf, returning the index of the first one
+fn write_digits(mut value: u64, buf: &mut [u8]) -> usize {
+    let mut start = buf.len();
+    loop {
+        start -= 1;
+        buf[start] = b'0' + (value % 10) as u8;
+        value /= 10;
+        if value == 0 {
+            return start;
+        }
+    }
+}
+
+/// Formats an integer in decimal, like "-1234".
+pub fn format_i32(value: i32, buf: &mut [u8; INT_BUFFER_LEN]) -> &str {
+    let mut start = write_digits(value.unsigned_abs() as u64, buf);
+    if value < 0 {
+        start -= 1;
+        buf[start] = b'-';
+    }
+    core::str::from_utf8(&buf[start..]).unwrap()
+}
+
+/// Formats a fixed point number with the given number of decimal places (at most `MAX_FIXED_DECIMALS`),
+/// rounding to the nearest, like "-12.50".
+pub fn format_fixed(value: I20F12, decimals: u8, buf: &mut [u8; FIXED_BUFFER_LEN]) -> &str {
+    let decimals = decimals.min(MAX_FIXED_DECIMALS);
+    let scale = 10u64.pow(decimals as u32);
+    let raw = value.to_bits().unsigned_abs() as u64;
+    let scaled = (raw * scale + (1 << (I20F12::FRAC_NBITS - 1))) >> I20F12::FRAC_NBITS;
+
+    let mut start = buf.len();
+    if decimals > 0 {
+        let frac_start = start - decimals as usize;
+        // Pad the fraction with leading zeros, e.g. 5 hundredths is ".05"
+        buf[frac_start..start].fill(b'0');
+        write_digits(scaled % scale, &mut buf[frac_start..start]);
+        start = frac_start - 1;
+        buf[start] = b'.';
+    }
+    start = write_digits(scaled / scale, &mut buf[..start]);
+    if value < 0 && scaled != 0 {
+        start -= 1;
+        buf[start] = b'-';
+    }
+    core::str::from_utf8(&buf[start..]).unwrap()
+}
+
+#[cfg(all(test, feature = "no-hardware"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_integers() {
+        let mut buf = [0; INT_BUFFER_LEN];
+        assert_eq!(format_i32(0, &mut buf), "0");
+        assert_eq!(format_i32(7, &mut buf), "7");
+        assert_eq!(format_i32(1_234_567, &mut buf), "1234567");
+        assert_eq!(format_i32(i32::MAX, &mut buf), "2147483647");
+        assert_eq!(format_i32(-42, &mut buf), "-42");
+        assert_eq!(format_i32(i32::MIN, &mut buf), "-2147483648");
+    }
+
+    #[test]
+    fn format_fixed_point() {
+        let mut buf = [0; FIXED_BUFFER_LEN];
+        assert_eq!(format_fixed(I20F12::ZERO, 2, &mut buf), "0.00");
+        assert_eq!(format_fixed(I20F12::from_num(3.25), 2, &mut buf), "3.25");
+        assert_eq!(format_fixed(I20F12::from_num(-12.5), 1, &mut buf), "-12.5");
+        assert_eq!(format_fixed(I20F12::from_num(1.05), 2, &mut buf), "1.05");
+        assert_eq!(format_fixed(I20F12::from_num(2.75), 0, &mut buf), "3");
+        assert_eq!(format_fixed(I20F12::from_num(0.999), 2, &mut buf), "1.00");
+        assert_eq!(format_fixed(I20F12::from_num(-0.001), 2, &mut buf), "0.00");
+        assert_eq!(format_fixed(I20F12::MIN, 4, &mut buf), "-524288.0000");
+        assert_eq!(format_fixed(I20F12::from_num(5), 9, &mut buf), "5.0000");
+    }
+}
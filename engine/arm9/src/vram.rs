@@ -0,0 +1,315 @@
+//! Keeps track of which graphics are loaded into OBJ VRAM, and where.
+//! Graphics get tiles and palettes allocated the first time they're requested,
+//! and a graphic that doesn't fit is refused instead of overwriting another one.
+
+use alloc::{string::String, vec::Vec};
+use sandstone_common::{SavedGraphic, SpriteSize};
+use crate::HashMap;
+
+/// Tiles a sprite can point to, with 1D mapping and a 32 byte boundary.
+pub const OBJ_TILE_CAPACITY: u16 = 1024;
+/// 16 colour palettes in OBJ palette RAM.
+pub const OBJ_PALETTE_CAPACITY: u16 = 16;
+
+pub(crate) const SIZEOF_TILE: usize = (8 * 8) / 2;
+pub(crate) const SIZEOF_PALETTE: usize = 2 * 16;
+
+/// Where a loaded graphic is in VRAM.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VramSlot {
+    /// Index of the first tile, as used by OAM.
+    pub tile_index: u16,
+    /// First palette bank.
+    pub pal_index: u8,
+    /// Size of the graphic's sprites.
+    pub size: SpriteSize,
+}
+
+/// Why a graphic couldn't be loaded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VramError {
+    /// The graphic needs this many tiles, but the largest free run of tiles is shorter.
+    OutOfTiles { needed: u16, largest_free: u16 },
+    /// The graphic needs this many palette banks, but the largest free run of banks is shorter.
+    OutOfPalettes { needed: u16, largest_free: u16 },
+}
+
+impl core::fmt::Display for VramError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::OutOfTiles { needed, largest_free } =>
+                f.write_fmt(format_args!("out of OBJ VRAM: needed {needed} tiles, but only {largest_free} are free in a row")),
+            Self::OutOfPalettes { needed, largest_free } =>
+                f.write_fmt(format_args!("out of OBJ palettes: needed {needed} banks, but only {largest_free} are free in a row")),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Allocation {
+    slot: VramSlot,
+    tile_count: u16,
+    pal_count: u16,
+}
+
+// Free runs of units as (start, length), sorted by start and never touching each other
+struct FreeList(Vec<(u16, u16)>);
+
+impl FreeList {
+    fn new(capacity: u16) -> Self {
+        Self(if capacity > 0 { alloc::vec![(0, capacity)] } else { Vec::new() })
+    }
+
+    fn largest(&self) -> u16 {
+        self.0.iter().map(|&(_, len)| len).max().unwrap_or(0)
+    }
+
+    fn total(&self) -> u16 {
+        self.0.iter().map(|&(_, len)| len).sum()
+    }
+
+    // First fit
+    fn allocate(&mut self, len: u16) -> Option<u16> {
+        let i = self.0.iter().position(|&(_, free_len)| free_len >= len)?;
+        let (start, free_len) = self.0[i];
+        if free_len == len {
+            self.0.remove(i);
+        } else {
+            self.0[i] = (start + len, free_len - len);
+        }
+        Some(start)
+    }
+
+    fn free(&mut self, start: u16, len: u16) {
+        if len == 0 {
+            return;
+        }
+        let i = self.0.partition_point(|&(s, _)| s < start);
+        self.0.insert(i, (start, len));
+        // Merge with the next run, then the previous one
+        if i + 1 < self.0.len() && self.0[i].0 + self.0[i].1 == self.0[i + 1].0 {
+            self.0[i].1 += self.0.remove(i + 1).1;
+        }
+        if i > 0 && self.0[i - 1].0 + self.0[i - 1].1 == self.0[i].0 {
+            self.0[i - 1].1 += self.0.remove(i).1;
+        }
+    }
+}
+
+pub struct VramAllocator {
+    free_tiles: FreeList,
+    free_palettes: FreeList,
+    loaded: HashMap<String, Allocation>,
+}
+
+impl VramAllocator {
+    /// An allocator for the whole of OBJ VRAM and palette RAM.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_capacity(OBJ_TILE_CAPACITY, OBJ_PALETTE_CAPACITY)
+    }
+
+    #[must_use]
+    pub fn with_capacity(tiles: u16, palettes: u16) -> Self {
+        Self {
+            free_tiles: FreeList::new(tiles),
+            free_palettes: FreeList::new(palettes),
+            loaded: HashMap::default(),
+        }
+    }
+
+    /// Where the graphic is, or None if it isn't loaded.
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<VramSlot> {
+        self.loaded.get(name).map(|a| a.slot)
+    }
+
+    #[must_use]
+    pub fn is_loaded(&self, name: &str) -> bool {
+        self.loaded.contains_key(name)
+    }
+
+    /// Tiles that aren't used by any graphic, though they might not all be in a row.
+    #[must_use]
+    pub fn free_tile_count(&self) -> u16 {
+        self.free_tiles.total()
+    }
+
+    /// Returns where the graphic is, making room for it if it isn't loaded yet.
+    /// The bool is true if the space is new, so the graphic's data still has to be copied in.
+    pub fn load(&mut self, name: &str, graphic: &SavedGraphic) -> Result<(VramSlot, bool), VramError> {
+        if let Some(allocation) = self.loaded.get(name) {
+            return Ok((allocation.slot, false));
+        }
+        let tile_count = graphic.tiles.len().div_ceil(SIZEOF_TILE) as u16;
+        let pal_count = graphic.palette.len().div_ceil(SIZEOF_PALETTE) as u16;
+        let largest_free = self.free_tiles.largest();
+        if largest_free < tile_count {
+            return Err(VramError::OutOfTiles { needed: tile_count, largest_free });
+        }
+        let largest_free = self.free_palettes.largest();
+        if largest_free < pal_count {
+            return Err(VramError::OutOfPalettes { needed: pal_count, largest_free });
+        }
+        // Both have been checked, so neither can fail
+        let tile_index = if tile_count > 0 { self.free_tiles.allocate(tile_count).unwrap() } else { 0 };
+        let pal_index = if pal_count > 0 { self.free_palettes.allocate(pal_count).unwrap() as u8 } else { 0 };
+        let slot = VramSlot { tile_index, pal_index, size: graphic.size };
+        self.loaded.insert(String::from(name), Allocation { slot, tile_count, pal_count });
+        Ok((slot, true))
+    }
+
+    /// Frees the graphic's tiles and palettes. Returns false if it wasn't loaded.
+    pub fn unload(&mut self, name: &str) -> bool {
+        let Some(allocation) = self.loaded.remove(name) else { return false; };
+        self.free_tiles.free(allocation.slot.tile_index, allocation.tile_count);
+        self.free_palettes.free(u16::from(allocation.slot.pal_index), allocation.pal_count);
+        true
+    }
+}
+
+impl Default for VramAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Copy)]
+struct CachedGraphic {
+    refs: u32,
+    pinned: bool,
+}
+
+/// Loaded graphics by asset name, so that everything using the same graphic shares one upload.
+/// A graphic is unloaded when nothing references it, unless it's pinned.
+pub struct GraphicsCache {
+    vram: VramAllocator,
+    entries: HashMap<String, CachedGraphic>,
+    uploads: u32,
+}
+
+impl GraphicsCache {
+    #[must_use]
+    pub fn new(vram: VramAllocator) -> Self {
+        Self { vram, entries: HashMap::default(), uploads: 0 }
+    }
+
+    /// Adds a reference to the graphic, loading it if it isn't loaded yet.
+    /// The bool is true if the graphic's data still has to be copied in.
+    pub fn acquire(&mut self, name: &str, graphic: &SavedGraphic) -> Result<(VramSlot, bool), VramError> {
+        let result = self.load(name, graphic)?;
+        self.entries.get_mut(name).unwrap().refs += 1;
+        Ok(result)
+    }
+
+    /// Removes a reference added by `acquire`.
+    pub fn release(&mut self, name: &str) {
+        let entry = self.entries.get_mut(name).filter(|e| e.refs > 0)
+            .expect("Tried to release a graphic with no references");
+        entry.refs -= 1;
+        self.unload_if_unused(name);
+    }
+
+    /// Loads the graphic and keeps it loaded even when nothing references it.
+    pub fn pin(&mut self, name: &str, graphic: &SavedGraphic) -> Result<(VramSlot, bool), VramError> {
+        let result = self.load(name, graphic)?;
+        self.entries.get_mut(name).unwrap().pinned = true;
+        Ok(result)
+    }
+
+    pub fn unpin(&mut self, name: &str) {
+        if let Some(entry) = self.entries.get_mut(name) {
+            entry.pinned = false;
+            self.unload_if_unused(name);
+        }
+    }
+
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<VramSlot> {
+        self.vram.get(name)
+    }
+
+    #[must_use]
+    pub fn ref_count(&self, name: &str) -> u32 {
+        self.entries.get(name).map_or(0, |e| e.refs)
+    }
+
+    /// Number of times a graphic has been newly loaded, counting reloads after being unloaded.
+    #[must_use]
+    pub fn upload_count(&self) -> u32 {
+        self.uploads
+    }
+
+    fn load(&mut self, name: &str, graphic: &SavedGraphic) -> Result<(VramSlot, bool), VramError> {
+        let (slot, is_new) = self.vram.load(name, graphic)?;
+        if is_new {
+            self.uploads += 1;
+            self.entries.insert(String::from(name), CachedGraphic { refs: 0, pinned: false });
+        }
+        Ok((slot, is_new))
+    }
+
+    fn unload_if_unused(&mut self, name: &str) {
+        if self.entries.get(name).is_some_and(|e| e.refs == 0 && !e.pinned) {
+            self.entries.remove(name);
+            self.vram.unload(name);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "no-hardware"))]
+mod tests {
+    use super::*;
+
+    fn graphic(tiles: usize) -> SavedGraphic {
+        SavedGraphic { tiles: alloc::vec![0; tiles * SIZEOF_TILE], palette: alloc::vec![0; SIZEOF_PALETTE], size: SpriteSize::_8x8 }
+    }
+
+    #[test]
+    fn allocates_after_previous_graphic() {
+        let mut vram = VramAllocator::with_capacity(16, 4);
+        assert_eq!(vram.load("A", &graphic(4)), Ok((VramSlot { tile_index: 0, pal_index: 0, size: SpriteSize::_8x8 }, true)));
+        assert_eq!(vram.load("B", &graphic(2)), Ok((VramSlot { tile_index: 4, pal_index: 1, size: SpriteSize::_8x8 }, true)));
+        assert_eq!(vram.free_tile_count(), 10);
+        assert_eq!(vram.get("B"), Some(VramSlot { tile_index: 4, pal_index: 1, size: SpriteSize::_8x8 }));
+    }
+
+    #[test]
+    fn reuses_loaded_graphic() {
+        let mut vram = VramAllocator::with_capacity(16, 4);
+        let (slot, _) = vram.load("A", &graphic(4)).unwrap();
+        assert_eq!(vram.load("A", &graphic(4)), Ok((slot, false)));
+        assert_eq!(vram.free_tile_count(), 12);
+    }
+
+    #[test]
+    fn out_of_vram() {
+        let mut vram = VramAllocator::with_capacity(8, 2);
+        vram.load("A", &graphic(4)).unwrap();
+        vram.load("B", &graphic(2)).unwrap();
+        assert_eq!(vram.load("C", &graphic(4)), Err(VramError::OutOfTiles { needed: 4, largest_free: 2 }));
+        assert_eq!(vram.load("D", &graphic(1)), Err(VramError::OutOfPalettes { needed: 1, largest_free: 0 }));
+        assert!(!vram.is_loaded("C"));
+
+        // Freeing the first graphic makes room again
+        assert!(vram.unload("A"));
+        assert_eq!(vram.load("C", &graphic(4)), Ok((VramSlot { tile_index: 0, pal_index: 0, size: SpriteSize::_8x8 }, true)));
+    }
+
+    #[test]
+    fn cache_shares_uploads() {
+        let mut cache = GraphicsCache::new(VramAllocator::with_capacity(16, 4));
+        let (slot, is_new) = cache.acquire("A", &graphic(4)).unwrap();
+        assert!(is_new);
+        assert_eq!(cache.acquire("A", &graphic(4)), Ok((slot, false)));
+        assert_eq!((cache.upload_count(), cache.ref_count("A")), (1, 2));
+
+        cache.release("A");
+        assert_eq!(cache.get("A"), Some(slot));
+        cache.release("A");
+        assert_eq!(cache.get("A"), None);
+        // Loading it again after it's gone is another upload
+        cache.acquire("A", &graphic(4)).unwrap();
+        assert_eq!(cache.upload_count(), 2);
+    }
+}
@@ -1,8 +1,8 @@
 use alloc::format;
 use sandstone::node::{Node, NodeExtensionHandle};
 use sandstone::{Script, ScriptContext};
-use sandstone::ironds::input;
-use sandstone::ironds::display::console;
+use sandstone::input;
+use sandstone::console;
 use sandstone::hierarchy::HierarchyPoolTrait;
 use sandstone::fixed::types::*;
 
@@ -41,7 +41,7 @@ impl Script for BallScript {
             }
 
             // Check for collision
-            let child_handle = node.child_handle.unwrap();
+            let child_handle = context.hierarchy.first_child(context.handle).unwrap();
             let child = context.hierarchy.borrow(child_handle);
     
             let NodeExtensionHandle::RectCollider(collider_handle) = child.node_extension else {
@@ -49,8 +49,7 @@ impl Script for BallScript {
             };
             let collider = context.hierarchy.borrow(collider_handle);
             for intersecting_node_handle in collider.intersect_list.iter() {
-                let col_node = context.hierarchy.borrow(*intersecting_node_handle);
-                if col_node.name.contains("Wall") {
+                if context.hierarchy.node_name(*intersecting_node_handle).contains("Wall") {
                     // Collided with a wall - bounce horizontally
                     self.x_vel = -self.x_vel;
                 } else {
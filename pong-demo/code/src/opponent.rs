@@ -14,8 +14,8 @@ pub struct AIPaddleScript {
 sandstone::register_script!(AIPaddleScript, 3);
 impl Script for AIPaddleScript {
     fn start(&mut self, context: &mut ScriptContext) {
-        let node = context.hierarchy.borrow(context.handle);
-        self.ball_handle = context.hierarchy.find_by_name(node.parent_handle.unwrap(), "Ball");
+        let parent = context.hierarchy.parent(context.handle).unwrap();
+        self.ball_handle = context.hierarchy.find_by_name(parent, "Ball");
     }
 
     fn update(&mut self, context: &mut ScriptContext) {
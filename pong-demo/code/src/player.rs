@@ -1,5 +1,5 @@
 use sandstone::{Script, ScriptContext};
-use sandstone::ironds::input;
+use sandstone::input;
 use sandstone::hierarchy::HierarchyPoolTrait;
 use sandstone::fixed::types::*;
 
@@ -5,7 +5,7 @@ use sandstone::fixed::types::*;
 use sandstone::hierarchy::HierarchyPoolTrait;
 
 const BULLET_SPEED: I20F12 = I20F12::lit("5");
-const SCORE_PER_ENEMY: u32 = 10;
+const BULLET_DAMAGE: u32 = 1;
 
 #[derive(Default)]
 pub struct BulletScript {
@@ -20,11 +20,10 @@ impl Script for BulletScript {
         let node = context.hierarchy.borrow_mut(context.handle);
         node.transform.y -= BULLET_SPEED;
         if node.transform.y < -64 {
-            context.hierarchy.destroy_node(context.handle);
+            context.destroy(context.handle);
         }
 
-        let node = context.hierarchy.borrow(context.handle);
-        let child = context.hierarchy.borrow(node.child_handle.unwrap());
+        let child = context.hierarchy.borrow(context.hierarchy.first_child(context.handle).unwrap());
 
         let sandstone::node::NodeExtensionHandle::RectCollider(collider_handle) = child.node_extension else {
             panic!("Bullet has no Collider");
@@ -32,22 +31,17 @@ impl Script for BulletScript {
         let mut hit_enemy_handle: Option<Handle<Node>> = None;
         let collider = context.hierarchy.borrow(collider_handle);
         for intersecting_node_handle in collider.intersect_list.iter() {
-            if context.hierarchy.borrow(*intersecting_node_handle).name.contains("Enemy") {
+            if context.hierarchy.node_name(*intersecting_node_handle).contains("Enemy") {
                 hit_enemy_handle = Some(*intersecting_node_handle);
             }
         }
         if let Some(hit_enemy_handle) = hit_enemy_handle {
             // Destroy bullet
-            context.hierarchy.destroy_node(context.handle);
+            context.destroy(context.handle);
 
-            // Destroy enemy
-            let enemy_handle = context.hierarchy.borrow(hit_enemy_handle).parent_handle.unwrap();
-            context.hierarchy.destroy_node(enemy_handle);
-
-            // Add score
-            let game_manager_handle = context.hierarchy.borrow(context.hierarchy.root).child_handle.unwrap();
-            let game_manager = context.hierarchy.borrow_mut(game_manager_handle).cast_script_mut::<crate::GameManagerScript>();
-            game_manager.add_score(SCORE_PER_ENEMY);
+            // Damage enemy - it adds the score when it dies
+            let enemy_handle = context.hierarchy.parent(hit_enemy_handle).unwrap();
+            context.hierarchy.apply_damage(enemy_handle, BULLET_DAMAGE);
         }
     }
 }
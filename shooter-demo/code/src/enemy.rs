@@ -1,8 +1,11 @@
 use sandstone::{Script, ScriptContext};
 use sandstone::fixed::types::*;
 use sandstone::hierarchy::HierarchyPoolTrait;
+use sandstone::node::Health;
 
 const SPEED: I20F12 = I20F12::lit("3");
+const MAX_HEALTH: u32 = 1;
+const SCORE_PER_ENEMY: u32 = 10;
 
 #[derive(Default)]
 pub struct EnemyScript {
@@ -12,7 +15,8 @@ pub struct EnemyScript {
 
 sandstone::register_script!(EnemyScript, 5);
 impl Script for EnemyScript {
-    fn start(&mut self, _context: &mut ScriptContext) {
+    fn start(&mut self, context: &mut ScriptContext) {
+        context.hierarchy.borrow_mut(context.handle).health = Some(Health::new(MAX_HEALTH));
     }
 
     fn update(&mut self, context: &mut ScriptContext) {
@@ -21,12 +25,18 @@ impl Script for EnemyScript {
         node.transform.x += self.x_velocity * SPEED;
 
         if node.transform.y > 192*2 {
-            context.hierarchy.destroy_node(context.handle);
+            context.destroy(context.handle);
 
             // Trigger game over
-            let game_manager_handle = context.hierarchy.borrow(context.hierarchy.root).child_handle.unwrap();
+            let game_manager_handle = context.hierarchy.first_child(context.hierarchy.root).unwrap();
             let game_manager = context.hierarchy.borrow_mut(game_manager_handle).cast_script_mut::<crate::GameManagerScript>();
             game_manager.game_over();
         }
     }
+
+    fn on_death(&mut self, context: &mut ScriptContext) {
+        let game_manager_handle = context.hierarchy.first_child(context.hierarchy.root).unwrap();
+        let game_manager = context.hierarchy.borrow_mut(game_manager_handle).cast_script_mut::<crate::GameManagerScript>();
+        game_manager.add_score(SCORE_PER_ENEMY);
+    }
 }
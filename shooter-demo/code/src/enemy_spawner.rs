@@ -23,9 +23,9 @@ impl Script for EnemySpawnerScript {
 }
 
 fn spawn_enemy(context: &mut ScriptContext) {
-    let new_enemy_x = sandstone::random::rand_i32_in_range(64, 256-32-64);
+    let new_enemy_x = context.rng.range(64, 256-32-64);
     let new_enemy_angle =
-        I20F12::from_num(sandstone::random::rand_i32_in_range(-128, 128))
+        I20F12::from_num(context.rng.range(-128, 128))
         / 128 // number from -1 to 1
         / 3;  // number from -0.333 to 0.333
     let new_enemy_handle = context.hierarchy.spawn_object("Enemy", context.handle);
@@ -1,7 +1,7 @@
 use alloc::format;
 use sandstone::{Script, ScriptContext};
-use sandstone::ironds::display::console;
-use sandstone::ironds::input;
+use sandstone::console;
+use sandstone::input;
 
 const DISPLAY_START_MESSAGE_TIME: u32 = 120;
 const START_MESSAGE: &str = "Don't let any get past!";
@@ -1,5 +1,5 @@
 use sandstone::{Script, ScriptContext};
-use sandstone::ironds::{input, display::console};
+use sandstone::{input, console};
 
 #[derive(Default)]
 pub struct MenuScript {
@@ -1,7 +1,8 @@
 use sandstone::{Script, ScriptContext};
 use sandstone::fixed::types::*;
+use sandstone::math::Vec2;
 use sandstone::hierarchy::HierarchyPoolTrait;
-use sandstone::ironds::input;
+use sandstone::input;
 
 const MOVEMENT_SPEED: I20F12 = I20F12::lit("3");
 const MOVEMENT_SPEED_WHILE_SHOOTING: I20F12 = I20F12::lit("1.5");
@@ -27,32 +28,33 @@ impl Script for PlayerScript {
         }
 
         let keys = input::read_keys();
+        let mut direction = Vec2::ZERO;
         if keys.contains(input::Buttons::UP) {
-            node.transform.y -= speed;
+            direction.y -= I20F12::ONE;
         }
         if keys.contains(input::Buttons::DOWN) {
-            node.transform.y += speed;
+            direction.y += I20F12::ONE;
         }
         if keys.contains(input::Buttons::LEFT) {
-            node.transform.x -= speed;
+            direction.x -= I20F12::ONE;
         }
         if keys.contains(input::Buttons::RIGHT) {
-            node.transform.x += speed;
+            direction.x += I20F12::ONE;
         }
+        node.transform.set_position(node.transform.position() + direction * speed);
         if keys.contains(input::Buttons::A) && self.shoot_cooldown == 0 {
             let node = context.hierarchy.borrow(context.handle);
             let mut transform = node.transform;
             transform.x += I20F12::lit("12"); // center
 
             let handle = context.hierarchy.spawn_object(
-                "Bullet", node.parent_handle.unwrap());
+                "Bullet", context.hierarchy.parent(context.handle).unwrap());
             let bullet = context.hierarchy.borrow_mut(handle);
             bullet.transform = transform;
             self.shoot_cooldown = SHOOT_COOLDOWN_RELOAD;
         }
         // Check for collision with the enemy
-        let node = context.hierarchy.borrow(context.handle);
-        let child = context.hierarchy.borrow(node.child_handle.unwrap());
+        let child = context.hierarchy.borrow(context.hierarchy.first_child(context.handle).unwrap());
 
         let sandstone::node::NodeExtensionHandle::RectCollider(collider_handle) = child.node_extension else {
             panic!("Player has no Collider");
@@ -60,16 +62,107 @@ impl Script for PlayerScript {
         let mut hit_enemy = false;
         let collider = context.hierarchy.borrow(collider_handle);
         for intersecting_node_handle in collider.intersect_list.iter() {
-            if context.hierarchy.borrow(*intersecting_node_handle).name.contains("Enemy") {
+            if context.hierarchy.node_name(*intersecting_node_handle).contains("Enemy") {
                 hit_enemy = true;
             }
         }
         if hit_enemy {
-            context.hierarchy.destroy_node(context.handle);
+            context.destroy(context.handle);
             // Trigger game over
-            let game_manager_handle = context.hierarchy.borrow(context.hierarchy.root).child_handle.unwrap();
+            let game_manager_handle = context.hierarchy.first_child(context.hierarchy.root).unwrap();
             let game_manager = context.hierarchy.borrow_mut(game_manager_handle).cast_script_mut::<crate::GameManagerScript>();
             game_manager.game_over();
         }
     }
 }
+
+#[cfg(all(test, feature = "no-hardware"))]
+mod tests {
+    use super::*;
+    use alloc::{boxed::Box, string::String, vec};
+    use core::num::NonZeroU32;
+    use sandstone::ScriptRegistry;
+    use sandstone::hierarchy::Hierarchy;
+    use sandstone::sandstone_common::*;
+
+    const SCRIPTS: ScriptRegistry = ScriptRegistry {
+        factory: |id| match id.get() {
+            1 => Some(Box::<PlayerScript>::default()),
+            _ => None,
+        },
+        name: |id| match id.get() {
+            1 => Some("PlayerScript"),
+            _ => None,
+        },
+    };
+
+    fn player_game_data() -> SavedGameData {
+        let transform = SavedTransform { x: I20F12::lit("100"), y: I20F12::lit("100") };
+        let mut graphs = HashMap::default();
+        graphs.insert(String::from("Player"), SavedNodeGraph { nodes: vec![
+            SavedNode {
+                child_index: NonZeroU32::new(1),
+                parent_index: None,
+                sibling_index: None,
+                name: String::from("Player"),
+                transform,
+                node_extension: SavedNodeExtension::None,
+                script_type_id: NonZeroU32::new(1),
+                enabled: true,
+                user_data: 0,
+            },
+            SavedNode {
+                child_index: None,
+                parent_index: Some(0),
+                sibling_index: None,
+                name: String::from("Collider"),
+                transform: SavedTransform { x: I20F12::ZERO, y: I20F12::ZERO },
+                node_extension: SavedNodeExtension::RectCollider(SavedRectColliderExtension {
+                    width: I20F12::lit("16"),
+                    height: I20F12::lit("16"),
+                    offset_x: I20F12::ZERO,
+                    offset_y: I20F12::ZERO,
+                    response: CollisionResponse::Trigger,
+                    is_static: false,
+                    continuous: false,
+                }),
+                script_type_id: None,
+                enabled: true,
+                user_data: 0,
+            },
+        ], backdrop_colour: None});
+        SavedGameData { main_graph: String::from("Player"), graphs, graphics: HashMap::default() }
+    }
+
+    #[test]
+    fn moves_up_when_up_pressed() {
+        let mut hierarchy = Hierarchy::new(&serialize(&player_game_data()), SCRIPTS);
+        let player = hierarchy.spawn_object("Player", hierarchy.root);
+
+        input::set_keys(input::Buttons::UP);
+        hierarchy.run_frame();
+
+        let transform = hierarchy.borrow(player).transform;
+        assert_eq!(transform.x, I20F12::lit("100"));
+        assert_eq!(transform.y, I20F12::lit("100") - MOVEMENT_SPEED);
+    }
+
+    #[test]
+    fn moves_with_injected_input() {
+        let mut hierarchy = Hierarchy::new(&serialize(&player_game_data()), SCRIPTS);
+        let player = hierarchy.spawn_object("Player", hierarchy.root);
+
+        let mut frame = 0;
+        hierarchy.set_input_provider(Box::new(move || {
+            frame += 1;
+            let keys = if frame == 1 { input::Buttons::RIGHT } else { input::Buttons::DOWN | input::Buttons::LEFT };
+            input::FrameInput { keys }
+        }));
+        hierarchy.run_frame();
+        assert_eq!(hierarchy.borrow(player).transform.x, I20F12::lit("100") + MOVEMENT_SPEED);
+        assert_eq!(hierarchy.borrow(player).transform.y, I20F12::lit("100"));
+        hierarchy.run_frame();
+        assert_eq!(hierarchy.borrow(player).transform.x, I20F12::lit("100"));
+        assert_eq!(hierarchy.borrow(player).transform.y, I20F12::lit("100") + MOVEMENT_SPEED);
+    }
+}